@@ -0,0 +1,46 @@
+use authfix::middleware::PathMatcher;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn matcher() -> PathMatcher {
+    PathMatcher::new(
+        vec![
+            "/login",
+            "/register",
+            "/public/*",
+            "/static/*",
+            "/api/v1/health",
+            "/api/v1/docs/*",
+        ],
+        true,
+    )
+}
+
+const PATHS: [&str; 4] = [
+    "/api/v1/users/231/edit",
+    "/public/about",
+    "/static/js/app.js",
+    "/login",
+];
+
+fn bench_path_matcher(c: &mut Criterion) {
+    let uncached = matcher();
+    c.bench_function("path_matcher_uncached", |b| {
+        b.iter(|| {
+            for path in PATHS {
+                black_box(uncached.matches(black_box(path)));
+            }
+        })
+    });
+
+    let cached = matcher().with_cache(128);
+    c.bench_function("path_matcher_cached", |b| {
+        b.iter(|| {
+            for path in PATHS {
+                black_box(cached.matches(black_box(path)));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_path_matcher);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use authfix::session::user_serializer::{
+    CompressedUserSerializer, Compression, JsonUserSerializer, UserSerializer,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct User {
+    email: String,
+    name: String,
+    permissions: Vec<String>,
+}
+
+fn large_user() -> User {
+    User {
+        email: "anna@example.org".to_owned(),
+        name: "Anna".to_owned(),
+        permissions: (0..100).map(|i| format!("documents:{i}:read")).collect(),
+    }
+}
+
+fn bench_user_serializer(c: &mut Criterion) {
+    let user = large_user();
+    let json = JsonUserSerializer;
+    let gzip = CompressedUserSerializer::new(JsonUserSerializer, Compression::Gzip);
+
+    let json_bytes = UserSerializer::<User>::serialize(&json, &user).unwrap();
+    let gzip_bytes = UserSerializer::<User>::serialize(&gzip, &user).unwrap();
+    println!(
+        "serialized size: json {} bytes, gzip {} bytes",
+        json_bytes.len(),
+        gzip_bytes.len()
+    );
+
+    c.bench_function("user_serializer_json_roundtrip", |b| {
+        b.iter(|| {
+            let bytes = json.serialize(black_box(&user)).unwrap();
+            let _: User = json.deserialize(&bytes).unwrap();
+        })
+    });
+
+    c.bench_function("user_serializer_gzip_roundtrip", |b| {
+        b.iter(|| {
+            let bytes = gzip.serialize(black_box(&user)).unwrap();
+            let _: User = gzip.deserialize(&bytes).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, bench_user_serializer);
+criterion_main!(benches);
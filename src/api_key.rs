@@ -0,0 +1,138 @@
+//! Authentication of machine clients with static API keys
+use std::{
+    collections::HashMap,
+    future::{ready, Future},
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use actix_web::{web::Query, HttpRequest};
+use serde::de::DeserializeOwned;
+
+use crate::{errors::UnauthorizedError, AuthState, AuthToken, AuthenticationProvider};
+
+pub const DEFAULT_API_KEY_HEADER: &str = "X-API-Key";
+pub const DEFAULT_API_KEY_QUERY_PARAM: &str = "api_key";
+
+/// Loads the user (or client) that belongs to an API key
+pub trait ApiKeyLookup<U> {
+    /// Returns the user for the key, an [UnauthorizedError] if the key is unknown or revoked
+    fn lookup(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<U, UnauthorizedError>>>>;
+    /// Is called by [ApiKeyAuthProvider::invalidate](AuthenticationProvider::invalidate) with the key of the request
+    fn revoke(&self, _key: &str) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+}
+
+/// Provider for API keys sent in a header (default: `X-API-Key`) or as query parameter (default: `?api_key=`)
+///
+/// The header takes precedence over the query parameter.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, Client>::new(
+///     ApiKeyAuthProvider::new(DatabaseKeyLookup::new(pool))
+///         .header_name("X-Service-Key")
+///         .query_param("key"),
+///     PathMatcher::default(),
+/// )
+/// ```
+pub struct ApiKeyAuthProvider<U, L>
+where
+    L: ApiKeyLookup<U>,
+{
+    lookup: Arc<L>,
+    header_name: String,
+    query_param: String,
+    user_type: PhantomData<U>,
+}
+
+impl<U, L> ApiKeyAuthProvider<U, L>
+where
+    L: ApiKeyLookup<U>,
+{
+    pub fn new(lookup: L) -> Self {
+        Self {
+            lookup: Arc::new(lookup),
+            header_name: DEFAULT_API_KEY_HEADER.to_owned(),
+            query_param: DEFAULT_API_KEY_QUERY_PARAM.to_owned(),
+            user_type: PhantomData,
+        }
+    }
+
+    pub fn header_name(mut self, header_name: &str) -> Self {
+        self.header_name = header_name.to_owned();
+        self
+    }
+
+    pub fn query_param(mut self, query_param: &str) -> Self {
+        self.query_param = query_param.to_owned();
+        self
+    }
+
+    fn extract_key(&self, req: &HttpRequest) -> Option<String> {
+        let from_header = req
+            .headers()
+            .get(self.header_name.as_str())
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        from_header.or_else(|| {
+            Query::<HashMap<String, String>>::from_query(req.query_string())
+                .ok()?
+                .remove(&self.query_param)
+        })
+    }
+}
+
+impl<U, L> Clone for ApiKeyAuthProvider<U, L>
+where
+    L: ApiKeyLookup<U>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            lookup: Arc::clone(&self.lookup),
+            header_name: self.header_name.clone(),
+            query_param: self.query_param.clone(),
+            user_type: PhantomData,
+        }
+    }
+}
+
+impl<U, L> AuthenticationProvider<U> for ApiKeyAuthProvider<U, L>
+where
+    U: DeserializeOwned + Clone + 'static,
+    L: ApiKeyLookup<U> + 'static,
+{
+    fn get_auth_token(
+        &self,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<AuthToken<U>, UnauthorizedError>>>> {
+        let Some(key) = self.extract_key(req) else {
+            return Box::pin(ready(Err(UnauthorizedError::new("Missing API key"))));
+        };
+
+        let user = self.lookup.lookup(&key);
+
+        Box::pin(async move {
+            Ok(AuthToken::new(
+                user.await?,
+                AuthState::Authenticated,
+                SystemTime::now(),
+            ))
+        })
+    }
+
+    fn invalidate(&self, req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        match self.extract_key(&req) {
+            Some(key) => self.lookup.revoke(&key),
+            None => Box::pin(ready(())),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "api_key"
+    }
+}
@@ -0,0 +1,186 @@
+//! Audit logging of authentication events
+//!
+//! An [AuditLogger] is informed about logins, mfa and logouts, e.g. to write them to a compliance log.
+//! Login and mfa events are reported by [SessionLoginHandler::with_audit_logger](crate::session::handlers::SessionLoginHandler::with_audit_logger),
+//! logouts (every invalidated [AuthToken](crate::AuthToken)) by [AuthMiddleware::with_audit_logger](crate::middleware::AuthMiddleware::with_audit_logger).
+//! To log all events with the same logger, wrap it in an [Arc] and pass a clone to both.
+use std::{
+    future::{ready, Future},
+    pin::Pin,
+    sync::Arc,
+};
+
+use actix_web::HttpRequest;
+use log::info;
+
+/// Receives authentication events, the default implementations do nothing
+///
+/// Never log passwords or codes, the events only carry the username and the request.
+pub trait AuditLogger: Send + Sync {
+    /// The credentials were correct (mfa may still be pending)
+    fn on_login_success(
+        &self,
+        _username: &str,
+        _req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+    /// The credentials were wrong or the client is rate limited
+    fn on_login_failure(
+        &self,
+        _username: &str,
+        _req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+    fn on_logout(&self, _req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+    fn on_mfa_success(&self, _req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+    fn on_mfa_failure(&self, _req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+}
+
+impl<A: AuditLogger> AuditLogger for Arc<A> {
+    fn on_login_success(
+        &self,
+        username: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.as_ref().on_login_success(username, req)
+    }
+
+    fn on_login_failure(
+        &self,
+        username: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.as_ref().on_login_failure(username, req)
+    }
+
+    fn on_logout(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.as_ref().on_logout(req)
+    }
+
+    fn on_mfa_success(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.as_ref().on_mfa_success(req)
+    }
+
+    fn on_mfa_failure(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.as_ref().on_mfa_failure(req)
+    }
+}
+
+/// Logs every event as one line with the target `authfix::audit`, e.g. `event=login_success username=anna ip=10.0.0.1`
+///
+/// The events are logged with [log::info], so they can be routed to their own file by the logger of the app.
+#[derive(Clone, Copy, Default)]
+pub struct LogAuditLogger {
+    trust_proxy_headers: bool,
+}
+
+impl LogAuditLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Logs the address from `X-Forwarded-For` (or `Forwarded`) instead of the peer address (default: `false`)
+    ///
+    /// Should be the same as [SessionLoginHandler::trust_proxy_headers](crate::session::handlers::SessionLoginHandler::trust_proxy_headers).
+    pub fn trust_proxy_headers(mut self, trust_proxy_headers: bool) -> Self {
+        self.trust_proxy_headers = trust_proxy_headers;
+        self
+    }
+
+    fn client_ip(&self, req: &HttpRequest) -> String {
+        if self.trust_proxy_headers {
+            return req
+                .connection_info()
+                .realip_remote_addr()
+                .unwrap_or("unknown")
+                .to_owned();
+        }
+        req.peer_addr()
+            .map_or_else(|| "unknown".to_owned(), |addr| addr.ip().to_string())
+    }
+
+    fn log(&self, event: &str, username: Option<&str>, req: &HttpRequest) {
+        let ip = self.client_ip(req);
+        match username {
+            Some(username) => {
+                info!(target: "authfix::audit", "event={event} username={username} ip={ip}")
+            }
+            None => info!(target: "authfix::audit", "event={event} ip={ip}"),
+        }
+    }
+}
+
+impl AuditLogger for LogAuditLogger {
+    fn on_login_success(
+        &self,
+        username: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.log("login_success", Some(username), req);
+        Box::pin(ready(()))
+    }
+
+    fn on_login_failure(
+        &self,
+        username: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.log("login_failure", Some(username), req);
+        Box::pin(ready(()))
+    }
+
+    fn on_logout(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.log("logout", None, req);
+        Box::pin(ready(()))
+    }
+
+    fn on_mfa_success(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.log("mfa_success", None, req);
+        Box::pin(ready(()))
+    }
+
+    fn on_mfa_failure(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.log("mfa_failure", None, req);
+        Box::pin(ready(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::LogAuditLogger;
+
+    fn proxied_request() -> TestRequest {
+        TestRequest::default()
+            .peer_addr("10.0.0.1:4711".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+    }
+
+    #[test]
+    fn peer_address_should_be_logged_by_default() {
+        let req = proxied_request().to_http_request();
+
+        assert_eq!(LogAuditLogger::new().client_ip(&req), "10.0.0.1");
+    }
+
+    #[test]
+    fn forwarded_address_should_be_logged_if_proxy_headers_are_trusted() {
+        let req = proxied_request().to_http_request();
+
+        assert_eq!(
+            LogAuditLogger::new()
+                .trust_proxy_headers(true)
+                .client_ip(&req),
+            "203.0.113.7"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! HTTP Basic authentication ([RFC 7617](https://www.rfc-editor.org/rfc/rfc7617))
+use std::{
+    future::{ready, Future},
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use actix_web::{http::header::AUTHORIZATION, HttpRequest};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::de::DeserializeOwned;
+
+use crate::{errors::UnauthorizedError, AuthState, AuthToken, AuthenticationProvider};
+
+pub const DEFAULT_REALM: &str = "Restricted";
+
+/// Checks the credentials of the `Authorization: Basic` header
+pub trait CredentialVerifier<U> {
+    /// Returns the user if the credentials are correct, an [UnauthorizedError] otherwise
+    fn verify(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<U, UnauthorizedError>>>>;
+}
+
+/// Provider for HTTP Basic authentication (e.g. for internal tools or REST clients)
+///
+/// The credentials are verified on every request. If they are missing or wrong,
+/// the response contains `WWW-Authenticate: Basic realm="<realm>"`, so that browsers ask for them.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new(
+///     BasicAuthProvider::new(DatabaseVerifier::new(pool)).realm("admin"),
+///     PathMatcher::default(),
+/// )
+/// ```
+pub struct BasicAuthProvider<U, V>
+where
+    V: CredentialVerifier<U>,
+{
+    verifier: Arc<V>,
+    realm: String,
+    user_type: PhantomData<U>,
+}
+
+impl<U, V> BasicAuthProvider<U, V>
+where
+    V: CredentialVerifier<U>,
+{
+    pub fn new(verifier: V) -> Self {
+        Self {
+            verifier: Arc::new(verifier),
+            realm: DEFAULT_REALM.to_owned(),
+            user_type: PhantomData,
+        }
+    }
+
+    /// The realm of the `WWW-Authenticate` challenge (default: [DEFAULT_REALM])
+    pub fn realm(mut self, realm: &str) -> Self {
+        self.realm = realm.to_owned();
+        self
+    }
+
+    fn unauthorized(&self, message: &str) -> UnauthorizedError {
+        UnauthorizedError::new(message).with_www_authenticate(&format!(
+            "Basic realm=\"{}\", charset=\"UTF-8\"",
+            self.realm.replace('"', "'")
+        ))
+    }
+}
+
+impl<U, V> Clone for BasicAuthProvider<U, V>
+where
+    V: CredentialVerifier<U>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            verifier: Arc::clone(&self.verifier),
+            realm: self.realm.clone(),
+            user_type: PhantomData,
+        }
+    }
+}
+
+impl<U, V> AuthenticationProvider<U> for BasicAuthProvider<U, V>
+where
+    U: DeserializeOwned + Clone + 'static,
+    V: CredentialVerifier<U> + 'static,
+{
+    fn get_auth_token(
+        &self,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<AuthToken<U>, UnauthorizedError>>>> {
+        let Some(header) = req.headers().get(AUTHORIZATION) else {
+            return Box::pin(ready(Err(self.unauthorized("Missing credentials"))));
+        };
+
+        let Some((username, password)) = header.to_str().ok().and_then(decode_credentials) else {
+            return Box::pin(ready(Err(self.unauthorized("Malformed credentials"))));
+        };
+
+        let user = self.verifier.verify(&username, &password);
+        let unauthorized = self.unauthorized("Wrong credentials");
+
+        Box::pin(async move {
+            match user.await {
+                Ok(user) => Ok(AuthToken::new(
+                    user,
+                    AuthState::Authenticated,
+                    SystemTime::now(),
+                )),
+                Err(_) => Err(unauthorized),
+            }
+        })
+    }
+
+    /// Stateless, the client sends the credentials with every request
+    fn invalidate(&self, _req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+
+    fn name(&self) -> &'static str {
+        "basic"
+    }
+}
+
+/// Decodes `Basic <base64(username:password)>`, the password may contain colons
+fn decode_credentials(header: &str) -> Option<(String, String)> {
+    let (scheme, encoded) = header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Basic") {
+        return None;
+    }
+
+    let decoded = String::from_utf8(BASE64_STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+
+    Some((username.to_owned(), password.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_credentials;
+
+    #[test]
+    fn password_may_contain_colons() {
+        // anna:pass:word
+        let credentials = decode_credentials("Basic YW5uYTpwYXNzOndvcmQ=");
+
+        assert_eq!(
+            credentials,
+            Some(("anna".to_owned(), "pass:word".to_owned()))
+        );
+    }
+
+    #[test]
+    fn other_schemes_should_be_rejected() {
+        assert_eq!(decode_credentials("Bearer YW5uYTpwYXNzOndvcmQ="), None);
+    }
+}
@@ -0,0 +1,391 @@
+//! Combines several [AuthenticationProvider]s into one
+use std::{collections::HashMap, future::Future, pin::Pin, rc::Rc};
+
+use actix_web::{HttpMessage, HttpRequest};
+use serde::de::DeserializeOwned;
+
+use crate::{errors::InitError, AuthToken, AuthenticationProvider, UnauthorizedError};
+
+/// How the results of the providers of a [CompositeAuthProvider] are combined
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    /// The providers are tried in order, the first `Ok` is returned (e.g. session or API key)
+    #[default]
+    FirstSuccessful,
+    /// Every provider must return `Ok`, the token of the last one is returned (e.g. JWT validation and an additional ACL check)
+    AllMustSucceed,
+}
+
+/// Provider that delegates to several providers, combined by the [Mode]
+///
+/// Uses [Rc] internally, so create it inside the factory closure of the `HttpServer`.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new(
+///     CompositeAuthProvider::builder()
+///         .mode(Mode::AllMustSucceed)
+///         .with_provider(JwtAuthProvider::with_secret(secret))
+///         .with_provider(AclProvider::new(acl))
+///         .build(),
+///     PathMatcher::default(),
+/// )
+/// ```
+pub struct CompositeAuthProvider<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    providers: Rc<Vec<Box<dyn AuthenticationProvider<U>>>>,
+    mode: Mode,
+}
+
+impl<U> CompositeAuthProvider<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    pub fn builder() -> CompositeAuthProviderBuilder<U> {
+        CompositeAuthProviderBuilder {
+            providers: Vec::new(),
+            mode: Mode::default(),
+        }
+    }
+
+    /// See [Mode::FirstSuccessful]
+    pub fn first_successful(providers: Vec<Box<dyn AuthenticationProvider<U>>>) -> Self {
+        Self {
+            providers: Rc::new(providers),
+            mode: Mode::FirstSuccessful,
+        }
+    }
+
+    /// See [Mode::AllMustSucceed]
+    pub fn all_must_succeed(providers: Vec<Box<dyn AuthenticationProvider<U>>>) -> Self {
+        Self {
+            providers: Rc::new(providers),
+            mode: Mode::AllMustSucceed,
+        }
+    }
+}
+
+impl<U> Clone for CompositeAuthProvider<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            providers: Rc::clone(&self.providers),
+            mode: self.mode,
+        }
+    }
+}
+
+/// Index of the provider that has authenticated the request in [Mode::FirstSuccessful], per composite (nested composites are possible)
+#[derive(Default)]
+struct AuthenticatedBy(HashMap<*const (), usize>);
+
+impl<U> CompositeAuthProvider<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    fn key(providers: &Rc<Vec<Box<dyn AuthenticationProvider<U>>>>) -> *const () {
+        Rc::as_ptr(providers) as *const ()
+    }
+}
+
+/// Builder for [CompositeAuthProvider], created by [CompositeAuthProvider::builder]
+pub struct CompositeAuthProviderBuilder<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    providers: Vec<Box<dyn AuthenticationProvider<U>>>,
+    mode: Mode,
+}
+
+impl<U> CompositeAuthProviderBuilder<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    /// Default: [Mode::FirstSuccessful]
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The providers are called in the order they have been added
+    pub fn with_provider(mut self, provider: impl AuthenticationProvider<U> + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    pub fn build(self) -> CompositeAuthProvider<U> {
+        CompositeAuthProvider {
+            providers: Rc::new(self.providers),
+            mode: self.mode,
+        }
+    }
+}
+
+impl<U> AuthenticationProvider<U> for CompositeAuthProvider<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    fn get_auth_token(
+        &self,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<AuthToken<U>, UnauthorizedError>>>> {
+        let providers = Rc::clone(&self.providers);
+        let mode = self.mode;
+        let req = req.clone();
+
+        Box::pin(async move {
+            let mut result = Err(UnauthorizedError::new("No authentication provider"));
+
+            for (index, provider) in providers.iter().enumerate() {
+                result = provider.get_auth_token(&req).await;
+                if let Ok(token) = &result {
+                    token.set_provider_if_unset(provider.name());
+                    if mode == Mode::FirstSuccessful {
+                        let mut extensions = req.extensions_mut();
+                        if !extensions.contains::<AuthenticatedBy>() {
+                            extensions.insert(AuthenticatedBy::default());
+                        }
+                        if let Some(authenticated_by) = extensions.get_mut::<AuthenticatedBy>() {
+                            authenticated_by.0.insert(Self::key(&providers), index);
+                        }
+                    }
+                }
+
+                match (mode, &result) {
+                    (Mode::FirstSuccessful, Ok(_)) | (Mode::AllMustSucceed, Err(_)) => break,
+                    _ => (),
+                }
+            }
+
+            result
+        })
+    }
+
+    /// Invalidates the provider that has authenticated the request in [Mode::FirstSuccessful], otherwise every provider
+    fn invalidate(&self, req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        let providers = Rc::clone(&self.providers);
+        let authenticated_by = req
+            .extensions()
+            .get::<AuthenticatedBy>()
+            .and_then(|authenticated_by| authenticated_by.0.get(&Self::key(&providers)).copied());
+
+        Box::pin(async move {
+            match authenticated_by.and_then(|index| providers.get(index)) {
+                Some(provider) => provider.invalidate(req).await,
+                None => {
+                    for provider in providers.iter() {
+                        provider.invalidate(req.clone()).await;
+                    }
+                }
+            }
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "composite"
+    }
+
+    fn init(&self) -> Pin<Box<dyn Future<Output = Result<(), InitError>>>> {
+        let providers = Rc::clone(&self.providers);
+
+        Box::pin(async move {
+            for provider in providers.iter() {
+                provider.init().await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn persist_token(
+        &self,
+        token: &AuthToken<U>,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        let providers = Rc::clone(&self.providers);
+        let token = AuthToken::from_ref(token);
+        let req = req.clone();
+
+        Box::pin(async move {
+            for provider in providers.iter() {
+                provider.persist_token(&token, &req).await;
+            }
+        })
+    }
+
+    fn configure_request(&self, req: &HttpRequest) {
+        for provider in self.providers.iter() {
+            provider.configure_request(req);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        future::{ready, Future},
+        pin::Pin,
+        rc::Rc,
+        time::SystemTime,
+    };
+
+    use actix_web::{test::TestRequest, HttpRequest};
+
+    use super::{CompositeAuthProvider, Mode};
+    use crate::{AuthState, AuthToken, AuthenticationProvider, UnauthorizedError};
+
+    struct FixedProvider(Option<&'static str>);
+
+    /// Succeeds and counts the calls of [AuthenticationProvider::invalidate]
+    struct CountingProvider(Rc<Cell<u32>>);
+
+    impl AuthenticationProvider<String> for CountingProvider {
+        fn get_auth_token(
+            &self,
+            _req: &HttpRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<AuthToken<String>, UnauthorizedError>>>> {
+            Box::pin(ready(Ok(AuthToken::new(
+                "anna".to_owned(),
+                AuthState::Authenticated,
+                SystemTime::now(),
+            ))))
+        }
+
+        fn invalidate(&self, _req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+            self.0.set(self.0.get() + 1);
+            Box::pin(async {})
+        }
+    }
+
+    impl AuthenticationProvider<String> for FixedProvider {
+        fn get_auth_token(
+            &self,
+            _req: &HttpRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<AuthToken<String>, UnauthorizedError>>>> {
+            let result = self
+                .0
+                .map(|user| {
+                    AuthToken::new(user.to_owned(), AuthState::Authenticated, SystemTime::now())
+                })
+                .ok_or_else(UnauthorizedError::default);
+            Box::pin(ready(result))
+        }
+
+        fn invalidate(&self, _req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+            Box::pin(async {})
+        }
+
+        fn name(&self) -> &'static str {
+            self.0.unwrap_or("nobody")
+        }
+    }
+
+    async fn authenticated_user(provider: CompositeAuthProvider<String>) -> Option<String> {
+        let req = TestRequest::default().to_http_request();
+        let token = provider.get_auth_token(&req).await.ok()?;
+        let user = token.get_authenticated_user().clone();
+        Some(user)
+    }
+
+    #[actix_rt::test]
+    async fn first_successful_should_return_the_first_ok() {
+        let provider = CompositeAuthProvider::builder()
+            .mode(Mode::FirstSuccessful)
+            .with_provider(FixedProvider(None))
+            .with_provider(FixedProvider(Some("anna")))
+            .with_provider(FixedProvider(Some("bob")))
+            .build();
+
+        assert_eq!(authenticated_user(provider).await.as_deref(), Some("anna"));
+    }
+
+    #[actix_rt::test]
+    async fn first_successful_should_fail_if_all_fail() {
+        let provider = CompositeAuthProvider::first_successful(vec![
+            Box::new(FixedProvider(None)),
+            Box::new(FixedProvider(None)),
+        ]);
+
+        assert!(authenticated_user(provider).await.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn all_must_succeed_should_return_the_last_user() {
+        let provider = CompositeAuthProvider::all_must_succeed(vec![
+            Box::new(FixedProvider(Some("anna"))),
+            Box::new(FixedProvider(Some("bob"))),
+        ]);
+
+        assert_eq!(authenticated_user(provider).await.as_deref(), Some("bob"));
+    }
+
+    #[actix_rt::test]
+    async fn all_must_succeed_should_fail_if_one_fails() {
+        let provider = CompositeAuthProvider::builder()
+            .mode(Mode::AllMustSucceed)
+            .with_provider(FixedProvider(Some("anna")))
+            .with_provider(FixedProvider(None))
+            .build();
+
+        assert!(authenticated_user(provider).await.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn token_should_name_the_provider_that_has_authenticated_the_user() {
+        let provider = CompositeAuthProvider::first_successful(vec![
+            Box::new(FixedProvider(None)),
+            Box::new(FixedProvider(Some("bob"))),
+        ]);
+        let req = TestRequest::default().to_http_request();
+
+        let token = provider.get_auth_token(&req).await.unwrap();
+
+        assert_eq!(token.provider(), "bob");
+    }
+
+    #[actix_rt::test]
+    async fn without_providers_nobody_should_be_authenticated() {
+        let provider = CompositeAuthProvider::<String>::builder().build();
+
+        assert!(authenticated_user(provider).await.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn invalidate_should_be_forwarded_to_the_provider_that_has_authenticated_the_user() {
+        let first = Rc::new(Cell::new(0));
+        let second = Rc::new(Cell::new(0));
+        let provider = CompositeAuthProvider::first_successful(vec![
+            Box::new(FixedProvider(None)),
+            Box::new(CountingProvider(Rc::clone(&first))),
+            Box::new(CountingProvider(Rc::clone(&second))),
+        ]);
+        let req = TestRequest::default().to_http_request();
+
+        provider.get_auth_token(&req).await.unwrap();
+        provider.invalidate(req).await;
+
+        assert_eq!(first.get(), 1);
+        assert_eq!(second.get(), 0);
+    }
+
+    #[actix_rt::test]
+    async fn invalidate_without_authentication_should_invalidate_every_provider() {
+        let first = Rc::new(Cell::new(0));
+        let second = Rc::new(Cell::new(0));
+        let provider = CompositeAuthProvider::first_successful(vec![
+            Box::new(CountingProvider(Rc::clone(&first))),
+            Box::new(CountingProvider(Rc::clone(&second))),
+        ]);
+
+        provider
+            .invalidate(TestRequest::default().to_http_request())
+            .await;
+
+        assert_eq!(first.get(), 1);
+        assert_eq!(second.get(), 1);
+    }
+}
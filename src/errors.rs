@@ -1,16 +1,63 @@
-use std::fmt;
+use std::{fmt, rc::Rc};
 
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{
+    http::{
+        header::{LOCATION, WWW_AUTHENTICATE},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use serde::Serialize;
+
+/// Header of the `401` response of the middleware with the path and query the client has requested
+///
+/// The frontend can redirect to it after the login, e.g. `/login?next=/dashboard`.
+pub const REQUESTED_PATH_HEADER: &str = "X-Requested-Path";
 
 #[derive(Debug)]
 pub struct UnauthorizedError {
     message: String,
+    www_authenticate: Option<String>,
+    requested_path: Option<String>,
+    response: Option<Rc<UnauthorizedResponseBuilder>>,
 }
 
 impl UnauthorizedError {
     pub fn new(message: &str) -> Self {
         Self {
             message: message.to_owned(),
+            www_authenticate: None,
+            requested_path: None,
+            response: None,
+        }
+    }
+
+    /// Sends the challenge in the `WWW-Authenticate` header (e.g. `Basic realm="admin"`)
+    pub fn with_www_authenticate(mut self, challenge: &str) -> Self {
+        self.www_authenticate = Some(challenge.to_owned());
+        self
+    }
+
+    /// Sends `path` in the [REQUESTED_PATH_HEADER]
+    pub(crate) fn with_requested_path(mut self, path: &str) -> Self {
+        self.requested_path = Some(path.to_owned());
+        self
+    }
+
+    /// Responds as configured by the builder instead of the default JSON message
+    pub(crate) fn with_response(
+        mut self,
+        response: Option<Rc<UnauthorizedResponseBuilder>>,
+    ) -> Self {
+        self.response = response;
+        self
+    }
+
+    /// The middleware does not reveal why the authentication failed, but the client still needs the challenge
+    pub(crate) fn hide_message(self) -> Self {
+        Self {
+            www_authenticate: self.www_authenticate,
+            ..Self::default()
         }
     }
 }
@@ -19,6 +66,9 @@ impl Default for UnauthorizedError {
     fn default() -> Self {
         Self {
             message: "Not authorized".to_owned(),
+            www_authenticate: None,
+            requested_path: None,
+            response: None,
         }
     }
 }
@@ -29,12 +79,271 @@ impl fmt::Display for UnauthorizedError {
     }
 }
 
+/// Maps the error to a message for the client, the details are only logged
+#[cfg(feature = "jwt")]
+impl From<jsonwebtoken::errors::Error> for UnauthorizedError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        use jsonwebtoken::errors::ErrorKind;
+
+        log::debug!("JWT rejected: {e}");
+        let message = match e.kind() {
+            ErrorKind::ExpiredSignature => "Token expired",
+            ErrorKind::ImmatureSignature => "Token not yet valid",
+            ErrorKind::InvalidSignature => "Invalid token signature",
+            ErrorKind::InvalidAlgorithm | ErrorKind::InvalidAlgorithmName => {
+                "Invalid token algorithm"
+            }
+            ErrorKind::InvalidIssuer
+            | ErrorKind::InvalidAudience
+            | ErrorKind::InvalidSubject
+            | ErrorKind::MissingRequiredClaim(_) => "Invalid token claims",
+            ErrorKind::InvalidToken
+            | ErrorKind::Base64(_)
+            | ErrorKind::Json(_)
+            | ErrorKind::Utf8(_) => "Malformed token",
+            _ => "Invalid token",
+        };
+        Self::new(message)
+    }
+}
+
 impl ResponseError for UnauthorizedError {
+    fn status_code(&self) -> StatusCode {
+        match self.response.as_ref().map(|response| &response.body) {
+            Some(UnauthorizedBody::Redirect(_)) => StatusCode::FOUND,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        let mut res = HttpResponse::build(self.status_code());
+        if let Some(challenge) = &self.www_authenticate {
+            res.insert_header((WWW_AUTHENTICATE, challenge.as_str()));
+        }
+        if let Some(requested_path) = &self.requested_path {
+            res.insert_header((REQUESTED_PATH_HEADER, requested_path.as_str()));
+        }
+
+        let Some(response) = &self.response else {
+            return res.json(self.message.clone());
+        };
+        for (name, value) in &response.headers {
+            res.insert_header((name.as_str(), value.as_str()));
+        }
+        match &response.body {
+            UnauthorizedBody::Message => res.json(self.message.clone()),
+            UnauthorizedBody::Json(value) => res.json(value),
+            UnauthorizedBody::Empty => res.finish(),
+            UnauthorizedBody::Redirect(url) => res.insert_header((LOCATION, url.as_str())).finish(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum UnauthorizedBody {
+    Message,
+    Json(serde_json::Value),
+    Empty,
+    Redirect(String),
+}
+
+/// Configures the response of the middleware if the user is not authenticated
+///
+/// By default the middleware responds with `401` and the message as JSON string. Browser flows can redirect to the login page instead:
+/// ```no_run
+/// use authfix::errors::UnauthorizedResponseBuilder;
+///
+/// let response = UnauthorizedResponseBuilder::default().redirect_to("/login.html");
+/// ```
+/// The `WWW-Authenticate` and [REQUESTED_PATH_HEADER] headers are sent in any case.
+#[derive(Clone, Debug)]
+pub struct UnauthorizedResponseBuilder {
+    body: UnauthorizedBody,
+    headers: Vec<(String, String)>,
+}
+
+impl UnauthorizedResponseBuilder {
+    /// Responds with `302 Found` and `url` in the `Location` header
+    pub fn redirect_to(mut self, url: &str) -> Self {
+        self.body = UnauthorizedBody::Redirect(url.to_owned());
+        self
+    }
+
+    /// Responds with `value` as JSON instead of the message
+    ///
+    /// Falls back to the message if `value` cannot be serialized.
+    pub fn json_body(mut self, value: impl Serialize) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => self.body = UnauthorizedBody::Json(value),
+            Err(e) => log::error!("Cannot serialize the body of the unauthorized response: {e}"),
+        }
+        self
+    }
+
+    /// Responds without body
+    pub fn empty_body(mut self) -> Self {
+        self.body = UnauthorizedBody::Empty;
+        self
+    }
+
+    /// Adds a header to the response, can be called several times
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+impl Default for UnauthorizedResponseBuilder {
+    fn default() -> Self {
+        Self {
+            body: UnauthorizedBody::Message,
+            headers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ForbiddenError {
+    message: String,
+}
+
+impl ForbiddenError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl Default for ForbiddenError {
+    fn default() -> Self {
+        Self {
+            message: "Forbidden".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ForbiddenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ResponseError for ForbiddenError {
     fn status_code(&self) -> actix_web::http::StatusCode {
-        actix_web::http::StatusCode::UNAUTHORIZED
+        actix_web::http::StatusCode::FORBIDDEN
     }
 
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-        HttpResponse::Unauthorized().json(self.message.clone())
+        HttpResponse::Forbidden().json(self.message.clone())
+    }
+}
+
+/// Returned by [AuthMiddleware::init](crate::middleware::AuthMiddleware::init) if the authentication cannot be set up
+#[derive(Debug)]
+pub struct InitError {
+    message: String,
+}
+
+impl InitError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cannot initialize authentication: {}", self.message)
+    }
+}
+
+impl std::error::Error for InitError {}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::to_bytes, http::StatusCode, ResponseError};
+    #[cfg(feature = "jwt")]
+    use jsonwebtoken::errors::{Error, ErrorKind};
+
+    use std::rc::Rc;
+
+    use super::{UnauthorizedError, UnauthorizedResponseBuilder};
+
+    #[cfg(feature = "jwt")]
+    #[test]
+    fn expired_jwt_should_be_mapped_to_token_expired() {
+        let error: UnauthorizedError = Error::from(ErrorKind::ExpiredSignature).into();
+
+        assert_eq!(error.message, "Token expired");
+    }
+
+    #[cfg(feature = "jwt")]
+    #[test]
+    fn jwt_claim_errors_should_not_leak_details() {
+        let error: UnauthorizedError =
+            Error::from(ErrorKind::MissingRequiredClaim("secret_claim".to_owned())).into();
+
+        assert_eq!(error.message, "Invalid token claims");
+    }
+
+    async fn body_of(error: &UnauthorizedError) -> String {
+        let body = to_bytes(error.error_response().into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn default_response_should_be_the_message_as_json() {
+        let error = UnauthorizedError::default().with_response(None);
+
+        assert_eq!(error.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body_of(&error).await, "\"Not authorized\"");
+    }
+
+    #[actix_rt::test]
+    async fn redirect_should_respond_with_found_and_location() {
+        let builder = UnauthorizedResponseBuilder::default().redirect_to("/login.html");
+        let error = UnauthorizedError::default().with_response(Some(Rc::new(builder)));
+
+        let res = error.error_response();
+
+        assert_eq!(res.status(), StatusCode::FOUND);
+        assert_eq!(res.headers().get("Location").unwrap(), "/login.html");
+    }
+
+    #[actix_rt::test]
+    async fn json_body_should_replace_the_message() {
+        let builder = UnauthorizedResponseBuilder::default()
+            .json_body(serde_json::json!({ "error": "unauthenticated" }));
+        let error = UnauthorizedError::default().with_response(Some(Rc::new(builder)));
+
+        assert_eq!(error.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body_of(&error).await, "{\"error\":\"unauthenticated\"}");
+    }
+
+    #[actix_rt::test]
+    async fn empty_body_should_have_no_content() {
+        let builder = UnauthorizedResponseBuilder::default().empty_body();
+        let error = UnauthorizedError::default().with_response(Some(Rc::new(builder)));
+
+        assert_eq!(error.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(body_of(&error).await, "");
+    }
+
+    #[actix_rt::test]
+    async fn headers_should_be_added_to_the_response() {
+        let builder = UnauthorizedResponseBuilder::default()
+            .with_header("X-Login-Url", "/login.html")
+            .with_header("Cache-Control", "no-store");
+        let error = UnauthorizedError::default()
+            .with_www_authenticate("Basic realm=\"admin\"")
+            .with_response(Some(Rc::new(builder)));
+
+        let res = error.error_response();
+
+        assert_eq!(res.headers().get("X-Login-Url").unwrap(), "/login.html");
+        assert_eq!(res.headers().get("Cache-Control").unwrap(), "no-store");
+        assert!(res.headers().contains_key("WWW-Authenticate"));
     }
 }
@@ -0,0 +1,57 @@
+//! Blocking of requests by the location of the client
+//!
+//! A [GeoBlocker] is registered with [AuthMiddleware::with_geo_blocker](crate::middleware::AuthMiddleware::with_geo_blocker).
+//! The middleware checks every request before the authentication, including the login routes and unsecured paths.
+//! Blocked requests get a `403 Forbidden`.
+//!
+//! The IP is the peer address of the connection. Behind a reverse proxy this is the address of the proxy,
+//! so the blocking has to happen there.
+//!
+//! # Examples
+//! A blocker with a country database of [MaxMind](https://www.maxmind.com) (needs the `maxminddb` crate):
+//! ```ignore
+//! use std::{collections::HashSet, net::IpAddr};
+//!
+//! use authfix::geo::GeoBlocker;
+//! use maxminddb::{geoip2, Reader};
+//!
+//! pub struct MaxMindGeoBlocker {
+//!     reader: Reader<Vec<u8>>,
+//!     blocked_countries: HashSet<String>,
+//! }
+//!
+//! impl MaxMindGeoBlocker {
+//!     pub fn new(database_path: &str, blocked_countries: &[&str]) -> Result<Self, maxminddb::MaxMindDbError> {
+//!         Ok(Self {
+//!             reader: Reader::open_readfile(database_path)?,
+//!             blocked_countries: blocked_countries.iter().map(|code| code.to_string()).collect(),
+//!         })
+//!     }
+//! }
+//!
+//! impl GeoBlocker for MaxMindGeoBlocker {
+//!     fn is_blocked(&self, ip: IpAddr) -> bool {
+//!         let country = self
+//!             .reader
+//!             .lookup::<geoip2::Country>(ip)
+//!             .ok()
+//!             .flatten()
+//!             .and_then(|country| country.country)
+//!             .and_then(|country| country.iso_code);
+//!
+//!         country.is_some_and(|code| self.blocked_countries.contains(code))
+//!     }
+//! }
+//!
+//! AuthMiddleware::<_, User>::new(provider, PathMatcher::default())
+//!     .with_geo_blocker(MaxMindGeoBlocker::new("GeoLite2-Country.mmdb", &["XX", "YY"])?)
+//! ```
+use std::net::IpAddr;
+
+/// Decides whether requests from an IP are rejected
+pub trait GeoBlocker {
+    /// Returns `true` if the request has to be rejected
+    ///
+    /// Is called for every request, so lookups should be fast (e.g. a local database instead of a web service).
+    fn is_blocked(&self, ip: IpAddr) -> bool;
+}
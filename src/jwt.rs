@@ -0,0 +1,329 @@
+//! Support for JSON Web Tokens (feature `jwt`)
+use std::{
+    future::{ready, Future},
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    dev::{AppService, HttpServiceFactory},
+    guard::Post,
+    http::header::AUTHORIZATION,
+    web::{Data, Json, ServiceConfig},
+    HttpRequest, HttpResponse, Resource,
+};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{errors::UnauthorizedError, AuthState, AuthToken, AuthenticationProvider};
+
+/// Revocation of tokens before they expire (e.g. on logout)
+///
+/// JWTs are stateless, so a revoked token has to be remembered until it expires (e.g. in Redis).
+pub trait TokenBlocklist {
+    fn is_revoked(&self, token: &str) -> Pin<Box<dyn Future<Output = bool>>>;
+    /// Is called by [JwtAuthProvider::invalidate](AuthenticationProvider::invalidate)
+    fn revoke(&self, token: &str) -> Pin<Box<dyn Future<Output = ()>>>;
+}
+
+/// Provider for stateless authentication with a JWT in the `Authorization: Bearer <token>` header
+///
+/// The payload of the token is deserialized as the user. The `iat` claim is used as [AuthToken::authenticated_at].
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new(
+///     JwtAuthProvider::with_rsa_pem(public_key)?
+///         .audience(&["my-api"])
+///         .issuer(&["https://auth.example.org"])
+///         .leeway(Duration::from_secs(30)),
+///     PathMatcher::default(),
+/// )
+/// ```
+pub struct JwtAuthProvider<U> {
+    decoding_key: Arc<DecodingKey>,
+    validation: Validation,
+    blocklist: Option<Arc<dyn TokenBlocklist>>,
+    user_type: PhantomData<U>,
+}
+
+impl<U> JwtAuthProvider<U> {
+    /// Verifies tokens signed with `HS256` and the given secret
+    pub fn with_secret(secret: &[u8]) -> Self {
+        Self::with_key(DecodingKey::from_secret(secret), Algorithm::HS256)
+    }
+
+    /// Verifies tokens signed with `RS256` and the given PEM encoded public key
+    pub fn with_rsa_pem(public_key: &[u8]) -> Result<Self, jsonwebtoken::errors::Error> {
+        Ok(Self::with_key(
+            DecodingKey::from_rsa_pem(public_key)?,
+            Algorithm::RS256,
+        ))
+    }
+
+    /// Verifies tokens signed with `ES256` and the given PEM encoded public key
+    pub fn with_ec_pem(public_key: &[u8]) -> Result<Self, jsonwebtoken::errors::Error> {
+        Ok(Self::with_key(
+            DecodingKey::from_ec_pem(public_key)?,
+            Algorithm::ES256,
+        ))
+    }
+
+    fn with_key(decoding_key: DecodingKey, algorithm: Algorithm) -> Self {
+        Self {
+            decoding_key: Arc::new(decoding_key),
+            validation: Validation::new(algorithm),
+            blocklist: None,
+            user_type: PhantomData,
+        }
+    }
+
+    /// Overrides the algorithm chosen by the constructor, it has to fit the key
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.validation.algorithms = vec![algorithm];
+        self
+    }
+
+    /// Only accepts tokens whose `aud` claim contains one of `audience`
+    pub fn audience(mut self, audience: &[&str]) -> Self {
+        self.validation.set_audience(audience);
+        self
+    }
+
+    /// Only accepts tokens whose `iss` claim is one of `issuer`
+    pub fn issuer(mut self, issuer: &[&str]) -> Self {
+        self.validation.set_issuer(issuer);
+        self
+    }
+
+    /// Tolerated clock skew for `exp` and `nbf` (default: 60 seconds)
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.validation.leeway = leeway.as_secs();
+        self
+    }
+
+    pub fn with_blocklist(mut self, blocklist: impl TokenBlocklist + 'static) -> Self {
+        self.blocklist = Some(Arc::new(blocklist));
+        self
+    }
+}
+
+impl<U> Clone for JwtAuthProvider<U> {
+    fn clone(&self) -> Self {
+        Self {
+            decoding_key: Arc::clone(&self.decoding_key),
+            validation: self.validation.clone(),
+            blocklist: self.blocklist.clone(),
+            user_type: PhantomData,
+        }
+    }
+}
+
+impl<U> JwtAuthProvider<U>
+where
+    U: DeserializeOwned,
+{
+    fn decode_user(&self, token: &str) -> Result<(U, SystemTime), UnauthorizedError> {
+        let claims =
+            decode::<serde_json::Value>(token, &self.decoding_key, &self.validation)?.claims;
+
+        // Tokens without `iat` are treated as if they were very old
+        let authenticated_at = claims
+            .get("iat")
+            .and_then(|iat| iat.as_u64())
+            .map(|iat| UNIX_EPOCH + Duration::from_secs(iat))
+            .unwrap_or(UNIX_EPOCH);
+
+        let user = serde_json::from_value(claims).map_err(|e| {
+            debug!("Cannot deserialize user from token: {e}");
+            UnauthorizedError::new("Invalid token claims")
+        })?;
+
+        Ok((user, authenticated_at))
+    }
+}
+
+impl<U> AuthenticationProvider<U> for JwtAuthProvider<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    fn get_auth_token(
+        &self,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<AuthToken<U>, UnauthorizedError>>>> {
+        let Some(token) = bearer_token(req) else {
+            return Box::pin(ready(Err(UnauthorizedError::new("Missing bearer token"))));
+        };
+
+        let decoded = self.decode_user(&token);
+        let blocklist = self.blocklist.clone();
+
+        Box::pin(async move {
+            let (user, authenticated_at) = decoded?;
+
+            if let Some(blocklist) = blocklist {
+                if blocklist.is_revoked(&token).await {
+                    return Err(UnauthorizedError::new("Token revoked"));
+                }
+            }
+
+            Ok(AuthToken::new(
+                user,
+                AuthState::Authenticated,
+                authenticated_at,
+            ))
+        })
+    }
+
+    /// Stateless, only revokes the token if there is a [TokenBlocklist]
+    fn invalidate(&self, req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        match (&self.blocklist, bearer_token(&req)) {
+            (Some(blocklist), Some(token)) => blocklist.revoke(&token),
+            _ => Box::pin(async {}),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "jwt"
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_owned())
+}
+
+pub const INTROSPECTION_ROUTE: &str = "/auth/introspect";
+
+/// Body of an introspection request
+#[derive(Deserialize)]
+pub struct IntrospectionRequest {
+    token: String,
+}
+
+/// Answer of the introspection endpoint as defined in [RFC 7662](https://www.rfc-editor.org/rfc/rfc7662#section-2.2)
+///
+/// Inactive tokens only contain `"active": false`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+impl IntrospectionResponse {
+    pub fn inactive() -> Self {
+        Self {
+            active: false,
+            sub: None,
+            exp: None,
+            scope: None,
+        }
+    }
+}
+
+/// The claims exposed by the introspection endpoint, all other claims are ignored
+#[derive(Deserialize)]
+struct IntrospectedClaims {
+    sub: Option<String>,
+    exp: Option<u64>,
+    scope: Option<String>,
+}
+
+/// Endpoint that lets resource servers check a token (`POST /auth/introspect`)
+///
+/// Only clients accepted by `is_trusted_client` may introspect tokens, all others get a `401 Unauthorized`.
+pub struct TokenIntrospection {
+    decoding_key: DecodingKey,
+    validation: Validation,
+    is_trusted_client: fn(&HttpRequest) -> bool,
+}
+
+impl TokenIntrospection {
+    pub fn new(
+        decoding_key: DecodingKey,
+        validation: Validation,
+        is_trusted_client: fn(&HttpRequest) -> bool,
+    ) -> Self {
+        Self {
+            decoding_key,
+            validation,
+            is_trusted_client,
+        }
+    }
+
+    fn introspect(&self, token: &str) -> IntrospectionResponse {
+        match decode::<IntrospectedClaims>(token, &self.decoding_key, &self.validation) {
+            Ok(data) => IntrospectionResponse {
+                active: true,
+                sub: data.claims.sub,
+                exp: data.claims.exp,
+                scope: data.claims.scope,
+            },
+            Err(e) => {
+                debug!("Introspected token is not active: {e}");
+                IntrospectionResponse::inactive()
+            }
+        }
+    }
+}
+
+async fn introspect(
+    introspection: Data<TokenIntrospection>,
+    body: Json<IntrospectionRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, UnauthorizedError> {
+    if !(introspection.is_trusted_client)(&req) {
+        warn!("Untrusted client tried to introspect a token");
+        return Err(UnauthorizedError::default());
+    }
+
+    Ok(HttpResponse::Ok().json(introspection.introspect(&body.token)))
+}
+
+impl HttpServiceFactory for TokenIntrospection {
+    fn register(self, __config: &mut AppService) {
+        let resource = Resource::new(INTROSPECTION_ROUTE)
+            .name("introspect")
+            .guard(Post())
+            .app_data(Data::new(self))
+            .to(introspect);
+        HttpServiceFactory::register(resource, __config);
+    }
+}
+
+/// Configuration function to setup the [TokenIntrospection] endpoint
+///
+/// # Examples
+///
+/// ```ignore
+/// App::new().configure(token_introspection_config(
+///     DecodingKey::from_secret(secret),
+///     Validation::default(),
+///     |req| is_known_resource_server(req),
+/// ))
+/// ```
+pub fn token_introspection_config(
+    decoding_key: DecodingKey,
+    validation: Validation,
+    is_trusted_client: fn(&HttpRequest) -> bool,
+) -> impl FnOnce(&mut ServiceConfig) {
+    move |config: &mut ServiceConfig| {
+        config.service(TokenIntrospection::new(
+            decoding_key,
+            validation,
+            is_trusted_client,
+        ));
+    }
+}
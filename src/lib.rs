@@ -24,7 +24,7 @@
 //! async fn main() -> std::io::Result<()> {
 //!     HttpServer::new(move || {
 //!         App::new()
-//!           .wrap(AuthMiddleware::<_, User>::new(SessionAuthProvider, PathMatcher::default()))
+//!           .wrap(AuthMiddleware::<_, User>::new(SessionAuthProvider::default(), PathMatcher::default()))
 //!             .wrap(create_actix_session_middleware())
 //!     })
 //!     .bind(("127.0.0.1", 8080))?
@@ -41,27 +41,56 @@
 //! ```
 
 use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
-use errors::UnauthorizedError;
+use errors::{ForbiddenError, InitError, UnauthorizedError, UnauthorizedResponseBuilder};
+use log::debug;
 use serde::de::DeserializeOwned;
 use std::{
     cell::{Ref, RefCell},
-    future::{ready, Future, Ready},
+    fmt,
+    future::{ready, Future},
+    ops::Deref,
     pin::Pin,
     rc::Rc,
+    time::{Duration, SystemTime},
 };
 
+pub mod api_key;
+pub mod audit;
+pub mod basic_auth;
+pub mod composite;
 pub mod errors;
+pub mod geo;
+#[cfg(feature = "jwt")]
+pub mod jwt;
 pub mod login;
 pub mod middleware;
 pub mod multifactor;
+pub mod password;
+#[cfg(feature = "pkce")]
+pub mod pkce;
+pub mod ratelimit;
 pub mod session;
+pub mod signature;
+pub mod sticky_session;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod web;
 
+#[cfg(all(feature = "testing", not(debug_assertions)))]
+compile_error!(
+    "The `testing` feature bypasses authentication and must not be used in release builds"
+);
+
+#[cfg(all(feature = "passthrough", not(debug_assertions)))]
+compile_error!(
+    "The `passthrough` feature bypasses authentication and must not be used in release builds"
+);
+
 /// This trait is used to retrieve the logged in user.
 /// If no user was found (e.g. in Actix-Session) it will return an Err.
 ///
 /// Currently it is only implemented for actix-session:
-/// [SessionAuthProvider](crate::session::session_auth::SessionAuthProvider)
+/// [SessionAuthProvider](struct@crate::session::session_auth::SessionAuthProvider)
 pub trait AuthenticationProvider<U>
 where
     U: DeserializeOwned + Clone + 'static,
@@ -71,6 +100,58 @@ where
         req: &HttpRequest,
     ) -> Pin<Box<dyn Future<Output = Result<AuthToken<U>, UnauthorizedError>>>>;
     fn invalidate(&self, req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>>;
+
+    /// Identifies how the user has been authenticated (e.g. `"session"` or `"jwt"`), see [AuthToken::provider]
+    fn name(&self) -> &'static str {
+        "unknown"
+    }
+
+    /// Loads the user again from the backing store (DB, cache), even if a user is cached e.g. in the session
+    ///
+    /// The default implementation delegates to [AuthenticationProvider::get_auth_token].
+    fn refresh_user(
+        &self,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<U, UnauthorizedError>>>> {
+        let token = self.get_auth_token(req);
+        Box::pin(async move {
+            let token = token.await?;
+            let user = token.try_get_authenticated_user().map(|user| user.clone());
+            user.ok_or_else(UnauthorizedError::default)
+        })
+    }
+
+    /// Sets up the provider at startup (e.g. loads keys or connects to a database)
+    ///
+    /// Is called by [AuthMiddleware::init](crate::middleware::AuthMiddleware::init). The default implementation does nothing.
+    fn init(&self) -> Pin<Box<dyn Future<Output = Result<(), InitError>>>> {
+        Box::pin(ready(Ok(())))
+    }
+
+    /// Writes changes of the token back to the store (e.g. after [AuthToken::touch] or [AuthToken::extend_session])
+    ///
+    /// Is called by the [AuthMiddleware](crate::middleware::AuthMiddleware) after the request, if the token has been changed.
+    /// The default implementation does nothing.
+    fn persist_token(
+        &self,
+        _token: &AuthToken<U>,
+        _req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+
+    /// Is called by the [AuthMiddleware](crate::middleware::AuthMiddleware) for every request, before the path is checked.
+    ///
+    /// Providers can use it to make their configuration available to the handlers (e.g. via the request extensions).
+    fn configure_request(&self, _req: &HttpRequest) {}
+}
+
+/// Additional cleanup when [AuthToken::invalidate] is called (e.g. logging the logout or clearing a database row)
+///
+/// Is registered with [AuthMiddleware::with_session_invalidator](crate::middleware::AuthMiddleware::with_session_invalidator),
+/// which injects it into every [AuthToken]. The authentication itself is still invalidated by the [AuthenticationProvider].
+pub trait SessionInvalidator {
+    fn invalidate(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>>;
 }
 
 /// Extractor that holds the authenticated user
@@ -99,8 +180,31 @@ impl<U> AuthToken<U>
 where
     U: DeserializeOwned + Clone,
 {
-    pub fn get_authenticated_user(&self) -> Ref<U> {
-        Ref::map(self.inner.borrow(), |inner| &inner.user)
+    /// # Panics
+    /// If the token is [AuthToken::anonymous]
+    pub fn get_authenticated_user(&self) -> Ref<'_, U> {
+        Ref::map(self.inner.borrow(), |inner| {
+            inner
+                .user
+                .as_ref()
+                .expect("AuthToken is anonymous and holds no user")
+        })
+    }
+
+    /// Like [AuthToken::get_authenticated_user], but returns `None` instead of panicking if the token is currently mutably borrowed
+    /// or if it is [AuthToken::anonymous]
+    pub fn try_get_authenticated_user(&self) -> Option<Ref<'_, U>> {
+        self.inner
+            .try_borrow()
+            .ok()
+            .and_then(|inner| Ref::filter_map(inner, |inner| inner.user.as_ref()).ok())
+    }
+
+    /// Creates a token without a user, **only meant for tests**
+    ///
+    /// [AuthToken::get_authenticated_user] panics for this token and [AuthToken::try_get_authenticated_user] returns `None`.
+    pub fn anonymous() -> Self {
+        Self::with_optional_user(None, AuthState::Invalid, SystemTime::UNIX_EPOCH)
     }
 
     pub(crate) fn needs_mfa(&self) -> bool {
@@ -118,25 +222,327 @@ where
         inner.auth_state == AuthState::Authenticated
     }
 
+    /// Logs the user out after the request
+    ///
+    /// An injected [SessionInvalidator] is spawned on the current Actix runtime when the token is invalidated for the first time.
     pub fn invalidate(&self) {
         let mut inner = self.inner.borrow_mut();
+        let is_first_invalidation = inner.invalidated_at.is_none();
         inner.auth_state = AuthState::Invalid;
+        inner.invalidated_at = Some(SystemTime::now());
+
+        if let (true, Some(invalidator), Some(req)) =
+            (is_first_invalidation, &inner.invalidator, &inner.request)
+        {
+            actix_web::rt::spawn(invalidator.invalidate(req));
+        }
+    }
+
+    /// Point in time when [AuthToken::invalidate] has been called (e.g. for audit logs of logouts)
+    pub fn invalidated_at(&self) -> Option<SystemTime> {
+        self.inner.borrow().invalidated_at
+    }
+
+    /// Resets the idle timer for a sliding session expiry
+    ///
+    /// The new time is written back by [AuthenticationProvider::persist_token] at the end of the request.
+    pub fn touch(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.last_active_at = Some(SystemTime::now());
+        inner.is_touched = true;
+    }
+
+    /// Point in time when [AuthToken::touch] has been called the last time, `None` if it has never been called
+    pub fn last_active_at(&self) -> Option<SystemTime> {
+        self.inner.borrow().last_active_at
+    }
+
+    /// Point in time when the session expires, `None` if the provider does not limit it
+    /// (see [SessionAuthProvider::with_session_timeout](crate::session::session_auth::SessionAuthProvider::with_session_timeout))
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.inner.borrow().expires_at
+    }
+
+    /// Extends the session by `by`, e.g. before a long running operation
+    ///
+    /// The new expiry is written back by [AuthenticationProvider::persist_token] at the end of the request.
+    /// Has no effect if the session has no expiry ([AuthToken::expires_at] is `None`).
+    pub fn extend_session(&self, by: Duration) {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(expires_at) = inner.expires_at {
+            inner.expires_at = Some(expires_at.max(SystemTime::now()) + by);
+            inner.is_touched = true;
+        }
+    }
+
+    pub(crate) fn is_touched(&self) -> bool {
+        self.inner.borrow().is_touched
+    }
+
+    pub(crate) fn with_last_active_at(self, last_active_at: Option<SystemTime>) -> Self {
+        self.inner.borrow_mut().last_active_at = last_active_at;
+        self
+    }
+
+    pub(crate) fn with_expires_at(self, expires_at: Option<SystemTime>) -> Self {
+        self.inner.borrow_mut().expires_at = expires_at;
+        self
+    }
+
+    /// The request in which the user has been authenticated
+    ///
+    /// Useful for hooks that run after the authentication (e.g. audit logging).
+    /// The request is attached when the token is extracted in a handler, so it returns `None` before.
+    pub fn request(&self) -> Option<Ref<'_, HttpRequest>> {
+        Ref::filter_map(self.inner.borrow(), |inner| inner.request.as_ref()).ok()
+    }
+
+    /// The [AuthenticationProvider::name] of the provider that has authenticated the user
+    ///
+    /// Lets handlers treat users differently depending on how they have been authenticated:
+    /// ```ignore
+    /// if token.provider() == "jwt" {
+    ///     return Err(ErrorForbidden("Please confirm with your password"));
+    /// }
+    /// ```
+    pub fn provider(&self) -> &'static str {
+        self.inner.borrow().provider.unwrap_or("unknown")
+    }
+
+    /// Composite providers set the name of the inner provider, so the middleware must not overwrite it
+    pub(crate) fn set_provider_if_unset(&self, name: &'static str) {
+        self.inner.borrow_mut().provider.get_or_insert(name);
+    }
+
+    /// Loads the user again with [AuthenticationProvider::refresh_user], e.g. after its roles have been changed in the database
+    ///
+    /// The next [AuthToken::get_authenticated_user] returns the reloaded user. The token itself (e.g. the session) is not changed.
+    /// ```ignore
+    /// #[post("/roles")]
+    /// pub async fn add_role(token: AuthToken<User>, req: HttpRequest) -> Result<impl Responder, Error> {
+    ///     user_repository.add_role(&token.get_authenticated_user().email, "editor").await?;
+    ///     token.refresh(&req).await?;
+    ///     // ...
+    /// }
+    /// ```
+    pub fn refresh(
+        &self,
+        req: &HttpRequest,
+    ) -> impl Future<Output = Result<(), UnauthorizedError>> {
+        let refresher = self.inner.borrow().refresher.clone();
+        let user = refresher.map(|refresher| refresher(req));
+        let token = AuthToken::from_ref(self);
+
+        async move {
+            let Some(user) = user else {
+                return Err(UnauthorizedError::new("No provider to refresh the user"));
+            };
+            let user = user.await?;
+            token.inner.borrow_mut().user = Some(user);
+            Ok(())
+        }
+    }
+
+    pub(crate) fn inject_refresher(&self, refresher: UserRefresher<U>) {
+        self.inner.borrow_mut().refresher = Some(refresher);
+    }
+
+    /// Point in time when the user has been authenticated (including mfa)
+    pub fn authenticated_at(&self) -> SystemTime {
+        self.inner.borrow().authenticated_at
+    }
+
+    /// Returns an [UnauthorizedError] if the authentication is older than `max_age`
+    ///
+    /// Can be used to require a recent login for sensitive operations:
+    /// ```ignore
+    /// #[post("/change-password")]
+    /// pub async fn change_password(token: AuthToken<User>) -> Result<impl Responder, Error> {
+    ///     token.assert_not_expired(Duration::from_secs(60 * 10))?;
+    ///     // ...
+    /// }
+    /// ```
+    pub fn assert_not_expired(&self, max_age: Duration) -> Result<(), UnauthorizedError> {
+        match self.authenticated_at().elapsed() {
+            Ok(age) if age > max_age => Err(UnauthorizedError::new("Session too old")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates an authenticated token with the user deserialized from `value`, meant for tests and mocks
+    pub fn from_value(value: serde_json::Value) -> Result<AuthToken<U>, serde_json::Error> {
+        let user = serde_json::from_value(value)?;
+        Ok(Self::new(user, AuthState::Authenticated, SystemTime::now()))
     }
 
-    pub(crate) fn new(user: U, auth_state: AuthState) -> Self {
+    pub(crate) fn new(user: U, auth_state: AuthState, authenticated_at: SystemTime) -> Self {
+        Self::with_optional_user(Some(user), auth_state, authenticated_at)
+    }
+
+    fn with_optional_user(
+        user: Option<U>,
+        auth_state: AuthState,
+        authenticated_at: SystemTime,
+    ) -> Self {
         Self {
-            inner: Rc::new(RefCell::new(AuthTokenInner { user, auth_state })),
+            inner: Rc::new(RefCell::new(AuthTokenInner {
+                user,
+                auth_state,
+                authenticated_at,
+                invalidated_at: None,
+                last_active_at: None,
+                expires_at: None,
+                is_touched: false,
+                request: None,
+                invalidator: None,
+                provider: None,
+                refresher: None,
+            })),
+        }
+    }
+
+    // Actix Web panics if a clone of the request exists while routing, so it can only be attached after the routing
+    fn attach_request(&self, req: &HttpRequest) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.request.is_none() {
+            inner.request = Some(req.clone());
         }
     }
 
+    // If the service fails, the middleware has no request anymore, but the token still has it
+    pub(crate) fn detach_request(&self) -> Option<HttpRequest> {
+        self.inner.borrow_mut().request.take()
+    }
+
+    pub(crate) fn inject_invalidator(&self, invalidator: Rc<dyn SessionInvalidator>) {
+        self.inner.borrow_mut().invalidator = Some(invalidator);
+    }
+
     pub(crate) fn from_ref(token: &AuthToken<U>) -> Self {
         AuthToken {
             inner: Rc::clone(&token.inner),
         }
     }
+
+    /// Creates a copy with its own state, unlike [Clone], which shares the state with the original
+    ///
+    /// The copy is detached from the request: [AuthToken::invalidate] and [AuthToken::touch] only change the copy
+    /// and are not seen by the middleware. Useful for snapshots that are moved into spawned tasks.
+    pub fn clone_independent(&self) -> AuthToken<U> {
+        let inner = self.inner.borrow();
+        AuthToken {
+            inner: Rc::new(RefCell::new(AuthTokenInner {
+                user: inner.user.clone(),
+                auth_state: inner.auth_state.clone(),
+                authenticated_at: inner.authenticated_at,
+                invalidated_at: inner.invalidated_at,
+                last_active_at: inner.last_active_at,
+                expires_at: inner.expires_at,
+                is_touched: inner.is_touched,
+                request: None,
+                invalidator: None,
+                provider: inner.provider,
+                refresher: inner.refresher.clone(),
+            })),
+        }
+    }
+}
+
+/// Prints `AuthToken { is_valid: true, user: <redacted> }`, so that the user does not end up in logs
+///
+/// With the feature `debug_user` the user is printed if it implements [Debug](fmt::Debug).
+#[cfg(not(feature = "debug_user"))]
+impl<U> fmt::Debug for AuthToken<U>
+where
+    U: DeserializeOwned + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthToken")
+            .field("is_valid", &self.is_valid())
+            .field("user", &format_args!("<redacted>"))
+            .finish()
+    }
+}
+
+#[cfg(feature = "debug_user")]
+impl<U> fmt::Debug for AuthToken<U>
+where
+    U: DeserializeOwned + Clone + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("AuthToken");
+        debug.field("is_valid", &self.is_valid());
+        match self.try_get_authenticated_user() {
+            Some(user) => debug.field("user", &*user),
+            None => debug.field("user", &format_args!("None")),
+        };
+        debug.finish()
+    }
+}
+
+/// Users with roles and permissions, needed for the role rules of [AuthMiddleware::new_with_roles](crate::middleware::AuthMiddleware::new_with_roles)
+///
+/// Every [Authorizable] user also has the role checks of [HasRoles].
+pub trait Authorizable {
+    fn roles(&self) -> Vec<String>;
+    fn permissions(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Users that have roles, needed for the role checks of [AuthToken]
+pub trait HasRoles {
+    fn has_role(&self, role: &str) -> bool;
+}
+
+impl<U: Authorizable> HasRoles for U {
+    fn has_role(&self, role: &str) -> bool {
+        self.roles().iter().any(|r| r == role)
+    }
+}
+
+impl<U> AuthToken<U>
+where
+    U: DeserializeOwned + Clone + HasRoles,
+{
+    pub fn has_role(&self, role: &str) -> bool {
+        self.get_authenticated_user().has_role(role)
+    }
+
+    /// Calls `f` with the user if the user has all `required` roles, otherwise returns a [ForbiddenError]
+    ///
+    /// # Examples
+    /// ```ignore
+    /// #[get("/admin/users")]
+    /// pub async fn list_users(token: AuthToken<User>) -> Result<impl Responder, Error> {
+    ///     let users = token.with_roles_checked(&["admin"], |admin| load_users_visible_to(admin))?;
+    ///     Ok(HttpResponse::Ok().json(users))
+    /// }
+    /// ```
+    pub fn with_roles_checked<F, R>(&self, required: &[&str], f: F) -> Result<R, ForbiddenError>
+    where
+        F: FnOnce(&U) -> R,
+    {
+        let user = self.get_authenticated_user();
+        if let Some(missing) = required.iter().find(|role| !user.has_role(role)) {
+            debug!("User is missing role '{missing}'");
+            return Err(ForbiddenError::default());
+        }
+        Ok(f(&user))
+    }
 }
 
-#[derive(PartialEq, Debug)]
+impl<U> Default for AuthToken<U>
+where
+    U: DeserializeOwned + Clone,
+{
+    /// See [AuthToken::anonymous]
+    fn default() -> Self {
+        Self::anonymous()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum AuthState {
     Authenticated,
     NeedsMfa,
@@ -147,25 +553,223 @@ struct AuthTokenInner<U>
 where
     U: DeserializeOwned + Clone,
 {
-    user: U,
+    user: Option<U>,
     auth_state: AuthState,
+    authenticated_at: SystemTime,
+    invalidated_at: Option<SystemTime>,
+    last_active_at: Option<SystemTime>,
+    expires_at: Option<SystemTime>,
+    // last_active_at and expires_at have to be written back to the store
+    is_touched: bool,
+    // The token is stored in the extensions of this request. The middleware removes it after the request to break the cycle.
+    request: Option<HttpRequest>,
+    invalidator: Option<Rc<dyn SessionInvalidator>>,
+    provider: Option<&'static str>,
+    refresher: Option<UserRefresher<U>>,
 }
 
+/// [AuthenticationProvider::refresh_user] of the provider that has created the token
+type UserRefresher<U> =
+    Rc<dyn Fn(&HttpRequest) -> Pin<Box<dyn Future<Output = Result<U, UnauthorizedError>>>>>;
+
 impl<U> FromRequest for AuthToken<U>
 where
     U: DeserializeOwned + Clone + 'static,
 {
     type Error = Error;
-    type Future = Ready<Result<AuthToken<U>, Error>>;
+    type Future = Pin<Box<dyn Future<Output = Result<AuthToken<U>, Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let extensions = req.extensions();
+        if let Some(token) = extensions.get::<AuthToken<U>>() {
+            token.attach_request(req);
+            return Box::pin(ready(Ok(AuthToken::from_ref(token))));
+        }
+
+        let Some(loader) = extensions.get::<LazyAuthLoader<U>>().cloned() else {
+            // ToDo: not a good error, needs 500
+            return Box::pin(ready(Err(UnauthorizedError::default().into())));
+        };
+        // the provider may need the extensions itself (e.g. for the session)
+        drop(extensions);
+        let token = loader.provider.get_auth_token(req);
+        let req = req.clone();
+
+        Box::pin(async move {
+            let token = match token.await {
+                Ok(token) if token.is_authenticated() => token,
+                Ok(_) => {
+                    return Err(UnauthorizedError::default()
+                        .with_requested_path(&loader.requested_path)
+                        .with_response(loader.unauthorized_response)
+                        .into())
+                }
+                Err(e) => {
+                    return Err(e
+                        .hide_message()
+                        .with_requested_path(&loader.requested_path)
+                        .with_response(loader.unauthorized_response)
+                        .into())
+                }
+            };
+
+            let provider = loader.provider;
+            token.set_provider_if_unset(provider.name());
+            token.inject_refresher(Rc::new(move |req| provider.refresh_user(req)));
+            if let Some(session_invalidator) = loader.session_invalidator {
+                token.inject_invalidator(session_invalidator);
+            }
+            // the middleware handles the token after the request like in the eager mode
+            req.extensions_mut().insert(AuthToken::from_ref(&token));
+            token.attach_request(&req);
+            loader.loaded.replace(Some(AuthToken::from_ref(&token)));
+            Ok(token)
+        })
+    }
+}
+
+/// Loads the [AuthToken] when it is extracted, if the [AuthMiddleware](crate::middleware::AuthMiddleware) is lazy
+pub(crate) struct LazyAuthLoader<U>
+where
+    U: DeserializeOwned + Clone,
+{
+    pub(crate) provider: Rc<dyn AuthenticationProvider<U>>,
+    pub(crate) session_invalidator: Option<Rc<dyn SessionInvalidator>>,
+    pub(crate) unauthorized_response: Option<Rc<UnauthorizedResponseBuilder>>,
+    pub(crate) requested_path: String,
+    // the loaded token for the middleware, which has to remove it from the request even if the service fails
+    pub(crate) loaded: Rc<RefCell<Option<AuthToken<U>>>>,
+}
+
+impl<U> Clone for LazyAuthLoader<U>
+where
+    U: DeserializeOwned + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            provider: Rc::clone(&self.provider),
+            session_invalidator: self.session_invalidator.clone(),
+            unauthorized_response: self.unauthorized_response.clone(),
+            requested_path: self.requested_path.clone(),
+            loaded: Rc::clone(&self.loaded),
+        }
+    }
+}
+
+/// Extractor for the authenticated user, without the [AuthToken] wrapper
+///
+/// Holds a clone of the user, so the authentication cannot be invalidated with it. Use [AuthToken] for that.
+/// ```ignore
+/// #[get("/profile")]
+/// pub async fn profile(user: UserExtractor<User>) -> impl Responder {
+///     HttpResponse::Ok().body(format!("Hello {}", user.name))
+/// }
+/// ```
+pub struct UserExtractor<U>(pub U);
+
+impl<U> UserExtractor<U> {
+    pub fn into_inner(self) -> U {
+        self.0
+    }
+}
+
+impl<U> Deref for UserExtractor<U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<U> FromRequest for UserExtractor<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<UserExtractor<U>, Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let token = AuthToken::<U>::from_request(req, payload);
+
+        Box::pin(async move {
+            let token = token.await?;
+            let user = token
+                .try_get_authenticated_user()
+                .map(|user| user.clone())
+                .ok_or_else(UnauthorizedError::default)?;
+            Ok(UserExtractor(user))
+        })
+    }
+}
+
+/// Extractor for routes that are public, but show more if a user is logged in
+///
+/// Never fails: [OptionalAuthToken::user] is `Some` if the request is authenticated and `None` otherwise.
+/// On routes not secured by the [AuthMiddleware](crate::middleware::AuthMiddleware), the user is only loaded when this extractor is used.
+/// ```ignore
+/// #[get("/")]
+/// pub async fn index(token: OptionalAuthToken<User>) -> impl Responder {
+///     match token.user() {
+///         Some(user) => HttpResponse::Ok().body(format!("Hello {}", user.name)),
+///         None => HttpResponse::Ok().body("Hello anonymous"),
+///     }
+/// }
+/// ```
+pub struct OptionalAuthToken<U>(Option<AuthToken<U>>)
+where
+    U: DeserializeOwned + Clone;
+
+impl<U> OptionalAuthToken<U>
+where
+    U: DeserializeOwned + Clone,
+{
+    pub fn user(&self) -> Option<Ref<'_, U>> {
+        self.0
+            .as_ref()
+            .and_then(|token| token.try_get_authenticated_user())
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+/// Loads the [AuthToken] for an [OptionalAuthToken] on routes that are not secured
+pub(crate) struct OptionalAuthLoader<U>(pub(crate) Rc<dyn AuthenticationProvider<U>>);
+
+impl<U> FromRequest for OptionalAuthToken<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<OptionalAuthToken<U>, Error>>>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
         let extensions = req.extensions();
         if let Some(token) = extensions.get::<AuthToken<U>>() {
-            return ready(Ok(AuthToken::from_ref(token)));
+            token.attach_request(req);
+            let token = AuthToken::from_ref(token);
+            return Box::pin(ready(Ok(OptionalAuthToken(Some(token)))));
         }
 
-        // ToDo: not a good error, needs 500
-        ready(Err(UnauthorizedError::default().into()))
+        let Some(provider) = extensions
+            .get::<OptionalAuthLoader<U>>()
+            .map(|loader| Rc::clone(&loader.0))
+        else {
+            return Box::pin(ready(Ok(OptionalAuthToken(None))));
+        };
+        // the provider may need the extensions itself (e.g. for the session)
+        drop(extensions);
+        let token = provider.get_auth_token(req);
+
+        Box::pin(async move {
+            let token = token.await.ok().filter(|token| token.is_authenticated());
+            if let Some(token) = &token {
+                token.set_provider_if_unset(provider.name());
+                token.inject_refresher(Rc::new(move |req| provider.refresh_user(req)));
+            }
+            Ok(OptionalAuthToken(token))
+        })
     }
 }
 
@@ -180,3 +784,254 @@ impl AuthTokenExt for HttpRequest {
             .map(|auth_token_ref| AuthToken::from_ref(auth_token_ref))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        future::{ready, Future},
+        pin::Pin,
+        rc::Rc,
+        time::{Duration, SystemTime},
+    };
+
+    use actix_web::{test::TestRequest, HttpRequest};
+
+    use crate::{AuthState, AuthToken, AuthenticationProvider, HasRoles, UnauthorizedError};
+
+    struct FixedUserProvider;
+
+    impl AuthenticationProvider<String> for FixedUserProvider {
+        fn get_auth_token(
+            &self,
+            _req: &HttpRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<AuthToken<String>, UnauthorizedError>>>> {
+            Box::pin(ready(Ok(AuthToken::new(
+                "anna".to_owned(),
+                AuthState::Authenticated,
+                SystemTime::now(),
+            ))))
+        }
+
+        fn invalidate(&self, _req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+            Box::pin(async {})
+        }
+    }
+
+    #[actix_web::test]
+    async fn refresh_user_should_delegate_to_get_auth_token_by_default() {
+        let req = TestRequest::default().to_http_request();
+
+        let user = FixedUserProvider.refresh_user(&req).await.unwrap();
+
+        assert_eq!(user, "anna");
+    }
+
+    /// Returns another user on every call, like a user that has been changed in the database
+    struct ChangingUserProvider {
+        calls: Cell<u32>,
+    }
+
+    impl AuthenticationProvider<String> for ChangingUserProvider {
+        fn get_auth_token(
+            &self,
+            _req: &HttpRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<AuthToken<String>, UnauthorizedError>>>> {
+            self.calls.set(self.calls.get() + 1);
+            Box::pin(ready(Ok(AuthToken::new(
+                format!("anna-{}", self.calls.get()),
+                AuthState::Authenticated,
+                SystemTime::now(),
+            ))))
+        }
+
+        fn invalidate(&self, _req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+            Box::pin(async {})
+        }
+    }
+
+    #[actix_web::test]
+    async fn refresh_should_replace_the_user() {
+        let req = TestRequest::default().to_http_request();
+        let provider = Rc::new(ChangingUserProvider {
+            calls: Cell::new(0),
+        });
+        let token = provider.get_auth_token(&req).await.unwrap();
+        let refresh_provider = Rc::clone(&provider);
+        token.inject_refresher(Rc::new(move |req| refresh_provider.refresh_user(req)));
+        assert_eq!(*token.get_authenticated_user(), "anna-1");
+
+        token.refresh(&req).await.unwrap();
+        assert_eq!(*token.get_authenticated_user(), "anna-2");
+
+        token.refresh(&req).await.unwrap();
+        assert_eq!(*token.get_authenticated_user(), "anna-3");
+    }
+
+    #[actix_web::test]
+    async fn refresh_without_provider_should_fail() {
+        let req = TestRequest::default().to_http_request();
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+
+        assert!(token.refresh(&req).await.is_err());
+        assert_eq!(*token.get_authenticated_user(), "anna");
+    }
+
+    #[cfg(not(feature = "debug_user"))]
+    #[test]
+    fn debug_should_redact_user() {
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+
+        assert_eq!(
+            format!("{token:?}"),
+            "AuthToken { is_valid: true, user: <redacted> }"
+        );
+    }
+
+    #[cfg(feature = "debug_user")]
+    #[test]
+    fn debug_should_print_user() {
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+
+        assert_eq!(
+            format!("{token:?}"),
+            "AuthToken { is_valid: true, user: \"anna\" }"
+        );
+    }
+
+    #[test]
+    fn try_get_authenticated_user_should_not_panic_while_mutably_borrowed() {
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+
+        assert_eq!(*token.try_get_authenticated_user().unwrap(), "anna");
+
+        let _guard = token.inner.borrow_mut();
+        assert!(token.try_get_authenticated_user().is_none());
+    }
+
+    #[test]
+    fn invalidate_should_record_the_time() {
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+        assert!(token.invalidated_at().is_none());
+
+        let before = SystemTime::now();
+        token.invalidate();
+
+        assert!(token.invalidated_at().unwrap() >= before);
+    }
+
+    #[derive(serde::Deserialize, Clone)]
+    struct Admin;
+
+    impl HasRoles for Admin {
+        fn has_role(&self, role: &str) -> bool {
+            role == "admin"
+        }
+    }
+
+    #[test]
+    fn with_roles_checked_should_only_call_f_if_all_roles_are_present() {
+        let token = AuthToken::new(Admin, AuthState::Authenticated, SystemTime::now());
+
+        assert_eq!(token.with_roles_checked(&["admin"], |_| 42).unwrap(), 42);
+        assert!(token
+            .with_roles_checked(&["admin", "root"], |_| 42)
+            .is_err());
+    }
+
+    #[test]
+    fn independent_clone_should_not_share_the_state() {
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+        let shared = token.clone();
+        let independent = token.clone_independent();
+
+        independent.invalidate();
+        assert!(token.is_valid());
+
+        shared.invalidate();
+        assert!(!token.is_valid());
+        assert_eq!(*independent.get_authenticated_user(), "anna");
+    }
+
+    #[test]
+    fn extend_session_should_move_the_expiry() {
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        )
+        .with_expires_at(Some(expires_at));
+
+        token.extend_session(Duration::from_secs(30));
+
+        assert_eq!(
+            token.expires_at(),
+            Some(expires_at + Duration::from_secs(30))
+        );
+        assert!(token.is_touched());
+    }
+
+    #[test]
+    fn extend_session_should_not_add_an_expiry() {
+        let token = AuthToken::new(
+            "anna".to_owned(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+
+        token.extend_session(Duration::from_secs(30));
+
+        assert!(token.expires_at().is_none());
+        assert!(!token.is_touched());
+    }
+
+    #[test]
+    fn from_value_should_deserialize_the_user() {
+        let token = AuthToken::<String>::from_value(serde_json::json!("anna")).unwrap();
+
+        assert_eq!(*token.get_authenticated_user(), "anna");
+        assert!(token.is_authenticated());
+        assert!(AuthToken::<String>::from_value(serde_json::json!(42)).is_err());
+    }
+
+    #[test]
+    fn anonymous_token_should_have_no_user() {
+        let token = AuthToken::<String>::default();
+
+        assert!(token.try_get_authenticated_user().is_none());
+        assert!(!token.is_authenticated());
+    }
+
+    #[test]
+    #[should_panic(expected = "AuthToken is anonymous")]
+    fn get_authenticated_user_should_panic_for_anonymous_token() {
+        let token = AuthToken::<String>::anonymous();
+
+        let _ = token.get_authenticated_user();
+    }
+}
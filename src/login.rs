@@ -1,8 +1,15 @@
-use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use std::time::Duration;
+
+use actix_web::{
+    http::{header::RETRY_AFTER, StatusCode},
+    HttpRequest, HttpResponse, ResponseError,
+};
 use futures::future::LocalBoxFuture;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::multifactor::ChallengeResponse;
+
 /// The unencrypted credentials coming directly from the request
 #[derive(Deserialize)]
 pub struct LoginToken {
@@ -10,6 +17,52 @@ pub struct LoginToken {
     pub password: String,
 }
 
+/// Request types that carry credentials for a login
+///
+/// The login handler converts them into a [LoginToken], so a [LoadUserService] works with every credential type.
+pub trait HasCredentials {
+    fn username(&self) -> &str;
+    fn password(&self) -> &str;
+}
+
+impl HasCredentials for LoginToken {
+    fn username(&self) -> &str {
+        &self.username
+    }
+
+    fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+/// Login with a numeric PIN (e.g. for kiosks or internal tools)
+///
+/// The [LoadUserService] receives the `device_id` as username and the `pin` as password.
+#[derive(Deserialize)]
+pub struct PinLoginRequest {
+    pub device_id: String,
+    pub pin: String,
+}
+
+impl HasCredentials for PinLoginRequest {
+    fn username(&self) -> &str {
+        &self.device_id
+    }
+
+    fn password(&self) -> &str {
+        &self.pin
+    }
+}
+
+impl From<PinLoginRequest> for LoginToken {
+    fn from(value: PinLoginRequest) -> Self {
+        Self {
+            username: value.device_id,
+            password: value.pin,
+        }
+    }
+}
+
 /// Trait that handles the loading of a user and executes a success and error handler
 pub trait LoadUserService: Send + Sync {
     type User: DeserializeOwned + Serialize + Clone;
@@ -31,6 +84,44 @@ pub trait LoadUserService: Send + Sync {
     fn on_error_handler(&self, req: &HttpRequest) -> LocalBoxFuture<'_, Result<(), HandlerError>>;
 }
 
+/// Result of a login attempt, mapped to the response by a [LoginOutcomeMapper]
+pub enum LoginOutcome {
+    Success,
+    MfaRequired(ChallengeResponse),
+    Failure(LoadUserError),
+    /// The account is temporarily locked (e.g. after too many attempts)
+    Locked,
+    /// Too many failed logins from this client, see [RateLimiter](crate::ratelimit::RateLimiter)
+    RateLimited(Duration),
+}
+
+/// Creates the response of the login route for a [LoginOutcome]
+///
+/// Set it with [SessionLoginHandler::with_outcome_mapper](crate::session::handlers::SessionLoginHandler::with_outcome_mapper).
+pub trait LoginOutcomeMapper: Send + Sync {
+    fn map(&self, outcome: LoginOutcome) -> HttpResponse;
+}
+
+/// `200` on success, `200` with the [ChallengeResponse] if mfa is required, `401` on failure, `423` if locked
+/// and `429` with a `Retry-After` header if rate limited
+#[derive(Clone, Default)]
+pub struct DefaultLoginOutcomeMapper;
+
+impl LoginOutcomeMapper for DefaultLoginOutcomeMapper {
+    fn map(&self, outcome: LoginOutcome) -> HttpResponse {
+        match outcome {
+            LoginOutcome::Success => HttpResponse::Ok().finish(),
+            LoginOutcome::MfaRequired(challenge) => HttpResponse::Ok().json(challenge),
+            LoginOutcome::Failure(e) => e.error_response(),
+            LoginOutcome::Locked => HttpResponse::build(StatusCode::LOCKED).finish(),
+            LoginOutcome::RateLimited(remaining) => HttpResponse::TooManyRequests()
+                // rounded up, so that the client does not retry too early
+                .insert_header((RETRY_AFTER, remaining.as_secs_f64().ceil() as u64))
+                .finish(),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum LoadUserError {
     #[error("Username or password wrong")]
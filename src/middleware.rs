@@ -1,26 +1,62 @@
 use std::{
-    future::{ready, Ready},
+    cell::RefCell,
+    future::{ready, Future, Ready},
     marker::PhantomData,
+    num::NonZeroUsize,
+    ops::BitOr,
+    pin::Pin,
     rc::Rc,
 };
 
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    body::MessageBody,
+    dev::{forward_ready, Service, ServiceFactory, ServiceRequest, ServiceResponse, Transform},
     error::ErrorBadRequest,
-    Error, HttpMessage,
+    http::Method,
+    Error, HttpMessage, Scope,
 };
+#[cfg(feature = "time_restriction")]
+use chrono::{DateTime, NaiveTime, Utc};
+#[cfg(feature = "time_restriction")]
+use chrono_tz::Tz;
 use futures::future::LocalBoxFuture;
-use log::{debug, trace};
+use glob::{MatchOptions, Pattern};
+use log::{debug, trace, warn};
+use lru::LruCache;
 use regex::Regex;
 use serde::de::DeserializeOwned;
 use urlencoding::encode;
 
+#[cfg(feature = "passthrough")]
+use crate::AuthState;
 use crate::{
-    multifactor::Factor, web::MFA_ROUTE, AuthToken, AuthenticationProvider, UnauthorizedError,
+    audit::AuditLogger,
+    errors::{ForbiddenError, InitError, UnauthorizedResponseBuilder},
+    geo::GeoBlocker,
+    multifactor::Factor,
+    signature::{verify_request, RequestSignatureVerifier},
+    web::{LoginRouteConfig, LOGIN_ROUTE, MFA_ROUTE},
+    AuthToken, AuthenticationProvider, Authorizable, LazyAuthLoader, OptionalAuthLoader,
+    SessionInvalidator, UnauthorizedError,
 };
+#[cfg(feature = "passthrough")]
+use std::time::SystemTime;
 
 const PATH_MATCHER_ANY_ENCODED: &str = "%2A"; // to match *
 
+/// Environment variable that enables [AuthMiddleware::passthrough_user] if it is `1`
+#[cfg(feature = "passthrough")]
+pub const PASSTHROUGH_ENV: &str = "AUTHFIX_PASSTHROUGH";
+
+#[cfg(feature = "passthrough")]
+fn is_passthrough_enabled() -> bool {
+    let enabled = std::env::var(PASSTHROUGH_ENV).is_ok_and(|value| value == "1");
+    if enabled {
+        warn!("{PASSTHROUGH_ENV} is set, authentication is disabled");
+    }
+    enabled
+}
+
 /// It is used to specify secured paths
 ///
 /// [`PathMatcher`] stores the paths that should be excluded or included for authentication.
@@ -36,34 +72,658 @@ const PATH_MATCHER_ANY_ENCODED: &str = "%2A"; // to match *
 /// ```ignore
 /// PathMatcher::new(vec!["/private/*"], false)
 /// ```
-#[derive(Clone)]
+///
+/// For full glob syntax (e.g. `**` for any depth) use [`PathMatcher::with_glob`]
+/// ```ignore
+/// PathMatcher::with_glob(vec!["/static/**", "/favicon.ico"], true)
+/// ```
+///
+/// More patterns can be added later as `(pattern, is_exclusion)` pairs. Excluded patterns always win over secured ones,
+/// paths that match no pattern are secured if the matcher was created as exclusion list.
+/// ```ignore
+/// let mut matcher = PathMatcher::default();
+/// matcher.extend([("/public/*", true), ("/public/admin", false)]);
+/// ```
+///
+/// Patterns that only apply to some HTTP methods are created with [`PathMatcher::with_method_rules`],
+/// paths that need certain roles are added with [`PathMatcher::with_role_rules`].
+///
+/// For high-traffic services the results for the most recent paths can be cached with [`PathMatcher::with_cache`].
 pub struct PathMatcher {
     is_exclusion_list: bool,
-    path_regex_list: Vec<(&'static str, Regex)>,
+    syntax: PatternSyntax,
+    path_regex_list: Vec<PathPattern>,
+    method_rules: Vec<MethodRule>,
+    role_rules: Vec<RoleRule>,
+    cache: Option<RefCell<LruCache<String, bool>>>,
+    environment: Option<Environment>,
+    #[cfg(feature = "time_restriction")]
+    time_restrictions: Vec<TimeRestriction>,
 }
 
-impl PathMatcher {
-    pub fn new(path_list: Vec<&'static str>, is_exclusion_list: bool) -> Self {
-        let mut path_regex_list = Vec::new();
-        for pattern in path_list.into_iter() {
-            let regex_pattern = format!("^{}$", transform_to_encoded_regex(pattern));
-            path_regex_list.push((pattern, Regex::new(&regex_pattern).unwrap()));
+/// The environment the app runs in, for paths that are only public or secured in some environments (see [`PathMatcher::for_env`])
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Environment {
+    Development,
+    Staging,
+    Production,
+}
+
+/// Environment variable for [`Environment::current`] (`development`, `staging` or `production`)
+pub const ENVIRONMENT_ENV: &str = "AUTHFIX_ENV";
+
+impl Environment {
+    /// Reads [ENVIRONMENT_ENV], without (or with an unknown value) it is `Development` in debug builds and `Production` otherwise
+    pub fn current() -> Self {
+        match std::env::var(ENVIRONMENT_ENV).as_deref() {
+            Ok("development") => Environment::Development,
+            Ok("staging") => Environment::Staging,
+            Ok("production") => Environment::Production,
+            _ if cfg!(debug_assertions) => Environment::Development,
+            _ => Environment::Production,
+        }
+    }
+}
+
+impl Clone for PathMatcher {
+    /// The clone starts with an empty cache
+    fn clone(&self) -> Self {
+        Self {
+            is_exclusion_list: self.is_exclusion_list,
+            syntax: self.syntax,
+            path_regex_list: self.path_regex_list.clone(),
+            method_rules: self.method_rules.clone(),
+            role_rules: self.role_rules.clone(),
+            cache: self
+                .cache
+                .as_ref()
+                .map(|cache| RefCell::new(LruCache::new(cache.borrow().cap()))),
+            environment: self.environment,
+            #[cfg(feature = "time_restriction")]
+            time_restrictions: self.time_restrictions.clone(),
         }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PatternSyntax {
+    Wildcard,
+    Glob,
+    Exact,
+}
+
+#[derive(Clone)]
+enum CompiledPattern {
+    Wildcard(Regex),
+    Glob(Pattern),
+    Exact,
+}
+
+#[derive(Clone)]
+struct PathPattern {
+    pattern: String,
+    compiled: CompiledPattern,
+    is_exclusion: bool,
+}
+
+impl PathPattern {
+    fn new(pattern: &str, syntax: PatternSyntax, is_exclusion: bool) -> Self {
+        let compiled = match syntax {
+            PatternSyntax::Wildcard => {
+                let regex_pattern = format!("^{}$", transform_to_encoded_regex(pattern));
+                CompiledPattern::Wildcard(Regex::new(&regex_pattern).unwrap())
+            }
+            PatternSyntax::Glob => CompiledPattern::Glob(Pattern::new(pattern).unwrap()),
+            PatternSyntax::Exact => CompiledPattern::Exact,
+        };
+        Self {
+            pattern: pattern.to_owned(),
+            compiled,
+            is_exclusion,
+        }
+    }
+
+    fn with_prefix(&self, prefix: &str) -> Self {
+        let syntax = match self.compiled {
+            CompiledPattern::Wildcard(_) => PatternSyntax::Wildcard,
+            CompiledPattern::Glob(_) => PatternSyntax::Glob,
+            CompiledPattern::Exact => PatternSyntax::Exact,
+        };
+        Self::new(
+            &format!("{prefix}{}", self.pattern),
+            syntax,
+            self.is_exclusion,
+        )
+    }
+
+    fn is_match(&self, path: &str, encoded_path: &str) -> bool {
+        match &self.compiled {
+            CompiledPattern::Wildcard(regex) => regex.is_match(encoded_path),
+            CompiledPattern::Glob(pattern) => pattern.matches_with(path, GLOB_MATCH_OPTIONS),
+            CompiledPattern::Exact => self.pattern == path,
+        }
+    }
+}
+
+/// A path pattern that only applies to the given HTTP methods, see [`PathMatcher::with_method_rules`]
+#[derive(Clone)]
+pub struct PathMethodRule {
+    pattern: &'static str,
+    methods: Vec<Method>,
+}
+
+impl PathMethodRule {
+    pub fn new(pattern: &'static str, methods: Vec<Method>) -> Self {
+        Self { pattern, methods }
+    }
+}
+
+#[derive(Clone)]
+struct MethodRule {
+    pattern: PathPattern,
+    methods: Vec<Method>,
+}
+
+impl MethodRule {
+    fn is_match(&self, path: &str, encoded_path: &str, method: &Method) -> bool {
+        self.methods.contains(method) && self.pattern.is_match(path, encoded_path)
+    }
+}
+
+/// Roles and permissions a user needs for the paths matching the pattern, see [`PathMatcher::with_role_rules`]
+#[derive(Clone)]
+pub struct RolePathRule {
+    pattern: &'static str,
+    roles: Vec<String>,
+    permissions: Vec<String>,
+}
+
+impl RolePathRule {
+    /// The user needs all `roles`
+    pub fn new(pattern: &'static str, roles: &[&str]) -> Self {
         Self {
+            pattern,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            permissions: Vec::new(),
+        }
+    }
+
+    /// The user needs all `permissions` in addition to the roles
+    pub fn with_permissions(mut self, permissions: &[&str]) -> Self {
+        self.permissions = permissions.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    fn is_satisfied_by<U: Authorizable>(&self, user: &U) -> bool {
+        let roles = user.roles();
+        let permissions = user.permissions();
+        self.roles.iter().all(|role| roles.contains(role))
+            && self.permissions.iter().all(|p| permissions.contains(p))
+    }
+}
+
+#[derive(Clone)]
+struct RoleRule {
+    pattern: PathPattern,
+    rule: RolePathRule,
+}
+
+#[cfg(feature = "time_restriction")]
+#[derive(Clone)]
+struct TimeRestriction {
+    pattern: PathPattern,
+    from: NaiveTime,
+    to: NaiveTime,
+    timezone: Tz,
+}
+
+#[cfg(feature = "time_restriction")]
+impl TimeRestriction {
+    fn is_allowed_at(&self, now: DateTime<Utc>) -> bool {
+        let time = now.with_timezone(&self.timezone).time();
+        if self.from <= self.to {
+            self.from <= time && time < self.to
+        } else {
+            self.from <= time || time < self.to
+        }
+    }
+}
+
+// `*` should not match across path segments, use `**` for that
+const GLOB_MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl PathMatcher {
+    /// Takes borrowed or owned patterns (e.g. `Vec<&str>` or `Vec<String>` from a config), to collect them one by one see [`PathMatcherBuilder`]
+    pub fn new(
+        path_list: impl IntoIterator<Item = impl AsRef<str>>,
+        is_exclusion_list: bool,
+    ) -> Self {
+        Self::with_syntax(path_list, is_exclusion_list, PatternSyntax::Wildcard)
+    }
+
+    /// Collects the patterns one by one, see [`PathMatcherBuilder`]
+    pub fn builder(is_exclusion_list: bool) -> PathMatcherBuilder {
+        PathMatcherBuilder {
+            patterns: Vec::new(),
             is_exclusion_list,
-            path_regex_list,
+            syntax: PatternSyntax::Wildcard,
+        }
+    }
+
+    /// Like [`PathMatcher::new`], but the paths are [glob patterns](https://docs.rs/glob/latest/glob/struct.Pattern.html)
+    ///
+    /// `*` matches within one path segment, `**` matches any number of segments, so `/static/**` matches `/static/js/app.js`.
+    ///
+    /// # Panics
+    /// If a pattern is not a valid glob pattern
+    pub fn with_glob(
+        path_list: impl IntoIterator<Item = impl AsRef<str>>,
+        is_exclusion_list: bool,
+    ) -> Self {
+        Self::with_syntax(path_list, is_exclusion_list, PatternSyntax::Glob)
+    }
+
+    /// Secures exactly the given paths, all other paths are public
+    ///
+    /// `*` has no special meaning, so `/api/*` only secures the path `/api/*`. Meant for e.g. OpenID Connect resource servers,
+    /// where every protected endpoint is listed explicitly. Patterns added later with [`PathMatcher::extend`] are exact as well.
+    /// ```ignore
+    /// PathMatcher::strict(vec!["/userinfo", "/api/orders"])
+    /// ```
+    pub fn strict(paths: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        Self::with_syntax(paths, false, PatternSyntax::Exact)
+    }
+
+    fn with_syntax(
+        path_list: impl IntoIterator<Item = impl AsRef<str>>,
+        is_exclusion_list: bool,
+        syntax: PatternSyntax,
+    ) -> Self {
+        let mut matcher = Self {
+            is_exclusion_list,
+            syntax,
+            path_regex_list: Vec::new(),
+            method_rules: Vec::new(),
+            role_rules: Vec::new(),
+            cache: None,
+            environment: None,
+            #[cfg(feature = "time_restriction")]
+            time_restrictions: Vec::new(),
+        };
+        let path_list: Vec<_> = path_list.into_iter().collect();
+        matcher.extend(path_list.iter().map(|p| (p.as_ref(), is_exclusion_list)));
+        matcher
+    }
+
+    /// Like [`PathMatcher::new`], but the patterns only apply to the methods of their [`PathMethodRule`]
+    ///
+    /// Method rules are checked before the patterns without methods (e.g. of a merged [`PathMatcher::default`]).
+    /// If no method rule matches, the path is checked as usual.
+    ///
+    /// # Examples
+    /// `GET` is public, `POST` and `DELETE` are secured:
+    /// ```ignore
+    /// PathMatcher::with_method_rules(vec![PathMethodRule::new("/api/items", vec![Method::GET])], true)
+    /// ```
+    pub fn with_method_rules(rules: Vec<PathMethodRule>, is_exclusion_list: bool) -> Self {
+        let mut matcher = Self::new(Vec::<&str>::new(), is_exclusion_list);
+        matcher.method_rules = rules
+            .into_iter()
+            .map(|rule| MethodRule {
+                pattern: PathPattern::new(rule.pattern, matcher.syntax, is_exclusion_list),
+                methods: rule.methods,
+            })
+            .collect();
+        matcher
+    }
+
+    /// Paths matching a rule are only accessible for users with the roles and permissions of the rule, otherwise the
+    /// middleware responds with `403 Forbidden`
+    ///
+    /// Paths with a role rule are always secured, even if they are excluded. If several rules match, all of them must be satisfied.
+    /// The rules are only checked by a middleware created with [`AuthMiddleware::new_with_roles`].
+    pub fn with_role_rules(mut self, rules: Vec<RolePathRule>) -> Self {
+        let syntax = self.syntax;
+        self.role_rules
+            .extend(rules.into_iter().map(|rule| RoleRule {
+                pattern: PathPattern::new(rule.pattern, syntax, false),
+                rule,
+            }));
+        self.clear_cache();
+        self
+    }
+
+    fn has_role_rule(&self, path: &str, encoded_path: &str) -> bool {
+        self.role_rules
+            .iter()
+            .any(|role_rule| role_rule.pattern.is_match(path, encoded_path))
+    }
+
+    fn role_rules_for(&self, path: &str) -> Vec<RolePathRule> {
+        let encoded_path = transform_to_encoded_regex(path);
+        self.role_rules
+            .iter()
+            .filter(|role_rule| role_rule.pattern.is_match(path, &encoded_path))
+            .map(|role_rule| role_rule.rule.clone())
+            .collect()
+    }
+
+    /// Combines the patterns of both matchers
+    ///
+    /// Exclusions of one matcher win over secured patterns of the other. Paths that match no pattern are secured
+    /// if one of the matchers secures them (i.e. was created as exclusion list). `matcher1 | matcher2` does the same.
+    pub fn merge(mut self, other: PathMatcher) -> PathMatcher {
+        self.is_exclusion_list |= other.is_exclusion_list;
+        self.path_regex_list.extend(other.path_regex_list);
+        self.method_rules.extend(other.method_rules);
+        self.role_rules.extend(other.role_rules);
+        #[cfg(feature = "time_restriction")]
+        self.time_restrictions.extend(other.time_restrictions);
+        self.clear_cache();
+        self
+    }
+
+    /// Paths matching `pattern` are only accessible between `from` and `to` in `timezone` (e.g. during business hours)
+    ///
+    /// Outside of this window the middleware responds with `403 Forbidden`, no matter if the path is secured or not.
+    /// If `from` is after `to`, the window spans midnight.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// PathMatcher::default().restricted_to_hours(
+    ///     "/api/payouts/*",
+    ///     NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+    ///     NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+    ///     chrono_tz::Europe::Berlin,
+    /// )
+    /// ```
+    #[cfg(feature = "time_restriction")]
+    pub fn restricted_to_hours(
+        mut self,
+        pattern: &str,
+        from: NaiveTime,
+        to: NaiveTime,
+        timezone: Tz,
+    ) -> Self {
+        self.time_restrictions.push(TimeRestriction {
+            pattern: PathPattern::new(pattern, self.syntax, false),
+            from,
+            to,
+            timezone,
+        });
+        self
+    }
+
+    /// Returns a [ForbiddenError] if the path is restricted to hours (see [PathMatcher::restricted_to_hours]) and `now` is outside of them
+    #[cfg(feature = "time_restriction")]
+    pub fn check_time_restriction(
+        &self,
+        path: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), ForbiddenError> {
+        let encoded_path = transform_to_encoded_regex(path);
+        match self.time_restrictions.iter().find(|restriction| {
+            restriction.pattern.is_match(path, &encoded_path) && !restriction.is_allowed_at(now)
+        }) {
+            Some(restriction) => Err(ForbiddenError::new(&format!(
+                "This endpoint is only available between {} and {}.",
+                restriction.from, restriction.to
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Prepends `prefix` to all patterns, e.g. the path of the `Scope` the middleware wraps
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        self.path_regex_list = self
+            .path_regex_list
+            .iter()
+            .map(|pattern| pattern.with_prefix(prefix))
+            .collect();
+        for rule in &mut self.method_rules {
+            rule.pattern = rule.pattern.with_prefix(prefix);
+        }
+        for role_rule in &mut self.role_rules {
+            role_rule.pattern = role_rule.pattern.with_prefix(prefix);
+        }
+        #[cfg(feature = "time_restriction")]
+        for restriction in &mut self.time_restrictions {
+            restriction.pattern = restriction.pattern.with_prefix(prefix);
+        }
+        self.clear_cache();
+        self
+    }
+
+    /// Describes configurations that are most likely mistakes, e.g. if all paths are public
+    ///
+    /// The [AuthMiddleware] logs them as warnings at startup.
+    pub fn insecure_configuration_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        for pattern in self.path_regex_list.iter().filter(|p| p.is_exclusion) {
+            match pattern.pattern.as_str() {
+                "*" | "/*" | "/**" | "**" => {
+                    warnings.push(format!(
+                        "All paths are configured as public (pattern '{}')",
+                        pattern.pattern
+                    ));
+                }
+                "/" => warnings.push("The root path '/' is configured as public".to_owned()),
+                _ => {}
+            }
+        }
+
+        if !self.is_exclusion_list
+            && !self.path_regex_list.iter().any(|p| !p.is_exclusion)
+            && !self
+                .method_rules
+                .iter()
+                .any(|rule| !rule.pattern.is_exclusion)
+        {
+            warnings.push("No path is secured, the list of secured paths is empty".to_owned());
+        }
+
+        warnings
+    }
+
+    /// Adds public paths to a matcher, they are checked before the secured patterns
+    ///
+    /// Uses the syntax of the matcher, so with [`PathMatcher::with_glob`] a catch-all can whitelist single paths:
+    /// ```ignore
+    /// PathMatcher::with_glob(vec!["/**"], false).with_exclusions(vec!["/health".to_owned(), "/public/*".to_owned()])
+    /// ```
+    pub fn with_exclusions(mut self, patterns: Vec<String>) -> Self {
+        self.extend(patterns.iter().map(|pattern| (pattern.as_str(), true)));
+        self
+    }
+
+    /// Like [`PathMatcher::default`], but paths can be made public or secured only in some environments
+    ///
+    /// Replaces `if cfg!(debug_assertions)` blocks around the matcher:
+    /// ```ignore
+    /// PathMatcher::for_env(Environment::current())
+    ///     .public_in(Environment::Development, vec!["/swagger/*", "/debug/*"])
+    ///     .public_in(Environment::Staging, vec!["/swagger/*"])
+    /// ```
+    pub fn for_env(environment: Environment) -> Self {
+        Self {
+            environment: Some(environment),
+            ..Self::default()
+        }
+    }
+
+    /// Adds the paths as public, but only if the matcher has been created for `environment` with [`PathMatcher::for_env`]
+    pub fn public_in(mut self, environment: Environment, paths: Vec<&str>) -> Self {
+        if self.environment == Some(environment) {
+            self.extend(paths.into_iter().map(|path| (path, true)));
+        }
+        self
+    }
+
+    /// Adds the paths as secured, but only if the matcher has been created for `environment` with [`PathMatcher::for_env`]
+    ///
+    /// Public paths always win, so this only secures paths that would otherwise be public.
+    pub fn secured_in(mut self, environment: Environment, paths: Vec<&str>) -> Self {
+        if self.environment == Some(environment) {
+            self.extend(paths.into_iter().map(|path| (path, false)));
+        }
+        self
+    }
+
+    /// Caches the results for the `capacity` most recently matched paths (`0` disables the cache)
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = NonZeroUsize::new(capacity).map(|cap| RefCell::new(LruCache::new(cap)));
+        self
+    }
+
+    fn clear_cache(&mut self) {
+        if let Some(cache) = &self.cache {
+            cache.borrow_mut().clear();
         }
     }
 
     pub fn matches(&self, path: &str) -> bool {
+        let Some(cache) = &self.cache else {
+            return self.matches_uncached(path);
+        };
+
+        if let Some(is_secured) = cache.borrow_mut().get(path) {
+            return *is_secured;
+        }
+
+        let is_secured = self.matches_uncached(path);
+        cache.borrow_mut().put(path.to_owned(), is_secured);
+        is_secured
+    }
+
+    /// Like [`PathMatcher::matches`], but the rules of [`PathMatcher::with_method_rules`] are checked first
+    ///
+    /// Only role rules come before them, paths with a role rule are secured for every method.
+    pub fn matches_request(&self, path: &str, method: &Method) -> bool {
         let encoded_path = transform_to_encoded_regex(path);
-        let mut path_regex_iter = self.path_regex_list.iter();
+        if self.has_role_rule(path, &encoded_path) {
+            return true;
+        }
 
-        if self.is_exclusion_list {
-            path_regex_iter.all(|p| !p.1.is_match(&encoded_path))
-        } else {
-            path_regex_iter.any(|p| p.1.is_match(&encoded_path))
+        let mut matching_rules = self
+            .method_rules
+            .iter()
+            .filter(|rule| rule.is_match(path, &encoded_path, method))
+            .peekable();
+
+        if matching_rules.peek().is_none() {
+            return self.matches(path);
         }
+
+        // like for paths, exclusions win
+        !matching_rules.any(|rule| rule.pattern.is_exclusion)
+    }
+
+    fn matches_uncached(&self, path: &str) -> bool {
+        let encoded_path = transform_to_encoded_regex(path);
+        let mut is_secured = self.is_exclusion_list;
+
+        if self.has_role_rule(path, &encoded_path) {
+            return true;
+        }
+
+        for p in self.path_regex_list.iter() {
+            if p.is_match(path, &encoded_path) {
+                if p.is_exclusion {
+                    return false;
+                }
+                is_secured = true;
+            }
+        }
+
+        is_secured
+    }
+}
+
+impl<'a> Extend<(&'a str, bool)> for PathMatcher {
+    /// Adds `(pattern, is_exclusion)` pairs, the patterns have the same syntax as the existing ones
+    fn extend<T: IntoIterator<Item = (&'a str, bool)>>(&mut self, iter: T) {
+        let syntax = self.syntax;
+        self.path_regex_list.extend(
+            iter.into_iter()
+                .map(|(pattern, is_exclusion)| PathPattern::new(pattern, syntax, is_exclusion)),
+        );
+        self.clear_cache();
+    }
+}
+
+impl BitOr for PathMatcher {
+    type Output = PathMatcher;
+
+    /// See [`PathMatcher::merge`]
+    fn bitor(self, rhs: PathMatcher) -> Self::Output {
+        self.merge(rhs)
+    }
+}
+
+impl IntoIterator for PathMatcher {
+    type Item = (String, bool);
+    type IntoIter = std::vec::IntoIter<(String, bool)>;
+
+    /// Iterates over the `(pattern, is_exclusion)` pairs
+    fn into_iter(self) -> Self::IntoIter {
+        self.path_regex_list
+            .into_iter()
+            .map(|p| (p.pattern, p.is_exclusion))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a PathMatcher {
+    type Item = (&'a str, bool);
+    type IntoIter = std::vec::IntoIter<(&'a str, bool)>;
+
+    /// Iterates over the `(pattern, is_exclusion)` pairs
+    fn into_iter(self) -> Self::IntoIter {
+        self.path_regex_list
+            .iter()
+            .map(|p| (p.pattern.as_str(), p.is_exclusion))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// Builder for a [`PathMatcher`] that collects the patterns one by one (`String`, `&str` or e.g. `Cow<str>`)
+///
+/// # Examples
+/// ```ignore
+/// let mut builder = PathMatcher::builder(true).push("/login");
+/// for path in config.public_paths {
+///     builder = builder.push(path);
+/// }
+/// let matcher = builder.build();
+/// ```
+pub struct PathMatcherBuilder {
+    patterns: Vec<String>,
+    is_exclusion_list: bool,
+    syntax: PatternSyntax,
+}
+
+impl PathMatcherBuilder {
+    pub fn push(mut self, pattern: impl Into<String>) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    /// The patterns are glob patterns, see [`PathMatcher::with_glob`]
+    pub fn glob(mut self) -> Self {
+        self.syntax = PatternSyntax::Glob;
+        self
+    }
+
+    pub fn build(self) -> PathMatcher {
+        PathMatcher::with_syntax(self.patterns, self.is_exclusion_list, self.syntax)
     }
 }
 
@@ -74,6 +734,91 @@ impl Default for PathMatcher {
     }
 }
 
+#[cfg(feature = "clap")]
+const SECURE_PATH_ARG: &str = "secure_path";
+#[cfg(feature = "clap")]
+const PUBLIC_PATH_ARG: &str = "public_path";
+
+/// Command line arguments for a [`PathMatcher`] (`--secure-path` and `--public-path`, both can be repeated)
+///
+/// Implements [`clap::Args`], so it can be flattened into the arguments of a server CLI:
+/// ```ignore
+/// #[derive(Parser)]
+/// struct Cli {
+///     #[command(flatten)]
+///     paths: PathMatcherArgs,
+/// }
+///
+/// let matcher = PathMatcher::from_args(&Cli::parse().paths);
+/// ```
+#[cfg(feature = "clap")]
+#[derive(Clone, Debug, Default)]
+pub struct PathMatcherArgs {
+    pub secure_paths: Vec<String>,
+    pub public_paths: Vec<String>,
+}
+
+#[cfg(feature = "clap")]
+impl clap::FromArgMatches for PathMatcherArgs {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+        let mut args = Self::default();
+        args.update_from_arg_matches(matches)?;
+        Ok(args)
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), clap::Error> {
+        if let Some(paths) = matches.get_many::<String>(SECURE_PATH_ARG) {
+            self.secure_paths = paths.cloned().collect();
+        }
+        if let Some(paths) = matches.get_many::<String>(PUBLIC_PATH_ARG) {
+            self.public_paths = paths.cloned().collect();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "clap")]
+impl clap::Args for PathMatcherArgs {
+    fn augment_args(cmd: clap::Command) -> clap::Command {
+        cmd.arg(
+            clap::Arg::new(SECURE_PATH_ARG)
+                .long("secure-path")
+                .value_name("PATTERN")
+                .action(clap::ArgAction::Append)
+                .help("Path that needs authentication, can be repeated"),
+        )
+        .arg(
+            clap::Arg::new(PUBLIC_PATH_ARG)
+                .long("public-path")
+                .value_name("PATTERN")
+                .action(clap::ArgAction::Append)
+                .help("Path that is reachable without authentication, can be repeated"),
+        )
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+#[cfg(feature = "clap")]
+impl PathMatcher {
+    /// Creates the matcher from [`PathMatcherArgs`]
+    ///
+    /// Without public paths, only the secure paths are secured. With public paths, every other path is secured
+    /// and public paths win over secure ones. Without any path the result is [`PathMatcher::default`].
+    pub fn from_args(args: &PathMatcherArgs) -> Self {
+        if args.secure_paths.is_empty() && args.public_paths.is_empty() {
+            return Self::default();
+        }
+
+        let mut matcher = Self::new(Vec::<&str>::new(), !args.public_paths.is_empty());
+        matcher.extend(args.public_paths.iter().map(|p| (p.as_str(), true)));
+        matcher.extend(args.secure_paths.iter().map(|p| (p.as_str(), false)));
+        matcher
+    }
+}
+
 fn transform_to_encoded_regex(input: &str) -> String {
     let encoded = encode(input);
 
@@ -88,10 +833,14 @@ fn transform_to_encoded_regex(input: &str) -> String {
 ///
 /// To decide, if a user is logged in or not, [`AuthMiddleware`] uses the [AuthenticationProvider] trait to get the user from the underlying mechanism/store.
 ///
-/// Currently only [SessionAuthProvider](crate::session::session_auth::SessionAuthProvider) implements [AuthenticationProvider]. Internally it uses
+/// Currently only [SessionAuthProvider](struct@crate::session::session_auth::SessionAuthProvider) implements [AuthenticationProvider]. Internally it uses
 /// [Actix Session](https://crates.io/crates/actix-session). For session authentication it is important to wrap the `SessionMiddleware`
 /// after the `AuthMiddleware`, so that the session is created/handled before the `AuthMiddleware`.
-///  
+///
+/// Creating an `AuthMiddleware` does not require a `'static` provider, but wrapping an app or scope does:
+/// Actix Web needs `'static` middlewares and the provider is shared with every [AuthToken] via [Rc].
+/// If the provider needs data of its environment, share it with [Rc] or [Arc](std::sync::Arc) instead of borrowing it.
+///
 /// # Examples
 /// coming soon after applying lib in a reference project
 ///
@@ -105,6 +854,19 @@ where
     auth_provider: Rc<AuthProvider>,
     path_matcher: Rc<PathMatcher>,
     additional_factor: Rc<Option<Box<dyn Factor>>>,
+    login_route: Rc<String>,
+    // only set by with_login_routes, other setups might not mount the SessionLoginHandler
+    is_login_route_open: bool,
+    mfa_route: Rc<String>,
+    session_invalidator: Rc<Option<Rc<dyn SessionInvalidator>>>,
+    signature_verifier: Rc<Option<Rc<dyn RequestSignatureVerifier>>>,
+    unauthorized_response: Option<Rc<UnauthorizedResponseBuilder>>,
+    lazy: bool,
+    audit_logger: Option<Rc<dyn AuditLogger>>,
+    geo_blocker: Option<Rc<dyn GeoBlocker>>,
+    #[cfg(feature = "passthrough")]
+    passthrough_user: Option<Rc<U>>,
+    role_check: Option<fn(&RolePathRule, &U) -> bool>,
     user_type: PhantomData<U>,
 }
 
@@ -118,6 +880,18 @@ where
             auth_provider: Rc::new(auth_provider),
             path_matcher: Rc::new(path_matcher),
             additional_factor: Rc::new(None),
+            login_route: Rc::new(LOGIN_ROUTE.to_owned()),
+            is_login_route_open: false,
+            mfa_route: Rc::new(MFA_ROUTE.to_owned()),
+            session_invalidator: Rc::new(None),
+            signature_verifier: Rc::new(None),
+            unauthorized_response: None,
+            lazy: false,
+            audit_logger: None,
+            geo_blocker: None,
+            #[cfg(feature = "passthrough")]
+            passthrough_user: None,
+            role_check: None,
             user_type: PhantomData,
         }
     }
@@ -131,9 +905,287 @@ where
             auth_provider: Rc::new(auth_provider),
             path_matcher: Rc::new(path_matcher),
             additional_factor: Rc::new(Some(factor)),
+            login_route: Rc::new(LOGIN_ROUTE.to_owned()),
+            is_login_route_open: false,
+            mfa_route: Rc::new(MFA_ROUTE.to_owned()),
+            session_invalidator: Rc::new(None),
+            signature_verifier: Rc::new(None),
+            unauthorized_response: None,
+            lazy: false,
+            audit_logger: None,
+            geo_blocker: None,
+            #[cfg(feature = "passthrough")]
+            passthrough_user: None,
+            role_check: None,
             user_type: PhantomData,
         }
     }
+
+    /// Creates a middleware that also checks the roles of the user (see [PathMatcher::with_role_rules])
+    ///
+    /// # Examples
+    /// ```ignore
+    /// AuthMiddleware::<_, User>::new_with_roles(
+    ///     SessionAuthProvider::default(),
+    ///     PathMatcher::default(),
+    ///     vec![RolePathRule::new("/admin/*", &["admin"])],
+    /// )
+    /// ```
+    pub fn new_with_roles(
+        auth_provider: AuthProvider,
+        path_matcher: PathMatcher,
+        role_rules: Vec<RolePathRule>,
+    ) -> Self
+    where
+        U: Authorizable,
+    {
+        Self {
+            role_check: Some(RolePathRule::is_satisfied_by::<U>),
+            ..Self::new(auth_provider, path_matcher.with_role_rules(role_rules))
+        }
+    }
+
+    /// Adds a second pass for another user type with its own provider and [PathMatcher]
+    ///
+    /// Each pass secures its paths for its user type, so that e.g. `AuthToken<Admin>` can be used below `/admin`
+    /// and `AuthToken<User>` everywhere else.
+    /// ```ignore
+    /// App::new().wrap(
+    ///     AuthMiddleware::<_, User>::new(user_provider, PathMatcher::new(vec!["/api/*"], false))
+    ///         .for_user_type::<Admin, _>(admin_provider, PathMatcher::new(vec!["/admin/*"], false)),
+    /// )
+    /// ```
+    pub fn for_user_type<V, P>(
+        self,
+        auth_provider: P,
+        path_matcher: PathMatcher,
+    ) -> AuthMiddlewareChain<Self, AuthMiddleware<P, V>>
+    where
+        P: AuthenticationProvider<V>,
+        V: DeserializeOwned + Clone + 'static,
+    {
+        AuthMiddlewareChain {
+            first: self,
+            second: AuthMiddleware::new(auth_provider, path_matcher),
+        }
+    }
+
+    /// Initializes the [AuthenticationProvider] (see [AuthenticationProvider::init])
+    ///
+    /// The middleware is usually created for each worker inside of `HttpServer::new`, so `init` should be awaited
+    /// once before the server is started to fail fast on a misconfiguration:
+    /// ```ignore
+    /// AuthMiddleware::<_, User>::new(provider.clone(), PathMatcher::default()).init().await?;
+    ///
+    /// HttpServer::new(move || {
+    ///     App::new().wrap(AuthMiddleware::<_, User>::new(provider.clone(), PathMatcher::default()))
+    /// })
+    /// ```
+    pub fn init(&self) -> Pin<Box<dyn Future<Output = Result<(), InitError>>>> {
+        self.auth_provider.init()
+    }
+
+    /// The [PathMatcher] that decides which paths are secured
+    pub fn path_matcher(&self) -> &PathMatcher {
+        &self.path_matcher
+    }
+
+    /// Needed if the login routes are mounted below a prefix (see [login_config_with_prefix](crate::session::handlers::login_config_with_prefix))
+    pub fn with_route_prefix(self, prefix: &str) -> Self {
+        self.with_login_routes(&LoginRouteConfig::default().with_prefix(prefix))
+    }
+
+    /// Needed if the login routes are mounted at other paths (see [login_config_with_routes](crate::session::handlers::login_config_with_routes))
+    ///
+    /// `POST` to the login path is then reachable without authentication, even if the [PathMatcher] secures it.
+    /// The mfa and logout paths need the [AuthToken] of the login, so the [PathMatcher] has to secure them.
+    pub fn with_login_routes(mut self, routes: &LoginRouteConfig) -> Self {
+        self.login_route = Rc::new(routes.login_path.to_lowercase());
+        self.is_login_route_open = true;
+        self.mfa_route = Rc::new(routes.mfa_path.to_lowercase());
+        self
+    }
+
+    /// Prefixes all patterns of the [PathMatcher] with `scope`, if the middleware wraps a `Scope`
+    ///
+    /// The login routes are not changed, use [AuthMiddleware::with_route_prefix] if they are mounted in the scope as well.
+    /// ```ignore
+    /// web::scope("/api/v1").wrap(
+    ///     AuthMiddleware::<_, User>::new(provider, PathMatcher::new(vec!["/public/*"], true))
+    ///         .for_app_scope("/api/v1"),
+    /// )
+    /// ```
+    pub fn for_app_scope(mut self, scope: &str) -> Self {
+        self.path_matcher = Rc::new(self.path_matcher.as_ref().clone().with_prefix(scope));
+        self
+    }
+
+    /// Secured routes only accept signed requests (see [signature](crate::signature))
+    ///
+    /// The signature is verified before the authentication, requests without a valid signature get a `401 Unauthorized`.
+    /// ```ignore
+    /// AuthMiddleware::<_, User>::new(provider, PathMatcher::default())
+    ///     .with_signature_verifier(HmacSignatureVerifier::new(secret).with_max_age(Duration::from_secs(60)))
+    /// ```
+    pub fn with_signature_verifier(
+        mut self,
+        signature_verifier: impl RequestSignatureVerifier + 'static,
+    ) -> Self {
+        self.signature_verifier = Rc::new(Some(Rc::new(signature_verifier)));
+        self
+    }
+
+    /// Configures the response if the user is not authenticated, e.g. a redirect to the login page (see [UnauthorizedResponseBuilder])
+    pub fn with_unauthorized_response(mut self, response: UnauthorizedResponseBuilder) -> Self {
+        self.unauthorized_response = Some(Rc::new(response));
+        self
+    }
+
+    /// Reports logouts (every invalidated [AuthToken]) to the [AuditLogger]
+    ///
+    /// Logins and mfa checks are reported by [SessionLoginHandler::with_audit_logger](crate::session::handlers::SessionLoginHandler::with_audit_logger).
+    pub fn with_audit_logger(mut self, audit_logger: impl AuditLogger + 'static) -> Self {
+        self.audit_logger = Some(Rc::new(audit_logger));
+        self
+    }
+
+    /// Rejects requests from blocked locations with `403 Forbidden` before the authentication (see [geo](crate::geo))
+    ///
+    /// Applies to every request that passes the middleware, not only to secured paths.
+    pub fn with_geo_blocker(mut self, geo_blocker: impl GeoBlocker + 'static) -> Self {
+        self.geo_blocker = Some(Rc::new(geo_blocker));
+        self
+    }
+
+    /// Authenticates the user only when a handler extracts the [AuthToken], instead of before every request to a secured path
+    ///
+    /// Saves the call of the [AuthenticationProvider] (e.g. a database lookup) for handlers that do not need the user.
+    /// A secured path without [AuthToken] is then reachable without authentication, so use it only if every handler that
+    /// needs protection extracts the token. The mfa route and paths with role rules are always checked before the request.
+    pub fn lazy(mut self) -> Self {
+        self.lazy = true;
+        self
+    }
+
+    /// Authenticates every request to a secured path as `user` if the environment variable [PASSTHROUGH_ENV] is `1`
+    ///
+    /// Meant for local development without a login. The provider, mfa and role rules are skipped.
+    /// Only available with the `passthrough` feature, which cannot be compiled in release builds.
+    /// ```ignore
+    /// AuthMiddleware::<_, User>::new(SessionAuthProvider::default(), PathMatcher::default())
+    ///     .passthrough_user(User { name: "dev".to_owned() })
+    /// ```
+    #[cfg(feature = "passthrough")]
+    pub fn passthrough_user(mut self, user: U) -> Self {
+        self.passthrough_user = Some(Rc::new(user));
+        self
+    }
+
+    /// Creates a `Scope` at `path` with the routes added by `routes` and wraps only this scope with the middleware
+    ///
+    /// Routes outside of the scope are not affected, so different scopes can use different providers.
+    /// The patterns of the [PathMatcher] are relative to the scope (see [AuthMiddleware::for_app_scope]).
+    /// ```ignore
+    /// App::new()
+    ///     .service(
+    ///         AuthMiddleware::<_, User>::new(provider, PathMatcher::new(vec!["/public/*"], true))
+    ///             .wrap_scope("/api", |scope| scope.service(secured_route)),
+    ///     )
+    ///     .service(public_route)
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn wrap_scope<T, B>(
+        self,
+        path: &str,
+        routes: impl FnOnce(Scope) -> Scope<T>,
+    ) -> Scope<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<B>,
+            Error = Error,
+            InitError = (),
+        >,
+    >
+    where
+        T: ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<B>,
+            Error = Error,
+            InitError = (),
+        >,
+        T::Service: 'static,
+        B: MessageBody + 'static,
+        AuthProvider: Clone + 'static,
+    {
+        let middleware = self.for_app_scope(path);
+        routes(Scope::new(path)).wrap(middleware)
+    }
+
+    /// Injects the [SessionInvalidator] into every [AuthToken], so that it is called on [AuthToken::invalidate]
+    pub fn with_session_invalidator(
+        mut self,
+        session_invalidator: impl SessionInvalidator + 'static,
+    ) -> Self {
+        self.session_invalidator = Rc::new(Some(Rc::new(session_invalidator)));
+        self
+    }
+}
+
+/// Two middlewares that are applied after each other, created by [AuthMiddleware::for_user_type]
+#[derive(Clone)]
+pub struct AuthMiddlewareChain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> AuthMiddlewareChain<A, B> {
+    /// See [AuthMiddleware::for_user_type]
+    pub fn for_user_type<V, P>(
+        self,
+        auth_provider: P,
+        path_matcher: PathMatcher,
+    ) -> AuthMiddlewareChain<Self, AuthMiddleware<P, V>>
+    where
+        P: AuthenticationProvider<V>,
+        V: DeserializeOwned + Clone + 'static,
+    {
+        AuthMiddlewareChain {
+            first: self,
+            second: AuthMiddleware::new(auth_provider, path_matcher),
+        }
+    }
+}
+
+impl<S, A, B> Transform<S, ServiceRequest> for AuthMiddlewareChain<A, B>
+where
+    B: Transform<S, ServiceRequest, InitError = ()>,
+    B::Future: 'static,
+    A: Transform<
+            B::Transform,
+            ServiceRequest,
+            Response = B::Response,
+            Error = B::Error,
+            InitError = (),
+        > + Clone
+        + 'static,
+{
+    type Response = A::Response;
+    type Error = A::Error;
+    type InitError = ();
+    type Transform = A::Transform;
+    type Future = LocalBoxFuture<'static, Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let first = self.first.clone();
+        let second = self.second.new_transform(service);
+
+        Box::pin(async move {
+            let inner = second.await?;
+            first.new_transform(inner).await
+        })
+    }
 }
 
 pub struct AuthMiddlewareInner<S, AuthProvider, U>
@@ -145,6 +1197,18 @@ where
     auth_provider: Rc<AuthProvider>,
     path_matcher: Rc<PathMatcher>,
     factor: Rc<Option<Box<dyn Factor>>>,
+    // Some if the login route is open (see AuthMiddleware::with_login_routes)
+    login_route: Option<Rc<String>>,
+    mfa_route: Rc<String>,
+    session_invalidator: Rc<Option<Rc<dyn SessionInvalidator>>>,
+    signature_verifier: Rc<Option<Rc<dyn RequestSignatureVerifier>>>,
+    unauthorized_response: Option<Rc<UnauthorizedResponseBuilder>>,
+    lazy: bool,
+    audit_logger: Option<Rc<dyn AuditLogger>>,
+    geo_blocker: Option<Rc<dyn GeoBlocker>>,
+    #[cfg(feature = "passthrough")]
+    passthrough_user: Option<Rc<U>>,
+    role_check: Option<fn(&RolePathRule, &U) -> bool>,
     user_type: PhantomData<U>,
 }
 
@@ -169,55 +1233,182 @@ where
         let service = Rc::clone(&self.service);
         let auth_provider = Rc::clone(&self.auth_provider);
         let factor = Rc::clone(&self.factor);
+        let mfa_route = Rc::clone(&self.mfa_route);
+        let session_invalidator = Rc::clone(&self.session_invalidator);
+        let signature_verifier = Rc::clone(&self.signature_verifier);
+        let unauthorized_response = self.unauthorized_response.clone();
+        let audit_logger = self.audit_logger.clone();
 
         {
             // ToDo: Just a quick fix. Dont use an extra scope
             let mut extensions = req.extensions_mut();
-            extensions.insert(factor);
+            // in a chain (see AuthMiddleware::for_user_type) a middleware without factor must not remove the factor of another one
+            if factor.is_some() || !extensions.contains::<Rc<Option<Box<dyn Factor>>>>() {
+                extensions.insert(factor);
+            }
         }
+        auth_provider.configure_request(req.request());
 
-        if self.path_matcher.matches(&request_path) {
+        if let (Some(geo_blocker), Some(peer_addr)) = (&self.geo_blocker, req.peer_addr()) {
+            if geo_blocker.is_blocked(peer_addr.ip()) {
+                debug!(
+                    "Request from blocked IP {} to '{}'",
+                    peer_addr.ip(),
+                    debug_path
+                );
+                return Box::pin(async move {
+                    Err(ForbiddenError::new("Access from this location is not allowed").into())
+                });
+            }
+        }
+
+        #[cfg(feature = "time_restriction")]
+        if let Err(e) = self
+            .path_matcher
+            .check_time_restriction(&request_path, Utc::now())
+        {
+            debug!("Route '{}' is not available at this time", debug_path);
+            return Box::pin(async move { Err(e.into()) });
+        }
+
+        // users have to reach the login route to get authenticated
+        let is_login_route = req.method() == Method::POST
+            && self
+                .login_route
+                .as_ref()
+                .is_some_and(|login_route| request_path.to_lowercase() == **login_route);
+
+        if !is_login_route
+            && self
+                .path_matcher
+                .matches_request(&request_path, req.method())
+        {
             debug!("Secured route: '{}'", debug_path);
+            #[cfg(feature = "passthrough")]
+            if let Some(user) = &self.passthrough_user {
+                let token = AuthToken::new(
+                    user.as_ref().clone(),
+                    AuthState::Authenticated,
+                    SystemTime::now(),
+                );
+                token.set_provider_if_unset("passthrough");
+                let loaded = AuthToken::from_ref(&token);
+                req.extensions_mut().insert(token);
+
+                return Box::pin(async move {
+                    let res = service.call(req).await;
+                    match &res {
+                        Ok(res) => {
+                            res.request().extensions_mut().remove::<AuthToken<U>>();
+                        }
+                        Err(_) => remove_from_request(&loaded),
+                    }
+                    res
+                });
+            }
+            let role_rules = self.path_matcher.role_rules_for(&request_path);
+            let requested_path = req
+                .uri()
+                .path_and_query()
+                .map_or_else(|| request_path.clone(), |path| path.as_str().to_owned());
+            let role_check = self.role_check;
+            let lazy =
+                self.lazy && role_rules.is_empty() && request_path.to_lowercase() != *mfa_route;
 
             Box::pin(async move {
+                let mut req = req;
+                if let Some(signature_verifier) = signature_verifier.as_ref() {
+                    verify_request(&mut req, signature_verifier.as_ref()).await?;
+                }
+
                 // Before Request
-                match auth_provider.get_auth_token(req.request()).await {
-                    Ok(token) => {
-                        // ToDo: currently hardcoded: needs to be configurable
-                        if request_path.to_lowercase() == MFA_ROUTE {
-                            if !token.needs_mfa() {
-                                return Err(ErrorBadRequest("No mfa needed"));
+                let loaded = Rc::new(RefCell::new(None));
+                if lazy {
+                    req.extensions_mut().insert(LazyAuthLoader::<U> {
+                        provider: Rc::clone(&auth_provider) as Rc<dyn AuthenticationProvider<U>>,
+                        session_invalidator: session_invalidator.as_ref().clone(),
+                        unauthorized_response: unauthorized_response.clone(),
+                        requested_path: requested_path.clone(),
+                        loaded: Rc::clone(&loaded),
+                    });
+                } else {
+                    match auth_provider.get_auth_token(req.request()).await {
+                        Ok(token) => {
+                            token.set_provider_if_unset(auth_provider.name());
+                            let refresh_provider = Rc::clone(&auth_provider);
+                            token.inject_refresher(Rc::new(move |req| {
+                                refresh_provider.refresh_user(req)
+                            }));
+                            if request_path.to_lowercase() == *mfa_route {
+                                if !token.needs_mfa() {
+                                    return Err(ErrorBadRequest("No mfa needed"));
+                                }
+                            } else if !token.is_authenticated() {
+                                return Err(UnauthorizedError::default()
+                                    .with_requested_path(&requested_path)
+                                    .with_response(unauthorized_response.clone())
+                                    .into());
+                            } else if !role_rules.iter().all(|rule| {
+                                role_check.is_some_and(|check| {
+                                    check(rule, &token.get_authenticated_user())
+                                })
+                            }) {
+                                debug!("User has not the roles for: '{}'", debug_path);
+                                return Err(
+                                    ForbiddenError::new("Missing role or permission").into()
+                                );
                             }
-                        } else if !token.is_authenticated() {
-                            return Err(UnauthorizedError::default().into());
-                        }
 
-                        let mut extensions = req.extensions_mut();
-                        extensions.insert(token);
-                        // is it really needed on each secured route? or only on /mfa and /login?
-                    }
-                    Err(_) => {
-                        debug!("No authenticated user found");
-                        return Err(UnauthorizedError::default().into());
+                            if let Some(session_invalidator) = session_invalidator.as_ref() {
+                                token.inject_invalidator(Rc::clone(session_invalidator));
+                            }
+
+                            loaded.replace(Some(AuthToken::from_ref(&token)));
+                            let mut extensions = req.extensions_mut();
+                            extensions.insert(token);
+                            // is it really needed on each secured route? or only on /mfa and /login?
+                        }
+                        Err(e) => {
+                            debug!("No authenticated user found");
+                            return Err(e
+                                .hide_message()
+                                .with_requested_path(&requested_path)
+                                .with_response(unauthorized_response.clone())
+                                .into());
+                        }
                     }
                 }
 
-                let res = service.call(req).await?;
-
-                // After Request:
-                let token_valid = {
-                    let extensions = res.request().extensions();
-                    if let Some(token) = extensions.get::<AuthToken<U>>() {
-                        token.is_valid()
-                    } else {
-                        // If there is no AuthToken, authentication is no longer valid
-                        false
+                let res = match service.call(req).await {
+                    Ok(res) => res,
+                    Err(e) => {
+                        if let Some(token) = loaded.take() {
+                            remove_from_request(&token);
+                        }
+                        return Err(e);
                     }
                 };
 
+                // After Request:
+                // The token holds the request, so it has to be removed from its extensions to avoid a reference cycle
+                let token = res.request().extensions_mut().remove::<AuthToken<U>>();
+                if lazy && token.is_none() {
+                    // the handler has not extracted the AuthToken, so the user has never been loaded
+                    return Ok(res);
+                }
+                // If there is no AuthToken, authentication is no longer valid
+                let token_valid = token.as_ref().is_some_and(|token| token.is_valid());
+
+                if let Some(token) = token.filter(|token| token_valid && token.is_touched()) {
+                    auth_provider.persist_token(&token, res.request()).await;
+                }
+
                 if !token_valid {
                     debug!("AuthToken no longer valid (maybe logged out). Invalidate Authentication. (Triggered by: {})", debug_path);
                     let req = res.request().clone();
+                    if let Some(audit_logger) = &audit_logger {
+                        audit_logger.on_logout(&req).await;
+                    }
                     auth_provider.invalidate(req).await;
                 }
 
@@ -225,11 +1416,23 @@ where
             })
         } else {
             trace!("Route is not secured: {}", debug_path);
+            req.extensions_mut()
+                .insert(OptionalAuthLoader::<U>(auth_provider));
             Box::pin(async move { service.call(req).await })
         }
     }
 }
 
+/// Breaks the cycle between the token and its request (see [AuthMiddlewareInner::call]) if the service has failed
+fn remove_from_request<U>(token: &AuthToken<U>)
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    if let Some(req) = token.detach_request() {
+        req.extensions_mut().remove::<AuthToken<U>>();
+    }
+}
+
 impl<S, B, AuthProvider, U> Transform<S, ServiceRequest> for AuthMiddleware<AuthProvider, U>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
@@ -245,11 +1448,47 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
+        // The mfa route has to be secured, because the middleware checks that mfa is pending
+        if !self.is_login_route_open
+            && self
+                .path_matcher
+                .matches_request(&self.login_route, &Method::POST)
+        {
+            warn!(
+                "PathMatcher secures the login route '{}', users will not be able to log in",
+                self.login_route
+            );
+        }
+
+        for warning in self.path_matcher.insecure_configuration_warnings() {
+            warn!("Insecure PathMatcher configuration: {warning}");
+        }
+
+        if self.role_check.is_none() && !self.path_matcher.role_rules.is_empty() {
+            warn!("PathMatcher has role rules, but the middleware has not been created with AuthMiddleware::new_with_roles. Paths with role rules are always forbidden");
+        }
+
         ready(Ok(AuthMiddlewareInner {
             service: Rc::new(service),
             path_matcher: Rc::clone(&self.path_matcher),
             factor: Rc::clone(&self.additional_factor),
+            login_route: self
+                .is_login_route_open
+                .then(|| Rc::clone(&self.login_route)),
+            mfa_route: Rc::clone(&self.mfa_route),
             auth_provider: Rc::clone(&self.auth_provider),
+            session_invalidator: Rc::clone(&self.session_invalidator),
+            signature_verifier: Rc::clone(&self.signature_verifier),
+            unauthorized_response: self.unauthorized_response.clone(),
+            lazy: self.lazy,
+            audit_logger: self.audit_logger.clone(),
+            geo_blocker: self.geo_blocker.clone(),
+            #[cfg(feature = "passthrough")]
+            passthrough_user: self
+                .passthrough_user
+                .clone()
+                .filter(|_| is_passthrough_enabled()),
+            role_check: self.role_check,
             user_type: PhantomData,
         }))
     }
@@ -257,7 +1496,22 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::PathMatcher;
+    use std::{cell::RefCell, future::ready, future::Future, pin::Pin, time::SystemTime};
+
+    use crate::{
+        session::session_auth::SessionAuthProvider, AuthState, AuthToken, AuthenticationProvider,
+        UnauthorizedError,
+    };
+
+    use actix_web::{
+        dev::{Service, ServiceResponse},
+        error::ErrorInternalServerError,
+        http::Method,
+        test::{init_service, TestRequest},
+        web, App, HttpRequest, HttpResponse,
+    };
+
+    use super::{AuthMiddleware, Environment, PathMatcher, PathMethodRule, RolePathRule};
 
     #[test]
     fn path_matcher_should_match_wildcard() {
@@ -282,4 +1536,427 @@ mod tests {
         // As long as there is no wildcard, only the exact string should be matched
         assert!(matcher.matches("/login/something"))
     }
+
+    #[test]
+    fn path_matcher_should_match_extended_patterns() {
+        let mut matcher = PathMatcher::new(vec!["/api/*"], false);
+        matcher.extend([("/admin/*", false), ("/api/public", true)]);
+
+        assert!(matcher.matches("/admin/users"));
+        assert!(matcher.matches("/api/users"));
+        assert!(!matcher.matches("/api/public"));
+        assert!(!matcher.matches("/other"));
+    }
+
+    #[test]
+    fn path_matcher_should_iterate_over_patterns() {
+        let mut matcher = PathMatcher::default();
+        matcher.extend([("/api/*", false)]);
+
+        let patterns: Vec<(&str, bool)> = (&matcher).into_iter().collect();
+
+        assert_eq!(
+            patterns,
+            vec![("/login", true), ("/register", true), ("/api/*", false)]
+        );
+    }
+
+    #[test]
+    fn glob_path_matcher_should_match_at_any_depth() {
+        let matcher = PathMatcher::with_glob(vec!["/static/**"], true);
+
+        assert!(!matcher.matches("/static/js/app.js"));
+        assert!(!matcher.matches("/static/app.css"));
+        assert!(matcher.matches("/api/users"));
+    }
+
+    #[test]
+    fn glob_wildcard_should_not_match_across_segments() {
+        let matcher = PathMatcher::with_glob(vec!["/api/*/edit"], false);
+
+        assert!(matcher.matches("/api/231/edit"));
+        assert!(!matcher.matches("/api/users/231/edit"));
+    }
+
+    #[test]
+    fn strict_path_matcher_should_only_secure_the_exact_paths() {
+        let matcher = PathMatcher::strict(vec!["/userinfo", "/api/*"]);
+
+        assert!(matcher.matches("/userinfo"));
+        assert!(matcher.matches("/api/*"));
+        assert!(!matcher.matches("/userinfo/"));
+        assert!(!matcher.matches("/api/orders"));
+        assert!(!matcher.matches("/login"));
+    }
+
+    #[test]
+    fn extended_strict_path_matcher_should_stay_exact() {
+        let mut matcher = PathMatcher::strict(vec!["/userinfo"]);
+        matcher.extend([("/api/orders*", false)]);
+
+        assert!(matcher.matches("/api/orders*"));
+        assert!(!matcher.matches("/api/orders/1"));
+        assert!(!matcher
+            .clone()
+            .with_prefix("/v1")
+            .matches("/v1/api/orders/1"));
+        assert!(matcher.with_prefix("/v1").matches("/v1/userinfo"));
+    }
+
+    #[test]
+    fn builder_should_accept_owned_and_borrowed_patterns() {
+        let public = String::from("/public/*");
+        let matcher = PathMatcher::builder(true)
+            .push("/login")
+            .push(public)
+            .push(format!("/assets/{}", "*"))
+            .build();
+
+        assert!(!matcher.matches("/login"));
+        assert!(!matcher.matches("/public/index.html"));
+        assert!(!matcher.matches("/assets/app.js"));
+        assert!(matcher.matches("/secured"));
+    }
+
+    #[test]
+    fn new_should_accept_borrowed_patterns() {
+        let paths = [String::from("/api/*")];
+        let matcher = PathMatcher::new(paths.iter().map(String::as_str), false);
+
+        assert!(matcher.matches("/api/users"));
+        assert!(!matcher.matches("/other"));
+    }
+
+    #[test]
+    fn new_should_accept_owned_patterns() {
+        let paths: Vec<String> = vec!["/api/*".to_owned(), "/admin".to_owned()];
+        let matcher = PathMatcher::new(paths, false);
+
+        assert!(matcher.matches("/api/users"));
+        assert!(matcher.matches("/admin"));
+        assert!(!matcher.matches("/other"));
+    }
+
+    #[test]
+    fn environment_paths_should_only_apply_in_their_environment() {
+        let matcher = |environment| {
+            PathMatcher::for_env(environment)
+                .public_in(Environment::Development, vec!["/debug/*"])
+                .public_in(Environment::Staging, vec!["/swagger/*"])
+        };
+
+        let development = matcher(Environment::Development);
+        assert!(!development.matches("/debug/requests"));
+        assert!(development.matches("/swagger/index.html"));
+
+        let staging = matcher(Environment::Staging);
+        assert!(staging.matches("/debug/requests"));
+        assert!(!staging.matches("/swagger/index.html"));
+
+        let production = matcher(Environment::Production);
+        assert!(production.matches("/debug/requests"));
+        assert!(production.matches("/swagger/index.html"));
+        assert!(!production.matches("/login"));
+    }
+
+    #[test]
+    fn merged_path_matcher_should_prefer_exclusions() {
+        let admin = PathMatcher::new(vec!["/admin/*"], false);
+        let public = PathMatcher::new(vec!["/admin/login"], true);
+
+        let matcher = admin | public;
+
+        assert!(matcher.matches("/admin/users"));
+        assert!(!matcher.matches("/admin/login"));
+        // secured by default, because one of the matchers was an exclusion list
+        assert!(matcher.matches("/other"));
+    }
+
+    #[test]
+    fn auth_middleware_should_expose_path_matcher() {
+        let middleware = AuthMiddleware::<_, String>::new(
+            SessionAuthProvider::default(),
+            PathMatcher::new(vec!["/login"], true),
+        );
+
+        assert!(!middleware.path_matcher().matches("/login"));
+        assert!(middleware.path_matcher().matches("/secured"));
+    }
+
+    #[test]
+    fn for_app_scope_should_prefix_all_patterns() {
+        let middleware = AuthMiddleware::<_, String>::new(
+            SessionAuthProvider::default(),
+            PathMatcher::new(vec!["/public/*"], true)
+                | PathMatcher::with_glob(vec!["/docs/**"], true),
+        )
+        .for_app_scope("/api/v1/");
+        let matcher = middleware.path_matcher();
+
+        assert!(!matcher.matches("/api/v1/public/info"));
+        assert!(!matcher.matches("/api/v1/docs/guide/intro"));
+        assert!(matcher.matches("/public/info"));
+        assert!(matcher.matches("/api/v1/users"));
+    }
+
+    #[test]
+    fn path_matcher_should_detect_insecure_configurations() {
+        assert!(PathMatcher::default()
+            .insecure_configuration_warnings()
+            .is_empty());
+        assert_eq!(
+            PathMatcher::new(vec!["/*"], true).insecure_configuration_warnings(),
+            vec!["All paths are configured as public (pattern '/*')"]
+        );
+        assert_eq!(
+            PathMatcher::new(vec!["/"], true).insecure_configuration_warnings(),
+            vec!["The root path '/' is configured as public"]
+        );
+        assert_eq!(
+            PathMatcher::new(Vec::<&str>::new(), false).insecure_configuration_warnings(),
+            vec!["No path is secured, the list of secured paths is empty"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn init_should_delegate_to_the_provider() {
+        let middleware = AuthMiddleware::<_, String>::new(
+            SessionAuthProvider::default(),
+            PathMatcher::default(),
+        );
+
+        assert!(middleware.init().await.is_ok());
+    }
+
+    #[test]
+    fn method_rules_should_only_apply_to_their_methods() {
+        let matcher = PathMatcher::with_method_rules(
+            vec![PathMethodRule::new("/api/items", vec![Method::GET])],
+            true,
+        );
+
+        assert!(!matcher.matches_request("/api/items", &Method::GET));
+        assert!(matcher.matches_request("/api/items", &Method::POST));
+        assert!(matcher.matches_request("/api/items", &Method::DELETE));
+        assert!(matcher.matches_request("/api/other", &Method::GET));
+    }
+
+    #[test]
+    fn method_rules_should_be_checked_before_path_patterns() {
+        let matcher = PathMatcher::default()
+            | PathMatcher::with_method_rules(
+                vec![PathMethodRule::new("*", vec![Method::OPTIONS])],
+                true,
+            );
+
+        assert!(!matcher.matches_request("/api/items", &Method::OPTIONS));
+        assert!(matcher.matches_request("/api/items", &Method::GET));
+        assert!(!matcher.matches_request("/login", &Method::POST));
+    }
+
+    #[test]
+    fn paths_with_role_rules_should_always_be_secured() {
+        let matcher = PathMatcher::new(vec!["/admin/*"], true)
+            .with_role_rules(vec![RolePathRule::new("/admin/users", &["admin"])]);
+
+        assert!(matcher.matches("/admin/users"));
+        assert!(!matcher.matches("/admin/public"));
+    }
+
+    #[test]
+    fn method_exclusions_should_not_open_paths_with_role_rules() {
+        let matcher = PathMatcher::with_method_rules(
+            vec![PathMethodRule::new("/admin/*", vec![Method::GET])],
+            true,
+        )
+        .with_role_rules(vec![RolePathRule::new("/admin/users", &["admin"])]);
+
+        assert!(matcher.matches_request("/admin/users", &Method::GET));
+        assert!(!matcher.matches_request("/admin/public", &Method::GET));
+    }
+
+    #[test]
+    fn cached_path_matcher_should_see_extended_patterns() {
+        let mut matcher = PathMatcher::new(vec!["/login"], true).with_cache(10);
+        assert!(matcher.matches("/public"));
+        assert!(matcher.matches("/public"));
+
+        matcher.extend([("/public", true)]);
+
+        assert!(!matcher.matches("/public"));
+    }
+
+    #[cfg(feature = "time_restriction")]
+    #[test]
+    fn time_restricted_paths_should_only_be_available_within_the_window() {
+        use chrono::{NaiveTime, TimeZone, Utc};
+
+        let matcher = PathMatcher::default()
+            .restricted_to_hours(
+                "/payouts/*",
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+                chrono_tz::Europe::Berlin,
+            )
+            .restricted_to_hours(
+                "/batch/*",
+                NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(2, 0, 0).unwrap(),
+                chrono_tz::UTC,
+            );
+
+        // 10:00 in Berlin (UTC+2 in summer)
+        let morning = Utc.with_ymd_and_hms(2025, 7, 1, 8, 0, 0).unwrap();
+        // 18:00 in Berlin
+        let evening = Utc.with_ymd_and_hms(2025, 7, 1, 16, 0, 0).unwrap();
+        let midnight = Utc.with_ymd_and_hms(2025, 7, 1, 0, 30, 0).unwrap();
+
+        assert!(matcher
+            .check_time_restriction("/payouts/1", morning)
+            .is_ok());
+        assert_eq!(
+            matcher
+                .check_time_restriction("/payouts/1", evening)
+                .unwrap_err()
+                .to_string(),
+            "This endpoint is only available between 09:00:00 and 17:00:00."
+        );
+        assert!(matcher.check_time_restriction("/other", evening).is_ok());
+        assert!(matcher
+            .check_time_restriction("/batch/run", midnight)
+            .is_ok());
+        assert!(matcher
+            .check_time_restriction("/batch/run", morning)
+            .is_err());
+    }
+
+    #[cfg(feature = "clap")]
+    #[test]
+    fn path_matcher_should_be_created_from_command_line_arguments() {
+        use clap::{Args, Command, FromArgMatches};
+
+        use super::PathMatcherArgs;
+
+        let parse = |args: &[&str]| {
+            let matches = PathMatcherArgs::augment_args(Command::new("server"))
+                .try_get_matches_from(args)
+                .unwrap();
+            PathMatcher::from_args(&PathMatcherArgs::from_arg_matches(&matches).unwrap())
+        };
+
+        let secure_only = parse(&[
+            "server",
+            "--secure-path",
+            "/admin/*",
+            "--secure-path",
+            "/me",
+        ]);
+        assert!(secure_only.matches("/admin/users"));
+        assert!(secure_only.matches("/me"));
+        assert!(!secure_only.matches("/"));
+
+        let public = parse(&["server", "--public-path", "/health", "--secure-path", "/me"]);
+        assert!(!public.matches("/health"));
+        assert!(public.matches("/me"));
+        assert!(public.matches("/other"));
+
+        let default = parse(&["server"]);
+        assert!(!default.matches("/login"));
+        assert!(default.matches("/other"));
+    }
+
+    #[test]
+    fn exclusions_should_whitelist_paths_of_a_catch_all_glob() {
+        let matcher = PathMatcher::with_glob(vec!["/**"], false).with_exclusions(vec![
+            "/health".to_owned(),
+            "/public/*".to_owned(),
+            "/login".to_owned(),
+        ]);
+
+        assert!(!matcher.matches("/health"));
+        assert!(!matcher.matches("/login"));
+        assert!(!matcher.matches("/public/logo.png"));
+        assert!(matcher.matches("/public/images/logo.png"));
+        assert!(matcher.matches("/health/details"));
+        assert!(matcher.matches("/api/users/1"));
+        assert!(matcher.matches("/"));
+    }
+
+    #[derive(Clone)]
+    struct AnnaProvider;
+
+    impl AuthenticationProvider<String> for AnnaProvider {
+        fn get_auth_token(
+            &self,
+            _req: &HttpRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<AuthToken<String>, UnauthorizedError>>>> {
+            let token = AuthToken::new(
+                "anna".to_owned(),
+                AuthState::Authenticated,
+                SystemTime::now(),
+            );
+            Box::pin(ready(Ok(token)))
+        }
+
+        fn invalidate(&self, _req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+            Box::pin(async {})
+        }
+    }
+
+    thread_local! {
+        static EXTRACTED_TOKEN: RefCell<Option<AuthToken<String>>> = const { RefCell::new(None) };
+    }
+
+    async fn token_of_failed_request(
+        middleware: AuthMiddleware<AnnaProvider, String>,
+    ) -> AuthToken<String> {
+        let app = init_service(
+            App::new()
+                .route(
+                    "/secured",
+                    web::get().to(|token: AuthToken<String>| async move {
+                        EXTRACTED_TOKEN.with(|extracted| extracted.replace(Some(token)));
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .wrap_fn(|req, srv| {
+                    let res = srv.call(req);
+                    async move {
+                        res.await.and_then(|_| {
+                            Err::<ServiceResponse, _>(ErrorInternalServerError(
+                                "failed after the handler",
+                            ))
+                        })
+                    }
+                })
+                .wrap(middleware),
+        )
+        .await;
+
+        let res = app
+            .call(TestRequest::get().uri("/secured").to_request())
+            .await;
+
+        assert!(res.is_err());
+        EXTRACTED_TOKEN.with(|extracted| extracted.take()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn token_should_be_removed_from_the_request_if_the_service_fails() {
+        let token =
+            token_of_failed_request(AuthMiddleware::new(AnnaProvider, PathMatcher::default()))
+                .await;
+
+        assert!(token.detach_request().is_none());
+    }
+
+    #[actix_rt::test]
+    async fn lazy_token_should_be_removed_from_the_request_if_the_service_fails() {
+        let token = token_of_failed_request(
+            AuthMiddleware::new(AnnaProvider, PathMatcher::default()).lazy(),
+        )
+        .await;
+
+        assert!(token.detach_request().is_none());
+    }
 }
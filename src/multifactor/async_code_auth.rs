@@ -0,0 +1,128 @@
+use std::{future::Future, pin::Pin, time::Duration};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use log::{debug, warn};
+
+use super::{
+    random_code_auth::{
+        check_random_code, store_mfa_user, store_random_code, Charset, RandomCode, RandomCodeConfig,
+    },
+    CheckCodeError, Factor, FactorContext, GenerateCodeError,
+};
+
+/// Interface for sending the code to the user without blocking (e.g. with an async HTTP client for an SMS gateway)
+///
+/// The asynchronous counterpart of [CodeSender](super::random_code_auth::CodeSender).
+pub trait AsyncCodeSender {
+    type Error: std::error::Error + 'static;
+    fn send_code(
+        &self,
+        random_code: RandomCode,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Self::Error>>>>;
+    /// See [Factor::estimated_delivery_time]
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Random code implementation of [Factor] that sends the code with an [AsyncCodeSender]
+///
+/// Works like [MfaRandomCode](super::random_code_auth::MfaRandomCode), but [Factor::generate_code] is synchronous,
+/// so the code is saved in the session and then sent in the background on the Actix runtime.
+/// The login response does not wait for the delivery and a failed delivery is only logged.
+///
+/// # Examples
+/// ```ignore
+/// MfaRandomCodeAsync::new(|| RandomCode::generate_secure(6, Charset::Numeric), TwilioSender::new(config))
+/// ```
+pub struct MfaRandomCodeAsync<T: AsyncCodeSender> {
+    code_generator: Box<dyn Fn() -> RandomCode>,
+    code_sender: T,
+    case_sensitive: bool,
+    config: RandomCodeConfig,
+}
+
+impl<T: AsyncCodeSender> MfaRandomCodeAsync<T> {
+    pub fn new(code_generator: fn() -> RandomCode, code_sender: T) -> Self {
+        Self {
+            code_generator: Box::new(code_generator),
+            code_sender,
+            case_sensitive: false,
+            config: RandomCodeConfig::default(),
+        }
+    }
+
+    /// Uses [RandomCode::generate_secure] as code generator
+    pub fn with_secure_generator(
+        charset: Charset,
+        length: usize,
+        valid_for: Duration,
+        code_sender: T,
+    ) -> Self {
+        Self {
+            code_generator: Box::new(move || {
+                RandomCode::generate_secure_valid_for(length, charset, valid_for)
+            }),
+            code_sender,
+            case_sensitive: false,
+            config: RandomCodeConfig::default(),
+        }
+    }
+
+    /// Whether the code must be entered with the exact case (default: `false`)
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Replaces the default [RandomCodeConfig]
+    pub fn with_config(mut self, config: RandomCodeConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl<T: AsyncCodeSender> Factor for MfaRandomCodeAsync<T> {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        store_mfa_user(ctx.session, ctx.user_id)?;
+        if self.config.is_locked(ctx.user_id) {
+            warn!("Too many wrong codes of this user, no code is sent");
+            return Ok(());
+        }
+
+        let random_code = (self.code_generator)();
+        store_random_code(ctx.session, &random_code)?;
+
+        let sending = self.code_sender.send_code(random_code);
+        actix_web::rt::spawn(async move {
+            match sending.await {
+                Ok(()) => debug!("Code sent to user"),
+                Err(e) => warn!("Could not send code to user: {e}"),
+            }
+        });
+
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        "RNDCODE".to_owned()
+    }
+
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        self.code_sender.estimated_delivery_time()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        check_random_code(
+            req.get_session(),
+            code.to_owned(),
+            self.case_sensitive,
+            self.config.clone(),
+        )
+    }
+}
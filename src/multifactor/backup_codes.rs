@@ -0,0 +1,243 @@
+use std::{error::Error as StdError, future::Future, pin::Pin, sync::Arc};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use log::warn;
+use rand::{rngs::OsRng, Rng, TryRngCore};
+use sha2::{Digest, Sha256};
+
+use super::{CheckCodeError, Factor, FactorContext, GenerateCodeError};
+
+const MFA_BACKUP_CODES_USER_KEY: &str = "mfa_backup_codes_user";
+const CODE_LENGTH: usize = 10;
+// without 0/O and 1/I, so that printed codes can be typed in without confusion
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Stores the SHA-256 hashes of the backup codes of each user
+pub trait BackupCodeStore {
+    type Error: StdError + 'static;
+    /// Replaces all codes of the user
+    fn save_hashes(&self, user_id: &str, hashes: Vec<String>) -> Result<(), Self::Error>;
+    /// Hashes of the codes that have not been used yet
+    fn unused_hashes(&self, user_id: &str) -> Result<Vec<String>, Self::Error>;
+    fn mark_used(&self, user_id: &str, hash: &str) -> Result<(), Self::Error>;
+}
+
+/// The new backup codes in plain text, they are only stored as hashes
+///
+/// Show them to the user once (e.g. to print them), they cannot be restored afterwards.
+pub struct GenerateCodeResult(pub Vec<String>);
+
+/// Backup codes as [Factor] for users who have lost access to their primary factor
+///
+/// Every code can only be used once. The codes are created with [MfaBackupCodes::generate_codes]
+/// (e.g. when the user sets up mfa), so [Factor::generate_code] does not send anything.
+/// Codes are accepted in lower case and with `-` or spaces (e.g. `ABCDE-FGHJK`).
+///
+/// # Examples
+/// ```ignore
+/// let backup_codes = MfaBackupCodes::new(your_store);
+/// let GenerateCodeResult(codes) = backup_codes.generate_codes(&user.email)?;
+///
+/// AuthMiddleware::<_, User>::new_with_factor(
+///     SessionAuthProvider::default(),
+///     PathMatcher::default(),
+///     Box::new(FallbackChain::new(vec![Box::new(totp), Box::new(backup_codes)])),
+/// )
+/// ```
+pub struct MfaBackupCodes<S: BackupCodeStore> {
+    store: Arc<S>,
+    number_of_codes: usize,
+}
+
+impl<S: BackupCodeStore> MfaBackupCodes<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+            number_of_codes: 10,
+        }
+    }
+
+    /// Number of codes created by [MfaBackupCodes::generate_codes] (default: `10`)
+    pub fn number_of_codes(mut self, number_of_codes: usize) -> Self {
+        self.number_of_codes = number_of_codes;
+        self
+    }
+
+    /// Creates new codes for the user, the old ones are no longer valid
+    pub fn generate_codes(&self, user_id: &str) -> Result<GenerateCodeResult, GenerateCodeError> {
+        let mut rng = OsRng.unwrap_err();
+        let codes: Vec<String> = (0..self.number_of_codes)
+            .map(|_| {
+                (0..CODE_LENGTH)
+                    .map(|_| CODE_CHARS[rng.random_range(0..CODE_CHARS.len())] as char)
+                    .collect()
+            })
+            .collect();
+
+        self.store
+            .save_hashes(user_id, codes.iter().map(|code| hash(code)).collect())
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot save backup codes", e))?;
+
+        Ok(GenerateCodeResult(codes))
+    }
+}
+
+impl<S: BackupCodeStore + 'static> Factor for MfaBackupCodes<S> {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        // the user already has the codes, only the user is needed for the check
+        ctx.session
+            .insert(MFA_BACKUP_CODES_USER_KEY, ctx.user_id)
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot store backup code user", e))
+    }
+
+    fn get_unique_id(&self) -> String {
+        "BACKUP_CODES".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let session = req.get_session();
+        let store = Arc::clone(&self.store);
+        let code = normalize(code);
+
+        Box::pin(async move {
+            let user_id = session
+                .get::<String>(MFA_BACKUP_CODES_USER_KEY)
+                .ok()
+                .flatten()
+                .ok_or_else(|| {
+                    CheckCodeError::UnknownError("No backup code user in session".to_owned())
+                })?;
+            let Some(code) = code else {
+                return Err(CheckCodeError::InvalidCode);
+            };
+
+            let hash = hash(&code);
+            let unused_hashes = store.unused_hashes(&user_id).map_err(unknown_error)?;
+            if !unused_hashes.contains(&hash) {
+                warn!("Backup code of '{user_id}' is unknown or has already been used");
+                return Err(CheckCodeError::InvalidCode);
+            }
+
+            store.mark_used(&user_id, &hash).map_err(unknown_error)
+        })
+    }
+}
+
+/// Upper case without separators, `None` if it cannot be a backup code
+fn normalize(code: &str) -> Option<String> {
+    let code: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    (code.len() == CODE_LENGTH && code.bytes().all(|c| CODE_CHARS.contains(&c))).then_some(code)
+}
+
+fn hash(code: &str) -> String {
+    Sha256::digest(code.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn unknown_error(e: impl StdError) -> CheckCodeError {
+    CheckCodeError::UnknownError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, convert::Infallible, rc::Rc};
+
+    use actix_session::SessionExt;
+    use actix_web::{test::TestRequest, HttpRequest};
+
+    use super::{normalize, BackupCodeStore, GenerateCodeResult, MfaBackupCodes};
+    use crate::multifactor::{Factor, FactorContext};
+
+    // only the unused hashes
+    #[derive(Clone, Default)]
+    struct InMemoryStore {
+        hashes: Rc<RefCell<HashMap<String, Vec<String>>>>,
+    }
+
+    impl BackupCodeStore for InMemoryStore {
+        type Error = Infallible;
+
+        fn save_hashes(&self, user_id: &str, hashes: Vec<String>) -> Result<(), Self::Error> {
+            self.hashes.borrow_mut().insert(user_id.to_owned(), hashes);
+            Ok(())
+        }
+
+        fn unused_hashes(&self, user_id: &str) -> Result<Vec<String>, Self::Error> {
+            Ok(self
+                .hashes
+                .borrow()
+                .get(user_id)
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        fn mark_used(&self, user_id: &str, hash: &str) -> Result<(), Self::Error> {
+            if let Some(hashes) = self.hashes.borrow_mut().get_mut(user_id) {
+                hashes.retain(|stored| stored != hash);
+            }
+            Ok(())
+        }
+    }
+
+    fn login(backup_codes: &MfaBackupCodes<InMemoryStore>) -> HttpRequest {
+        let req = TestRequest::default().to_http_request();
+        backup_codes
+            .generate_code(&FactorContext::from_request(
+                &req,
+                &req.get_session(),
+                "anna",
+            ))
+            .unwrap();
+        req
+    }
+
+    #[actix_rt::test]
+    async fn code_should_only_be_accepted_once() {
+        let backup_codes = MfaBackupCodes::new(InMemoryStore::default());
+        let GenerateCodeResult(codes) = backup_codes.generate_codes("anna").unwrap();
+        assert_eq!(codes.len(), 10);
+        let req = login(&backup_codes);
+
+        assert!(backup_codes.check_code(&codes[0], &req).await.is_ok());
+        assert!(backup_codes.check_code(&codes[0], &req).await.is_err());
+        assert!(backup_codes.check_code(&codes[1], &req).await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn no_code_should_be_accepted_when_all_are_used() {
+        let backup_codes = MfaBackupCodes::new(InMemoryStore::default()).number_of_codes(2);
+        let GenerateCodeResult(codes) = backup_codes.generate_codes("anna").unwrap();
+        let req = login(&backup_codes);
+
+        for code in &codes {
+            backup_codes.check_code(code, &req).await.unwrap();
+        }
+
+        for code in &codes {
+            assert!(backup_codes.check_code(code, &req).await.is_err());
+        }
+    }
+
+    #[test]
+    fn code_format_should_be_validated() {
+        assert_eq!(normalize("abcde-fghjk"), Some("ABCDEFGHJK".to_owned()));
+        assert_eq!(normalize("ABCDE FGHJK"), Some("ABCDEFGHJK".to_owned()));
+        assert_eq!(normalize("ABCDEFGHJ"), None);
+        assert_eq!(normalize("ABCDEFGHJKL"), None);
+        // 0, O, 1 and I are never part of a code
+        assert_eq!(normalize("ABCDEFGHJ0"), None);
+        assert_eq!(normalize("ABCDEFGH!K"), None);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, SystemTime},
+};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use log::warn;
+
+use super::{
+    random_code_auth::{
+        check_random_code, cleanup_and_unknown_error, remove_random_code, store_mfa_user,
+        store_random_code, Charset, RandomCode, RandomCodeConfig,
+    },
+    CheckCodeError, Factor, FactorContext, GenerateCodeError,
+};
+
+// long enough that a token in a link cannot be guessed within the allowed attempts
+const MIN_MAGIC_LINK_TOKEN_LENGTH: usize = 32;
+
+/// What [MfaEmailCode] sends to the user
+pub enum EmailMessage {
+    /// The code has to be entered in the app
+    Code(RandomCode),
+    /// The link leads to the app, which sends the `token` query parameter as code to the mfa route
+    MagicLink {
+        url: String,
+        valid_until: SystemTime,
+    },
+}
+
+/// Interface for sending the mail (similar to [CodeSender](super::random_code_auth::CodeSender))
+pub trait EmailSender {
+    type Error: std::error::Error + 'static;
+    /// `recipient` is the username of the login, so users should log in with their email address
+    fn send(&self, recipient: &str, message: EmailMessage) -> Result<(), Self::Error>;
+}
+
+/// Configuration of [MfaEmailCode]
+#[derive(Clone, Debug)]
+pub struct MfaEmailCodeConfig {
+    /// How long the code is valid (default: 10 minutes)
+    pub ttl: Duration,
+    /// Length of the code, magic links use at least 32 characters (default: `8`)
+    pub token_length: usize,
+}
+
+impl Default for MfaEmailCodeConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60 * 10),
+            token_length: 8,
+        }
+    }
+}
+
+/// Sends an alphanumeric code or a magic link by mail
+///
+/// The code is stored in the session like the one of [MfaRandomCode](super::random_code_auth::MfaRandomCode),
+/// so wrong codes are counted and lock the mfa as well (see [RandomCodeConfig]). Every code can only be used once.
+///
+/// In magic link mode ([MfaEmailCode::magic_link]) the mail contains `{base_url}?token={code}`. The page behind the
+/// link has to send the token to the mfa route, so it must be opened in the browser that has started the login.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new_with_factor(
+///     SessionAuthProvider::default(),
+///     PathMatcher::default(),
+///     Box::new(MfaEmailCode::new(mail_sender).magic_link("https://example.org/login/confirm")),
+/// )
+/// ```
+pub struct MfaEmailCode<T: EmailSender> {
+    email_sender: T,
+    config: MfaEmailCodeConfig,
+    random_code_config: RandomCodeConfig,
+    magic_link_base_url: Option<String>,
+}
+
+impl<T: EmailSender> MfaEmailCode<T> {
+    pub fn new(email_sender: T) -> Self {
+        Self {
+            email_sender,
+            config: MfaEmailCodeConfig::default(),
+            random_code_config: RandomCodeConfig::default(),
+            magic_link_base_url: None,
+        }
+    }
+
+    /// Replaces the default [MfaEmailCodeConfig]
+    pub fn with_config(mut self, config: MfaEmailCodeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the default [RandomCodeConfig] (attempts and lockout)
+    pub fn with_random_code_config(mut self, random_code_config: RandomCodeConfig) -> Self {
+        self.random_code_config = random_code_config;
+        self
+    }
+
+    /// Sends a link with the token instead of the code
+    pub fn magic_link(mut self, base_url: &str) -> Self {
+        self.magic_link_base_url = Some(base_url.to_owned());
+        self
+    }
+
+    fn message(&self, random_code: RandomCode) -> EmailMessage {
+        match &self.magic_link_base_url {
+            Some(base_url) => {
+                let separator = if base_url.contains('?') { '&' } else { '?' };
+                EmailMessage::MagicLink {
+                    url: format!("{base_url}{separator}token={}", random_code.value()),
+                    valid_until: *random_code.valid_until(),
+                }
+            }
+            None => EmailMessage::Code(random_code),
+        }
+    }
+}
+
+impl<T: EmailSender> Factor for MfaEmailCode<T> {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        store_mfa_user(ctx.session, ctx.user_id)?;
+        if self.random_code_config.is_locked(ctx.user_id) {
+            warn!("Too many wrong codes of this user, no mail is sent");
+            return Ok(());
+        }
+
+        let token_length = match self.magic_link_base_url {
+            Some(_) => self.config.token_length.max(MIN_MAGIC_LINK_TOKEN_LENGTH),
+            None => self.config.token_length,
+        };
+        let random_code = RandomCode::generate_secure_valid_for(
+            token_length,
+            Charset::Alphanumeric,
+            self.config.ttl,
+        );
+        store_random_code(ctx.session, &random_code)?;
+
+        self.email_sender
+            .send(ctx.user_id, self.message(random_code))
+            .map_err(|e| cleanup_and_unknown_error(ctx.session, "Could not send mail to user", e))
+    }
+
+    fn get_unique_id(&self) -> String {
+        match self.magic_link_base_url {
+            Some(_) => "MAGIC_LINK".to_owned(),
+            None => "EMAIL_CODE".to_owned(),
+        }
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let session = req.get_session();
+        // codes are typed in, tokens of links are copied exactly
+        let check = check_random_code(
+            session.clone(),
+            code.trim().to_owned(),
+            self.magic_link_base_url.is_some(),
+            self.random_code_config.clone(),
+        );
+
+        Box::pin(async move {
+            check.await?;
+            remove_random_code(&session);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use actix_session::SessionExt;
+    use actix_web::{test::TestRequest, HttpRequest};
+
+    use super::{EmailMessage, EmailSender, MfaEmailCode, MfaEmailCodeConfig};
+    use crate::multifactor::{CheckCodeError, Factor, FactorContext};
+
+    /// Keeps the code or the token of the link
+    #[derive(Clone, Default)]
+    struct RecordingSender {
+        sent: Rc<RefCell<Vec<(String, String)>>>,
+    }
+
+    impl EmailSender for RecordingSender {
+        type Error = std::fmt::Error;
+
+        fn send(&self, recipient: &str, message: EmailMessage) -> Result<(), Self::Error> {
+            let sent = match message {
+                EmailMessage::Code(random_code) => random_code.value().to_owned(),
+                EmailMessage::MagicLink { url, .. } => url,
+            };
+            self.sent.borrow_mut().push((recipient.to_owned(), sent));
+            Ok(())
+        }
+    }
+
+    impl RecordingSender {
+        fn last(&self) -> String {
+            self.sent.borrow().last().unwrap().1.clone()
+        }
+    }
+
+    fn login(factor: &MfaEmailCode<RecordingSender>) -> HttpRequest {
+        let req = TestRequest::default().to_http_request();
+        factor
+            .generate_code(&FactorContext::from_request(
+                &req,
+                &req.get_session(),
+                "anna@example.org",
+            ))
+            .unwrap();
+        req
+    }
+
+    #[actix_rt::test]
+    async fn code_should_be_sent_to_the_user_and_accepted_once() {
+        let sender = RecordingSender::default();
+        let factor = MfaEmailCode::new(sender.clone());
+        let req = login(&factor);
+
+        let (recipient, code) = sender.sent.borrow()[0].clone();
+        assert_eq!(recipient, "anna@example.org");
+        assert_eq!(code.len(), 8);
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        assert!(factor.check_code(&code, &req).await.is_ok());
+        assert!(factor.check_code(&code, &req).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn expired_code_should_be_rejected() {
+        let sender = RecordingSender::default();
+        let factor = MfaEmailCode::new(sender.clone()).with_config(MfaEmailCodeConfig {
+            ttl: Duration::ZERO,
+            ..MfaEmailCodeConfig::default()
+        });
+        let req = login(&factor);
+
+        assert!(matches!(
+            factor.check_code(&sender.last(), &req).await,
+            Err(CheckCodeError::TimeIsUp(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn token_of_the_magic_link_should_be_accepted_as_code() {
+        let sender = RecordingSender::default();
+        let factor = MfaEmailCode::new(sender.clone()).magic_link("https://example.org/confirm");
+        let req = login(&factor);
+
+        let url = sender.last();
+        let token = url
+            .strip_prefix("https://example.org/confirm?token=")
+            .unwrap();
+        assert_eq!(token.len(), 32);
+
+        assert!(factor.check_code(&token[1..], &req).await.is_err());
+        assert!(factor.check_code(token, &req).await.is_ok());
+    }
+
+    #[test]
+    fn default_config_should_create_eight_characters_valid_for_ten_minutes() {
+        let config = MfaEmailCodeConfig::default();
+
+        assert_eq!(config.token_length, 8);
+        assert_eq!(config.ttl, Duration::from_secs(600));
+    }
+}
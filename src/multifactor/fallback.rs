@@ -0,0 +1,206 @@
+use std::{
+    future::{ready, Future},
+    pin::Pin,
+};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use log::warn;
+
+use super::{
+    ChallengeResponse, CheckCodeError, Factor, FactorContext, GenerateCodeError, StoredContext,
+};
+
+const SESSION_KEY_SELECTED_FACTOR: &str = "mfa_fallback_factor";
+
+/// Tries the factors in order until one of them could generate (and send) the code
+///
+/// The factor that has been used is stored in the session, so that [Factor::check_code] is delegated to it.
+///
+/// # Examples
+/// ```ignore
+/// SessionLoginHandler::with_mfa_fallback_chain(
+///     user_service,
+///     vec![Box::new(MfaRandomCode::new(generator, mail_sender)), Box::new(totp)],
+/// )
+/// ```
+pub struct FallbackChain {
+    factors: Vec<Box<dyn Factor>>,
+}
+
+impl FallbackChain {
+    pub fn new(factors: Vec<Box<dyn Factor>>) -> Self {
+        Self { factors }
+    }
+
+    fn selected_factor(&self, req: &HttpRequest) -> Option<&dyn Factor> {
+        let id = req
+            .get_session()
+            .get::<String>(SESSION_KEY_SELECTED_FACTOR)
+            .ok()??;
+
+        self.factors
+            .iter()
+            .find(|factor| factor.get_unique_id() == id)
+            .map(|factor| factor.as_ref())
+    }
+}
+
+impl Factor for FallbackChain {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        let mut last_error = GenerateCodeError::new("No factor in the fallback chain");
+
+        for factor in &self.factors {
+            match factor.generate_code(ctx) {
+                Ok(()) => {
+                    ctx.session
+                        .insert(SESSION_KEY_SELECTED_FACTOR, factor.get_unique_id())
+                        .map_err(|e| {
+                            GenerateCodeError::new_with_cause("Cannot store the used factor", e)
+                        })?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(
+                        "Factor '{}' failed, trying the next one: {e}",
+                        factor.get_unique_id()
+                    );
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn get_unique_id(&self) -> String {
+        "FALLBACK".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        match self.selected_factor(req) {
+            Some(factor) => factor.check_code(code, req),
+            None => Box::pin(ready(Err(CheckCodeError::FinallyRejected))),
+        }
+    }
+
+    fn validate_context(&self, original_req: &StoredContext, current_req: &HttpRequest) -> bool {
+        self.selected_factor(current_req)
+            .is_none_or(|factor| factor.validate_context(original_req, current_req))
+    }
+
+    fn on_success(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        match self.selected_factor(req) {
+            Some(factor) => factor.on_success(req),
+            None => Box::pin(ready(())),
+        }
+    }
+
+    fn on_failure(
+        &self,
+        error: &CheckCodeError,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        match self.selected_factor(req) {
+            Some(factor) => factor.on_failure(error, req),
+            None => Box::pin(ready(())),
+        }
+    }
+
+    fn next_challenge(
+        &self,
+        req: &HttpRequest,
+    ) -> Result<Option<ChallengeResponse>, GenerateCodeError> {
+        match self.selected_factor(req) {
+            Some(factor) => factor.next_challenge(req),
+            None => Ok(None),
+        }
+    }
+
+    fn challenge(&self, req: &HttpRequest) -> ChallengeResponse {
+        match self.selected_factor(req) {
+            Some(factor) => factor.challenge(req),
+            None => ChallengeResponse::new(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{ready, Future},
+        pin::Pin,
+    };
+
+    use actix_session::SessionExt;
+    use actix_web::{test::TestRequest, HttpRequest};
+
+    use super::FallbackChain;
+    use crate::multifactor::{CheckCodeError, Factor, FactorContext, GenerateCodeError};
+
+    struct StaticFactor {
+        id: &'static str,
+        is_available: bool,
+    }
+
+    impl Factor for StaticFactor {
+        fn generate_code(&self, _ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+            if self.is_available {
+                Ok(())
+            } else {
+                Err(GenerateCodeError::new("unavailable"))
+            }
+        }
+
+        fn get_unique_id(&self) -> String {
+            self.id.to_owned()
+        }
+
+        fn check_code(
+            &self,
+            code: &str,
+            _req: &HttpRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+            let result = if code == self.id {
+                Ok(())
+            } else {
+                Err(CheckCodeError::InvalidCode)
+            };
+            Box::pin(ready(result))
+        }
+    }
+
+    fn factor(id: &'static str, is_available: bool) -> Box<dyn Factor> {
+        Box::new(StaticFactor { id, is_available })
+    }
+
+    #[actix_rt::test]
+    async fn should_fall_back_to_the_next_factor() {
+        let chain = FallbackChain::new(vec![factor("MAIL", false), factor("TOTP", true)]);
+        let req = TestRequest::default().to_http_request();
+        let session = req.get_session();
+
+        chain
+            .generate_code(&FactorContext::from_request(&req, &session, "anna"))
+            .unwrap();
+
+        assert_eq!(chain.challenge(&req).factor, "TOTP");
+        assert!(chain.check_code("TOTP", &req).await.is_ok());
+        assert!(chain.check_code("MAIL", &req).await.is_err());
+    }
+
+    #[test]
+    fn should_fail_if_no_factor_is_available() {
+        let chain = FallbackChain::new(vec![factor("MAIL", false), factor("SMS", false)]);
+        let req = TestRequest::default().to_http_request();
+        let session = req.get_session();
+
+        assert!(chain
+            .generate_code(&FactorContext::from_request(&req, &session, "anna"))
+            .is_err());
+    }
+}
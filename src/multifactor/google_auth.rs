@@ -12,7 +12,7 @@ use serde::de::DeserializeOwned;
 use thiserror::Error;
 
 use crate::{
-    multifactor::{CheckCodeError, Factor, GenerateCodeError, TotpSecretRepository},
+    multifactor::{CheckCodeError, Factor, FactorContext, GenerateCodeError, TotpSecretRepository},
     AuthToken,
 };
 
@@ -26,7 +26,7 @@ use crate::{
 /// App::new()
 ///    //...
 ///   .wrap(AuthMiddleware::<_, User>::new_with_factor(
-///     SessionAuthProvider,
+///     SessionAuthProvider::default(),
 ///     PathMatcher::default(),
 ///     Box::new(GoogleAuthFactor::<_, User>::new(Arc::clone(&your_totp_repository))),
 ///   )
@@ -64,7 +64,7 @@ where
     T: TotpSecretRepository<U> + 'static,
     U: DeserializeOwned + Clone + 'static,
 {
-    fn generate_code(&self, _req: &actix_web::HttpRequest) -> Result<(), GenerateCodeError> {
+    fn generate_code(&self, _ctx: &FactorContext) -> Result<(), GenerateCodeError> {
         Ok(())
     }
 
@@ -0,0 +1,243 @@
+use std::{error::Error as StdError, future::Future, pin::Pin, sync::Arc};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use hmac::{Hmac, Mac};
+use log::warn;
+use sha1::Sha1;
+
+use super::{CheckCodeError, Factor, FactorContext, GenerateCodeError};
+
+const MFA_HOTP_USER_KEY: &str = "mfa_hotp_user";
+const CODE_LENGTH: usize = 6;
+
+/// Stores the counter of each user, it has to survive restarts of the app
+pub trait HotpCounterStore {
+    type Error: StdError + 'static;
+    /// The next counter that is expected, `None` for users without counter (starts at `0`)
+    fn get_counter(&self, user_id: &str) -> Result<Option<u64>, Self::Error>;
+    fn set_counter(&self, user_id: &str, counter: u64) -> Result<(), Self::Error>;
+}
+
+/// HOTP ([RFC 4226](https://www.rfc-editor.org/rfc/rfc4226)) implementation of [Factor], e.g. for hardware tokens
+///
+/// The codes are generated by the device from a counter instead of the time (see [MfaTOTP](super::totp::MfaTOTP)).
+/// The counter of the device increases on every button press, even if the code is never sent.
+/// So the codes of the next counters are accepted as well (see [MfaHOTP::look_ahead]).
+/// After a valid code, the counter in the [HotpCounterStore] is set behind it, so every code can only be used once.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new_with_factor(
+///     SessionAuthProvider::default(),
+///     PathMatcher::default(),
+///     Box::new(MfaHOTP::new(your_store, b"secret of the token")),
+/// )
+/// ```
+pub struct MfaHOTP<S: HotpCounterStore> {
+    store: Arc<S>,
+    secret: Arc<[u8]>,
+    look_ahead: u64,
+}
+
+impl<S: HotpCounterStore> MfaHOTP<S> {
+    /// `secret` is the key that is shared with the device
+    pub fn new(store: S, secret: &[u8]) -> Self {
+        Self {
+            store: Arc::new(store),
+            secret: secret.into(),
+            look_ahead: 10,
+        }
+    }
+
+    /// Number of counters after the expected one whose codes are accepted as well (default: `10`)
+    pub fn look_ahead(mut self, counters: u64) -> Self {
+        self.look_ahead = counters;
+        self
+    }
+}
+
+impl<S: HotpCounterStore + 'static> Factor for MfaHOTP<S> {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        // the device creates the code, only the user is needed for the check
+        ctx.session
+            .insert(MFA_HOTP_USER_KEY, ctx.user_id)
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot store HOTP user", e))
+    }
+
+    fn get_unique_id(&self) -> String {
+        "HOTP".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let session = req.get_session();
+        let store = Arc::clone(&self.store);
+        let secret = Arc::clone(&self.secret);
+        let look_ahead = self.look_ahead;
+        let code = code.to_owned();
+
+        Box::pin(async move {
+            let user_id = session
+                .get::<String>(MFA_HOTP_USER_KEY)
+                .ok()
+                .flatten()
+                .ok_or_else(|| {
+                    CheckCodeError::UnknownError("No HOTP user in session".to_owned())
+                })?;
+
+            let counter = store
+                .get_counter(&user_id)
+                .map_err(unknown_error)?
+                .unwrap_or_default();
+            let Some(matching_counter) = matching_counter(&secret, &code, counter, look_ahead)
+            else {
+                warn!("HOTP code does not match any counter from {counter} to {counter} + {look_ahead}");
+                return Err(CheckCodeError::InvalidCode);
+            };
+
+            store
+                .set_counter(&user_id, matching_counter + 1)
+                .map_err(unknown_error)
+        })
+    }
+}
+
+/// Returns the counter of the code, if it matches one within `counter..=counter + look_ahead`
+fn matching_counter(secret: &[u8], code: &str, counter: u64, look_ahead: u64) -> Option<u64> {
+    if code.len() != CODE_LENGTH || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    (counter..=counter.saturating_add(look_ahead)).find(|counter| hotp(secret, *counter) == code)
+}
+
+fn hotp(secret: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC can take keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation, see RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(CODE_LENGTH as u32),
+        width = CODE_LENGTH
+    )
+}
+
+fn unknown_error(e: impl StdError) -> CheckCodeError {
+    CheckCodeError::UnknownError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, convert::Infallible, rc::Rc};
+
+    use actix_session::SessionExt;
+    use actix_web::{test::TestRequest, HttpRequest};
+
+    use super::{hotp, HotpCounterStore, MfaHOTP};
+    use crate::multifactor::{Factor, FactorContext};
+
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[derive(Clone, Default)]
+    struct InMemoryStore {
+        counters: Rc<RefCell<HashMap<String, u64>>>,
+    }
+
+    impl HotpCounterStore for InMemoryStore {
+        type Error = Infallible;
+
+        fn get_counter(&self, user_id: &str) -> Result<Option<u64>, Self::Error> {
+            Ok(self.counters.borrow().get(user_id).copied())
+        }
+
+        fn set_counter(&self, user_id: &str, counter: u64) -> Result<(), Self::Error> {
+            self.counters
+                .borrow_mut()
+                .insert(user_id.to_owned(), counter);
+            Ok(())
+        }
+    }
+
+    fn login(factor: &MfaHOTP<InMemoryStore>) -> HttpRequest {
+        let req = TestRequest::default().to_http_request();
+        factor
+            .generate_code(&FactorContext::from_request(
+                &req,
+                &req.get_session(),
+                "anna",
+            ))
+            .unwrap();
+        req
+    }
+
+    #[actix_rt::test]
+    async fn code_within_the_look_ahead_window_should_be_accepted() {
+        let hotp_factor = MfaHOTP::new(InMemoryStore::default(), RFC_SECRET);
+        let req = login(&hotp_factor);
+
+        assert!(hotp_factor
+            .check_code(&hotp(RFC_SECRET, 10), &req)
+            .await
+            .is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn code_outside_of_the_look_ahead_window_should_be_rejected() {
+        let hotp_factor = MfaHOTP::new(InMemoryStore::default(), RFC_SECRET).look_ahead(3);
+        let req = login(&hotp_factor);
+
+        assert!(hotp_factor
+            .check_code(&hotp(RFC_SECRET, 4), &req)
+            .await
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn valid_code_should_advance_the_counter() {
+        let store = InMemoryStore::default();
+        let hotp_factor = MfaHOTP::new(store.clone(), RFC_SECRET);
+        let req = login(&hotp_factor);
+
+        hotp_factor
+            .check_code(&hotp(RFC_SECRET, 3), &req)
+            .await
+            .unwrap();
+
+        assert_eq!(store.get_counter("anna").unwrap(), Some(4));
+        // the code and the ones before cannot be used again
+        assert!(hotp_factor
+            .check_code(&hotp(RFC_SECRET, 3), &req)
+            .await
+            .is_err());
+        assert!(hotp_factor
+            .check_code(&hotp(RFC_SECRET, 4), &req)
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn codes_should_match_the_test_values_of_the_rfc() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(RFC_SECRET, counter as u64), *code);
+        }
+    }
+}
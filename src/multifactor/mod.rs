@@ -1,18 +1,44 @@
+#[cfg(feature = "async_sender")]
+pub mod async_code_auth;
+#[cfg(feature = "backup_codes")]
+pub mod backup_codes;
+#[cfg(feature = "mfa_send_code")]
+pub mod email_code;
+pub mod fallback;
 #[cfg(feature = "google_auth")]
 pub mod google_auth;
+#[cfg(feature = "hotp")]
+pub mod hotp;
+#[cfg(feature = "mfa_send_code")]
+pub mod push_code_auth;
 #[cfg(feature = "mfa_send_code")]
 pub mod random_code_auth;
+pub mod sequence;
+#[cfg(feature = "signed_link")]
+pub mod signed_link;
+#[cfg(feature = "metrics")]
+pub mod timed_factor;
+#[cfg(feature = "google_auth")]
+pub mod totp;
+#[cfg(feature = "webauthn")]
+pub mod webauthn;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 
 use std::{
     error::Error as StdError,
     future::{ready, Future, Ready},
+    net::{IpAddr, Ipv4Addr},
     pin::Pin,
     rc::Rc,
+    time::Duration,
 };
 
+use actix_session::Session;
 use actix_web::{
-    dev::Payload, http::StatusCode, FromRequest, HttpMessage, HttpRequest, HttpResponse,
-    ResponseError,
+    dev::Payload,
+    http::{header::USER_AGENT, StatusCode},
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
@@ -37,8 +63,8 @@ pub enum GetTotpSecretError {
 // Split Factor in two traits:
 // one should be public, the other needs to be pub (crate) to hide is_condition_met() and generate_code()
 pub trait Factor {
-    /// Responsible for generating the code and sending it to the user. Currently its needed to retrieve the user from the request
-    fn generate_code(&self, req: &HttpRequest) -> Result<(), GenerateCodeError>;
+    /// Responsible for generating the code and sending it to the user
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError>;
     /// Identifier for the Factor. Can be any String it only needs to be unique inside the app
     fn get_unique_id(&self) -> String;
     /// checks the code and returns empty Ok if code is correct, an Error otherwise
@@ -47,8 +73,204 @@ pub trait Factor {
         code: &str,
         req: &HttpRequest,
     ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>>;
+    /// Is called by the mfa route after [Factor::check_code] succeeded, e.g. to record the event
+    ///
+    /// For a streaming check it is called before the `approved` event, session changes are not saved in this case.
+    fn on_success(&self, _req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+    /// Is called by the mfa route after [Factor::check_code] failed, e.g. to clear the code or alert the user
+    fn on_failure(
+        &self,
+        _error: &CheckCodeError,
+        _req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(ready(()))
+    }
+    /// Whether [Factor::check_code] waits for the user (e.g. to approve a push notification)
+    ///
+    /// If `true` and the client accepts `text/event-stream`, the mfa route responds immediately with a stream of server-sent
+    /// `keep-alive` events, until the check is done (`approved` or `rejected` event) or the
+    /// [timeout](crate::session::handlers::SessionLoginHandler::with_stream_timeout) has passed.
+    /// The approved login is completed with the next request of the client. The session can only be read by [Factor::check_code]
+    /// before its first `await`, i.e. before the response starts.
+    ///
+    /// Streaming needs a [session registry](crate::session::handlers::SessionLoginHandler::with_session_registry),
+    /// without it the code is checked like without streaming. No "remember me" cookie is set for streamed logins.
+    /// Wrong codes are counted and [Factor::on_failure] is called like without streaming.
+    fn is_streaming(&self) -> bool {
+        false
+    }
+    /// How long it usually takes until the code reaches the user (e.g. ~30 seconds for SMS)
+    ///
+    /// Is sent to the client in the [ChallengeResponse], so that the UI can show a hint or a countdown.
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        None
+    }
+    /// Is called before [Factor::check_code] with the context of the request that has triggered [Factor::generate_code]
+    ///
+    /// Factors can reject the code if the context has changed (e.g. another user agent). The default accepts any context.
+    fn validate_context(&self, _original_req: &StoredContext, _current_req: &HttpRequest) -> bool {
+        true
+    }
+    /// Is called after [Factor::check_code] succeeded, returns the challenge of the next step if the mfa is not complete yet
+    ///
+    /// Factors with several steps (e.g. [FactorSequence](sequence::FactorSequence)) generate the code for the next step here.
+    /// The default has only one step and returns `None`.
+    fn next_challenge(
+        &self,
+        _req: &HttpRequest,
+    ) -> Result<Option<ChallengeResponse>, GenerateCodeError> {
+        Ok(None)
+    }
+    /// The challenge that is sent to the client after [Factor::generate_code]
+    ///
+    /// Composite factors (e.g. [FallbackChain](fallback::FallbackChain)) return the challenge of the factor that has actually sent the code.
+    fn challenge(&self, _req: &HttpRequest) -> ChallengeResponse {
+        ChallengeResponse {
+            factor: self.get_unique_id(),
+            estimated_delivery_seconds: self.estimated_delivery_time().map(|d| d.as_secs()),
+            qr_code: None,
+            channel: None,
+            webauthn: None,
+        }
+    }
+}
+
+/// The data of the login request a [Factor] needs to generate the code
+pub struct FactorContext<'a> {
+    /// The session of the login, e.g. to store the code
+    pub session: &'a Session,
+    /// The username of the [LoginToken](crate::login::LoginToken)
+    pub user_id: &'a str,
+    /// `0.0.0.0` if the peer address is unknown
+    pub request_ip: IpAddr,
+}
+
+impl<'a> FactorContext<'a> {
+    pub fn new(session: &'a Session, user_id: &'a str, request_ip: IpAddr) -> Self {
+        Self {
+            session,
+            user_id,
+            request_ip,
+        }
+    }
+
+    pub(crate) fn from_request(req: &HttpRequest, session: &'a Session, user_id: &'a str) -> Self {
+        Self::new(
+            session,
+            user_id,
+            req.peer_addr()
+                .map(|addr| addr.ip())
+                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        )
+    }
+}
+
+/// Response of the login route if a second factor is needed
+#[derive(Serialize, Deserialize)]
+pub struct ChallengeResponse {
+    pub factor: String,
+    pub estimated_delivery_seconds: Option<u64>,
+    /// Only set if the user has to set up an authenticator app first (e.g. by `MfaTOTP`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qr_code: Option<QrCodeResponse>,
+    /// Where the code has been sent to (e.g. by `MfaRandomCode`), so that the UI can show it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<ChannelInfo>,
+    /// Options for `navigator.credentials.get()`, only set by `MfaWebAuthn`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webauthn: Option<WebAuthnChallenge>,
+}
+
+/// Provisioning data for an authenticator app, sent with the [ChallengeResponse]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QrCodeResponse {
+    /// `otpauth://totp/...`
+    pub provisioning_uri: String,
+    /// The provisioning URI as QR code (SVG)
+    pub qr_code_svg: String,
+}
+
+/// WebAuthn challenge, sent with the [ChallengeResponse]
+///
+/// The client passes it to `navigator.credentials.get({ publicKey: { challenge, rpId, timeout } })`
+/// (the challenge decoded from base64url).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnChallenge {
+    /// Random bytes, base64url encoded without padding
+    pub challenge: String,
+    /// The domain of the app (relying party)
+    pub rp_id: String,
+    /// In milliseconds
+    pub timeout: u64,
+}
+
+/// The channel a code has been sent through, sent with the [ChallengeResponse]
+///
+/// Is also stored in the session for audit purposes, so the destination must be masked
+/// (e.g. `{ "channel": "sms", "masked_destination": "***-1234" }`).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelInfo {
+    /// E.g. `sms` or `email`
+    pub channel: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub masked_destination: Option<String>,
+}
+
+impl ChannelInfo {
+    pub fn new(channel: &str) -> Self {
+        Self {
+            channel: channel.to_owned(),
+            masked_destination: None,
+        }
+    }
+
+    /// Keeps only the last 4 characters of the destination, e.g. `+49 170 1231234` becomes `***-1234`
+    pub fn with_destination(mut self, destination: &str) -> Self {
+        let chars: Vec<char> = destination.chars().collect();
+        let visible: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+        self.masked_destination = Some(format!("***-{visible}"));
+        self
+    }
+}
+
+impl ChallengeResponse {
+    pub fn new(factor: &dyn Factor) -> Self {
+        Self {
+            factor: factor.get_unique_id(),
+            estimated_delivery_seconds: factor.estimated_delivery_time().map(|d| d.as_secs()),
+            qr_code: None,
+            channel: None,
+            webauthn: None,
+        }
+    }
+}
+
+/// Context of the request that has triggered [Factor::generate_code], stored in the session alongside the code
+///
+/// See [Factor::validate_context].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoredContext {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl StoredContext {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        Self {
+            ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+            user_agent: req
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|user_agent| user_agent.to_str().ok())
+                .map(str::to_owned),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct MfaRegistry {
     value: Rc<Option<Box<dyn Factor>>>,
 }
@@ -59,11 +281,21 @@ impl MfaRegistry {
     }
 }
 
+/// Factor registered by a login handler for its routes (see [SessionLoginHandler::with_mfa_fallback_chain](crate::session::handlers::SessionLoginHandler::with_mfa_fallback_chain))
+pub(crate) struct HandlerFactor(pub(crate) Rc<Option<Box<dyn Factor>>>);
+
 impl FromRequest for MfaRegistry {
     type Error = actix_web::Error;
     type Future = Ready<Result<MfaRegistry, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // a factor of the login handler takes precedence over the one of the middleware
+        if let Some(HandlerFactor(factor)) = req.app_data::<HandlerFactor>() {
+            return ready(Ok(Self {
+                value: Rc::clone(factor),
+            }));
+        }
+
         let extensions = req.extensions();
         if let Some(factor) = extensions.get::<Rc<Option<Box<dyn Factor>>>>() {
             ready(Ok(Self {
@@ -83,6 +315,7 @@ pub enum ConditionCheckError {
     CantCheckCondition(String),
 }
 
+/// Error of [Factor::generate_code], converts into [actix_web::Error] (`500 Internal Server Error`)
 #[derive(Error, Debug)]
 #[error("GenerateCodeError: {message}{}", cause.as_ref().map(|e| format!(", caused by: {e}")).unwrap_or_else(|| ".".to_owned()))]
 pub struct GenerateCodeError {
@@ -113,6 +346,9 @@ impl GenerateCodeError {
     }
 }
 
+/// Error of [Factor::check_code]
+///
+/// Implements [ResponseError], so it converts into [actix_web::Error] and can be returned from handlers with `?`.
 #[derive(Error, Debug)]
 pub enum CheckCodeError {
     #[error("unknown server error: {0}")]
@@ -123,6 +359,8 @@ pub enum CheckCodeError {
     InvalidCode,
     #[error("login rejected. unauthorized")]
     FinallyRejected,
+    #[error("too many attempts")]
+    TooManyAttempts,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -142,36 +380,108 @@ impl MfaError {
     }
 }
 
+impl CheckCodeError {
+    fn mfa_error(&self) -> MfaError {
+        match self {
+            CheckCodeError::UnknownError(m) => MfaError::new("unknown_error", m, false),
+            CheckCodeError::TimeIsUp(m) => MfaError::new("time_is_up", m, false),
+            CheckCodeError::InvalidCode => MfaError::new("code_invalid", "", true),
+            CheckCodeError::FinallyRejected => MfaError::new("login_finally_rejected", "", false),
+            CheckCodeError::TooManyAttempts => MfaError::new("too_many_attempts", "", false),
+        }
+    }
+
+    /// The body of the error response, e.g. for server-sent events
+    pub(crate) fn error_response_json(&self) -> String {
+        serde_json::to_string(&self.mfa_error()).unwrap_or_default()
+    }
+}
+
 impl ResponseError for CheckCodeError {
     fn status_code(&self) -> StatusCode {
         match self {
             CheckCodeError::UnknownError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            _ => StatusCode::UNAUTHORIZED,
+            CheckCodeError::InvalidCode => StatusCode::BAD_REQUEST,
+            CheckCodeError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            CheckCodeError::TimeIsUp(_) | CheckCodeError::FinallyRejected => {
+                StatusCode::UNAUTHORIZED
+            }
         }
     }
     fn error_response(&self) -> HttpResponse {
-        match self {
-            CheckCodeError::UnknownError(m) => {
-                HttpResponse::InternalServerError().json(MfaError::new("unknown_error", m, false))
-            }
-            CheckCodeError::TimeIsUp(m) => {
-                HttpResponse::Unauthorized().json(MfaError::new("time_is_up", m, false))
-            }
-            CheckCodeError::InvalidCode => {
-                HttpResponse::Unauthorized().json(MfaError::new("code_invalid", "", true))
-            }
-            CheckCodeError::FinallyRejected => HttpResponse::Unauthorized().json(MfaError::new(
-                "login_finally_rejected",
-                "",
-                false,
-            )),
-        }
+        HttpResponse::build(self.status_code()).json(self.mfa_error())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{GenerateCodeError, GetTotpSecretError};
+    use actix_web::test::TestRequest;
+
+    use actix_web::http::StatusCode;
+
+    use super::{
+        ChannelInfo, CheckCodeError, GenerateCodeError, GetTotpSecretError, StoredContext,
+    };
+
+    #[test]
+    fn check_code_errors_should_convert_into_actix_errors_with_status() {
+        let status =
+            |e: CheckCodeError| actix_web::Error::from(e).as_response_error().status_code();
+
+        assert_eq!(status(CheckCodeError::InvalidCode), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status(CheckCodeError::TimeIsUp("expired".to_owned())),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(CheckCodeError::TooManyAttempts),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            status(CheckCodeError::UnknownError("store".to_owned())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn generate_code_error_should_be_a_std_error_with_source() {
+        let orig = GetTotpSecretError::DefaultError("orig error".to_owned());
+        let code_error = GenerateCodeError::new_with_cause("error", orig);
+
+        assert!(std::error::Error::source(&code_error).is_some());
+        assert_eq!(
+            actix_web::Error::from(code_error)
+                .as_response_error()
+                .status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn destination_should_be_masked() {
+        let info = ChannelInfo::new("sms").with_destination("+49 170 1231234");
+
+        assert_eq!(info.masked_destination.as_deref(), Some("***-1234"));
+        assert_eq!(
+            ChannelInfo::new("sms")
+                .with_destination("12")
+                .masked_destination,
+            Some("***-12".to_owned())
+        );
+    }
+
+    #[test]
+    fn stored_context_should_contain_ip_and_user_agent() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4321".parse().unwrap())
+            .insert_header(("User-Agent", "authfix-test"))
+            .to_http_request();
+
+        let context = StoredContext::from_request(&req);
+
+        assert_eq!(context.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(context.user_agent.as_deref(), Some("authfix-test"));
+    }
 
     #[test]
     fn generate_error_should_print_cause_test() {
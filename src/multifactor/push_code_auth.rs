@@ -0,0 +1,115 @@
+use std::{future::Future, pin::Pin, time::Duration};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use log::{info, warn};
+
+use super::{
+    random_code_auth::{
+        check_random_code, cleanup_and_unknown_error, store_mfa_user, store_random_code,
+        CodeSender, DryRunCodeSender, RandomCode, RandomCodeConfig,
+    },
+    CheckCodeError, Factor, FactorContext, GenerateCodeError,
+};
+
+/// Interface for sending the code as push notification (e.g. FCM or APNs) to a device of the user
+pub trait PushSender: CodeSender {
+    fn send_push(&self, device_token: &str, code: &RandomCode) -> Result<(), Self::Error>;
+}
+
+impl<T: PushSender> PushSender for DryRunCodeSender<T> {
+    fn send_push(&self, device_token: &str, code: &RandomCode) -> Result<(), Self::Error> {
+        info!(
+            "Dry run, push to device {device_token} not sent: {}",
+            code.value()
+        );
+        Ok(())
+    }
+}
+
+/// Looks up the device token of the user that is currently logging in (e.g. from the session or a database)
+pub trait DeviceTokenResolver {
+    type Error: std::error::Error + 'static;
+    fn resolve_device_token(&self, ctx: &FactorContext) -> Result<String, Self::Error>;
+}
+
+/// Push notification implementation of [Factor]
+///
+/// Works like [MfaRandomCode](super::random_code_auth::MfaRandomCode), but the code is sent with [PushSender::send_push]
+/// to the device returned by the [DeviceTokenResolver].
+pub struct MfaPush<P: PushSender, R: DeviceTokenResolver> {
+    code_generator: Box<dyn Fn() -> RandomCode>,
+    push_sender: P,
+    device_token_resolver: R,
+    config: RandomCodeConfig,
+}
+
+impl<P: PushSender, R: DeviceTokenResolver> MfaPush<P, R> {
+    pub fn new(
+        code_generator: fn() -> RandomCode,
+        push_sender: P,
+        device_token_resolver: R,
+    ) -> Self {
+        Self {
+            code_generator: Box::new(code_generator),
+            push_sender,
+            device_token_resolver,
+            config: RandomCodeConfig::default(),
+        }
+    }
+
+    /// Replaces the default [RandomCodeConfig]
+    pub fn with_config(mut self, config: RandomCodeConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl<P: PushSender, R: DeviceTokenResolver> Factor for MfaPush<P, R> {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        store_mfa_user(ctx.session, ctx.user_id)?;
+        if self.config.is_locked(ctx.user_id) {
+            warn!("Too many wrong codes of this user, no push is sent");
+            return Ok(());
+        }
+
+        let device_token = self
+            .device_token_resolver
+            .resolve_device_token(ctx)
+            .map_err(|e| {
+                cleanup_and_unknown_error(ctx.session, "Could not resolve device token", e)
+            })?;
+
+        let random_code = (self.code_generator)();
+        store_random_code(ctx.session, &random_code)?;
+
+        self.push_sender
+            .send_push(&device_token, &random_code)
+            .map_err(|e| {
+                cleanup_and_unknown_error(ctx.session, "Could not send push notification", e)
+            })?;
+
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        "PUSHCODE".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        check_random_code(
+            req.get_session(),
+            code.to_owned(),
+            false,
+            self.config.clone(),
+        )
+    }
+
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        self.push_sender.estimated_delivery_time()
+    }
+}
@@ -1,17 +1,173 @@
-use std::{future::Future, time::SystemTime};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
 use actix_session::{Session, SessionExt};
 use actix_web::HttpRequest;
+use log::{info, warn};
+use rand::{rngs::OsRng, Rng, TryRngCore};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use super::{CheckCodeError, Factor, GenerateCodeError};
+use super::{
+    ChallengeResponse, ChannelInfo, CheckCodeError, Factor, FactorContext, GenerateCodeError,
+};
 
 const MFA_RANDOM_CODE_KEY: &str = "mfa_random_code";
+const MFA_RANDOM_CODE_USER_KEY: &str = "mfa_random_code_user";
+const MFA_RANDOM_CODE_CHANNEL_KEY: &str = "mfa_random_code_channel";
+const DEFAULT_CODE_VALIDITY: Duration = Duration::from_secs(60 * 5);
+const DEFAULT_LOCKOUT_DURATION: Duration = Duration::from_secs(60 * 15);
 
 /// Interface for sending the code to the user
 pub trait CodeSender {
     type Error: std::error::Error + 'static;
     fn send_code(&self, random_code: RandomCode) -> Result<(), Self::Error>;
+    /// See [Factor::estimated_delivery_time]
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        None
+    }
+    /// The channel of the last [CodeSender::send_code], stored in the session and sent with the [ChallengeResponse]
+    ///
+    /// The destination must be masked (see [ChannelInfo::with_destination]). Default: `unknown`
+    fn channel_info(&self) -> ChannelInfo {
+        ChannelInfo::new("unknown")
+    }
+}
+
+/// Wraps a [CodeSender] and only logs the code instead of sending it (e.g. to avoid real emails or SMS in CI)
+///
+/// Create it with [CodeSenderExt::dry_run]:
+/// ```ignore
+/// MfaRandomCode::new(generator, MailSender::new(config).dry_run())
+/// ```
+pub struct DryRunCodeSender<T: CodeSender> {
+    inner: T,
+}
+
+impl<T: CodeSender> CodeSender for DryRunCodeSender<T> {
+    type Error = T::Error;
+
+    fn send_code(&self, random_code: RandomCode) -> Result<(), Self::Error> {
+        info!("Dry run, code not sent: {}", random_code.value());
+        Ok(())
+    }
+
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        self.inner.estimated_delivery_time()
+    }
+
+    fn channel_info(&self) -> ChannelInfo {
+        self.inner.channel_info()
+    }
+}
+
+/// Adds [CodeSenderExt::dry_run] to every [CodeSender]
+pub trait CodeSenderExt: CodeSender + Sized {
+    fn dry_run(self) -> DryRunCodeSender<Self> {
+        DryRunCodeSender { inner: self }
+    }
+}
+
+impl<T: CodeSender> CodeSenderExt for T {}
+
+/// Sends the code through the first channel that succeeds (e.g. mail and if the mail server is down, SMS)
+///
+/// The channels are tried in the order they have been added, later channels are only used if all previous ones failed.
+/// ```ignore
+/// MfaRandomCode::new(
+///     generator,
+///     PrioritizedMultiChannelSender::new()
+///         .with_channel(MailSender::new(config))
+///         .with_channel(SmsSender::new(config)),
+/// )
+/// ```
+#[derive(Default)]
+pub struct PrioritizedMultiChannelSender {
+    channels: Vec<Box<dyn ErasedCodeSender>>,
+    // the channel that has sent the last code
+    last_used: Cell<usize>,
+}
+
+impl PrioritizedMultiChannelSender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_channel(mut self, sender: impl CodeSender + 'static) -> Self {
+        self.channels.push(Box::new(sender));
+        self
+    }
+}
+
+impl CodeSender for PrioritizedMultiChannelSender {
+    type Error = AllChannelsFailedError;
+
+    fn send_code(&self, random_code: RandomCode) -> Result<(), Self::Error> {
+        let mut errors = Vec::new();
+
+        for (index, channel) in self.channels.iter().enumerate() {
+            match channel.send_erased(random_code.clone()) {
+                Ok(()) => {
+                    self.last_used.set(index);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("Cannot send code, trying the next channel: {e}");
+                    errors.push(e);
+                }
+            }
+        }
+
+        Err(AllChannelsFailedError { errors })
+    }
+
+    /// The delivery time of the first channel
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        self.channels
+            .first()
+            .and_then(|channel| channel.estimated_delivery_time_erased())
+    }
+
+    /// The channel that has sent the last code
+    fn channel_info(&self) -> ChannelInfo {
+        self.channels.get(self.last_used.get()).map_or_else(
+            || ChannelInfo::new("unknown"),
+            |channel| channel.channel_info_erased(),
+        )
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("No channel could send the code: {}", errors.join(", "))]
+pub struct AllChannelsFailedError {
+    errors: Vec<String>,
+}
+
+/// Object safe version of [CodeSender], so that senders with different error types can be combined
+trait ErasedCodeSender {
+    fn send_erased(&self, random_code: RandomCode) -> Result<(), String>;
+    fn estimated_delivery_time_erased(&self) -> Option<Duration>;
+    fn channel_info_erased(&self) -> ChannelInfo;
+}
+
+impl<T: CodeSender> ErasedCodeSender for T {
+    fn send_erased(&self, random_code: RandomCode) -> Result<(), String> {
+        self.send_code(random_code).map_err(|e| e.to_string())
+    }
+
+    fn estimated_delivery_time_erased(&self) -> Option<Duration> {
+        self.estimated_delivery_time()
+    }
+
+    fn channel_info_erased(&self) -> ChannelInfo {
+        self.channel_info()
+    }
 }
 
 /// The code and its validity generated by [MfaRandomCode]
@@ -29,6 +185,30 @@ impl RandomCode {
         }
     }
 
+    /// Generates a code with the given length using the randomness of the operating system ([OsRng]).
+    ///
+    /// The code is valid for 5 minutes.
+    pub fn generate_secure(length: usize, charset: Charset) -> Self {
+        Self::generate_secure_valid_for(length, charset, DEFAULT_CODE_VALIDITY)
+    }
+
+    pub(crate) fn generate_secure_valid_for(
+        length: usize,
+        charset: Charset,
+        valid_for: Duration,
+    ) -> Self {
+        let chars = charset.chars();
+        let mut rng = OsRng.unwrap_err();
+        let value: String = (0..length)
+            .map(|_| chars[rng.random_range(0..chars.len())] as char)
+            .collect();
+
+        Self {
+            value,
+            valid_until: SystemTime::now() + valid_for,
+        }
+    }
+
     pub fn value(&self) -> &str {
         &self.value
     }
@@ -37,87 +217,417 @@ impl RandomCode {
     }
 }
 
+/// The characters a code generated by [RandomCode::generate_secure] consists of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+    /// 0-9
+    Numeric,
+    /// A-Z and 0-9
+    UppercaseAlphanumeric,
+    /// a-z, A-Z and 0-9
+    Alphanumeric,
+}
+
+impl Charset {
+    fn chars(&self) -> &'static [u8] {
+        match self {
+            Charset::Numeric => b"0123456789",
+            Charset::UppercaseAlphanumeric => b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789",
+            Charset::Alphanumeric => {
+                b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789"
+            }
+        }
+    }
+}
+
+/// Counts wrong codes and keeps the lock per user, so that a new session does not lift the lock
+///
+/// Implementations must be shareable between the workers of the server (e.g. an in-memory map behind a mutex or Redis).
+pub trait MfaLockStore: Send + Sync {
+    /// Records a wrong code and returns the number of wrong codes since the last reset
+    fn record_failure(&self, user_id: &str) -> u32;
+    /// Locks the mfa of the user until `locked_until` and forgets the wrong codes
+    fn lock(&self, user_id: &str, locked_until: SystemTime);
+    /// Returns the end of the lock if the user has been locked
+    fn locked_until(&self, user_id: &str) -> Option<SystemTime>;
+    /// Forgets the wrong codes of the user, called after a correct code
+    fn reset(&self, user_id: &str);
+}
+
+impl<S: MfaLockStore> MfaLockStore for Arc<S> {
+    fn record_failure(&self, user_id: &str) -> u32 {
+        self.as_ref().record_failure(user_id)
+    }
+
+    fn lock(&self, user_id: &str, locked_until: SystemTime) {
+        self.as_ref().lock(user_id, locked_until)
+    }
+
+    fn locked_until(&self, user_id: &str) -> Option<SystemTime> {
+        self.as_ref().locked_until(user_id)
+    }
+
+    fn reset(&self, user_id: &str) {
+        self.as_ref().reset(user_id)
+    }
+}
+
+#[derive(Default)]
+struct UserAttempts {
+    failures: u32,
+    locked_until: Option<SystemTime>,
+}
+
+/// [MfaLockStore] that keeps the wrong codes in memory, so they are not shared between instances of the app
+///
+/// Create it once and pass a clone of the [Arc] to the [RandomCodeConfig] of every worker:
+/// ```ignore
+/// let lock_store = Arc::new(InMemoryMfaLockStore::default());
+///
+/// HttpServer::new(move || {
+///     let config = RandomCodeConfig::default().lock_store(Arc::clone(&lock_store));
+///     App::new().wrap(AuthMiddleware::new_with_factor(
+///         SessionAuthProvider,
+///         PathMatcher::default(),
+///         Box::new(MfaRandomCode::new(generator, sender).with_config(config)),
+///     ))
+/// })
+/// ```
+#[derive(Default)]
+pub struct InMemoryMfaLockStore {
+    attempts: Mutex<HashMap<String, UserAttempts>>,
+}
+
+impl MfaLockStore for InMemoryMfaLockStore {
+    fn record_failure(&self, user_id: &str) -> u32 {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = attempts.entry(user_id.to_owned()).or_default();
+        entry.failures += 1;
+        entry.failures
+    }
+
+    fn lock(&self, user_id: &str, locked_until: SystemTime) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let now = SystemTime::now();
+        // expired locks are removed here, so that the map only grows with locked users
+        attempts.retain(|_, entry| {
+            entry.failures > 0 || entry.locked_until.is_some_and(|until| until > now)
+        });
+        attempts.insert(
+            user_id.to_owned(),
+            UserAttempts {
+                failures: 0,
+                locked_until: Some(locked_until),
+            },
+        );
+    }
+
+    fn locked_until(&self, user_id: &str) -> Option<SystemTime> {
+        let attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        attempts.get(user_id).and_then(|entry| entry.locked_until)
+    }
+
+    fn reset(&self, user_id: &str) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        attempts.remove(user_id);
+    }
+}
+
+/// Configuration of [MfaRandomCode]
+#[derive(Clone)]
+pub struct RandomCodeConfig {
+    min_length: usize,
+    max_attempts: u32,
+    lockout_duration: Duration,
+    lock_store: Arc<dyn MfaLockStore>,
+}
+
+impl RandomCodeConfig {
+    /// Codes shorter than `min_length` are still sent, but a security warning is logged (default: `6`)
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    /// After `max_attempts` wrong codes the login is discarded and the mfa is locked (default: `3`)
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// How long the mfa is locked after too many wrong codes (default: 15 minutes)
+    ///
+    /// The lock is kept per user in the [MfaLockStore], so a new login does not lift it. No code is sent while it is active.
+    pub fn lockout_duration(mut self, lockout_duration: Duration) -> Self {
+        self.lockout_duration = lockout_duration;
+        self
+    }
+
+    /// Where the wrong codes and locks of the users are kept (default: an [InMemoryMfaLockStore] of this config)
+    ///
+    /// The default store is not shared between the workers of the server, pass the same store to every worker.
+    pub fn lock_store(mut self, lock_store: impl MfaLockStore + 'static) -> Self {
+        self.lock_store = Arc::new(lock_store);
+        self
+    }
+
+    /// Whether the mfa of the user is locked after too many wrong codes
+    pub(crate) fn is_locked(&self, user_id: &str) -> bool {
+        self.lock_store
+            .locked_until(user_id)
+            .is_some_and(|locked_until| locked_until > SystemTime::now())
+    }
+}
+
+impl fmt::Debug for RandomCodeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RandomCodeConfig")
+            .field("min_length", &self.min_length)
+            .field("max_attempts", &self.max_attempts)
+            .field("lockout_duration", &self.lockout_duration)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for RandomCodeConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 6,
+            max_attempts: 3,
+            lockout_duration: DEFAULT_LOCKOUT_DURATION,
+            lock_store: Arc::new(InMemoryMfaLockStore::default()),
+        }
+    }
+}
+
 /// Random code implementation of [Factor]
 ///
 /// Takes in a function that should generate a random code and [CodeSender]
 /// The generated code is then saved in the Session.
+///
+/// The code entered by the user is compared case-insensitively by default (see [MfaRandomCode::case_sensitive]).
 pub struct MfaRandomCode<T: CodeSender> {
-    code_generator: fn() -> RandomCode,
+    code_generator: Box<dyn Fn() -> RandomCode>,
     code_sender: T,
+    case_sensitive: bool,
+    config: RandomCodeConfig,
 }
 
 impl<T: CodeSender> MfaRandomCode<T> {
     pub fn new(code_generator: fn() -> RandomCode, code_sender: T) -> Self {
         Self {
-            code_generator,
+            code_generator: Box::new(code_generator),
+            code_sender,
+            case_sensitive: false,
+            config: RandomCodeConfig::default(),
+        }
+    }
+
+    /// Uses [RandomCode::generate_secure] as code generator
+    ///
+    /// # Examples
+    /// ```ignore
+    /// MfaRandomCode::with_secure_generator(Charset::Numeric, 6, Duration::from_secs(300), your_sender)
+    /// ```
+    pub fn with_secure_generator(
+        charset: Charset,
+        length: usize,
+        valid_for: Duration,
+        code_sender: T,
+    ) -> Self {
+        Self {
+            code_generator: Box::new(move || {
+                RandomCode::generate_secure_valid_for(length, charset, valid_for)
+            }),
             code_sender,
+            case_sensitive: false,
+            config: RandomCodeConfig::default(),
         }
     }
+
+    /// Whether the code must be entered with the exact case (default: `false`)
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Replaces the default [RandomCodeConfig]
+    pub fn with_config(mut self, config: RandomCodeConfig) -> Self {
+        self.config = config;
+        self
+    }
 }
 
 impl<T: CodeSender> Factor for MfaRandomCode<T> {
-    fn generate_code(&self, req: &HttpRequest) -> Result<(), GenerateCodeError> {
-        let random_code = (self.code_generator)();
-        let session = req.get_session();
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        store_mfa_user(ctx.session, ctx.user_id)?;
+        if self.config.is_locked(ctx.user_id) {
+            warn!("Too many wrong codes of this user, no code is sent");
+            return Ok(());
+        }
 
-        session
-            .insert(MFA_RANDOM_CODE_KEY, random_code.clone())
-            .map_err(|e| {
-                cleanup_and_unknown_error(&session, "Could not insert mfa code into session", e)
-            })?;
+        let random_code = (self.code_generator)();
+        if is_too_short(&random_code, self.config.min_length) {
+            warn!(
+                "Generated code has less than {} characters, short codes are easy to guess",
+                self.config.min_length
+            );
+        }
+        store_random_code(ctx.session, &random_code)?;
 
-        self.code_sender
-            .send_code(random_code)
-            .map_err(|e| cleanup_and_unknown_error(&session, "Could not send code to user", e))?;
+        self.code_sender.send_code(random_code).map_err(|e| {
+            cleanup_and_unknown_error(ctx.session, "Could not send code to user", e)
+        })?;
 
-        Ok(())
+        ctx.session
+            .insert(MFA_RANDOM_CODE_CHANNEL_KEY, self.code_sender.channel_info())
+            .map_err(|e| {
+                cleanup_and_unknown_error(ctx.session, "Could not insert channel into session", e)
+            })
     }
 
     fn get_unique_id(&self) -> String {
         "RNDCODE".to_owned()
     }
 
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        self.code_sender.estimated_delivery_time()
+    }
+
+    fn challenge(&self, req: &HttpRequest) -> ChallengeResponse {
+        let mut challenge = ChallengeResponse::new(self);
+        challenge.channel = req
+            .get_session()
+            .get::<ChannelInfo>(MFA_RANDOM_CODE_CHANNEL_KEY)
+            .ok()
+            .flatten();
+        challenge
+    }
+
     fn check_code(
         &self,
         code: &str,
         req: &HttpRequest,
     ) -> std::pin::Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
-        let session = req.get_session();
-        let owned_code = code.to_owned();
-
-        Box::pin(async move {
-            let random_code = session
-                .get::<RandomCode>(MFA_RANDOM_CODE_KEY)
-                .map_err(|_| {
-                    cleanup_and_unknown_code_error(
-                        &session,
-                        "Could not load random code from session",
-                    )
-                })?;
-
-            if let Some(random_code) = random_code {
-                let now = SystemTime::now();
-                if &now >= random_code.valid_until() {
-                    return Err(cleanup_and_time_is_up_error(&session));
-                }
+        check_random_code(
+            req.get_session(),
+            code.to_owned(),
+            self.case_sensitive,
+            self.config.clone(),
+        )
+    }
+}
 
-                if owned_code != random_code.value() {
-                    // ToDo: here we need to cound the attempts and reject finally with cleanup
-                    return Err(CheckCodeError::InvalidCode);
-                }
+/// Saves the user in the session, so that [check_random_code] counts wrong codes and locks per user
+///
+/// Must be called before the lock is checked, so that a locked user is rejected even if no code has been sent.
+pub(crate) fn store_mfa_user(session: &Session, user_id: &str) -> Result<(), GenerateCodeError> {
+    session
+        .insert(MFA_RANDOM_CODE_USER_KEY, user_id)
+        .map_err(|e| {
+            cleanup_and_unknown_error(session, "Could not insert mfa user into session", e)
+        })
+}
 
-                Ok(())
-            } else {
-                Err(cleanup_and_unknown_code_error(
-                    &session,
-                    "No random code in session",
-                ))
-            }
+/// Saves the code in the session, so that it can be checked by [check_random_code]
+pub(crate) fn store_random_code(
+    session: &Session,
+    random_code: &RandomCode,
+) -> Result<(), GenerateCodeError> {
+    session
+        .insert(MFA_RANDOM_CODE_KEY, random_code.clone())
+        .map_err(|e| {
+            cleanup_and_unknown_error(session, "Could not insert mfa code into session", e)
         })
+}
+
+/// Checks the given code against the one saved by [store_random_code]
+///
+/// Wrong codes are counted per user (see [store_mfa_user]), after [RandomCodeConfig::max_attempts] the mfa is locked.
+pub(crate) fn check_random_code(
+    session: Session,
+    code: String,
+    case_sensitive: bool,
+    config: RandomCodeConfig,
+) -> std::pin::Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+    Box::pin(async move {
+        let user_id = session
+            .get::<String>(MFA_RANDOM_CODE_USER_KEY)
+            .map_err(|_| {
+                cleanup_and_unknown_code_error(&session, "Could not load mfa user from session")
+            })?
+            .ok_or_else(|| cleanup_and_unknown_code_error(&session, "No mfa user in session"))?;
+
+        if config.is_locked(&user_id) {
+            return Err(CheckCodeError::TooManyAttempts);
+        }
+
+        let random_code = session
+            .get::<RandomCode>(MFA_RANDOM_CODE_KEY)
+            .map_err(|_| {
+                cleanup_and_unknown_code_error(&session, "Could not load random code from session")
+            })?;
+
+        if let Some(random_code) = random_code {
+            let now = SystemTime::now();
+            if &now >= random_code.valid_until() {
+                return Err(cleanup_and_time_is_up_error(&session));
+            }
+
+            if !codes_match(random_code.value(), &code, case_sensitive) {
+                return Err(count_failed_attempt(&session, &user_id, &config));
+            }
+
+            config.lock_store.reset(&user_id);
+            Ok(())
+        } else {
+            Err(cleanup_and_unknown_code_error(
+                &session,
+                "No random code in session",
+            ))
+        }
+    })
+}
+
+/// Removes the code saved by [store_random_code], so that it cannot be used again
+pub(crate) fn remove_random_code(session: &Session) {
+    session.remove(MFA_RANDOM_CODE_KEY);
+}
+
+fn count_failed_attempt(
+    session: &Session,
+    user_id: &str,
+    config: &RandomCodeConfig,
+) -> CheckCodeError {
+    let attempts = config.lock_store.record_failure(user_id);
+    if attempts < config.max_attempts {
+        return CheckCodeError::InvalidCode;
+    }
+
+    warn!(
+        "{attempts} wrong codes of '{user_id}', mfa is locked for {} seconds",
+        config.lockout_duration.as_secs()
+    );
+    config
+        .lock_store
+        .lock(user_id, SystemTime::now() + config.lockout_duration);
+    session.purge();
+    CheckCodeError::TooManyAttempts
+}
+
+fn is_too_short(random_code: &RandomCode, min_length: usize) -> bool {
+    random_code.value().chars().count() < min_length
+}
+
+fn codes_match(expected: &str, given: &str, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        expected == given
+    } else {
+        expected.to_lowercase() == given.to_lowercase()
     }
 }
 
-fn cleanup_and_unknown_error(
+pub(crate) fn cleanup_and_unknown_error(
     session: &Session,
     msg: &str,
     e: impl std::error::Error + 'static,
@@ -134,3 +644,251 @@ fn cleanup_and_time_is_up_error(session: &Session) -> CheckCodeError {
     session.purge();
     CheckCodeError::TimeIsUp("Code is no longer valid".to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        time::{Duration, SystemTime},
+    };
+
+    use actix_session::{Session, SessionExt};
+    use actix_web::test::TestRequest;
+
+    use super::{
+        check_random_code, codes_match, is_too_short, store_mfa_user, store_random_code, Charset,
+        CodeSender, CodeSenderExt, MfaRandomCode, PrioritizedMultiChannelSender, RandomCode,
+        RandomCodeConfig,
+    };
+    use crate::multifactor::{ChannelInfo, CheckCodeError, Factor, FactorContext};
+
+    struct FailingSender;
+
+    impl CodeSender for FailingSender {
+        type Error = std::fmt::Error;
+
+        fn send_code(&self, _: RandomCode) -> Result<(), Self::Error> {
+            Err(std::fmt::Error)
+        }
+    }
+
+    struct RecordingSender {
+        sent: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl CodeSender for RecordingSender {
+        type Error = std::fmt::Error;
+
+        fn send_code(&self, random_code: RandomCode) -> Result<(), Self::Error> {
+            self.sent.borrow_mut().push(random_code.value().to_owned());
+            Ok(())
+        }
+
+        fn channel_info(&self) -> ChannelInfo {
+            ChannelInfo::new("sms").with_destination("0170 1231234")
+        }
+    }
+
+    #[test]
+    fn prioritized_sender_should_fall_through_to_the_next_channel() {
+        let sms = Rc::new(RefCell::new(Vec::new()));
+        let backup = Rc::new(RefCell::new(Vec::new()));
+        let sender = PrioritizedMultiChannelSender::new()
+            .with_channel(FailingSender)
+            .with_channel(RecordingSender {
+                sent: Rc::clone(&sms),
+            })
+            .with_channel(RecordingSender {
+                sent: Rc::clone(&backup),
+            });
+
+        sender
+            .send_code(RandomCode::new("123456", SystemTime::now()))
+            .unwrap();
+
+        assert_eq!(*sms.borrow(), vec!["123456"]);
+        assert!(backup.borrow().is_empty());
+        assert_eq!(sender.channel_info().channel, "sms");
+    }
+
+    #[test]
+    fn channel_should_be_stored_in_the_session_and_sent_with_the_challenge() {
+        let factor = MfaRandomCode::new(
+            || RandomCode::generate_secure(6, Charset::Numeric),
+            RecordingSender {
+                sent: Rc::new(RefCell::new(Vec::new())),
+            },
+        );
+        let req = TestRequest::default().to_http_request();
+
+        factor
+            .generate_code(&FactorContext::from_request(
+                &req,
+                &req.get_session(),
+                "anna",
+            ))
+            .unwrap();
+
+        let channel = factor.challenge(&req).channel.unwrap();
+        assert_eq!(channel.channel, "sms");
+        assert_eq!(channel.masked_destination.as_deref(), Some("***-1234"));
+    }
+
+    #[test]
+    fn prioritized_sender_should_fail_if_all_channels_fail() {
+        let sender = PrioritizedMultiChannelSender::new()
+            .with_channel(FailingSender)
+            .with_channel(FailingSender);
+
+        assert!(sender
+            .send_code(RandomCode::generate_secure(6, Charset::Numeric))
+            .is_err());
+    }
+
+    #[test]
+    fn dry_run_should_not_call_the_sender() {
+        let sender = FailingSender.dry_run();
+
+        assert!(sender
+            .send_code(RandomCode::generate_secure(6, Charset::Numeric))
+            .is_ok());
+    }
+
+    #[test]
+    fn secure_code_should_have_requested_length_and_charset() {
+        let code = RandomCode::generate_secure(8, Charset::Numeric);
+
+        assert_eq!(code.value().len(), 8);
+        assert!(code.value().chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn secure_codes_should_not_be_equal() {
+        let code1 = RandomCode::generate_secure(32, Charset::Alphanumeric);
+        let code2 = RandomCode::generate_secure(32, Charset::Alphanumeric);
+
+        assert_ne!(code1.value(), code2.value());
+    }
+
+    #[test]
+    fn secure_code_should_be_valid_for_five_minutes() {
+        let code = RandomCode::generate_secure(6, Charset::UppercaseAlphanumeric);
+        let valid_for = code
+            .valid_until()
+            .duration_since(SystemTime::now())
+            .unwrap();
+
+        assert!(valid_for > Duration::from_secs(60 * 4));
+        assert!(valid_for <= Duration::from_secs(60 * 5));
+    }
+
+    #[test]
+    fn codes_should_match_ignoring_case_by_default() {
+        assert!(codes_match("123ABC", "123abc", false));
+        assert!(!codes_match("123ABC", "123abd", false));
+    }
+
+    #[test]
+    fn case_sensitive_codes_should_only_match_exactly() {
+        assert!(codes_match("123ABC", "123ABC", true));
+        assert!(!codes_match("123ABC", "123abc", true));
+    }
+
+    #[test]
+    fn codes_below_min_length_should_be_too_short() {
+        let default_min_length = RandomCodeConfig::default().min_length;
+
+        assert!(is_too_short(
+            &RandomCode::generate_secure(4, Charset::Numeric),
+            default_min_length
+        ));
+        assert!(!is_too_short(
+            &RandomCode::generate_secure(6, Charset::Numeric),
+            default_min_length
+        ));
+        assert!(!is_too_short(
+            &RandomCode::generate_secure(4, Charset::Numeric),
+            RandomCodeConfig::default().min_length(4).min_length
+        ));
+    }
+
+    fn session_with_code() -> Session {
+        let session = TestRequest::default().to_http_request().get_session();
+        let code = RandomCode::new("123abc", SystemTime::now() + Duration::from_secs(60));
+        store_mfa_user(&session, "anna").unwrap();
+        store_random_code(&session, &code).unwrap();
+        session
+    }
+
+    async fn check(
+        session: &Session,
+        config: &RandomCodeConfig,
+        code: &str,
+    ) -> Result<(), CheckCodeError> {
+        check_random_code(session.clone(), code.to_owned(), false, config.clone()).await
+    }
+
+    #[actix_rt::test]
+    async fn should_reject_with_too_many_attempts_at_exactly_max_attempts() {
+        let config = RandomCodeConfig::default();
+        let session = session_with_code();
+
+        assert!(matches!(
+            check(&session, &config, "wrong").await,
+            Err(CheckCodeError::InvalidCode)
+        ));
+        assert!(matches!(
+            check(&session, &config, "wrong").await,
+            Err(CheckCodeError::InvalidCode)
+        ));
+        assert!(matches!(
+            check(&session, &config, "wrong").await,
+            Err(CheckCodeError::TooManyAttempts)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn new_session_of_a_locked_user_should_stay_locked() {
+        let config = RandomCodeConfig::default();
+        let session = session_with_code();
+        for _ in 0..3 {
+            let _ = check(&session, &config, "wrong").await;
+        }
+
+        assert!(matches!(
+            check(&session_with_code(), &config, "123abc").await,
+            Err(CheckCodeError::TooManyAttempts)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn wrong_codes_should_be_counted_across_sessions_of_a_user() {
+        let config = RandomCodeConfig::default();
+        let _ = check(&session_with_code(), &config, "wrong").await;
+        let _ = check(&session_with_code(), &config, "wrong").await;
+
+        assert!(matches!(
+            check(&session_with_code(), &config, "wrong").await,
+            Err(CheckCodeError::TooManyAttempts)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn correct_code_should_reset_the_attempts() {
+        let config = RandomCodeConfig::default();
+        let session = session_with_code();
+        let _ = check(&session, &config, "wrong").await;
+        let _ = check(&session, &config, "wrong").await;
+
+        assert!(check(&session, &config, "123abc").await.is_ok());
+        assert!(matches!(
+            check(&session, &config, "wrong").await,
+            Err(CheckCodeError::InvalidCode)
+        ));
+        assert!(matches!(
+            check(&session, &config, "wrong").await,
+            Err(CheckCodeError::InvalidCode)
+        ));
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    future::{ready, Future},
+    pin::Pin,
+};
+
+use actix_session::{Session, SessionExt};
+use actix_web::HttpRequest;
+use log::debug;
+
+use super::{
+    ChallengeResponse, CheckCodeError, Factor, FactorContext, GenerateCodeError, StoredContext,
+};
+
+const SESSION_KEY_SEQUENCE_STEP: &str = "mfa_sequence_step";
+const SESSION_KEY_SEQUENCE_USER: &str = "mfa_sequence_user";
+
+/// Requires all factors one after another (e.g. first a code sent by mail, then TOTP)
+///
+/// The index of the current factor is stored in the session. After a correct code the next factor generates its code
+/// and the mfa route responds with its [ChallengeResponse] instead of completing the login.
+///
+/// # Examples
+/// ```ignore
+/// SessionLoginHandler::with_mfa_factors(
+///     user_service,
+///     vec![Box::new(MfaRandomCode::new(generator, mail_sender)), Box::new(totp)],
+/// )
+/// ```
+pub struct FactorSequence {
+    factors: Vec<Box<dyn Factor>>,
+}
+
+impl FactorSequence {
+    pub fn new(factors: Vec<Box<dyn Factor>>) -> Self {
+        Self { factors }
+    }
+
+    fn current_step(&self, session: &Session) -> Option<usize> {
+        session
+            .get::<usize>(SESSION_KEY_SEQUENCE_STEP)
+            .ok()?
+            .filter(|step| *step < self.factors.len())
+    }
+
+    fn current_factor(&self, req: &HttpRequest) -> Option<&dyn Factor> {
+        let step = self.current_step(&req.get_session())?;
+        Some(self.factors[step].as_ref())
+    }
+
+    fn start_step(&self, step: usize, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        ctx.session
+            .insert(SESSION_KEY_SEQUENCE_STEP, step)
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot store the mfa step", e))?;
+        self.factors[step].generate_code(ctx)
+    }
+}
+
+impl Factor for FactorSequence {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        if self.factors.is_empty() {
+            return Err(GenerateCodeError::new("No factor in the sequence"));
+        }
+
+        // the user id is needed for the next steps, which are started by the mfa route
+        ctx.session
+            .insert(SESSION_KEY_SEQUENCE_USER, ctx.user_id)
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot store the mfa user", e))?;
+        self.start_step(0, ctx)
+    }
+
+    fn get_unique_id(&self) -> String {
+        "SEQUENCE".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        match self.current_factor(req) {
+            Some(factor) => factor.check_code(code, req),
+            None => Box::pin(ready(Err(CheckCodeError::FinallyRejected))),
+        }
+    }
+
+    fn on_success(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        match self.current_factor(req) {
+            Some(factor) => factor.on_success(req),
+            None => Box::pin(ready(())),
+        }
+    }
+
+    fn on_failure(
+        &self,
+        error: &CheckCodeError,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        match self.current_factor(req) {
+            Some(factor) => factor.on_failure(error, req),
+            None => Box::pin(ready(())),
+        }
+    }
+
+    fn next_challenge(
+        &self,
+        req: &HttpRequest,
+    ) -> Result<Option<ChallengeResponse>, GenerateCodeError> {
+        let session = req.get_session();
+        let Some(step) = self.current_step(&session) else {
+            return Ok(None);
+        };
+
+        if let Some(challenge) = self.factors[step].next_challenge(req)? {
+            return Ok(Some(challenge));
+        }
+
+        let next_step = step + 1;
+        if next_step == self.factors.len() {
+            return Ok(None);
+        }
+
+        let user_id = session
+            .get::<String>(SESSION_KEY_SEQUENCE_USER)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        debug!("Mfa step {step} done, starting step {next_step}");
+        self.start_step(
+            next_step,
+            &FactorContext::from_request(req, &session, &user_id),
+        )?;
+        Ok(Some(self.factors[next_step].challenge(req)))
+    }
+
+    fn validate_context(&self, original_req: &StoredContext, current_req: &HttpRequest) -> bool {
+        self.current_factor(current_req)
+            .is_none_or(|factor| factor.validate_context(original_req, current_req))
+    }
+
+    fn challenge(&self, req: &HttpRequest) -> ChallengeResponse {
+        match self.current_factor(req) {
+            Some(factor) => factor.challenge(req),
+            None => ChallengeResponse::new(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{ready, Future},
+        pin::Pin,
+    };
+
+    use actix_session::SessionExt;
+    use actix_web::{test::TestRequest, HttpRequest};
+
+    use super::FactorSequence;
+    use crate::multifactor::{CheckCodeError, Factor, FactorContext, GenerateCodeError};
+
+    struct StaticFactor(&'static str);
+
+    impl Factor for StaticFactor {
+        fn generate_code(&self, _ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+            Ok(())
+        }
+
+        fn get_unique_id(&self) -> String {
+            self.0.to_owned()
+        }
+
+        fn check_code(
+            &self,
+            code: &str,
+            _req: &HttpRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+            let result = if code == self.0 {
+                Ok(())
+            } else {
+                Err(CheckCodeError::InvalidCode)
+            };
+            Box::pin(ready(result))
+        }
+    }
+
+    #[actix_rt::test]
+    async fn should_require_the_factors_one_after_another() {
+        let sequence = FactorSequence::new(vec![
+            Box::new(StaticFactor("MAIL")),
+            Box::new(StaticFactor("TOTP")),
+        ]);
+        let req = TestRequest::default().to_http_request();
+        let session = req.get_session();
+
+        sequence
+            .generate_code(&FactorContext::from_request(&req, &session, "anna"))
+            .unwrap();
+        assert_eq!(sequence.challenge(&req).factor, "MAIL");
+        assert!(sequence.check_code("TOTP", &req).await.is_err());
+        assert!(sequence.check_code("MAIL", &req).await.is_ok());
+
+        let next = sequence.next_challenge(&req).unwrap().unwrap();
+        assert_eq!(next.factor, "TOTP");
+        assert!(sequence.check_code("MAIL", &req).await.is_err());
+        assert!(sequence.check_code("TOTP", &req).await.is_ok());
+        assert!(sequence.next_challenge(&req).unwrap().is_none());
+    }
+
+    #[test]
+    fn empty_sequence_should_not_generate_a_code() {
+        let sequence = FactorSequence::new(vec![]);
+        let req = TestRequest::default().to_http_request();
+        let session = req.get_session();
+
+        assert!(sequence
+            .generate_code(&FactorContext::from_request(&req, &session, "anna"))
+            .is_err());
+    }
+}
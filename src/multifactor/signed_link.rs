@@ -0,0 +1,143 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::random_code_auth::{CodeSender, RandomCode};
+
+/// Delivers the link of [MfaSignedLink] to the user (e.g. by mail)
+pub trait LinkSender {
+    type Error: std::error::Error + 'static;
+    fn send_link(&self, link: &str) -> Result<(), Self::Error>;
+}
+
+/// Sends a signed link instead of the code, the user confirms the login by opening it
+///
+/// The link is `{base_url}/{token}`, the token contains the code of the login and its expiry, signed with HMAC-SHA256.
+/// The route `/login/confirm/{token}` is registered with
+/// [SessionLoginHandler::with_link_confirmation](crate::session::handlers::SessionLoginHandler::with_link_confirmation),
+/// it checks the signature and completes the login like the mfa route.
+///
+/// The code is stored in the session, so the link has to be opened in the browser that has started the login.
+/// The confirm route must be reachable without authentication (e.g. exclude `/login/confirm/*` in the [PathMatcher](crate::middleware::PathMatcher)).
+///
+/// # Examples
+/// ```ignore
+/// MfaRandomCode::new(
+///     generator,
+///     MfaSignedLink::new("https://example.org/login/confirm", secret, Duration::from_secs(600), mail_sender),
+/// )
+/// ```
+pub struct MfaSignedLink<T: LinkSender> {
+    base_url: String,
+    secret: [u8; 32],
+    ttl: Duration,
+    link_sender: T,
+}
+
+impl<T: LinkSender> MfaSignedLink<T> {
+    pub fn new(base_url: &str, secret: [u8; 32], ttl: Duration, link_sender: T) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            secret,
+            ttl,
+            link_sender,
+        }
+    }
+}
+
+impl<T: LinkSender> CodeSender for MfaSignedLink<T> {
+    type Error = T::Error;
+
+    fn send_code(&self, random_code: RandomCode) -> Result<(), Self::Error> {
+        let token = sign_token(
+            random_code.value(),
+            SystemTime::now() + self.ttl,
+            &self.secret,
+        );
+        self.link_sender
+            .send_link(&format!("{}/{token}", self.base_url))
+    }
+}
+
+fn mac(payload: &[u8], secret: &[u8; 32]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take a key of any size");
+    mac.update(payload);
+    mac
+}
+
+/// `base64url(code:expires).base64url(signature)`
+fn sign_token(code: &str, valid_until: SystemTime, secret: &[u8; 32]) -> String {
+    let expires = valid_until
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let payload = format!("{code}:{expires}");
+    let signature = mac(payload.as_bytes(), secret).finalize().into_bytes();
+
+    format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(payload),
+        BASE64_URL_SAFE_NO_PAD.encode(signature)
+    )
+}
+
+/// Returns the code of the token if the signature is valid and it has not expired
+pub(crate) fn verify_token(token: &str, secret: &[u8; 32]) -> Option<String> {
+    let (payload, signature) = token.split_once('.')?;
+    let payload = BASE64_URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let signature = BASE64_URL_SAFE_NO_PAD.decode(signature).ok()?;
+    mac(&payload, secret).verify_slice(&signature).ok()?;
+
+    let payload = String::from_utf8(payload).ok()?;
+    let (code, expires) = payload.rsplit_once(':')?;
+    let valid_until = UNIX_EPOCH + Duration::from_secs(expires.parse().ok()?);
+    if SystemTime::now() >= valid_until {
+        return None;
+    }
+
+    Some(code.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{sign_token, verify_token};
+
+    const SECRET: [u8; 32] = [7; 32];
+
+    #[test]
+    fn valid_token_should_contain_the_code() {
+        let token = sign_token(
+            "123abc",
+            SystemTime::now() + Duration::from_secs(60),
+            &SECRET,
+        );
+
+        assert_eq!(verify_token(&token, &SECRET).as_deref(), Some("123abc"));
+    }
+
+    #[test]
+    fn token_with_another_secret_should_be_rejected() {
+        let token = sign_token(
+            "123abc",
+            SystemTime::now() + Duration::from_secs(60),
+            &[8; 32],
+        );
+
+        assert!(verify_token(&token, &SECRET).is_none());
+    }
+
+    #[test]
+    fn expired_token_should_be_rejected() {
+        let token = sign_token(
+            "123abc",
+            SystemTime::now() - Duration::from_secs(1),
+            &SECRET,
+        );
+
+        assert!(verify_token(&token, &SECRET).is_none());
+    }
+}
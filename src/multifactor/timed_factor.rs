@@ -0,0 +1,101 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use actix_web::HttpRequest;
+use metrics::histogram;
+
+use super::{
+    ChallengeResponse, CheckCodeError, Factor, FactorContext, GenerateCodeError, StoredContext,
+};
+
+const CHECK_CODE_HISTOGRAM: &str = "authfix_mfa_check_code_duration_seconds";
+
+/// Wraps a [Factor] and records the latency of [Factor::check_code] with the [metrics](https://docs.rs/metrics/latest/metrics/) crate
+///
+/// The latency is recorded in seconds in the histogram `authfix_mfa_check_code_duration_seconds`
+/// with the labels `factor` (the unique id of the factor) and `outcome` (`success` or `failure`).
+/// A recorder (e.g. a Prometheus exporter) needs to be installed by the application.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new_with_factor(
+///     SessionAuthProvider::default(),
+///     PathMatcher::default(),
+///     Box::new(TimedFactor::new(Box::new(MfaRandomCode::new(generator, sender)))),
+/// )
+/// ```
+pub struct TimedFactor {
+    inner: Box<dyn Factor>,
+}
+
+impl TimedFactor {
+    pub fn new(inner: Box<dyn Factor>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Factor for TimedFactor {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        self.inner.generate_code(ctx)
+    }
+
+    fn get_unique_id(&self) -> String {
+        self.inner.get_unique_id()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let factor_id = self.inner.get_unique_id();
+        let start = Instant::now();
+        let check = self.inner.check_code(code, req);
+
+        Box::pin(async move {
+            let result = check.await;
+            let outcome = if result.is_ok() { "success" } else { "failure" };
+            histogram!(CHECK_CODE_HISTOGRAM, "factor" => factor_id, "outcome" => outcome)
+                .record(start.elapsed().as_secs_f64());
+            result
+        })
+    }
+
+    fn on_success(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.inner.on_success(req)
+    }
+
+    fn on_failure(
+        &self,
+        error: &CheckCodeError,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.inner.on_failure(error, req)
+    }
+
+    fn estimated_delivery_time(&self) -> Option<Duration> {
+        self.inner.estimated_delivery_time()
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.inner.is_streaming()
+    }
+
+    fn validate_context(&self, original_req: &StoredContext, current_req: &HttpRequest) -> bool {
+        self.inner.validate_context(original_req, current_req)
+    }
+
+    fn next_challenge(
+        &self,
+        req: &HttpRequest,
+    ) -> Result<Option<ChallengeResponse>, GenerateCodeError> {
+        self.inner.next_challenge(req)
+    }
+
+    fn challenge(&self, req: &HttpRequest) -> ChallengeResponse {
+        self.inner.challenge(req)
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    error::Error as StdError,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_session::{Session, SessionExt};
+use actix_web::HttpRequest;
+use google_authenticator::GoogleAuthenticator;
+use log::warn;
+
+use super::{
+    google_auth::TotpSecretGenerator, ChallengeResponse, CheckCodeError, Factor, FactorContext,
+    GenerateCodeError, QrCodeResponse,
+};
+
+const MFA_TOTP_USER_KEY: &str = "mfa_totp_user";
+const MFA_TOTP_PENDING_SECRET_KEY: &str = "mfa_totp_pending_secret";
+const TIME_STEP_SECONDS: u64 = 30;
+const CODE_LENGTH: usize = 6;
+
+/// Stores the TOTP secret and the last used time step of each user
+///
+/// The time step is needed to reject a code that has already been used (replay prevention).
+pub trait TotpSecretStore {
+    type Error: StdError + 'static;
+    fn get_secret(&self, user_id: &str) -> Result<Option<String>, Self::Error>;
+    /// Is called after the user has entered the first valid code for a new secret
+    fn save_secret(&self, user_id: &str, secret: &str) -> Result<(), Self::Error>;
+    fn last_used_step(&self, user_id: &str) -> Result<Option<u64>, Self::Error>;
+    fn set_last_used_step(&self, user_id: &str, step: u64) -> Result<(), Self::Error>;
+}
+
+/// TOTP ([RFC 6238](https://www.rfc-editor.org/rfc/rfc6238)) implementation of [Factor], e.g. for Google Authenticator or Authy
+///
+/// Accepts 6 digit codes with a period of 30 seconds. Codes of the adjacent periods are accepted as well (see [MfaTOTP::allowed_skew]),
+/// but every code can only be used once.
+///
+/// If the user has no secret yet, a new one is created and sent as [QrCodeResponse] with the challenge of the login.
+/// It is saved in the [TotpSecretStore] after the user has entered the first valid code.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new_with_factor(
+///     SessionAuthProvider::default(),
+///     PathMatcher::default(),
+///     Box::new(MfaTOTP::new(your_store, "MyApp").allowed_skew(2)),
+/// )
+/// ```
+pub struct MfaTOTP<S: TotpSecretStore> {
+    store: Arc<S>,
+    issuer: String,
+    allowed_skew: u64,
+}
+
+impl<S: TotpSecretStore> MfaTOTP<S> {
+    /// `issuer` is the name of the app shown in the authenticator app
+    pub fn new(store: S, issuer: &str) -> Self {
+        Self {
+            store: Arc::new(store),
+            issuer: issuer.to_owned(),
+            allowed_skew: 1,
+        }
+    }
+
+    /// Number of periods before and after the current one whose codes are accepted as well (default: `1`)
+    ///
+    /// Compensates clock differences between server and device, usually `1` or `2`.
+    pub fn allowed_skew(mut self, periods: u64) -> Self {
+        self.allowed_skew = periods;
+        self
+    }
+
+    fn qr_code(&self, secret: &str, user_id: &str) -> Option<QrCodeResponse> {
+        let issuer = urlencoding::encode(&self.issuer);
+        let provisioning_uri = format!(
+            "otpauth://totp/{issuer}:{}?secret={secret}&issuer={issuer}&digits={CODE_LENGTH}&period={TIME_STEP_SECONDS}",
+            urlencoding::encode(user_id)
+        );
+
+        let qr_code_svg = qrcode_generator::to_svg_to_string(
+            &provisioning_uri,
+            qrcode_generator::QrCodeEcc::Low,
+            200,
+            None::<&str>,
+        )
+        .inspect_err(|e| warn!("Cannot create QR code: {e}"))
+        .ok()?;
+
+        Some(QrCodeResponse {
+            provisioning_uri,
+            qr_code_svg,
+        })
+    }
+}
+
+impl<S: TotpSecretStore + 'static> Factor for MfaTOTP<S> {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        let secret = self
+            .store
+            .get_secret(ctx.user_id)
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot load TOTP secret", e))?;
+
+        ctx.session
+            .insert(MFA_TOTP_USER_KEY, ctx.user_id)
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot store TOTP user", e))?;
+
+        if secret.is_none() {
+            ctx.session
+                .insert(
+                    MFA_TOTP_PENDING_SECRET_KEY,
+                    TotpSecretGenerator::new().create_secret(),
+                )
+                .map_err(|e| GenerateCodeError::new_with_cause("Cannot store TOTP secret", e))?;
+        }
+
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        "TOTP".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let session = req.get_session();
+        let store = Arc::clone(&self.store);
+        let allowed_skew = self.allowed_skew;
+        let code = code.to_owned();
+
+        Box::pin(async move {
+            let user_id = session
+                .get::<String>(MFA_TOTP_USER_KEY)
+                .ok()
+                .flatten()
+                .ok_or_else(|| {
+                    CheckCodeError::UnknownError("No TOTP user in session".to_owned())
+                })?;
+            let pending_secret = pending_secret(&session);
+
+            let secret = match &pending_secret {
+                Some(secret) => secret.clone(),
+                None => store
+                    .get_secret(&user_id)
+                    .map_err(unknown_error)?
+                    .ok_or_else(|| CheckCodeError::UnknownError("No TOTP secret".to_owned()))?,
+            };
+
+            let step = matching_step(&secret, &code, current_step(), allowed_skew)
+                .ok_or(CheckCodeError::InvalidCode)?;
+
+            let last_used_step = store.last_used_step(&user_id).map_err(unknown_error)?;
+            if last_used_step.is_some_and(|last_used_step| step <= last_used_step) {
+                warn!("TOTP code has already been used");
+                return Err(CheckCodeError::InvalidCode);
+            }
+            store
+                .set_last_used_step(&user_id, step)
+                .map_err(unknown_error)?;
+
+            if let Some(secret) = pending_secret {
+                store
+                    .save_secret(&user_id, &secret)
+                    .map_err(unknown_error)?;
+                session.remove(MFA_TOTP_PENDING_SECRET_KEY);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn challenge(&self, req: &HttpRequest) -> ChallengeResponse {
+        let mut challenge = ChallengeResponse::new(self);
+        let session = req.get_session();
+
+        if let (Some(secret), Ok(Some(user_id))) = (
+            pending_secret(&session),
+            session.get::<String>(MFA_TOTP_USER_KEY),
+        ) {
+            challenge.qr_code = self.qr_code(&secret, &user_id);
+        }
+
+        challenge
+    }
+}
+
+fn pending_secret(session: &Session) -> Option<String> {
+    session
+        .get::<String>(MFA_TOTP_PENDING_SECRET_KEY)
+        .ok()
+        .flatten()
+}
+
+fn current_step() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() / TIME_STEP_SECONDS)
+        .unwrap_or_default()
+}
+
+/// Returns the time step of the code, if it matches one within `current_step ± allowed_skew`
+fn matching_step(secret: &str, code: &str, current_step: u64, allowed_skew: u64) -> Option<u64> {
+    if code.len() != CODE_LENGTH || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let authenticator = GoogleAuthenticator::new();
+    (current_step.saturating_sub(allowed_skew)..=current_step.saturating_add(allowed_skew)).find(
+        |step| {
+            authenticator
+                .get_code(secret, *step)
+                .is_ok_and(|expected| expected == code)
+        },
+    )
+}
+
+fn unknown_error(e: impl StdError) -> CheckCodeError {
+    CheckCodeError::UnknownError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, convert::Infallible, rc::Rc};
+
+    use actix_session::SessionExt;
+    use actix_web::test::TestRequest;
+    use google_authenticator::GoogleAuthenticator;
+
+    use super::{current_step, matching_step, MfaTOTP, TotpSecretStore};
+    use crate::multifactor::{google_auth::TotpSecretGenerator, Factor, FactorContext};
+
+    #[derive(Clone, Default)]
+    struct InMemoryStore {
+        secrets: Rc<RefCell<HashMap<String, String>>>,
+        last_used_steps: Rc<RefCell<HashMap<String, u64>>>,
+    }
+
+    impl TotpSecretStore for InMemoryStore {
+        type Error = Infallible;
+
+        fn get_secret(&self, user_id: &str) -> Result<Option<String>, Self::Error> {
+            Ok(self.secrets.borrow().get(user_id).cloned())
+        }
+
+        fn save_secret(&self, user_id: &str, secret: &str) -> Result<(), Self::Error> {
+            self.secrets
+                .borrow_mut()
+                .insert(user_id.to_owned(), secret.to_owned());
+            Ok(())
+        }
+
+        fn last_used_step(&self, user_id: &str) -> Result<Option<u64>, Self::Error> {
+            Ok(self.last_used_steps.borrow().get(user_id).copied())
+        }
+
+        fn set_last_used_step(&self, user_id: &str, step: u64) -> Result<(), Self::Error> {
+            self.last_used_steps
+                .borrow_mut()
+                .insert(user_id.to_owned(), step);
+            Ok(())
+        }
+    }
+
+    fn code(secret: &str, step: u64) -> String {
+        GoogleAuthenticator::new().get_code(secret, step).unwrap()
+    }
+
+    #[test]
+    fn code_of_the_current_period_should_be_valid() {
+        let secret = TotpSecretGenerator::new().create_secret();
+
+        assert_eq!(
+            matching_step(&secret, &code(&secret, 1000), 1000, 1),
+            Some(1000)
+        );
+    }
+
+    #[test]
+    fn code_of_the_previous_period_should_be_valid() {
+        let secret = TotpSecretGenerator::new().create_secret();
+
+        assert_eq!(
+            matching_step(&secret, &code(&secret, 999), 1000, 1),
+            Some(999)
+        );
+    }
+
+    #[test]
+    fn code_outside_of_the_allowed_skew_should_be_invalid() {
+        let secret = TotpSecretGenerator::new().create_secret();
+
+        assert_eq!(matching_step(&secret, &code(&secret, 997), 1000, 2), None);
+    }
+
+    #[actix_rt::test]
+    async fn code_should_not_be_accepted_twice() {
+        let store = InMemoryStore::default();
+        let secret = TotpSecretGenerator::new().create_secret();
+        store.save_secret("anna", &secret).unwrap();
+        let totp = MfaTOTP::new(store, "TestApp");
+
+        let req = TestRequest::default().to_http_request();
+        totp.generate_code(&FactorContext::from_request(
+            &req,
+            &req.get_session(),
+            "anna",
+        ))
+        .unwrap();
+
+        let code = code(&secret, current_step());
+        assert!(totp.check_code(&code, &req).await.is_ok());
+        assert!(totp.check_code(&code, &req).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn new_secret_should_be_saved_after_first_valid_code() {
+        let store = InMemoryStore::default();
+        let totp = MfaTOTP::new(store.clone(), "TestApp");
+
+        let req = TestRequest::default().to_http_request();
+        totp.generate_code(&FactorContext::from_request(
+            &req,
+            &req.get_session(),
+            "anna",
+        ))
+        .unwrap();
+
+        let qr_code = totp.challenge(&req).qr_code.unwrap();
+        let secret = qr_code
+            .provisioning_uri
+            .split("secret=")
+            .nth(1)
+            .and_then(|query| query.split('&').next())
+            .unwrap()
+            .to_owned();
+        assert!(store.get_secret("anna").unwrap().is_none());
+
+        totp.check_code(&code(&secret, current_step()), &req)
+            .await
+            .unwrap();
+
+        assert_eq!(store.get_secret("anna").unwrap(), Some(secret));
+        assert!(totp.challenge(&req).qr_code.is_none());
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    error::Error as StdError,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use log::warn;
+use rand::{rngs::OsRng, RngCore, TryRngCore};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ChallengeResponse, CheckCodeError, Factor, FactorContext, GenerateCodeError, WebAuthnChallenge,
+};
+
+const MFA_WEBAUTHN_CHALLENGE_KEY: &str = "mfa_webauthn_challenge";
+const CHALLENGE_LENGTH: usize = 32;
+
+/// The result of `navigator.credentials.get()`, sent as JSON string in the `code` of the mfa route
+///
+/// All binary values are base64url encoded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebAuthnAssertion {
+    /// The id of the credential
+    pub id: String,
+    pub response: AuthenticatorAssertionResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorAssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(default)]
+    pub user_handle: Option<String>,
+}
+
+/// The part of the client data that is checked by [MfaWebAuthn]
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony: String,
+    challenge: String,
+}
+
+/// Verifies the signature of the assertion, e.g. with `webauthn-rs`
+pub trait WebAuthnVerifier {
+    type Error: StdError + 'static;
+    /// Returns `false` if the assertion is not valid for the user
+    ///
+    /// [MfaWebAuthn] has already checked that the client data belongs to `challenge`.
+    /// The verifier has to check the origin, the credential of the user, the signature and the signature counter.
+    fn verify(
+        &self,
+        user_id: &str,
+        challenge: &WebAuthnChallenge,
+        assertion: &WebAuthnAssertion,
+    ) -> Result<bool, Self::Error>;
+}
+
+/// The challenge in the session, with the user it has been created for
+#[derive(Serialize, Deserialize)]
+struct PendingChallenge {
+    user_id: String,
+    challenge: WebAuthnChallenge,
+    valid_until: SystemTime,
+}
+
+/// WebAuthn (passkeys, security keys) as second factor
+///
+/// This is a stub: it creates the challenge and checks that the assertion belongs to it,
+/// but the signature is verified by the [WebAuthnVerifier]. The registration of credentials is up to the app.
+///
+/// The login route responds with the challenge in [ChallengeResponse::webauthn]. The client calls
+/// `navigator.credentials.get()` with it and sends the serialised [WebAuthnAssertion] as `code` to the mfa route.
+///
+/// # Examples
+/// ```ignore
+/// AuthMiddleware::<_, User>::new_with_factor(
+///     SessionAuthProvider::default(),
+///     PathMatcher::default(),
+///     Box::new(MfaWebAuthn::new(your_verifier, "example.org")),
+/// )
+/// ```
+pub struct MfaWebAuthn<V: WebAuthnVerifier> {
+    verifier: Arc<V>,
+    rp_id: String,
+    timeout: Duration,
+}
+
+impl<V: WebAuthnVerifier> MfaWebAuthn<V> {
+    /// `rp_id` is the domain of the app, credentials are bound to it
+    pub fn new(verifier: V, rp_id: &str) -> Self {
+        Self {
+            verifier: Arc::new(verifier),
+            rp_id: rp_id.to_owned(),
+            timeout: Duration::from_secs(60 * 2),
+        }
+    }
+
+    /// How long the user has to complete the assertion (default: 2 minutes)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl<V: WebAuthnVerifier + 'static> Factor for MfaWebAuthn<V> {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        let mut bytes = [0u8; CHALLENGE_LENGTH];
+        OsRng.unwrap_err().fill_bytes(&mut bytes);
+
+        let pending = PendingChallenge {
+            user_id: ctx.user_id.to_owned(),
+            challenge: WebAuthnChallenge {
+                challenge: BASE64_URL_SAFE_NO_PAD.encode(bytes),
+                rp_id: self.rp_id.clone(),
+                timeout: self.timeout.as_millis() as u64,
+            },
+            valid_until: SystemTime::now() + self.timeout,
+        };
+
+        ctx.session
+            .insert(MFA_WEBAUTHN_CHALLENGE_KEY, pending)
+            .map_err(|e| GenerateCodeError::new_with_cause("Cannot store WebAuthn challenge", e))
+    }
+
+    fn get_unique_id(&self) -> String {
+        "WEBAUTHN".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let session = req.get_session();
+        let verifier = Arc::clone(&self.verifier);
+        let assertion = serde_json::from_str::<WebAuthnAssertion>(code);
+
+        Box::pin(async move {
+            let pending = session
+                .get::<PendingChallenge>(MFA_WEBAUTHN_CHALLENGE_KEY)
+                .ok()
+                .flatten()
+                .ok_or_else(|| {
+                    CheckCodeError::UnknownError("No WebAuthn challenge in session".to_owned())
+                })?;
+            if SystemTime::now() >= pending.valid_until {
+                session.remove(MFA_WEBAUTHN_CHALLENGE_KEY);
+                return Err(CheckCodeError::TimeIsUp(
+                    "WebAuthn challenge is no longer valid".to_owned(),
+                ));
+            }
+
+            let Ok(assertion) = assertion else {
+                warn!("Code is not a WebAuthn assertion");
+                return Err(CheckCodeError::InvalidCode);
+            };
+            if !belongs_to(&assertion, &pending.challenge) {
+                warn!("WebAuthn assertion does not belong to the challenge");
+                return Err(CheckCodeError::InvalidCode);
+            }
+
+            let is_valid = verifier
+                .verify(&pending.user_id, &pending.challenge, &assertion)
+                .map_err(|e| CheckCodeError::UnknownError(e.to_string()))?;
+            if !is_valid {
+                return Err(CheckCodeError::InvalidCode);
+            }
+
+            session.remove(MFA_WEBAUTHN_CHALLENGE_KEY);
+            Ok(())
+        })
+    }
+
+    fn challenge(&self, req: &HttpRequest) -> ChallengeResponse {
+        let mut challenge = ChallengeResponse::new(self);
+        challenge.webauthn = req
+            .get_session()
+            .get::<PendingChallenge>(MFA_WEBAUTHN_CHALLENGE_KEY)
+            .ok()
+            .flatten()
+            .map(|pending| pending.challenge);
+        challenge
+    }
+}
+
+/// Whether the client data of the assertion has been created by `navigator.credentials.get()` for the challenge
+fn belongs_to(assertion: &WebAuthnAssertion, challenge: &WebAuthnChallenge) -> bool {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(&assertion.response.client_data_json)
+        .ok()
+        .and_then(|json| serde_json::from_slice::<ClientData>(&json).ok())
+        .is_some_and(|client_data| {
+            client_data.ceremony == "webauthn.get" && client_data.challenge == challenge.challenge
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use actix_session::SessionExt;
+    use actix_web::{test::TestRequest, HttpRequest};
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+
+    use super::{AuthenticatorAssertionResponse, MfaWebAuthn, WebAuthnAssertion, WebAuthnVerifier};
+    use crate::multifactor::{CheckCodeError, Factor, FactorContext, WebAuthnChallenge};
+
+    /// Accepts the signature `valid` of anna
+    struct FakeVerifier;
+
+    impl WebAuthnVerifier for FakeVerifier {
+        type Error = Infallible;
+
+        fn verify(
+            &self,
+            user_id: &str,
+            _: &WebAuthnChallenge,
+            assertion: &WebAuthnAssertion,
+        ) -> Result<bool, Self::Error> {
+            Ok(user_id == "anna" && assertion.response.signature == "valid")
+        }
+    }
+
+    fn login(factor: &MfaWebAuthn<FakeVerifier>) -> (HttpRequest, WebAuthnChallenge) {
+        let req = TestRequest::default().to_http_request();
+        factor
+            .generate_code(&FactorContext::from_request(
+                &req,
+                &req.get_session(),
+                "anna",
+            ))
+            .unwrap();
+        let challenge = factor.challenge(&req).webauthn.unwrap();
+        (req, challenge)
+    }
+
+    fn assertion(ceremony: &str, challenge: &str, signature: &str) -> String {
+        let client_data = format!("{{\"type\":\"{ceremony}\",\"challenge\":\"{challenge}\"}}");
+        serde_json::to_string(&WebAuthnAssertion {
+            id: "credential".to_owned(),
+            response: AuthenticatorAssertionResponse {
+                client_data_json: BASE64_URL_SAFE_NO_PAD.encode(client_data),
+                authenticator_data: String::new(),
+                signature: signature.to_owned(),
+                user_handle: None,
+            },
+        })
+        .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn challenge_should_be_sent_to_the_client() {
+        let factor = MfaWebAuthn::new(FakeVerifier, "example.org");
+        let (_, challenge) = login(&factor);
+
+        assert_eq!(challenge.rp_id, "example.org");
+        assert_eq!(challenge.timeout, 120_000);
+        assert_eq!(
+            BASE64_URL_SAFE_NO_PAD
+                .decode(&challenge.challenge)
+                .unwrap()
+                .len(),
+            32
+        );
+    }
+
+    #[actix_rt::test]
+    async fn verified_assertion_should_clear_the_challenge() {
+        let factor = MfaWebAuthn::new(FakeVerifier, "example.org");
+        let (req, challenge) = login(&factor);
+        let code = assertion("webauthn.get", &challenge.challenge, "valid");
+
+        assert!(factor.check_code(&code, &req).await.is_ok());
+        assert!(factor.challenge(&req).webauthn.is_none());
+        assert!(factor.check_code(&code, &req).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn assertion_of_another_challenge_should_be_rejected() {
+        let factor = MfaWebAuthn::new(FakeVerifier, "example.org");
+        let (req, challenge) = login(&factor);
+
+        for code in [
+            assertion("webauthn.get", "other", "valid"),
+            assertion("webauthn.create", &challenge.challenge, "valid"),
+            assertion("webauthn.get", &challenge.challenge, "invalid"),
+            "123456".to_owned(),
+        ] {
+            assert!(matches!(
+                factor.check_code(&code, &req).await,
+                Err(CheckCodeError::InvalidCode)
+            ));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn expired_challenge_should_be_rejected() {
+        let factor = MfaWebAuthn::new(FakeVerifier, "example.org").timeout(Duration::ZERO);
+        let (req, challenge) = login(&factor);
+        let code = assertion("webauthn.get", &challenge.challenge, "valid");
+
+        assert!(matches!(
+            factor.check_code(&code, &req).await,
+            Err(CheckCodeError::TimeIsUp(_))
+        ));
+    }
+}
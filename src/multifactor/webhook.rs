@@ -0,0 +1,92 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use log::{debug, warn};
+use serde::Serialize;
+use sha2::Sha256;
+use thiserror::Error;
+
+use super::random_code_auth::{CodeSender, RandomCode};
+use crate::signature::SIGNATURE_HEADER;
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    code: &'a str,
+    /// RFC 3339
+    expires_at: String,
+}
+
+/// Sends the code as JSON (`{ "code": "...", "expires_at": "..." }`) with an HTTP POST to a webhook (e.g. of an enterprise messaging system)
+///
+/// The body is signed with HMAC-SHA256 and the base64 encoded signature is sent in the `X-Signature` header.
+///
+/// The request is sent in the background on the Actix runtime, so a failed delivery is only logged.
+///
+/// # Examples
+/// ```ignore
+/// MfaRandomCode::new(generator, WebhookCodeSender::new("https://hooks.example.org/mfa", "shared-secret"))
+/// ```
+pub struct WebhookCodeSender {
+    url: String,
+    secret: String,
+    client: reqwest::Client,
+}
+
+impl WebhookCodeSender {
+    pub fn new(url: &str, secret: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            secret: secret.to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Uses a preconfigured client (e.g. with timeouts or a proxy)
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    fn sign(&self, body: &[u8]) -> String {
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(body);
+        BASE64_STANDARD.encode(mac.finalize().into_bytes())
+    }
+}
+
+impl CodeSender for WebhookCodeSender {
+    type Error = WebhookError;
+
+    fn send_code(&self, random_code: RandomCode) -> Result<(), Self::Error> {
+        let body = serde_json::to_vec(&WebhookPayload {
+            code: random_code.value(),
+            expires_at: DateTime::<Utc>::from(*random_code.valid_until())
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        })?;
+
+        let request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, self.sign(&body))
+            .body(body);
+
+        let url = self.url.clone();
+        actix_web::rt::spawn(async move {
+            match request.send().await.and_then(|res| res.error_for_status()) {
+                Ok(_) => debug!("Code sent to webhook {url}"),
+                Err(e) => warn!("Cannot send code to webhook {url}: {e}"),
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WebhookError {
+    #[error("Cannot create webhook payload: {0}")]
+    Payload(#[from] serde_json::Error),
+}
@@ -0,0 +1,234 @@
+//! Verification of password hashes for a [LoadUserService]
+//!
+//! [VerifyingLoadUserService] loads the user together with the stored hash from a [PasswordHashStore]
+//! and checks the password with a [PasswordVerifier], so the hashing algorithm can be swapped without a custom [LoadUserService].
+//! See [SessionLoginHandler::with_password_verifier](crate::session::handlers::SessionLoginHandler::with_password_verifier).
+//!
+//! The built-in verifiers need the features `argon2` (`Argon2Verifier`) and `bcrypt` (`BcryptVerifier`), they do not exist without them:
+#![cfg_attr(
+    not(feature = "argon2"),
+    doc = "```compile_fail\nuse authfix::password::Argon2Verifier;\n```"
+)]
+#![cfg_attr(
+    not(feature = "bcrypt"),
+    doc = "```compile_fail\nuse authfix::password::BcryptVerifier;\n```"
+)]
+use actix_web::HttpRequest;
+use futures::future::LocalBoxFuture;
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::login::{HandlerError, LoadUserError, LoadUserService, LoginToken};
+
+/// Checks a password against a stored hash (e.g. Argon2 or bcrypt)
+pub trait PasswordVerifier: Send + Sync {
+    /// `Ok(false)` if the password is wrong, [VerifyError] if the hash cannot be checked (e.g. unknown format)
+    fn verify(&self, raw: &str, hash: &str) -> Result<bool, VerifyError>;
+}
+
+#[derive(Error, Debug)]
+pub enum VerifyError {
+    #[error("Invalid password hash: {0}")]
+    InvalidHash(String),
+}
+
+/// Checks passwords against Argon2 hashes in the PHC string format (`$argon2id$v=19$...`)
+#[cfg(feature = "argon2")]
+#[derive(Clone, Copy, Default)]
+pub struct Argon2Verifier;
+
+#[cfg(feature = "argon2")]
+impl PasswordVerifier for Argon2Verifier {
+    fn verify(&self, raw: &str, hash: &str) -> Result<bool, VerifyError> {
+        use argon2::{password_hash::Error, Argon2, PasswordVerifier as _};
+
+        match Argon2::default().verify_password(raw.as_bytes(), hash) {
+            Ok(()) => Ok(true),
+            Err(Error::PasswordInvalid) => Ok(false),
+            Err(e) => Err(VerifyError::InvalidHash(e.to_string())),
+        }
+    }
+}
+
+/// Checks passwords against bcrypt hashes (`$2b$12$...`)
+#[cfg(feature = "bcrypt")]
+#[derive(Clone, Copy, Default)]
+pub struct BcryptVerifier;
+
+#[cfg(feature = "bcrypt")]
+impl PasswordVerifier for BcryptVerifier {
+    fn verify(&self, raw: &str, hash: &str) -> Result<bool, VerifyError> {
+        bcrypt::verify(raw, hash).map_err(|e| VerifyError::InvalidHash(e.to_string()))
+    }
+}
+
+/// Compares the password with the "hash" as plain text, **only meant for tests**
+#[cfg(any(test, feature = "testing"))]
+#[derive(Clone, Copy, Default)]
+pub struct PlainTextVerifier;
+
+#[cfg(any(test, feature = "testing"))]
+impl PasswordVerifier for PlainTextVerifier {
+    fn verify(&self, raw: &str, hash: &str) -> Result<bool, VerifyError> {
+        Ok(raw == hash)
+    }
+}
+
+/// A user and the stored hash of its password
+pub type UserWithHash<U> = (U, String);
+
+/// Loads a user and the hash of its password (e.g. from a database)
+pub trait PasswordHashStore: Send + Sync {
+    type User: DeserializeOwned + Serialize + Clone;
+
+    /// Returns `None` if there is no user with this name
+    fn load_user_with_hash(
+        &self,
+        username: &str,
+    ) -> LocalBoxFuture<'_, Result<Option<UserWithHash<Self::User>>, LoadUserError>>;
+}
+
+/// [LoadUserService] that checks the password of the user from a [PasswordHashStore] with a [PasswordVerifier]
+///
+/// # Examples
+/// ```ignore
+/// SessionLoginHandler::with_mfa(VerifyingLoadUserService::new(UserRepository::new(pool), Argon2Verifier))
+/// ```
+pub struct VerifyingLoadUserService<S, V> {
+    store: S,
+    verifier: V,
+}
+
+impl<S, V> VerifyingLoadUserService<S, V>
+where
+    S: PasswordHashStore,
+    V: PasswordVerifier,
+{
+    pub fn new(store: S, verifier: V) -> Self {
+        Self { store, verifier }
+    }
+}
+
+impl<S, V> LoadUserService for VerifyingLoadUserService<S, V>
+where
+    S: PasswordHashStore,
+    V: PasswordVerifier,
+{
+    type User = S::User;
+
+    fn load_user(
+        &self,
+        login_token: &LoginToken,
+    ) -> LocalBoxFuture<'_, Result<Self::User, LoadUserError>> {
+        let username = login_token.username.clone();
+        let password = login_token.password.clone();
+
+        Box::pin(async move {
+            let Some((user, hash)) = self.store.load_user_with_hash(&username).await? else {
+                return Err(LoadUserError::LoginFailed);
+            };
+
+            match self.verifier.verify(&password, &hash) {
+                Ok(true) => Ok(user),
+                Ok(false) => Err(LoadUserError::LoginFailed),
+                Err(e) => {
+                    warn!("Cannot verify the password of '{username}': {e}");
+                    Err(LoadUserError::LoginFailed)
+                }
+            }
+        })
+    }
+
+    fn on_success_handler(
+        &self,
+        _req: &HttpRequest,
+        _user: &Self::User,
+    ) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn on_error_handler(&self, _req: &HttpRequest) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::LocalBoxFuture;
+
+    use super::{PasswordHashStore, PlainTextVerifier, UserWithHash, VerifyingLoadUserService};
+    use crate::login::{LoadUserError, LoadUserService, LoginToken};
+
+    struct SingleUserStore;
+
+    impl PasswordHashStore for SingleUserStore {
+        type User = String;
+
+        fn load_user_with_hash(
+            &self,
+            username: &str,
+        ) -> LocalBoxFuture<'_, Result<Option<UserWithHash<Self::User>>, LoadUserError>> {
+            let user = (username == "anna").then(|| ("anna".to_owned(), "secret".to_owned()));
+            Box::pin(async move { Ok(user) })
+        }
+    }
+
+    fn login_token(username: &str, password: &str) -> LoginToken {
+        LoginToken {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn should_load_user_if_password_matches_the_hash() {
+        let service = VerifyingLoadUserService::new(SingleUserStore, PlainTextVerifier);
+
+        let user = service.load_user(&login_token("anna", "secret")).await;
+
+        assert_eq!(user.unwrap(), "anna");
+    }
+
+    #[actix_rt::test]
+    async fn should_fail_for_wrong_password_or_unknown_user() {
+        let service = VerifyingLoadUserService::new(SingleUserStore, PlainTextVerifier);
+
+        assert!(service
+            .load_user(&login_token("anna", "wrong"))
+            .await
+            .is_err());
+        assert!(service
+            .load_user(&login_token("bob", "secret"))
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "argon2")]
+    #[test]
+    fn argon2_verifier_should_check_the_password() {
+        use super::{Argon2Verifier, PasswordVerifier};
+        use argon2::{Argon2, PasswordHasher};
+
+        let hash = Argon2::default()
+            .hash_password(b"secret")
+            .unwrap()
+            .to_string();
+
+        assert!(Argon2Verifier.verify("secret", &hash).unwrap());
+        assert!(!Argon2Verifier.verify("wrong", &hash).unwrap());
+        assert!(Argon2Verifier.verify("secret", "no hash").is_err());
+    }
+
+    #[cfg(feature = "bcrypt")]
+    #[test]
+    fn bcrypt_verifier_should_check_the_password() {
+        use super::{BcryptVerifier, PasswordVerifier};
+
+        let hash = bcrypt::hash("secret", 4).unwrap();
+
+        assert!(BcryptVerifier.verify("secret", &hash).unwrap());
+        assert!(!BcryptVerifier.verify("wrong", &hash).unwrap());
+        assert!(BcryptVerifier.verify("secret", "no hash").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+//! PKCE ([RFC 7636](https://www.rfc-editor.org/rfc/rfc7636)) for single-page apps that log in with a redirect flow
+//!
+//! A public client cannot keep a secret, so an intercepted authorization code could be exchanged by anyone.
+//! With PKCE the client creates a random `code_verifier` and sends only its hash as `code_challenge` with the authorization request.
+//! The code can only be exchanged with the `code_verifier`, which never left the client.
+//!
+//! [PkceProtection] checks both requests: the authorization request needs `code_challenge` and `code_challenge_method=S256`
+//! as query parameters, the challenge is stored in the session. The token request needs `code_verifier` as query parameter,
+//! the middleware rejects it with `400 Bad Request` if the verifier does not match the challenge.
+//! Every challenge can only be used once, also if the verification fails.
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use actix_session::SessionExt;
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorBadRequest,
+    web::Query,
+    Error,
+};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use futures::future::LocalBoxFuture;
+use log::{debug, warn};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const SESSION_KEY_PKCE_CODE_CHALLENGE: &str = "pkce_code_challenge";
+// base64url of a SHA-256 hash without padding
+const CODE_CHALLENGE_LENGTH: usize = 43;
+
+/// Companion middleware that protects the code exchange of a redirect flow with PKCE
+///
+/// Only `S256` is supported, the `plain` method does not protect against interception.
+///
+/// # Examples
+/// ```ignore
+/// App::new()
+///     .wrap(PkceProtection::new("/authorize", "/token"))
+///     .wrap(AuthMiddleware::<_, User>::new(SessionAuthProvider::default(), PathMatcher::default()))
+///     .wrap(session_middleware)
+/// ```
+#[derive(Clone)]
+pub struct PkceProtection {
+    authorization_path: Rc<String>,
+    token_path: Rc<String>,
+}
+
+impl PkceProtection {
+    /// `authorization_path` receives the `code_challenge`, `token_path` the `code_verifier`
+    pub fn new(authorization_path: &str, token_path: &str) -> Self {
+        Self {
+            authorization_path: Rc::new(authorization_path.to_owned()),
+            token_path: Rc::new(token_path.to_owned()),
+        }
+    }
+}
+
+/// The `code_challenge` for `code_verifier` (`S256`), e.g. for Rust clients and tests
+pub fn code_challenge(code_verifier: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[derive(Deserialize)]
+struct AuthorizationParams {
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenParams {
+    code_verifier: Option<String>,
+}
+
+fn challenge_of_request(query: &str) -> Result<String, &'static str> {
+    let params = Query::<AuthorizationParams>::from_query(query)
+        .map_err(|_| "Invalid PKCE parameters")?
+        .into_inner();
+
+    if params.code_challenge_method.as_deref() != Some("S256") {
+        return Err("code_challenge_method must be S256");
+    }
+    match params.code_challenge {
+        Some(challenge)
+            if challenge.len() == CODE_CHALLENGE_LENGTH
+                && BASE64_URL_SAFE_NO_PAD.decode(&challenge).is_ok() =>
+        {
+            Ok(challenge)
+        }
+        _ => Err("Missing or invalid code_challenge"),
+    }
+}
+
+// 43 to 128 unreserved characters, see RFC 7636 section 4.1
+fn is_valid_verifier(code_verifier: &str) -> bool {
+    (43..=128).contains(&code_verifier.len())
+        && code_verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+}
+
+pub struct PkceProtectionInner<S> {
+    service: Rc<S>,
+    authorization_path: Rc<String>,
+    token_path: Rc<String>,
+}
+
+impl<S, B> Service<ServiceRequest> for PkceProtectionInner<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        if req.path() == self.authorization_path.as_str() {
+            let challenge = match challenge_of_request(req.query_string()) {
+                Ok(challenge) => challenge,
+                Err(msg) => {
+                    debug!("Authorization request without valid PKCE challenge: {msg}");
+                    return Box::pin(ready(Ok(req
+                        .error_response(ErrorBadRequest(msg))
+                        .map_into_right_body())));
+                }
+            };
+
+            return Box::pin(async move {
+                let res = service.call(req).await?;
+                // after the handler, so that a renewed session (e.g. by the login) keeps the challenge
+                if res.status().is_success() {
+                    if let Err(e) = res
+                        .request()
+                        .get_session()
+                        .insert(SESSION_KEY_PKCE_CODE_CHALLENGE, challenge)
+                    {
+                        warn!("Could not store PKCE challenge in session: {e}");
+                    }
+                }
+                Ok(res.map_into_left_body())
+            });
+        }
+
+        if req.path() == self.token_path.as_str() {
+            let session = req.get_session();
+            let challenge = session
+                .remove_as::<String>(SESSION_KEY_PKCE_CODE_CHALLENGE)
+                .and_then(Result::ok);
+            let code_verifier = Query::<TokenParams>::from_query(req.query_string())
+                .ok()
+                .and_then(|params| params.into_inner().code_verifier);
+
+            let is_verified = match (challenge, code_verifier) {
+                (Some(challenge), Some(code_verifier)) => {
+                    is_valid_verifier(&code_verifier) && code_challenge(&code_verifier) == challenge
+                }
+                _ => false,
+            };
+            if !is_verified {
+                debug!("Token request without matching PKCE code_verifier");
+                // a response instead of an error, so that the session middleware saves the removed challenge
+                return Box::pin(ready(Ok(req
+                    .error_response(ErrorBadRequest("Invalid code_verifier"))
+                    .map_into_right_body())));
+            }
+        }
+
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for PkceProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = PkceProtectionInner<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(PkceProtectionInner {
+            service: Rc::new(service),
+            authorization_path: Rc::clone(&self.authorization_path),
+            token_path: Rc::clone(&self.token_path),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{challenge_of_request, code_challenge, is_valid_verifier};
+
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+    #[test]
+    fn challenge_should_match_the_example_of_the_rfc() {
+        assert_eq!(
+            code_challenge(VERIFIER),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn only_s256_challenges_should_be_accepted() {
+        let challenge = code_challenge(VERIFIER);
+
+        assert_eq!(
+            challenge_of_request(&format!(
+                "code_challenge={challenge}&code_challenge_method=S256"
+            )),
+            Ok(challenge.clone())
+        );
+        assert!(challenge_of_request(&format!(
+            "code_challenge={challenge}&code_challenge_method=plain"
+        ))
+        .is_err());
+        assert!(challenge_of_request(&format!("code_challenge={challenge}")).is_err());
+        assert!(challenge_of_request("code_challenge=short&code_challenge_method=S256").is_err());
+    }
+
+    #[test]
+    fn verifier_should_have_43_to_128_unreserved_characters() {
+        assert!(is_valid_verifier(VERIFIER));
+        assert!(is_valid_verifier(&"a~.-_".repeat(25)));
+        assert!(!is_valid_verifier(&VERIFIER[1..]));
+        assert!(!is_valid_verifier(&"a".repeat(129)));
+        assert!(!is_valid_verifier(&format!("{VERIFIER}+")));
+    }
+}
@@ -0,0 +1,239 @@
+//! Rate limiting of failed logins
+//!
+//! A [RateLimiter] counts failed logins per client (the peer address, or the address from `X-Forwarded-For` if the
+//! login handler [trusts proxy headers](crate::session::handlers::SessionLoginHandler::trust_proxy_headers)).
+//! Once a client is locked, the login route responds with `429 Too Many Requests` without checking the credentials.
+//! A successful login does not reset the failures, otherwise a client could lift its lock by logging into its own account.
+//! Register it with [SessionLoginHandler::with_rate_limiter](crate::session::handlers::SessionLoginHandler::with_rate_limiter).
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+// stale entries are removed once there are this many clients, so that the map does not grow without limit
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Counts failed logins and decides if a client is locked
+///
+/// Implementations must be shareable between the workers of the server (e.g. an in-memory map behind a mutex or Redis).
+pub trait RateLimiter: Send + Sync {
+    /// Records a failed login and returns the number of failures within the current window
+    fn record_failure(&self, key: &str) -> u32;
+    /// Returns the remaining lockout if the client is locked
+    fn is_locked(&self, key: &str) -> Option<Duration>;
+    /// Forgets the failures of a client, e.g. if an admin unlocks it (not called by the login handler)
+    fn reset(&self, key: &str);
+}
+
+impl<R: RateLimiter> RateLimiter for Arc<R> {
+    fn record_failure(&self, key: &str) -> u32 {
+        self.as_ref().record_failure(key)
+    }
+
+    fn is_locked(&self, key: &str) -> Option<Duration> {
+        self.as_ref().is_locked(key)
+    }
+
+    fn reset(&self, key: &str) {
+        self.as_ref().reset(key)
+    }
+}
+
+/// When a client is locked by the [InMemoryRateLimiter]
+#[derive(Clone, Debug)]
+pub struct RateLimitPolicy {
+    max_attempts: u32,
+    window: Duration,
+    lockout_duration: Duration,
+}
+
+impl RateLimitPolicy {
+    /// The client is locked after `max_attempts` failures within the window (default: `5`)
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Failures older than `window` are not counted (default: 15 minutes)
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// How long a client is locked (default: 15 minutes)
+    pub fn lockout_duration(mut self, lockout_duration: Duration) -> Self {
+        self.lockout_duration = lockout_duration;
+        self
+    }
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            window: Duration::from_secs(60 * 15),
+            lockout_duration: Duration::from_secs(60 * 15),
+        }
+    }
+}
+
+struct Failures {
+    count: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    fn is_stale(&self, now: Instant, window: Duration) -> bool {
+        let is_locked = self
+            .locked_until
+            .is_some_and(|locked_until| locked_until > now);
+        !is_locked && now.duration_since(self.window_start) > window
+    }
+}
+
+/// [RateLimiter] that keeps the failures in memory, so they are not shared between instances of the app
+///
+/// Create it once and pass a clone of the [Arc] to the login handler of every worker:
+/// ```ignore
+/// let rate_limiter = Arc::new(InMemoryRateLimiter::new(RateLimitPolicy::default().max_attempts(3)));
+///
+/// HttpServer::new(move || {
+///     App::new().configure(login_config(
+///         SessionLoginHandler::new(user_service).with_rate_limiter(Arc::clone(&rate_limiter)),
+///     ))
+/// })
+/// ```
+#[derive(Default)]
+pub struct InMemoryRateLimiter {
+    policy: RateLimitPolicy,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl InMemoryRateLimiter {
+    pub fn new(policy: RateLimitPolicy) -> Self {
+        Self {
+            policy,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RateLimiter for InMemoryRateLimiter {
+    fn record_failure(&self, key: &str) -> u32 {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        if failures.len() >= PRUNE_THRESHOLD && !failures.contains_key(key) {
+            failures.retain(|_, failures| !failures.is_stale(now, self.policy.window));
+        }
+        let entry = failures.entry(key.to_owned()).or_insert(Failures {
+            count: 0,
+            window_start: now,
+            locked_until: None,
+        });
+
+        if now.duration_since(entry.window_start) > self.policy.window {
+            entry.count = 0;
+            entry.window_start = now;
+        }
+        entry.count += 1;
+
+        if entry.count >= self.policy.max_attempts {
+            warn!("{} failed logins from {key}, locked", entry.count);
+            entry.locked_until = Some(now + self.policy.lockout_duration);
+        }
+
+        entry.count
+    }
+
+    fn is_locked(&self, key: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        let locked_until = failures.get(key)?.locked_until?;
+
+        if locked_until > now {
+            return Some(locked_until - now);
+        }
+
+        // the lockout is over, the client starts again without failures
+        failures.remove(key);
+        None
+    }
+
+    fn reset(&self, key: &str) {
+        self.failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{InMemoryRateLimiter, RateLimitPolicy, RateLimiter, PRUNE_THRESHOLD};
+
+    #[test]
+    fn should_lock_after_max_attempts() {
+        let limiter = InMemoryRateLimiter::new(RateLimitPolicy::default().max_attempts(3));
+
+        assert_eq!(limiter.record_failure("10.0.0.1"), 1);
+        assert_eq!(limiter.record_failure("10.0.0.1"), 2);
+        assert!(limiter.is_locked("10.0.0.1").is_none());
+
+        assert_eq!(limiter.record_failure("10.0.0.1"), 3);
+        assert!(limiter.is_locked("10.0.0.1").is_some());
+        assert!(limiter.is_locked("10.0.0.2").is_none());
+    }
+
+    #[test]
+    fn failures_outside_the_window_should_not_count() {
+        let limiter = InMemoryRateLimiter::new(
+            RateLimitPolicy::default()
+                .max_attempts(2)
+                .window(Duration::from_millis(10)),
+        );
+
+        limiter.record_failure("10.0.0.1");
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(limiter.record_failure("10.0.0.1"), 1);
+        assert!(limiter.is_locked("10.0.0.1").is_none());
+    }
+
+    #[test]
+    fn reset_should_forget_the_failures() {
+        let limiter = InMemoryRateLimiter::new(RateLimitPolicy::default().max_attempts(2));
+
+        limiter.record_failure("10.0.0.1");
+        limiter.reset("10.0.0.1");
+
+        assert_eq!(limiter.record_failure("10.0.0.1"), 1);
+    }
+
+    #[test]
+    fn stale_clients_should_be_removed() {
+        let limiter = InMemoryRateLimiter::new(
+            RateLimitPolicy::default()
+                .max_attempts(2)
+                .window(Duration::from_millis(10)),
+        );
+
+        limiter.record_failure("locked");
+        limiter.record_failure("locked");
+        for i in 1..PRUNE_THRESHOLD {
+            limiter.record_failure(&format!("client-{i}"));
+        }
+        thread::sleep(Duration::from_millis(20));
+
+        limiter.record_failure("new-client");
+
+        // only the locked and the new client are left
+        assert_eq!(limiter.failures.lock().unwrap().len(), 2);
+        assert!(limiter.is_locked("locked").is_some());
+    }
+}
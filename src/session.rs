@@ -1,2 +1,6 @@
+pub mod garbage_collector;
 pub mod handlers;
+pub mod registry;
+pub mod remember_me;
 pub mod session_auth;
+pub mod user_serializer;
@@ -0,0 +1,231 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use actix_session::storage::{SessionKey, SessionStore};
+use actix_web::rt::time::interval;
+use log::{debug, error};
+use thiserror::Error;
+
+use super::session_auth::{SESSION_KEY_AUTHENTICATED_AT, SESSION_KEY_NEED_MFA};
+
+/// Session stores that can list their sessions, needed by [SessionGarbageCollector]
+pub trait ScanSessions {
+    type Error: std::error::Error + 'static;
+    fn session_keys(&self) -> impl Future<Output = Result<Vec<SessionKey>, Self::Error>>;
+}
+
+#[derive(Error, Debug)]
+pub enum GarbageCollectorError {
+    #[error("Cannot scan sessions: {0}")]
+    Scan(String),
+    #[error("Cannot load session: {0}")]
+    Load(String),
+    #[error("Cannot delete session: {0}")]
+    Delete(String),
+}
+
+/// Removes sessions of server-side stores whose mfa has not been completed within `max_age` (e.g. abandoned logins)
+///
+/// # Examples
+/// ```ignore
+/// let store = Arc::new(YourStore::new());
+/// actix_web::rt::spawn(
+///     SessionGarbageCollector::new(Arc::clone(&store), Duration::from_secs(60 * 10))
+///         .run(Duration::from_secs(60)),
+/// );
+/// ```
+pub struct SessionGarbageCollector<S> {
+    store: Arc<S>,
+    max_age: Duration,
+}
+
+impl<S> SessionGarbageCollector<S>
+where
+    S: SessionStore + ScanSessions,
+{
+    pub fn new(store: Arc<S>, max_age: Duration) -> Self {
+        Self { store, max_age }
+    }
+
+    /// Calls [SessionGarbageCollector::collect] every `interval`, errors are logged
+    pub async fn run(self, every: Duration) {
+        let mut interval = interval(every);
+        loop {
+            interval.tick().await;
+            match self.collect().await {
+                Ok(purged) => debug!("Purged {purged} stale mfa sessions"),
+                Err(e) => error!("{e}"),
+            }
+        }
+    }
+
+    /// Purges the stale mfa sessions once and returns their number
+    pub async fn collect(&self) -> Result<usize, GarbageCollectorError> {
+        let keys = self
+            .store
+            .session_keys()
+            .await
+            .map_err(|e| GarbageCollectorError::Scan(e.to_string()))?;
+
+        let mut purged = 0;
+        for key in keys {
+            let state = self
+                .store
+                .load(&key)
+                .await
+                .map_err(|e| GarbageCollectorError::Load(e.to_string()))?;
+
+            let Some(state) = state else {
+                continue;
+            };
+
+            if !state.contains_key(SESSION_KEY_NEED_MFA) {
+                continue;
+            }
+
+            // the values are stored as JSON, sessions without timestamp are treated as very old
+            let authenticated_at = state
+                .get(SESSION_KEY_AUTHENTICATED_AT)
+                .and_then(|value| serde_json::from_str::<SystemTime>(value).ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+
+            if authenticated_at
+                .elapsed()
+                .is_ok_and(|age| age > self.max_age)
+            {
+                self.store
+                    .delete(&key)
+                    .await
+                    .map_err(|e| GarbageCollectorError::Delete(e.to_string()))?;
+                purged += 1;
+            }
+        }
+
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+    use actix_web::cookie::time;
+
+    use super::{ScanSessions, SessionGarbageCollector};
+
+    #[derive(Default)]
+    struct InMemoryStore {
+        sessions: Mutex<HashMap<String, HashMap<String, String>>>,
+    }
+
+    impl InMemoryStore {
+        fn insert(&self, key: &str, state: &[(&str, String)]) {
+            self.sessions.lock().unwrap().insert(
+                key.to_owned(),
+                state
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            );
+        }
+    }
+
+    impl ScanSessions for InMemoryStore {
+        type Error = Infallible;
+
+        async fn session_keys(&self) -> Result<Vec<SessionKey>, Self::Error> {
+            Ok(self
+                .sessions
+                .lock()
+                .unwrap()
+                .keys()
+                .map(|k| SessionKey::try_from(k.clone()).unwrap())
+                .collect())
+        }
+    }
+
+    impl SessionStore for InMemoryStore {
+        async fn load(
+            &self,
+            session_key: &SessionKey,
+        ) -> Result<Option<HashMap<String, String>>, LoadError> {
+            Ok(self
+                .sessions
+                .lock()
+                .unwrap()
+                .get(session_key.as_ref())
+                .cloned())
+        }
+
+        async fn save(
+            &self,
+            _: HashMap<String, String>,
+            _: &time::Duration,
+        ) -> Result<SessionKey, SaveError> {
+            unimplemented!()
+        }
+
+        async fn update(
+            &self,
+            _: SessionKey,
+            _: HashMap<String, String>,
+            _: &time::Duration,
+        ) -> Result<SessionKey, UpdateError> {
+            unimplemented!()
+        }
+
+        async fn update_ttl(
+            &self,
+            _: &SessionKey,
+            _: &time::Duration,
+        ) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+
+        async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+            self.sessions.lock().unwrap().remove(session_key.as_ref());
+            Ok(())
+        }
+    }
+
+    fn timestamp(time: SystemTime) -> String {
+        serde_json::to_string(&time).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn should_only_purge_stale_mfa_sessions() {
+        let store = Arc::new(InMemoryStore::default());
+        let old = SystemTime::now() - Duration::from_secs(60 * 30);
+        let needs_mfa = ("needs_mfa", "\"RNDCODE\"".to_owned());
+
+        store.insert(
+            "stale-mfa",
+            &[needs_mfa.clone(), ("authenticated_at", timestamp(old))],
+        );
+        store.insert(
+            "fresh-mfa",
+            &[
+                needs_mfa,
+                ("authenticated_at", timestamp(SystemTime::now())),
+            ],
+        );
+        store.insert("logged-in", &[("authenticated_at", timestamp(old))]);
+
+        let gc = SessionGarbageCollector::new(Arc::clone(&store), Duration::from_secs(60 * 10));
+
+        assert_eq!(gc.collect().await.unwrap(), 1);
+        let sessions = store.sessions.lock().unwrap();
+        assert!(!sessions.contains_key("stale-mfa"));
+        assert!(sessions.contains_key("fresh-mfa"));
+        assert!(sessions.contains_key("logged-in"));
+    }
+}
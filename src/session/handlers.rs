@@ -1,31 +1,74 @@
 use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
     sync::Arc,
+    task::Poll,
     time::{Duration, SystemTime},
 };
 
+use actix_session::SessionExt;
 use actix_web::{
     dev::{AppService, HttpServiceFactory},
-    guard::Post,
-    web::{Data, Json, ServiceConfig},
+    guard::{fn_guard, GuardContext, Post},
+    http::header::{CacheControl, CacheDirective, ContentType, ACCEPT, RETRY_AFTER},
+    rt::time::{interval_at, timeout, Instant},
+    web::{route, Bytes, Data, Form, Json, ServiceConfig},
     Error, HttpRequest, HttpResponse, Resource, Responder,
 };
+#[cfg(feature = "signed_link")]
+use actix_web::{guard::Get, web::Path};
+use futures::{
+    future::{ready, select, Either},
+    poll, stream,
+};
+use log::{info, warn};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+#[cfg(feature = "signed_link")]
+use crate::multifactor::signed_link::verify_token;
 use crate::{
-    login::{LoadUserService, LoginToken},
-    multifactor::{CheckCodeError, MfaRegistry},
-    web::{LOGIN_ROUTE, LOGOUT_ROUTE, MFA_ROUTE},
+    audit::AuditLogger,
+    login::{
+        DefaultLoginOutcomeMapper, HasCredentials, LoadUserService, LoginOutcome,
+        LoginOutcomeMapper, LoginToken, PinLoginRequest,
+    },
+    multifactor::{
+        fallback::FallbackChain, sequence::FactorSequence, ChallengeResponse, CheckCodeError,
+        Factor, FactorContext, HandlerFactor, MfaRegistry, StoredContext,
+    },
+    password::{PasswordHashStore, PasswordVerifier, VerifyingLoadUserService},
+    ratelimit::RateLimiter,
+    web::LoginRouteConfig,
     AuthToken,
 };
 
-use super::session_auth::LoginSession;
+use super::{
+    registry::{make_room_for_session, new_session_id, SessionEvictionPolicy, SessionRegistry},
+    remember_me::RememberMeConfig,
+    session_auth::{user_serializer, LoginSession},
+};
+
+const DEFAULT_STREAM_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+// proxies close idle connections, so the stream sends an event from time to time
+const STREAM_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
 
 /// An [Actix Web handler](https://actix.rs/docs/handlers/) for login, logout and multi factor auth validation
+///
+/// `V` is the [PasswordVerifier] if the handler is created with [SessionLoginHandler::with_password_verifier],
+/// otherwise the [LoadUserService] checks the password itself.
 #[allow(clippy::type_complexity)]
-pub struct SessionLoginHandler<T: LoadUserService, U> {
+pub struct SessionLoginHandler<T: LoadUserService, U, V = ()> {
     user_service: Arc<T>,
     mfa_condition: Arc<Option<fn(&U, &HttpRequest) -> bool>>,
     is_with_mfa: bool,
+    factor: Option<Rc<Option<Box<dyn Factor>>>>,
+    routes: LoginRouteConfig,
+    options: LoginOptions,
+    #[cfg(feature = "signed_link")]
+    confirm_link_secret: Option<[u8; 32]>,
+    verifier: PhantomData<V>,
 }
 
 impl<T, U> SessionLoginHandler<T, U>
@@ -34,39 +77,371 @@ where
 {
     /// Creates a handler only for login without mfa
     pub fn new(user_service: T) -> Self {
-        Self {
-            user_service: Arc::new(user_service),
-            mfa_condition: Arc::new(None),
-            is_with_mfa: false,
-        }
+        Self::create(user_service, None, false)
     }
 
     // Creates a login handler with mfa and validation of the factor at each login
     pub fn with_mfa(user_service: T) -> Self {
-        Self {
-            user_service: Arc::new(user_service),
-            mfa_condition: Arc::new(None),
-            is_with_mfa: true,
-        }
+        Self::create(user_service, None, true)
     }
 
     // Creates a login handler with mfa that will be triggered when the given condition is met
     pub fn with_mfa_condition(
         user_service: T,
         mfa_condition: fn(&U, &HttpRequest) -> bool,
+    ) -> Self {
+        Self::create(user_service, Some(mfa_condition), true)
+    }
+
+    /// Creates a login handler with mfa that tries the `factors` in order until one could send the code
+    ///
+    /// E.g. if sending the code by mail fails because the mail server is down, TOTP is used instead.
+    /// The factors are used for the routes of this handler instead of the factor of the [AuthMiddleware](crate::middleware::AuthMiddleware).
+    /// See [FallbackChain].
+    pub fn with_mfa_fallback_chain(user_service: T, factors: Vec<Box<dyn Factor>>) -> Self {
+        let mut handler = Self::create(user_service, None, true);
+        handler.factor = Some(Rc::new(Some(Box::new(FallbackChain::new(factors)))));
+        handler
+    }
+
+    /// Creates a login handler with mfa that requires all `factors` one after another
+    ///
+    /// After each correct code, the mfa route responds with the [ChallengeResponse] of the next factor until the last one is done.
+    /// The factors are used for the routes of this handler instead of the factor of the [AuthMiddleware](crate::middleware::AuthMiddleware).
+    /// See [FactorSequence].
+    pub fn with_mfa_factors(user_service: T, factors: Vec<Box<dyn Factor>>) -> Self {
+        let mut handler = Self::create(user_service, None, true);
+        handler.factor = Some(Rc::new(Some(Box::new(FactorSequence::new(factors)))));
+        handler
+    }
+}
+
+impl<S, U, V> SessionLoginHandler<VerifyingLoadUserService<S, V>, U, V>
+where
+    S: PasswordHashStore<User = U>,
+    V: PasswordVerifier,
+{
+    /// Creates a handler only for login without mfa that checks the password of the user from `store` with `verifier`
+    ///
+    /// # Examples
+    /// ```ignore
+    /// // needs the feature `argon2`
+    /// SessionLoginHandler::with_password_verifier(UserRepository::new(pool), Argon2Verifier)
+    /// ```
+    pub fn with_password_verifier(store: S, verifier: V) -> Self {
+        Self::create(VerifyingLoadUserService::new(store, verifier), None, false)
+    }
+}
+
+impl<T, U, V> SessionLoginHandler<T, U, V>
+where
+    T: LoadUserService,
+{
+    fn create(
+        user_service: T,
+        mfa_condition: Option<fn(&U, &HttpRequest) -> bool>,
+        is_with_mfa: bool,
     ) -> Self {
         Self {
             user_service: Arc::new(user_service),
-            mfa_condition: Arc::new(Some(mfa_condition)),
-            is_with_mfa: true,
+            mfa_condition: Arc::new(mfa_condition),
+            is_with_mfa,
+            factor: None,
+            routes: LoginRouteConfig::default(),
+            options: LoginOptions::default(),
+            #[cfg(feature = "signed_link")]
+            confirm_link_secret: None,
+            verifier: PhantomData,
         }
     }
 
+    /// Cancels [LoadUserService::load_user] if it takes longer than `timeout`
+    ///
+    /// The client receives a `503 Service Unavailable` with a `Retry-After` header in this case.
+    pub fn with_load_user_timeout(mut self, timeout: Duration) -> Self {
+        self.options.load_user_timeout = Some(timeout);
+        self
+    }
+
+    /// Parses the login body as `application/x-www-form-urlencoded` instead of JSON
+    ///
+    /// Enables logins with traditional HTML forms. The form needs the fields `username` and `password`.
+    pub fn form_encoded(mut self) -> Self {
+        self.options.form_encoded = true;
+        self
+    }
+
+    /// Customizes the responses of the login route (see [LoginOutcomeMapper])
+    pub fn with_outcome_mapper(
+        mut self,
+        outcome_mapper: impl LoginOutcomeMapper + 'static,
+    ) -> Self {
+        self.options.outcome_mapper = Some(Arc::new(outcome_mapper));
+        self
+    }
+
+    /// Responds with `204 No Content` instead of `200 OK` after a successful login without mfa (default: `false`)
+    ///
+    /// Has no effect if a [LoginOutcomeMapper] is set.
+    pub fn no_content_on_success(mut self, no_content_on_success: bool) -> Self {
+        self.options.no_content_on_success = no_content_on_success;
+        self
+    }
+
+    /// Locks clients out of the login route after too many failed logins
+    ///
+    /// The client is identified by its IP address (see [ratelimit](crate::ratelimit)). Locked clients receive `429 Too Many Requests`
+    /// (or [LoginOutcome::RateLimited] if a [LoginOutcomeMapper] is set) and their credentials are not checked.
+    pub fn with_rate_limiter(mut self, rate_limiter: impl RateLimiter + 'static) -> Self {
+        self.options.rate_limiter = Some(Arc::new(rate_limiter));
+        self
+    }
+
+    /// Identifies the client by `X-Forwarded-For` (or `Forwarded`) instead of the peer address (default: `false`)
+    ///
+    /// Only enable it behind a reverse proxy that overwrites these headers. Otherwise a client can send another address
+    /// with every request and is never locked by the [RateLimiter].
+    pub fn trust_proxy_headers(mut self, trust_proxy_headers: bool) -> Self {
+        self.options.trust_proxy_headers = trust_proxy_headers;
+        self
+    }
+
+    /// Reports logins and mfa checks to the [AuditLogger]
+    ///
+    /// Logouts are reported by [AuthMiddleware::with_audit_logger](crate::middleware::AuthMiddleware::with_audit_logger).
+    pub fn with_audit_logger(mut self, audit_logger: impl AuditLogger + 'static) -> Self {
+        self.options.audit_logger = Some(Arc::new(audit_logger));
+        self
+    }
+
+    /// Sets a "remember me" cookie if the login body contains `"remember_me": true`
+    ///
+    /// If mfa is needed, the cookie is set after the mfa challenge. The [SessionAuthProvider](struct@super::session_auth::SessionAuthProvider)
+    /// needs the same config ([SessionAuthProvider::with_remember_me](super::session_auth::SessionAuthProvider::with_remember_me)) to restore expired sessions.
+    /// On logout, the cookie is removed and its token revoked.
+    pub fn with_remember_me(mut self, config: RememberMeConfig) -> Self {
+        self.options.remember_me = Some(config);
+        self
+    }
+
+    /// Limits the number of concurrent sessions of a user to `max_sessions`
+    ///
+    /// What happens if the limit is reached depends on the [SessionEvictionPolicy] (default: the oldest session is logged out).
+    /// The limit is only checked when the login is complete, i.e. after mfa if it is needed.
+    /// The [SessionAuthProvider](struct@super::session_auth::SessionAuthProvider) needs the same registry and limit
+    /// ([SessionAuthProvider::with_session_registry](super::session_auth::SessionAuthProvider::with_session_registry)) to log out evicted sessions
+    /// and to register sessions restored by "remember me".
+    pub fn with_session_registry(
+        mut self,
+        registry: impl SessionRegistry + 'static,
+        max_sessions: usize,
+    ) -> Self {
+        self.options.session_registry = Some(Arc::new(registry));
+        self.options.max_sessions = max_sessions;
+        self
+    }
+
+    /// See [SessionLoginHandler::with_session_registry]
+    pub fn session_eviction_policy(mut self, policy: SessionEvictionPolicy) -> Self {
+        self.options.session_eviction_policy = policy;
+        self
+    }
+
+    /// Rejects a login with `429 Too Many Requests` while another login of the same session is in progress (default: `false`)
+    ///
+    /// Prevents that a double-submitted login form runs the login twice. The login is marked as in progress in the session
+    /// until it has finished.
+    pub fn deduplicate_logins(mut self, deduplicate_logins: bool) -> Self {
+        self.options.deduplicate_logins = deduplicate_logins;
+        self
+    }
+
+    /// How long a streaming mfa check waits for the approval (default: 5 minutes)
+    ///
+    /// See [Factor::is_streaming]. After the timeout, the stream ends with a `rejected` event.
+    pub fn with_stream_timeout(mut self, timeout: Duration) -> Self {
+        self.options.stream_timeout = Some(timeout);
+        self
+    }
+
+    /// Expects a [PinLoginRequest] (`device_id` and `pin`) instead of `username` and `password`
+    pub fn pin_login(mut self) -> Self {
+        self.options.pin_login = true;
+        self
+    }
+
+    /// Registers `GET /login/confirm/{token}` for links sent by [MfaSignedLink](crate::multifactor::signed_link::MfaSignedLink)
+    ///
+    /// The `secret` must be the one of the [MfaSignedLink]. Only has an effect if the handler is created with mfa.
+    #[cfg(feature = "signed_link")]
+    pub fn with_link_confirmation(mut self, secret: [u8; 32]) -> Self {
+        self.confirm_link_secret = Some(secret);
+        self
+    }
+
     pub fn is_with_mfa(&self) -> bool {
         self.is_with_mfa
     }
 }
 
+/// Options of the login route
+#[derive(Clone, Default)]
+struct LoginOptions {
+    load_user_timeout: Option<Duration>,
+    form_encoded: bool,
+    pin_login: bool,
+    no_content_on_success: bool,
+    // DefaultLoginOutcomeMapper if None
+    outcome_mapper: Option<Arc<dyn LoginOutcomeMapper>>,
+    rate_limiter: Option<Arc<dyn RateLimiter>>,
+    trust_proxy_headers: bool,
+    audit_logger: Option<Arc<dyn AuditLogger>>,
+    remember_me: Option<RememberMeConfig>,
+    session_registry: Option<Arc<dyn SessionRegistry>>,
+    max_sessions: usize,
+    session_eviction_policy: SessionEvictionPolicy,
+    deduplicate_logins: bool,
+    // DEFAULT_STREAM_TIMEOUT if None
+    stream_timeout: Option<Duration>,
+}
+
+impl LoginOptions {
+    fn map_outcome(&self, outcome: LoginOutcome) -> HttpResponse {
+        match (&self.outcome_mapper, outcome) {
+            (Some(outcome_mapper), outcome) => outcome_mapper.map(outcome),
+            (None, LoginOutcome::Success) if self.no_content_on_success => {
+                HttpResponse::NoContent().finish()
+            }
+            (None, outcome) => DefaultLoginOutcomeMapper.map(outcome),
+        }
+    }
+
+    /// Identifies the client for the [RateLimiter] and the deduplication of logins
+    fn client_key(&self, req: &HttpRequest) -> String {
+        if self.trust_proxy_headers {
+            // `X-Forwarded-For` (or `Forwarded`) if present, the peer address otherwise
+            return req
+                .connection_info()
+                .realip_remote_addr()
+                .unwrap_or("unknown")
+                .to_owned();
+        }
+        req.peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_owned())
+    }
+
+    /// Returns `false` if the user has reached the limit of sessions and none may be evicted
+    fn make_room_for_session(&self, user_id: &str) -> bool {
+        self.session_registry.as_ref().is_none_or(|registry| {
+            make_room_for_session(
+                registry.as_ref(),
+                user_id,
+                self.max_sessions,
+                self.session_eviction_policy,
+            )
+        })
+    }
+
+    /// Registers the session (after mfa, if needed) in the [SessionRegistry]
+    fn register_session(&self, session: &LoginSession) {
+        if let (Some(registry), Some((user_id, session_id))) =
+            (&self.session_registry, session.registered_session())
+        {
+            registry.register(&user_id, &session_id);
+        }
+    }
+
+    fn deregister_session(&self, session: &LoginSession) {
+        if let (Some(registry), Some((user_id, session_id))) =
+            (&self.session_registry, session.registered_session())
+        {
+            registry.deregister(&user_id, &session_id);
+        }
+    }
+
+    /// Adds the "remember me" cookie for the user and keeps the id of its token in the session
+    fn add_remember_me_cookie(
+        &self,
+        res: &mut HttpResponse,
+        session: &LoginSession,
+        user_id: &str,
+    ) {
+        let Some(config) = &self.remember_me else {
+            return;
+        };
+        let Some((token_id, cookie)) = config.create_cookie(user_id, SystemTime::now()) else {
+            warn!("Cannot create remember me cookie");
+            return;
+        };
+        if let Err(e) = session.remembered(&token_id) {
+            warn!("Cannot store remember me token in session: {e}");
+            config.revoke(&token_id);
+            return;
+        }
+        if let Err(e) = res.add_cookie(&cookie) {
+            warn!("Cannot set remember me cookie: {e}");
+            config.revoke(&token_id);
+        }
+    }
+
+    /// Revokes the "remember me" token of the session
+    fn forget_remember_me(&self, session: &LoginSession) {
+        if let (Some(config), Some(token_id)) = (&self.remember_me, session.remember_me_token()) {
+            config.revoke(&token_id);
+        }
+    }
+}
+
+/// Marks the login of a session as in progress until it is dropped, also if the login is cancelled
+struct LoginInProgress(LoginSession);
+
+impl LoginInProgress {
+    fn start(session: &LoginSession) -> Result<Self, Error> {
+        session.start_login()?;
+        Ok(Self(session.clone()))
+    }
+}
+
+impl Drop for LoginInProgress {
+    fn drop(&mut self) {
+        self.0.login_finished();
+    }
+}
+
+/// Body of the login route: the credentials and an optional `remember_me` flag
+#[derive(Deserialize)]
+struct LoginRequest<C> {
+    #[serde(flatten)]
+    credentials: C,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    remember_me: bool,
+}
+
+// HTML forms send checkboxes as `on`, JSON as boolean
+impl<C: Into<LoginToken>> LoginRequest<C> {
+    fn into_login_token(self) -> LoginRequest<LoginToken> {
+        LoginRequest {
+            credentials: self.credentials.into(),
+            remember_me: self.remember_me,
+        }
+    }
+}
+
+fn deserialize_flag<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Text(String),
+    }
+
+    Ok(match Flag::deserialize(deserializer)? {
+        Flag::Bool(flag) => flag,
+        Flag::Text(text) => matches!(text.as_str(), "true" | "on" | "1"),
+    })
+}
+
 /// Request for validating the code
 #[derive(Deserialize)]
 pub struct MfaRequestBody {
@@ -82,34 +457,237 @@ impl MfaRequestBody {
 async fn mfa_route(
     factor: MfaRegistry,
     body: Json<MfaRequestBody>,
+    options: Data<LoginOptions>,
+    req: HttpRequest,
+    session: LoginSession,
+) -> Result<impl Responder, CheckCodeError> {
+    check_mfa_code(&factor, body.get_code(), &options, &req, &session).await
+}
+
+/// The raw body is the code (e.g. for CLI clients)
+async fn mfa_route_plain_text(
+    factor: MfaRegistry,
+    body: String,
+    options: Data<LoginOptions>,
     req: HttpRequest,
     session: LoginSession,
 ) -> Result<impl Responder, CheckCodeError> {
+    check_mfa_code(&factor, body.trim(), &options, &req, &session).await
+}
+
+#[cfg(feature = "signed_link")]
+struct ConfirmLinkSecret([u8; 32]);
+
+/// The code is taken from the signed token, the rest works like the mfa route
+#[cfg(feature = "signed_link")]
+async fn confirm_link(
+    factor: MfaRegistry,
+    token: Path<String>,
+    secret: Data<ConfirmLinkSecret>,
+    options: Data<LoginOptions>,
+    req: HttpRequest,
+    session: LoginSession,
+) -> Result<HttpResponse, CheckCodeError> {
+    let Some(code) = verify_token(&token, &secret.0) else {
+        warn!("Invalid or expired confirmation link");
+        return Err(CheckCodeError::FinallyRejected);
+    };
+    check_mfa_code(&factor, &code, &options, &req, &session).await
+}
+
+async fn check_mfa_code(
+    factor: &MfaRegistry,
+    code: &str,
+    options: &Data<LoginOptions>,
+    req: &HttpRequest,
+    session: &LoginSession,
+) -> Result<HttpResponse, CheckCodeError> {
     if session.no_longer_valid() {
         session.destroy();
         return Err(CheckCodeError::FinallyRejected);
     }
 
     if let Some(f) = factor.get_value() {
-        f.check_code(body.get_code(), &req).await?;
+        if let Some(context) = session.stored_context() {
+            if !f.validate_context(&context, req) {
+                warn!("Request context differs from the one of the login, mfa rejected");
+                session.destroy();
+                return Err(CheckCodeError::FinallyRejected);
+            }
+        }
+
+        if f.is_streaming() && accepts_event_stream(req) {
+            if let Some((user_id, session_id)) = session
+                .registered_session()
+                .filter(|_| options.session_registry.is_some())
+            {
+                // the session cannot be read anymore once the response has started
+                let mut check = f.check_code(code, req);
+                if let Poll::Ready(result) = poll!(check.as_mut()) {
+                    check = Box::pin(ready(result));
+                }
+                return Ok(stream_mfa_check(
+                    factor.clone(),
+                    check,
+                    options.clone(),
+                    req.clone(),
+                    user_id,
+                    session_id,
+                ));
+            }
+            info!("Streaming needs a session registry, the code is checked without streaming");
+        }
+
+        if let Err(e) = f.check_code(code, req).await {
+            on_mfa_failure(f.as_ref(), &e, options, req).await;
+            return Err(e);
+        }
+        f.on_success(req).await;
+        if let Some(audit_logger) = &options.audit_logger {
+            audit_logger.on_mfa_success(req).await;
+        }
+        if let Some(challenge) = f
+            .next_challenge(req)
+            .map_err(|e| CheckCodeError::UnknownError(e.to_string()))?
+        {
+            return Ok(HttpResponse::Ok().json(challenge));
+        }
+        if let Some((user_id, _)) = session.registered_session() {
+            if !options.make_room_for_session(&user_id) {
+                info!("Session limit of '{user_id}' reached after mfa");
+                session.destroy();
+                return Ok(HttpResponse::TooManyRequests().finish());
+            }
+        }
         session.mfa_challenge_done();
-        Ok(HttpResponse::Ok().finish())
+        session
+            .authenticated_at(SystemTime::now())
+            .map_err(|e| CheckCodeError::UnknownError(e.to_string()))?;
+        options.register_session(session);
+        let mut res = HttpResponse::Ok().finish();
+        if let Some(user_id) = session.wants_remember_me() {
+            options.add_remember_me_cookie(&mut res, session, &user_id);
+        }
+        Ok(res)
     } else {
         Ok(HttpResponse::Unauthorized().finish())
     }
 }
 
+fn accepts_event_stream(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+// wrong codes are counted by the factor, so the session has to be saved after the hooks
+async fn on_mfa_failure(
+    factor: &dyn Factor,
+    error: &CheckCodeError,
+    options: &LoginOptions,
+    req: &HttpRequest,
+) {
+    factor.on_failure(error, req).await;
+    if let Some(audit_logger) = &options.audit_logger {
+        audit_logger.on_mfa_failure(req).await;
+    }
+}
+
+/// Responds with a server-sent event stream and checks the code in the background
+///
+/// The session is saved with the response headers, so it cannot be changed by the stream. Instead, the approved login is
+/// registered in the [SessionRegistry] and the [SessionAuthProvider](struct@super::session_auth::SessionAuthProvider)
+/// completes it with the next request. Until the check is done, a `keep-alive` event is sent every 15 seconds.
+fn stream_mfa_check(
+    factor: MfaRegistry,
+    check: Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>>,
+    options: Data<LoginOptions>,
+    req: HttpRequest,
+    user_id: String,
+    session_id: String,
+) -> HttpResponse {
+    let stream_timeout = options.stream_timeout.unwrap_or(DEFAULT_STREAM_TIMEOUT);
+    let result = Box::pin(async move {
+        let result = timeout(stream_timeout, check).await.unwrap_or_else(|_| {
+            Err(CheckCodeError::TimeIsUp(
+                "No approval within the time limit".to_owned(),
+            ))
+        });
+        let Some(f) = factor.get_value() else {
+            return Err(CheckCodeError::FinallyRejected);
+        };
+        if let Err(e) = result {
+            on_mfa_failure(f.as_ref(), &e, &options, &req).await;
+            return Err(e);
+        }
+
+        // streaming factors have no next challenge, sequences and fallback chains do not stream
+        f.on_success(&req).await;
+        if let Some(audit_logger) = &options.audit_logger {
+            audit_logger.on_mfa_success(&req).await;
+        }
+        if !options.make_room_for_session(&user_id) {
+            info!("Session limit of '{user_id}' reached after mfa");
+            return Err(CheckCodeError::FinallyRejected);
+        }
+        if let Some(registry) = &options.session_registry {
+            registry.register(&user_id, &session_id);
+        }
+        Ok(())
+    });
+
+    let keep_alive = interval_at(
+        Instant::now() + STREAM_KEEP_ALIVE_INTERVAL,
+        STREAM_KEEP_ALIVE_INTERVAL,
+    );
+    let events = stream::unfold(Some((result, keep_alive)), |state| async move {
+        let (mut result, mut keep_alive) = state?;
+        let next = match select(result.as_mut(), Box::pin(keep_alive.tick())).await {
+            Either::Left((result, _)) => Some(result),
+            Either::Right(_) => None,
+        };
+        match next {
+            Some(result) => Some((Ok::<_, Error>(check_result_as_event(result)), None)),
+            None => Some((
+                Ok(Bytes::from_static(b"event: keep-alive\ndata: {}\n\n")),
+                Some((result, keep_alive)),
+            )),
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(events)
+}
+
+/// The result of [Factor::check_code] as server-sent event
+fn check_result_as_event(result: Result<(), CheckCodeError>) -> Bytes {
+    let event = match result {
+        Ok(()) => "event: approved\ndata: {}\n\n".to_owned(),
+        Err(e) => format!("event: rejected\ndata: {}\n\n", e.error_response_json()),
+    };
+    Bytes::from(event)
+}
+
+fn is_plain_text(ctx: &GuardContext) -> bool {
+    ctx.header::<ContentType>()
+        .is_some_and(|content_type| content_type.essence_str() == "text/plain")
+}
+
 /// Triggers the code generation and sets the login state to mfa needed
-/// Returns true if mfa needed
+/// Returns the challenge for the client if mfa needed
 fn generate_code_if_mfa_necessary<U: Serialize>(
     // U will need a trait bound like 'HasFactor' -> user.get_factor() -> String
     user: &U,
+    user_id: &str,
     mfa_registry: &MfaRegistry,
     condition: &Option<fn(&U, &HttpRequest) -> bool>,
     req: &HttpRequest,
     session: &LoginSession,
-) -> Result<bool, Error> {
-    let mut mfa_needed = false;
+) -> Result<Option<ChallengeResponse>, Error> {
+    let mut challenge = None;
 
     if let Some(factor) = mfa_registry.get_value() {
         let is_condition_met = if let Some(condition) = condition {
@@ -120,90 +698,292 @@ fn generate_code_if_mfa_necessary<U: Serialize>(
         };
 
         if is_condition_met {
-            factor.generate_code(req)?;
+            factor.generate_code(&FactorContext::from_request(
+                req,
+                &req.get_session(),
+                user_id,
+            ))?;
+            session.store_context(&StoredContext::from_request(req))?;
             session.needs_mfa(&factor.get_unique_id())?;
-            mfa_needed = true;
+            challenge = Some(factor.challenge(req));
         }
     }
 
-    Ok(mfa_needed)
+    Ok(challenge)
 }
 
 #[allow(clippy::type_complexity)]
-async fn login<T: LoadUserService<User = U>, U: Serialize>(
-    login_token: Json<LoginToken>,
+async fn login_json<C, T: LoadUserService<User = U>, U: Serialize + DeserializeOwned + 'static>(
+    body: Json<LoginRequest<C>>,
     user_service: Data<Arc<T>>,
     mfa_condition: Data<Arc<Option<fn(&U, &HttpRequest) -> bool>>>,
+    options: Data<LoginOptions>,
     mfa_registry: MfaRegistry,
     session: LoginSession,
     req: HttpRequest,
-) -> Result<impl Responder, Error> {
+) -> Result<HttpResponse, Error>
+where
+    C: HasCredentials + Into<LoginToken>,
+{
+    login(
+        &body.into_inner().into_login_token(),
+        &user_service,
+        &mfa_condition,
+        &options,
+        &mfa_registry,
+        &session,
+        &req,
+    )
+    .await
+}
+
+#[allow(clippy::type_complexity)]
+async fn login_form<C, T: LoadUserService<User = U>, U: Serialize + DeserializeOwned + 'static>(
+    body: Form<LoginRequest<C>>,
+    user_service: Data<Arc<T>>,
+    mfa_condition: Data<Arc<Option<fn(&U, &HttpRequest) -> bool>>>,
+    options: Data<LoginOptions>,
+    mfa_registry: MfaRegistry,
+    session: LoginSession,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error>
+where
+    C: HasCredentials + Into<LoginToken>,
+{
+    login(
+        &body.into_inner().into_login_token(),
+        &user_service,
+        &mfa_condition,
+        &options,
+        &mfa_registry,
+        &session,
+        &req,
+    )
+    .await
+}
+
+#[allow(clippy::type_complexity)]
+async fn login<T: LoadUserService<User = U>, U: Serialize + DeserializeOwned + 'static>(
+    login_request: &LoginRequest<LoginToken>,
+    user_service: &Data<Arc<T>>,
+    mfa_condition: &Data<Arc<Option<fn(&U, &HttpRequest) -> bool>>>,
+    options: &LoginOptions,
+    mfa_registry: &MfaRegistry,
+    session: &LoginSession,
+    req: &HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let login_token = &login_request.credentials;
+    let client = options.client_key(req);
+    if let Some(remaining) = options
+        .rate_limiter
+        .as_ref()
+        .and_then(|rate_limiter| rate_limiter.is_locked(&client))
+    {
+        log_login_attempt(login_token, "rate_limited");
+        if let Some(audit_logger) = &options.audit_logger {
+            audit_logger
+                .on_login_failure(&login_token.username, req)
+                .await;
+        }
+        return Ok(options.map_outcome(LoginOutcome::RateLimited(remaining)));
+    }
+
+    if options.deduplicate_logins && session.login_in_progress() {
+        log_login_attempt(login_token, "duplicate");
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, 1))
+            .finish());
+    }
+
+    // a new login replaces the session of this client
+    options.deregister_session(session);
     session.reset();
+    let _login_in_progress = match options.deduplicate_logins {
+        true => Some(LoginInProgress::start(session)?),
+        false => None,
+    };
+
+    let load_user = user_service.load_user(login_token);
+    let loaded_user = match options.load_user_timeout {
+        Some(load_user_timeout) => match timeout(load_user_timeout, load_user).await {
+            Ok(loaded_user) => loaded_user,
+            Err(_) => {
+                warn!("Loading the user took longer than {load_user_timeout:?}");
+                log_login_attempt(login_token, "timeout");
+                session.destroy();
+                return Ok(HttpResponse::ServiceUnavailable()
+                    .insert_header((RETRY_AFTER, load_user_timeout.as_secs().max(1)))
+                    .finish());
+            }
+        },
+        None => load_user.await,
+    };
 
-    match user_service.load_user(&login_token).await {
+    match loaded_user {
         Ok(user) => {
-            if !generate_code_if_mfa_necessary(
+            if let Some(audit_logger) = &options.audit_logger {
+                audit_logger
+                    .on_login_success(&login_token.username, req)
+                    .await;
+            }
+
+            let challenge = generate_code_if_mfa_necessary(
                 &user,
-                &mfa_registry,
-                &mfa_condition,
-                &req,
-                &session,
-            )? {
+                &login_token.username,
+                mfa_registry,
+                mfa_condition,
+                req,
+                session,
+            )?;
+
+            if challenge.is_none() {
+                // only a complete login may evict (or is rejected by) other sessions of the user
+                if !options.make_room_for_session(&login_token.username) {
+                    log_login_attempt(login_token, "session_limit");
+                    session.destroy();
+                    return Ok(HttpResponse::TooManyRequests().finish());
+                }
                 // MFA not needed, call success handler
-                user_service.on_success_handler(&req, &user).await?;
+                user_service.on_success_handler(req, &user).await?;
             } else {
                 // set timeout for login session
                 if let Some(validity) = SystemTime::now().checked_add(Duration::from_secs(60 * 5)) {
                     session.valid_until(validity)?;
                 } else {
-                    return Ok(HttpResponse::InternalServerError());
+                    return Ok(HttpResponse::InternalServerError().finish());
                 }
             }
 
-            session.set_user(user)?;
-            Ok(HttpResponse::Ok())
+            session.set_user(&user, user_serializer::<U>(req).as_ref())?;
+            session.authenticated_at(SystemTime::now())?;
+            if options.session_registry.is_some() {
+                session.registered(&login_token.username, &new_session_id())?;
+            }
+
+            match challenge {
+                Some(challenge) => {
+                    log_login_attempt(login_token, "mfa_required");
+                    if login_request.remember_me {
+                        session.remember_me_after_mfa(&login_token.username)?;
+                    }
+                    Ok(options.map_outcome(LoginOutcome::MfaRequired(challenge)))
+                }
+                None => {
+                    log_login_attempt(login_token, "success");
+                    options.register_session(session);
+                    let mut res = options.map_outcome(LoginOutcome::Success);
+                    if login_request.remember_me {
+                        options.add_remember_me_cookie(&mut res, session, &login_token.username);
+                    }
+                    Ok(res)
+                }
+            }
         }
         Err(e) => {
-            user_service.on_error_handler(&req).await?;
+            log_login_attempt(login_token, "failed");
+            if let Some(rate_limiter) = &options.rate_limiter {
+                rate_limiter.record_failure(&client);
+            }
+            if let Some(audit_logger) = &options.audit_logger {
+                audit_logger
+                    .on_login_failure(&login_token.username, req)
+                    .await;
+            }
+            user_service.on_error_handler(req).await?;
             session.destroy();
-            Err(e.into())
+            Ok(options.map_outcome(LoginOutcome::Failure(e)))
         }
     }
 }
 
-impl<T, U> HttpServiceFactory for SessionLoginHandler<T, U>
+// Never log the password
+fn log_login_attempt(login_token: &impl HasCredentials, outcome: &str) {
+    info!(
+        "Login attempt: username={}, outcome={outcome}",
+        login_token.username()
+    );
+}
+
+impl<T, U, V> HttpServiceFactory for SessionLoginHandler<T, U, V>
 where
     T: LoadUserService<User = U> + 'static,
     U: Serialize + DeserializeOwned + Clone + 'static,
 {
     fn register(self, __config: &mut AppService) {
-        let login_resource = Resource::new(LOGIN_ROUTE)
+        let login_resource = Resource::new(&self.routes.login_path)
             .name("login")
             .guard(Post())
             .app_data(Data::new(Arc::clone(&self.user_service)))
             .app_data(Data::new(Arc::clone(&self.mfa_condition)))
-            .to(login::<T, U>);
+            .app_data(Data::new(self.options.clone()));
+        let login_resource = match &self.factor {
+            Some(factor) => login_resource.app_data(HandlerFactor(Rc::clone(factor))),
+            None => login_resource,
+        };
+        let login_resource = match (self.options.form_encoded, self.options.pin_login) {
+            (true, true) => login_resource.to(login_form::<PinLoginRequest, T, U>),
+            (true, false) => login_resource.to(login_form::<LoginToken, T, U>),
+            (false, true) => login_resource.to(login_json::<PinLoginRequest, T, U>),
+            (false, false) => login_resource.to(login_json::<LoginToken, T, U>),
+        };
         HttpServiceFactory::register(login_resource, __config);
 
-        let logout_resource = Resource::new(LOGOUT_ROUTE)
+        let logout_resource = Resource::new(&self.routes.logout_path)
             .name("logout")
             .guard(Post())
+            .app_data(Data::new(self.options.clone()))
             .to(logout::<U>);
         HttpServiceFactory::register(logout_resource, __config);
 
         if self.is_with_mfa() {
-            let mfa_resource = Resource::new(MFA_ROUTE)
+            let mfa_resource = Resource::new(&self.routes.mfa_path)
                 .name("mfa")
                 .guard(Post())
-                .to(mfa_route);
+                .route(
+                    route()
+                        .guard(fn_guard(is_plain_text))
+                        .to(mfa_route_plain_text),
+                )
+                .route(route().to(mfa_route))
+                .app_data(Data::new(self.options.clone()));
+            let mfa_resource = match &self.factor {
+                Some(factor) => mfa_resource.app_data(HandlerFactor(Rc::clone(factor))),
+                None => mfa_resource,
+            };
             HttpServiceFactory::register(mfa_resource, __config);
+
+            #[cfg(feature = "signed_link")]
+            if let Some(secret) = self.confirm_link_secret {
+                let confirm_resource =
+                    Resource::new(format!("{}/{{token}}", self.routes.confirm_link_path()))
+                        .name("confirm_link")
+                        .app_data(Data::new(ConfirmLinkSecret(secret)))
+                        .app_data(Data::new(self.options.clone()))
+                        .guard(Get())
+                        .to(confirm_link);
+                let confirm_resource = match &self.factor {
+                    Some(factor) => confirm_resource.app_data(HandlerFactor(Rc::clone(factor))),
+                    None => confirm_resource,
+                };
+                HttpServiceFactory::register(confirm_resource, __config);
+            }
         }
     }
 }
 
-async fn logout<U: DeserializeOwned + Clone>(token: AuthToken<U>) -> impl Responder {
+async fn logout<U: DeserializeOwned + Clone>(
+    token: AuthToken<U>,
+    options: Data<LoginOptions>,
+    session: LoginSession,
+) -> impl Responder {
+    options.deregister_session(&session);
+    options.forget_remember_me(&session);
     token.invalidate();
-    HttpResponse::Ok()
+    let mut res = HttpResponse::Ok();
+    if let Some(remember_me) = &options.remember_me {
+        res.cookie(remember_me.removal_cookie());
+    }
+    res
 }
 
 /// Configuration function to setup a [SessionLoginHandler]
@@ -217,10 +997,178 @@ async fn logout<U: DeserializeOwned + Clone>(token: AuthToken<U>) -> impl Respon
 pub fn login_config<
     L: LoadUserService<User = U> + 'static,
     U: Serialize + DeserializeOwned + Clone + 'static,
+    V: 'static,
 >(
-    login_handler: SessionLoginHandler<L, U>,
+    login_handler: SessionLoginHandler<L, U, V>,
 ) -> impl FnOnce(&mut ServiceConfig) {
     |config: &mut ServiceConfig| {
         config.service(login_handler);
     }
 }
+
+/// Configuration function to setup a [SessionLoginHandler] whose routes are mounted below `prefix`
+///
+/// The [AuthMiddleware](crate::middleware::AuthMiddleware) needs to know the prefix as well (see
+/// [AuthMiddleware::with_route_prefix](crate::middleware::AuthMiddleware::with_route_prefix)).
+///
+/// # Examples
+///
+/// ```ignore
+/// // login: /auth/login, mfa: /auth/login/mfa, logout: /auth/logout
+/// App::new()
+///   .configure(login_config_with_prefix("/auth", SessionLoginHandler::new(YourLoadUserService {})))
+/// ```
+pub fn login_config_with_prefix<
+    L: LoadUserService<User = U> + 'static,
+    U: Serialize + DeserializeOwned + Clone + 'static,
+    V: 'static,
+>(
+    prefix: &str,
+    login_handler: SessionLoginHandler<L, U, V>,
+) -> impl FnOnce(&mut ServiceConfig) {
+    login_config_with_routes(
+        LoginRouteConfig::default().with_prefix(prefix),
+        login_handler,
+    )
+}
+
+/// Configuration function to setup a [SessionLoginHandler] whose routes are mounted at the paths of `routes`
+///
+/// The [AuthMiddleware](crate::middleware::AuthMiddleware) needs to know the paths as well (see
+/// [AuthMiddleware::with_login_routes](crate::middleware::AuthMiddleware::with_login_routes)).
+///
+/// # Examples
+///
+/// ```ignore
+/// let routes = LoginRouteConfig::default()
+///     .login_path("/auth/signin")
+///     .mfa_path("/auth/signin/mfa");
+///
+/// App::new()
+///   .configure(login_config_with_routes(routes.clone(), SessionLoginHandler::new(YourLoadUserService {})))
+///   .wrap(AuthMiddleware::<_, User>::new(SessionAuthProvider::default(), PathMatcher::default()).with_login_routes(&routes))
+/// ```
+pub fn login_config_with_routes<
+    L: LoadUserService<User = U> + 'static,
+    U: Serialize + DeserializeOwned + Clone + 'static,
+    V: 'static,
+>(
+    routes: LoginRouteConfig,
+    mut login_handler: SessionLoginHandler<L, U, V>,
+) -> impl FnOnce(&mut ServiceConfig) {
+    login_handler.routes = routes;
+    login_config(login_handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::{
+        cookie::{Cookie, Key},
+        http::StatusCode,
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpRequest, HttpResponse,
+    };
+    use futures::future::LocalBoxFuture;
+
+    use super::{login_config, SessionLoginHandler};
+    use crate::{
+        login::{HandlerError, LoadUserError, LoadUserService, LoginToken},
+        session::session_auth::LoginSession,
+    };
+
+    struct UsernameService;
+
+    impl LoadUserService for UsernameService {
+        type User = String;
+
+        fn load_user(
+            &self,
+            login_token: &LoginToken,
+        ) -> LocalBoxFuture<'_, Result<String, LoadUserError>> {
+            let username = login_token.username.clone();
+            Box::pin(async move { Ok(username) })
+        }
+
+        fn on_success_handler(
+            &self,
+            _: &HttpRequest,
+            _: &String,
+        ) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn on_error_handler(
+            &self,
+            _: &HttpRequest,
+        ) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn login_request(cookie: Option<Cookie<'static>>) -> TestRequest {
+        let req = TestRequest::post()
+            .uri("/login")
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(r#"{ "username": "anna", "password": "test123" }"#);
+        match cookie {
+            Some(cookie) => req.cookie(cookie),
+            None => req,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn login_should_be_rejected_while_another_login_of_the_session_is_in_progress() {
+        let app = init_service(
+            App::new()
+                // stands in for a login that has not finished yet
+                .route(
+                    "/unfinished-login",
+                    web::get().to(|session: LoginSession| async move {
+                        session.start_login().unwrap();
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .configure(login_config(
+                    SessionLoginHandler::new(UsernameService).deduplicate_logins(true),
+                ))
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                )),
+        )
+        .await;
+
+        let res = call_service(
+            &app,
+            TestRequest::get().uri("/unfinished-login").to_request(),
+        )
+        .await;
+        let cookie = res.response().cookies().next().unwrap().into_owned();
+
+        let res = call_service(&app, login_request(Some(cookie)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_rt::test]
+    async fn finished_login_should_not_be_in_progress() {
+        let app = init_service(
+            App::new()
+                .configure(login_config(
+                    SessionLoginHandler::new(UsernameService).deduplicate_logins(true),
+                ))
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                )),
+        )
+        .await;
+
+        let res = call_service(&app, login_request(None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = res.response().cookies().next().unwrap().into_owned();
+
+        let res = call_service(&app, login_request(Some(cookie)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,261 @@
+//! Limit of concurrent sessions per user
+//!
+//! A [SessionRegistry] knows the sessions of every user. If a user logs in while the limit of
+//! [SessionLoginHandler::with_session_registry](super::handlers::SessionLoginHandler::with_session_registry) is reached,
+//! the login is rejected or the oldest session is evicted (see [SessionEvictionPolicy]).
+//! To log out evicted sessions, the [SessionAuthProvider](struct@super::session_auth::SessionAuthProvider)
+//! needs the same registry ([SessionAuthProvider::with_session_registry](super::session_auth::SessionAuthProvider::with_session_registry)).
+//!
+//! Sessions are deregistered on logout and when the provider finds them expired. Sessions that are never used again
+//! (e.g. the cookie was deleted) are evicted by the next login ([SessionEvictionPolicy::EvictOldest]) or dropped by
+//! the registry after a while ([InMemorySessionRegistry::session_ttl]).
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::info;
+
+/// Keeps track of the sessions of every user
+///
+/// Implementations must be shareable between the workers of the server (e.g. an in-memory map behind a mutex or Redis).
+pub trait SessionRegistry: Send + Sync {
+    fn register(&self, user_id: &str, session_id: &str);
+    fn deregister(&self, user_id: &str, session_id: &str);
+    fn count(&self, user_id: &str) -> usize;
+    /// The session that was registered first
+    fn oldest(&self, user_id: &str) -> Option<String>;
+    fn is_registered(&self, user_id: &str, session_id: &str) -> bool;
+}
+
+impl<R: SessionRegistry> SessionRegistry for Arc<R> {
+    fn register(&self, user_id: &str, session_id: &str) {
+        self.as_ref().register(user_id, session_id)
+    }
+
+    fn deregister(&self, user_id: &str, session_id: &str) {
+        self.as_ref().deregister(user_id, session_id)
+    }
+
+    fn count(&self, user_id: &str) -> usize {
+        self.as_ref().count(user_id)
+    }
+
+    fn oldest(&self, user_id: &str) -> Option<String> {
+        self.as_ref().oldest(user_id)
+    }
+
+    fn is_registered(&self, user_id: &str, session_id: &str) -> bool {
+        self.as_ref().is_registered(user_id, session_id)
+    }
+}
+
+/// What happens if a user logs in while the limit of sessions is reached
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SessionEvictionPolicy {
+    /// The login is rejected with `429 Too Many Requests`
+    ///
+    /// Use a registry that drops old sessions (e.g. [InMemorySessionRegistry::session_ttl]), otherwise users whose
+    /// sessions were lost without logout are rejected until these sessions are gone.
+    Reject,
+    /// The oldest session of the user is logged out
+    #[default]
+    EvictOldest,
+}
+
+/// Returns `false` if the user has reached the limit of sessions and none may be evicted
+pub(crate) fn make_room_for_session(
+    registry: &dyn SessionRegistry,
+    user_id: &str,
+    max_sessions: usize,
+    policy: SessionEvictionPolicy,
+) -> bool {
+    while registry.count(user_id) >= max_sessions {
+        let oldest = match policy {
+            SessionEvictionPolicy::Reject => None,
+            SessionEvictionPolicy::EvictOldest => registry.oldest(user_id),
+        };
+        let Some(oldest) = oldest else {
+            return false;
+        };
+        info!("Session limit of '{user_id}' reached, oldest session is evicted");
+        registry.deregister(user_id, &oldest);
+    }
+
+    true
+}
+
+/// [SessionRegistry] that keeps the sessions in memory, so they are not shared between instances of the app
+///
+/// Create it once and pass a clone of the [Arc] to the login handler and the provider of every worker:
+/// ```ignore
+/// let registry = Arc::new(InMemorySessionRegistry::default());
+///
+/// HttpServer::new(move || {
+///     App::new()
+///         .configure(login_config(
+///             SessionLoginHandler::new(user_service).with_session_registry(Arc::clone(&registry), 2),
+///         ))
+///         .wrap(AuthMiddleware::<_, User>::new(
+///             SessionAuthProvider::default().with_session_registry(Arc::clone(&registry), 2),
+///             PathMatcher::default(),
+///         ))
+/// })
+/// ```
+#[derive(Default)]
+pub struct InMemorySessionRegistry {
+    // sessions with the time of registration, in the order of registration
+    sessions: Mutex<HashMap<String, Vec<(String, SystemTime)>>>,
+    session_ttl: Option<Duration>,
+}
+
+impl InMemorySessionRegistry {
+    /// Drops sessions `session_ttl` after their registration (default: never)
+    ///
+    /// Should be the lifetime of the sessions (e.g. the timeout of the [SessionAuthProvider](struct@super::session_auth::SessionAuthProvider)),
+    /// so that sessions that are never used again do not count anymore.
+    pub fn session_ttl(mut self, session_ttl: Duration) -> Self {
+        self.session_ttl = Some(session_ttl);
+        self
+    }
+
+    /// The sessions of the user that have not expired
+    fn sessions_of(&self, user_id: &str) -> Vec<String> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(user_sessions) = sessions.get_mut(user_id) else {
+            return Vec::new();
+        };
+
+        if let Some(session_ttl) = self.session_ttl {
+            let now = SystemTime::now();
+            user_sessions.retain(|(_, registered_at)| *registered_at + session_ttl > now);
+        }
+        let ids = user_sessions.iter().map(|(id, _)| id.clone()).collect();
+        if user_sessions.is_empty() {
+            sessions.remove(user_id);
+        }
+        ids
+    }
+}
+
+impl SessionRegistry for InMemorySessionRegistry {
+    fn register(&self, user_id: &str, session_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let user_sessions = sessions.entry(user_id.to_owned()).or_default();
+        if !user_sessions.iter().any(|(id, _)| id == session_id) {
+            user_sessions.push((session_id.to_owned(), SystemTime::now()));
+        }
+    }
+
+    fn deregister(&self, user_id: &str, session_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(user_sessions) = sessions.get_mut(user_id) {
+            user_sessions.retain(|(id, _)| id != session_id);
+            if user_sessions.is_empty() {
+                sessions.remove(user_id);
+            }
+        }
+    }
+
+    fn count(&self, user_id: &str) -> usize {
+        self.sessions_of(user_id).len()
+    }
+
+    fn oldest(&self, user_id: &str) -> Option<String> {
+        self.sessions_of(user_id).into_iter().next()
+    }
+
+    fn is_registered(&self, user_id: &str, session_id: &str) -> bool {
+        self.sessions_of(user_id).iter().any(|id| id == session_id)
+    }
+}
+
+/// Unique id for the registry, the session itself is protected by the session middleware
+pub(crate) fn new_session_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_nanos())
+        .unwrap_or_default();
+    format!("{nanos:x}-{:x}", COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{
+        make_room_for_session, new_session_id, InMemorySessionRegistry, SessionEvictionPolicy,
+        SessionRegistry,
+    };
+
+    #[test]
+    fn should_count_the_sessions_of_each_user() {
+        let registry = InMemorySessionRegistry::default();
+
+        registry.register("anna", "1");
+        registry.register("anna", "2");
+        registry.register("anna", "2");
+        registry.register("bob", "3");
+
+        assert_eq!(registry.count("anna"), 2);
+        assert_eq!(registry.count("bob"), 1);
+        assert_eq!(registry.count("carl"), 0);
+    }
+
+    #[test]
+    fn deregistered_session_should_no_longer_be_registered() {
+        let registry = InMemorySessionRegistry::default();
+        registry.register("anna", "1");
+        registry.register("anna", "2");
+
+        registry.deregister("anna", "1");
+
+        assert!(!registry.is_registered("anna", "1"));
+        assert!(registry.is_registered("anna", "2"));
+        assert_eq!(registry.oldest("anna"), Some("2".to_owned()));
+    }
+
+    #[test]
+    fn sessions_should_expire_after_the_ttl() {
+        let registry = InMemorySessionRegistry::default().session_ttl(Duration::from_millis(10));
+        registry.register("anna", "1");
+
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(registry.count("anna"), 0);
+        assert!(!registry.is_registered("anna", "1"));
+    }
+
+    #[test]
+    fn room_should_only_be_made_if_sessions_may_be_evicted() {
+        let registry = InMemorySessionRegistry::default();
+        registry.register("anna", "1");
+        registry.register("anna", "2");
+
+        assert!(!make_room_for_session(
+            &registry,
+            "anna",
+            2,
+            SessionEvictionPolicy::Reject
+        ));
+        assert!(make_room_for_session(
+            &registry,
+            "anna",
+            2,
+            SessionEvictionPolicy::EvictOldest
+        ));
+        assert!(!registry.is_registered("anna", "1"));
+        assert!(registry.is_registered("anna", "2"));
+    }
+
+    #[test]
+    fn session_ids_should_be_unique() {
+        assert_ne!(new_session_id(), new_session_id());
+    }
+}
@@ -0,0 +1,319 @@
+//! Long-lived logins with a "remember me" cookie
+//!
+//! If the login request contains `"remember_me": true`, the [SessionLoginHandler](super::handlers::SessionLoginHandler) sets an
+//! encrypted cookie with a random token id. The token is saved in a [RememberMeStore] and its id in the `remember_me` key of the session.
+//! When the session has expired, the [SessionAuthProvider](struct@super::session_auth::SessionAuthProvider) restores it, if the token
+//! is still in the store. The user is loaded again with a [RememberedUserLoader], so that changes (e.g. roles) take effect.
+//! Both need the same [RememberMeConfig].
+//!
+//! The logout route revokes the token. To log out the user everywhere (e.g. after a password change or a ban),
+//! call [RememberMeStore::revoke_all].
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    cookie::{time, Cookie, CookieJar, Key, SameSite},
+    HttpRequest,
+};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use futures::future::LocalBoxFuture;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Keeps the "remember me" tokens that have not been revoked
+///
+/// Implementations must be shareable between the workers of the server (e.g. an in-memory map behind a mutex or a database).
+pub trait RememberMeStore: Send + Sync {
+    fn save(&self, token_id: &str, user_id: &str, expires_at: SystemTime);
+    /// The user of the token, `None` if the token is unknown, revoked or expired
+    fn user_id(&self, token_id: &str) -> Option<String>;
+    fn revoke(&self, token_id: &str);
+    /// Revokes every token of the user, e.g. after a password change or a ban
+    fn revoke_all(&self, user_id: &str);
+}
+
+impl<R: RememberMeStore> RememberMeStore for Arc<R> {
+    fn save(&self, token_id: &str, user_id: &str, expires_at: SystemTime) {
+        self.as_ref().save(token_id, user_id, expires_at)
+    }
+
+    fn user_id(&self, token_id: &str) -> Option<String> {
+        self.as_ref().user_id(token_id)
+    }
+
+    fn revoke(&self, token_id: &str) {
+        self.as_ref().revoke(token_id)
+    }
+
+    fn revoke_all(&self, user_id: &str) {
+        self.as_ref().revoke_all(user_id)
+    }
+}
+
+/// [RememberMeStore] that keeps the tokens in memory, so they are lost on restart and not shared between instances of the app
+#[derive(Default)]
+pub struct InMemoryRememberMeStore {
+    // token id -> (user id, expiry)
+    tokens: Mutex<HashMap<String, (String, SystemTime)>>,
+}
+
+impl RememberMeStore for InMemoryRememberMeStore {
+    fn save(&self, token_id: &str, user_id: &str, expires_at: SystemTime) {
+        let now = SystemTime::now();
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.retain(|_, (_, expires_at)| *expires_at > now);
+        tokens.insert(token_id.to_owned(), (user_id.to_owned(), expires_at));
+    }
+
+    fn user_id(&self, token_id: &str) -> Option<String> {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(token_id)
+            .filter(|(_, expires_at)| *expires_at > SystemTime::now())
+            .map(|(user_id, _)| user_id.clone())
+    }
+
+    fn revoke(&self, token_id: &str) {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(token_id);
+    }
+
+    fn revoke_all(&self, user_id: &str) {
+        self.tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|_, (id, _)| id != user_id);
+    }
+}
+
+/// Loads the user of a "remember me" token, instead of restoring an old copy of it
+pub trait RememberedUserLoader<U>: Send + Sync {
+    /// `user_id` is the username of the login, returns `None` if the user does not exist anymore or must not be restored
+    fn load_user(&self, user_id: &str) -> LocalBoxFuture<'_, Option<U>>;
+}
+
+/// Configuration of the "remember me" cookie
+///
+/// # Examples
+/// ```ignore
+/// let remember_me = RememberMeConfig::new(key.clone()).ttl(Duration::from_secs(60 * 60 * 24 * 14));
+///
+/// session_login_factory(
+///     SessionLoginHandler::new(user_service).with_remember_me(remember_me.clone()),
+///     AuthMiddleware::<_, User>::new(
+///         SessionAuthProvider::default().with_remember_me(remember_me, user_repository),
+///         PathMatcher::default(),
+///     ),
+///     CookieSessionStore::default(),
+///     key,
+/// )
+/// ```
+#[derive(Clone)]
+pub struct RememberMeConfig {
+    cookie_name: String,
+    ttl: Duration,
+    key: Key,
+    store: Arc<dyn RememberMeStore>,
+}
+
+/// Content of the cookie, encrypted with the key of the [RememberMeConfig]
+#[derive(Serialize, Deserialize)]
+struct RememberMeToken {
+    id: String,
+    user_id: String,
+    authenticated_at: u64,
+    expires_at: u64,
+}
+
+impl RememberMeConfig {
+    /// The cookie is encrypted with `key`, which must be the same for every instance of the app
+    ///
+    /// The tokens are kept in an [InMemoryRememberMeStore], which is shared by all clones of the config.
+    pub fn new(key: Key) -> Self {
+        Self {
+            cookie_name: "remember_me".to_owned(),
+            ttl: Duration::from_secs(60 * 60 * 24 * 30),
+            key,
+            store: Arc::new(InMemoryRememberMeStore::default()),
+        }
+    }
+
+    /// Default: `remember_me`
+    pub fn cookie_name(mut self, cookie_name: &str) -> Self {
+        self.cookie_name = cookie_name.to_owned();
+        self
+    }
+
+    /// How long the user is remembered (default: 30 days)
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Keeps the tokens in `store` instead of memory, e.g. to share them between instances or to revoke them
+    pub fn with_store(mut self, store: impl RememberMeStore + 'static) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Revokes the token, so that its cookie cannot restore a session anymore
+    pub fn revoke(&self, token_id: &str) {
+        self.store.revoke(token_id);
+    }
+
+    /// Cookie that removes the "remember me" cookie from the browser
+    ///
+    /// The logout route of the [SessionLoginHandler](super::handlers::SessionLoginHandler) sends it and revokes the token.
+    /// If users are logged out elsewhere (e.g. [AuthToken::invalidate](crate::AuthToken::invalidate) in a custom route), add it to that response.
+    pub fn removal_cookie(&self) -> Cookie<'static> {
+        let mut cookie = Cookie::build(self.cookie_name.clone(), "")
+            .path("/")
+            .finish();
+        cookie.make_removal();
+        cookie
+    }
+
+    /// Saves a new token for the user and returns its id and the cookie
+    pub(crate) fn create_cookie(
+        &self,
+        user_id: &str,
+        authenticated_at: SystemTime,
+    ) -> Option<(String, Cookie<'static>)> {
+        let expires_at = SystemTime::now() + self.ttl;
+        let token = RememberMeToken {
+            id: new_token_id(),
+            user_id: user_id.to_owned(),
+            authenticated_at: secs_since_epoch(authenticated_at),
+            expires_at: secs_since_epoch(expires_at),
+        };
+        let value = serde_json::to_string(&token).ok()?;
+        let cookie = Cookie::build(self.cookie_name.clone(), value)
+            .path("/")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax)
+            .max_age(time::Duration::seconds(self.ttl.as_secs() as i64))
+            .finish();
+
+        let mut jar = CookieJar::new();
+        jar.private_mut(&self.key).add(cookie);
+        let cookie = jar.get(&self.cookie_name).cloned()?;
+
+        self.store.save(&token.id, user_id, expires_at);
+        Some((token.id, cookie))
+    }
+
+    /// Returns the token id, the user id and the time of the login, if the cookie is valid and its token not revoked
+    pub(crate) fn read_cookie(&self, req: &HttpRequest) -> Option<(String, String, SystemTime)> {
+        let cookie = req.cookie(&self.cookie_name)?;
+        let mut jar = CookieJar::new();
+        jar.add_original(cookie);
+        let Some(cookie) = jar.private(&self.key).get(&self.cookie_name) else {
+            debug!("Cannot decrypt the remember me cookie");
+            return None;
+        };
+
+        let token: RememberMeToken = serde_json::from_str(cookie.value()).ok()?;
+        if secs_since_epoch(SystemTime::now()) >= token.expires_at {
+            debug!("Remember me cookie has expired");
+            return None;
+        }
+        if self.store.user_id(&token.id).as_ref() != Some(&token.user_id) {
+            debug!("Remember me token has been revoked");
+            return None;
+        }
+
+        Some((
+            token.id,
+            token.user_id,
+            UNIX_EPOCH + Duration::from_secs(token.authenticated_at),
+        ))
+    }
+}
+
+// Key::generate uses the random generator of the OS
+fn new_token_id() -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(&Key::generate().master()[..32])
+}
+
+fn secs_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use actix_web::{cookie::Key, test::TestRequest};
+
+    use super::{InMemoryRememberMeStore, RememberMeConfig, RememberMeStore};
+
+    #[test]
+    fn cookie_should_be_readable_with_the_same_key() {
+        let config = RememberMeConfig::new(Key::generate());
+        let (token_id, cookie) = config.create_cookie("anna", SystemTime::now()).unwrap();
+
+        let req = TestRequest::default().cookie(cookie).to_http_request();
+        let (read_token_id, user_id, _) = config.read_cookie(&req).unwrap();
+
+        assert_eq!(read_token_id, token_id);
+        assert_eq!(user_id, "anna");
+    }
+
+    #[test]
+    fn cookie_of_another_key_should_be_rejected() {
+        let (_, cookie) = RememberMeConfig::new(Key::generate())
+            .create_cookie("anna", SystemTime::now())
+            .unwrap();
+
+        let req = TestRequest::default().cookie(cookie).to_http_request();
+
+        assert!(RememberMeConfig::new(Key::generate())
+            .read_cookie(&req)
+            .is_none());
+    }
+
+    #[test]
+    fn expired_cookie_should_be_rejected() {
+        let config = RememberMeConfig::new(Key::generate()).ttl(Duration::ZERO);
+        let (_, cookie) = config.create_cookie("anna", SystemTime::now()).unwrap();
+
+        let req = TestRequest::default().cookie(cookie).to_http_request();
+
+        assert!(config.read_cookie(&req).is_none());
+    }
+
+    #[test]
+    fn revoked_token_should_be_rejected() {
+        let config = RememberMeConfig::new(Key::generate());
+        let (token_id, cookie) = config.create_cookie("anna", SystemTime::now()).unwrap();
+        let req = TestRequest::default().cookie(cookie).to_http_request();
+
+        config.revoke(&token_id);
+
+        assert!(config.read_cookie(&req).is_none());
+    }
+
+    #[test]
+    fn revoke_all_should_only_revoke_the_tokens_of_the_user() {
+        let store = InMemoryRememberMeStore::default();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        store.save("1", "anna", expires_at);
+        store.save("2", "anna", expires_at);
+        store.save("3", "bob", expires_at);
+
+        store.revoke_all("anna");
+
+        assert!(store.user_id("1").is_none());
+        assert!(store.user_id("2").is_none());
+        assert_eq!(store.user_id("3"), Some("bob".to_owned()));
+    }
+}
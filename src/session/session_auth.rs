@@ -1,7 +1,10 @@
 use std::{
+    any::Any,
     future::{ready, Future, Ready},
+    net::IpAddr,
     pin::Pin,
-    time::SystemTime,
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 use actix_session::{
@@ -11,33 +14,341 @@ use actix_web::{
     body::MessageBody,
     cookie::Key,
     dev::{ServiceFactory, ServiceRequest, ServiceResponse},
-    App, Error, FromRequest, HttpRequest,
+    App, Error, FromRequest, HttpMessage, HttpRequest,
 };
-use log::error;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use log::{debug, error, warn};
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{
-    login::LoadUserService, middleware::AuthMiddleware, AuthState, AuthToken,
-    AuthenticationProvider, UnauthorizedError,
+    login::LoadUserService, middleware::AuthMiddleware, multifactor::StoredContext, AuthState,
+    AuthToken, AuthenticationProvider, UnauthorizedError,
 };
 
-use super::handlers::{login_config, SessionLoginHandler};
+#[cfg(feature = "compression")]
+use super::user_serializer::{CompressedUserSerializer, Compression};
+use super::{
+    handlers::{login_config, SessionLoginHandler},
+    registry::{make_room_for_session, new_session_id, SessionEvictionPolicy, SessionRegistry},
+    remember_me::{RememberMeConfig, RememberedUserLoader},
+    user_serializer::{JsonUserSerializer, UserSerializer},
+};
 
 const SESSION_KEY_USER: &str = "user";
-const SESSION_KEY_NEED_MFA: &str = "needs_mfa";
+pub(crate) const SESSION_KEY_NEED_MFA: &str = "needs_mfa";
 const SESSION_KEY_LOGIN_VALID_UNTIL: &str = "login_valid_until";
+pub(crate) const SESSION_KEY_AUTHENTICATED_AT: &str = "authenticated_at";
+const SESSION_KEY_CLIENT_IP: &str = "client_ip";
+const SESSION_KEY_MFA_CONTEXT: &str = "mfa_context";
+const SESSION_KEY_LAST_ACTIVE_AT: &str = "last_active_at";
+const SESSION_KEY_EXPIRES_AT: &str = "expires_at";
+// id of the "remember me" token
+const SESSION_KEY_REMEMBER_ME: &str = "remember_me";
+const SESSION_KEY_REMEMBER_ME_AFTER_MFA: &str = "remember_me_after_mfa";
+const SESSION_KEY_REGISTERED_SESSION: &str = "registered_session";
+const SESSION_KEY_LOGIN_IN_PROGRESS: &str = "login_in_progress";
 
 /// Provider for session based authentication.
 ///
 /// Uses [Actix-Session](https://docs.rs/actix-session/latest/actix_session/), so it must be set as middleware.
+/// The user is stored in the session with a [UserSerializer] ([JsonUserSerializer] by default).
+/// JSON is stored as it is, the bytes of other serializers are base64 encoded.
+///
+/// If the user type is not known at compile time (e.g. a proxy that forwards the claims as headers),
+/// use `serde_json::Value` as user, the JSON is then stored and loaded as it is:
+/// ```ignore
+/// AuthMiddleware::<_, serde_json::Value>::new(SessionAuthProvider::default(), PathMatcher::default())
+/// ```
 /// # Examples
 /// See crate example.
-#[derive(Clone)]
-pub struct SessionAuthProvider;
+pub struct SessionAuthProvider<S = JsonUserSerializer> {
+    serializer: S,
+    bind_to_ip: bool,
+    remember_me: Option<RememberMeConfig>,
+    // Arc<dyn RememberedUserLoader<U>>, the provider is not generic over the user
+    remembered_user_loader: Option<Arc<dyn Any + Send + Sync>>,
+    session_registry: Option<Arc<dyn SessionRegistry>>,
+    max_sessions: usize,
+    session_eviction_policy: SessionEvictionPolicy,
+    session_timeout: Option<Duration>,
+}
+
+/// Provider with the [JsonUserSerializer], so that `SessionAuthProvider` can still be used as a value
+/// like the unit struct it used to be. Same as [SessionAuthProvider::new].
+#[allow(non_upper_case_globals)]
+pub const SessionAuthProvider: SessionAuthProvider = SessionAuthProvider::new();
+
+impl SessionAuthProvider<JsonUserSerializer> {
+    pub const fn new() -> Self {
+        Self::with_serializer(JsonUserSerializer)
+    }
+}
+
+impl Default for SessionAuthProvider<JsonUserSerializer> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> SessionAuthProvider<S> {
+    /// Creates a provider that stores the user with the given [UserSerializer]
+    ///
+    /// # Examples
+    /// ```ignore
+    /// AuthMiddleware::<_, User>::new(
+    ///     SessionAuthProvider::with_serializer(BincodeUserSerializer),
+    ///     PathMatcher::default(),
+    /// )
+    /// ```
+    pub const fn with_serializer(serializer: S) -> Self {
+        Self {
+            serializer,
+            bind_to_ip: false,
+            remember_me: None,
+            remembered_user_loader: None,
+            session_registry: None,
+            max_sessions: 0,
+            session_eviction_policy: SessionEvictionPolicy::EvictOldest,
+            session_timeout: None,
+        }
+    }
+
+    /// Compresses the serialized user before it is stored in the session (see [CompressedUserSerializer])
+    #[cfg(feature = "compression")]
+    pub fn with_compression(
+        self,
+        compression: Compression,
+    ) -> SessionAuthProvider<CompressedUserSerializer<S>> {
+        SessionAuthProvider {
+            serializer: CompressedUserSerializer::new(self.serializer, compression),
+            bind_to_ip: self.bind_to_ip,
+            remember_me: self.remember_me,
+            remembered_user_loader: self.remembered_user_loader,
+            session_registry: self.session_registry,
+            max_sessions: self.max_sessions,
+            session_eviction_policy: self.session_eviction_policy,
+            session_timeout: self.session_timeout,
+        }
+    }
 
-impl<U> AuthenticationProvider<U> for SessionAuthProvider
+    /// Binds the session to the IP of the client on the first authenticated request (default: `false`)
+    ///
+    /// Requests from another IP with the same session cookie (e.g. a stolen cookie) are rejected with [UnauthorizedError].
+    /// The IP of the peer is used, so behind a reverse proxy all clients have the same IP.
+    pub fn bind_to_ip(mut self, bind_to_ip: bool) -> Self {
+        self.bind_to_ip = bind_to_ip;
+        self
+    }
+
+    /// Restores an expired session from the "remember me" cookie (see [RememberMeConfig])
+    ///
+    /// The user is loaded again with `user_loader`. Use the same config for [SessionLoginHandler::with_remember_me].
+    pub fn with_remember_me<U: 'static>(
+        mut self,
+        config: RememberMeConfig,
+        user_loader: impl RememberedUserLoader<U> + 'static,
+    ) -> Self {
+        let user_loader: Arc<dyn RememberedUserLoader<U>> = Arc::new(user_loader);
+        self.remember_me = Some(config);
+        self.remembered_user_loader = Some(Arc::new(user_loader));
+        self
+    }
+
+    /// Sessions expire `timeout` after the login, unless they are extended with [AuthToken::extend_session]
+    ///
+    /// The session of the store must live longer (e.g. the `session_ttl` of Actix-Session), otherwise it is gone before.
+    pub fn with_session_timeout(mut self, timeout: Duration) -> Self {
+        self.session_timeout = Some(timeout);
+        self
+    }
+
+    /// Rejects sessions that are no longer in the [SessionRegistry] (e.g. evicted by a newer login)
+    ///
+    /// Expired sessions are deregistered, sessions restored by "remember me" are registered within the limit of `max_sessions`.
+    /// Use the same registry and limit for [SessionLoginHandler::with_session_registry].
+    pub fn with_session_registry(
+        mut self,
+        registry: impl SessionRegistry + 'static,
+        max_sessions: usize,
+    ) -> Self {
+        self.session_registry = Some(Arc::new(registry));
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    /// See [SessionAuthProvider::with_session_registry], should be the policy of the [SessionLoginHandler]
+    pub fn session_eviction_policy(mut self, policy: SessionEvictionPolicy) -> Self {
+        self.session_eviction_policy = policy;
+        self
+    }
+}
+
+impl<S: Clone> Clone for SessionAuthProvider<S> {
+    fn clone(&self) -> Self {
+        Self {
+            serializer: self.serializer.clone(),
+            bind_to_ip: self.bind_to_ip,
+            remember_me: self.remember_me.clone(),
+            remembered_user_loader: self.remembered_user_loader.clone(),
+            session_registry: self.session_registry.clone(),
+            max_sessions: self.max_sessions,
+            session_eviction_policy: self.session_eviction_policy,
+            session_timeout: self.session_timeout,
+        }
+    }
+}
+
+impl<S> SessionAuthProvider<S> {
+    /// Checks the session of `user` (IP, registry, expiry) and creates the [AuthToken]
+    fn token_of_session<U: DeserializeOwned + Clone + 'static>(
+        &self,
+        user: U,
+        s: &Session,
+        req: &HttpRequest,
+    ) -> Result<AuthToken<U>, UnauthorizedError> {
+        if self.bind_to_ip && !is_bound_to_client_ip(s, req.peer_addr().map(|a| a.ip())) {
+            warn!("Session is used from another IP than the one it is bound to");
+            return Err(UnauthorizedError::default());
+        }
+
+        let mut state = match s.get::<String>(SESSION_KEY_NEED_MFA) {
+            Ok(Some(_mfa_id)) => AuthState::NeedsMfa,
+            Ok(None) => AuthState::Authenticated,
+            Err(_) => {
+                error!("Cannot read `need_mfa' value from session");
+                return Err(UnauthorizedError::default());
+            }
+        };
+
+        // a streaming mfa check cannot change the session, it registers the session once the code is approved
+        if let (Some(registry), AuthState::NeedsMfa) = (&self.session_registry, &state) {
+            if let Some((user_id, session_id)) = registered_session(s) {
+                if registry.is_registered(&user_id, &session_id) {
+                    debug!("Mfa of '{user_id}' has been approved in a stream");
+                    s.remove(SESSION_KEY_NEED_MFA);
+                    if let Err(e) = s.insert(SESSION_KEY_AUTHENTICATED_AT, SystemTime::now()) {
+                        error!("Cannot store authentication time in session: {e}");
+                        return Err(UnauthorizedError::default());
+                    }
+                    state = AuthState::Authenticated;
+                }
+            }
+        }
+
+        // sessions with pending mfa are registered after the mfa challenge
+        if let (Some(registry), AuthState::Authenticated) = (&self.session_registry, &state) {
+            if let Some((user_id, session_id)) = registered_session(s) {
+                if !registry.is_registered(&user_id, &session_id) {
+                    warn!("Session of '{user_id}' is no longer registered, probably evicted");
+                    s.purge();
+                    return Err(UnauthorizedError::default());
+                }
+            }
+        }
+
+        // Sessions without timestamp are treated as if they were very old
+        let authenticated_at = s
+            .get::<SystemTime>(SESSION_KEY_AUTHENTICATED_AT)
+            .ok()
+            .flatten()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let last_active_at = s
+            .get::<SystemTime>(SESSION_KEY_LAST_ACTIVE_AT)
+            .ok()
+            .flatten();
+
+        // extended sessions have their own expiry, the others expire after the timeout
+        let expires_at = s
+            .get::<SystemTime>(SESSION_KEY_EXPIRES_AT)
+            .ok()
+            .flatten()
+            .or_else(|| {
+                self.session_timeout
+                    .map(|session_timeout| authenticated_at + session_timeout)
+            });
+        if expires_at.is_some_and(|expires_at| expires_at <= SystemTime::now()) {
+            debug!("Session has expired");
+            if let (Some(registry), Some((user_id, session_id))) =
+                (&self.session_registry, registered_session(s))
+            {
+                registry.deregister(&user_id, &session_id);
+            }
+            s.purge();
+            return Err(UnauthorizedError::default());
+        }
+
+        Ok(AuthToken::new(user, state, authenticated_at)
+            .with_last_active_at(last_active_at)
+            .with_expires_at(expires_at))
+    }
+
+    /// Puts the user of a valid "remember me" cookie into the (new) session
+    async fn restore_from_remember_me<U: 'static>(
+        &self,
+        req: &HttpRequest,
+        session: &Session,
+    ) -> Option<U>
+    where
+        S: UserSerializer<U>,
+    {
+        let config = self.remember_me.as_ref()?;
+        let user_loader = self
+            .remembered_user_loader
+            .as_ref()?
+            .downcast_ref::<Arc<dyn RememberedUserLoader<U>>>()?;
+        let (token_id, user_id, authenticated_at) = config.read_cookie(req)?;
+
+        let Some(user) = user_loader.load_user(&user_id).await else {
+            debug!("User '{user_id}' of the remember me cookie cannot be restored");
+            return None;
+        };
+        if let Some(registry) = &self.session_registry {
+            if !make_room_for_session(
+                registry.as_ref(),
+                &user_id,
+                self.max_sessions,
+                self.session_eviction_policy,
+            ) {
+                debug!("Session limit of '{user_id}' reached, session is not restored");
+                return None;
+            }
+        }
+        debug!("Session restored from remember me cookie");
+
+        // the login may be older than the timeout, the restored session starts a new one
+        if let Some(session_timeout) = self.session_timeout {
+            session
+                .insert(SESSION_KEY_EXPIRES_AT, SystemTime::now() + session_timeout)
+                .inspect_err(|e| error!("Cannot store expiry in session: {e}"))
+                .ok()?;
+        }
+
+        store_user(session, &user, &self.serializer)
+            .inspect_err(|e| error!("Cannot store user in session: {e}"))
+            .ok()?;
+        session
+            .insert(SESSION_KEY_AUTHENTICATED_AT, authenticated_at)
+            .and_then(|_| session.insert(SESSION_KEY_REMEMBER_ME, token_id))
+            .inspect_err(|e| error!("Cannot restore session from remember me cookie: {e}"))
+            .ok()?;
+
+        if let Some(registry) = &self.session_registry {
+            let session_id = new_session_id();
+            session
+                .insert(SESSION_KEY_REGISTERED_SESSION, (&user_id, &session_id))
+                .inspect_err(|e| error!("Cannot register restored session: {e}"))
+                .ok()?;
+            registry.register(&user_id, &session_id);
+        }
+        Some(user)
+    }
+}
+
+impl<U, S> AuthenticationProvider<U> for SessionAuthProvider<S>
 where
     U: DeserializeOwned + Clone + 'static,
+    S: UserSerializer<U> + Clone + 'static,
 {
     fn get_auth_token(
         &self,
@@ -45,22 +356,19 @@ where
     ) -> Pin<Box<dyn Future<Output = Result<AuthToken<U>, UnauthorizedError>>>> {
         let s = req.get_session().clone();
 
-        // ToDo: refactor: remove the matches here
-        let user = match s.get::<U>(SESSION_KEY_USER) {
-            Ok(Some(user)) => user,
-            _ => return Box::pin(ready(Err(UnauthorizedError::default()))),
-        };
-
-        let state = match s.get::<String>(SESSION_KEY_NEED_MFA) {
-            Ok(Some(_mfa_id)) => AuthState::NeedsMfa,
-            Ok(None) => AuthState::Authenticated,
-            Err(_) => {
-                error!("Cannot read `need_mfa' value from session");
-                return Box::pin(ready(Err(UnauthorizedError::default())));
-            }
-        };
+        if let Some(user) = load_user(&s, &self.serializer) {
+            return Box::pin(ready(self.token_of_session(user, &s, req)));
+        }
 
-        Box::pin(ready(Ok(AuthToken::new(user, state))))
+        let provider = self.clone();
+        let req = req.clone();
+        Box::pin(async move {
+            let user = provider
+                .restore_from_remember_me(&req, &s)
+                .await
+                .ok_or_else(UnauthorizedError::default)?;
+            provider.token_of_session(user, &s, &req)
+        })
     }
 
     fn invalidate(&self, req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
@@ -69,8 +377,107 @@ where
 
         Box::pin(async {})
     }
+
+    fn name(&self) -> &'static str {
+        "session"
+    }
+
+    fn persist_token(
+        &self,
+        token: &AuthToken<U>,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        if let Some(last_active_at) = token.last_active_at() {
+            let _ = req
+                .get_session()
+                .insert(SESSION_KEY_LAST_ACTIVE_AT, last_active_at)
+                .inspect_err(|e| error!("Cannot store last activity in session: {e}"));
+        }
+        if let Some(expires_at) = token.expires_at() {
+            let _ = req
+                .get_session()
+                .insert(SESSION_KEY_EXPIRES_AT, expires_at)
+                .inspect_err(|e| error!("Cannot store expiry in session: {e}"));
+        }
+
+        Box::pin(async {})
+    }
+
+    fn configure_request(&self, req: &HttpRequest) {
+        // makes the serializer available for LoginSession::set_user
+        let serializer: Arc<dyn UserSerializer<U>> = Arc::new(self.serializer.clone());
+        req.extensions_mut().insert(serializer);
+    }
+}
+
+fn load_user<U>(session: &Session, serializer: &dyn UserSerializer<U>) -> Option<U> {
+    let value = session.get::<serde_json::Value>(SESSION_KEY_USER).ok()??;
+    let bytes = match value {
+        serde_json::Value::String(encoded) if !serializer.is_json() => BASE64_STANDARD
+            .decode(encoded)
+            .inspect_err(|e| error!("Cannot decode user from session: {e}"))
+            .ok()?,
+        // JSON, also of sessions that have been stored before the serializer was changed
+        value => serde_json::to_vec(&value).ok()?,
+    };
+
+    serializer
+        .deserialize(&bytes)
+        .inspect_err(|e| error!("{e}"))
+        .ok()
+}
+
+fn store_user<U>(
+    session: &Session,
+    user: &U,
+    serializer: &dyn UserSerializer<U>,
+) -> Result<(), Error> {
+    let bytes = serializer.serialize(user)?;
+    if serializer.is_json() {
+        let value = serde_json::from_slice::<serde_json::Value>(&bytes)?;
+        session.insert(SESSION_KEY_USER, value)?;
+    } else {
+        session.insert(SESSION_KEY_USER, BASE64_STANDARD.encode(bytes))?;
+    }
+    Ok(())
+}
+
+/// User id and session id in the [SessionRegistry]
+fn registered_session(session: &Session) -> Option<(String, String)> {
+    session
+        .get::<(String, String)>(SESSION_KEY_REGISTERED_SESSION)
+        .ok()
+        .flatten()
 }
 
+/// Stores the IP on first use, afterwards it has to match
+fn is_bound_to_client_ip(session: &Session, client_ip: Option<IpAddr>) -> bool {
+    let Some(client_ip) = client_ip.map(|ip| ip.to_string()) else {
+        return false;
+    };
+
+    match session.get::<String>(SESSION_KEY_CLIENT_IP) {
+        Ok(Some(bound_ip)) => bound_ip == client_ip,
+        Ok(None) => session
+            .insert(SESSION_KEY_CLIENT_IP, client_ip)
+            .inspect_err(|e| error!("Cannot bind session to client IP: {e}"))
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Returns the [UserSerializer] configured by the [SessionAuthProvider] or [JsonUserSerializer] if there is none
+pub(crate) fn user_serializer<U>(req: &HttpRequest) -> Arc<dyn UserSerializer<U>>
+where
+    U: Serialize + DeserializeOwned + 'static,
+{
+    req.extensions()
+        .get::<Arc<dyn UserSerializer<U>>>()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(JsonUserSerializer))
+}
+
+#[derive(Clone)]
 pub(crate) struct LoginSession {
     session: Session,
 }
@@ -88,8 +495,75 @@ impl LoginSession {
         self.session.insert(SESSION_KEY_NEED_MFA, mfa_id)
     }
 
-    pub fn set_user<U: Serialize>(&self, user: U) -> Result<(), SessionInsertError> {
-        self.session.insert(SESSION_KEY_USER, user)
+    pub fn set_user<U>(&self, user: &U, serializer: &dyn UserSerializer<U>) -> Result<(), Error> {
+        store_user(&self.session, user, serializer)
+    }
+
+    /// The "remember me" cookie for `user_id` is set after the mfa challenge
+    pub fn remember_me_after_mfa(&self, user_id: &str) -> Result<(), SessionInsertError> {
+        self.session
+            .insert(SESSION_KEY_REMEMBER_ME_AFTER_MFA, user_id)
+    }
+
+    /// The user id, if the "remember me" cookie is wanted after the mfa challenge
+    pub fn wants_remember_me(&self) -> Option<String> {
+        self.session
+            .remove_as::<String>(SESSION_KEY_REMEMBER_ME_AFTER_MFA)
+            .and_then(Result::ok)
+    }
+
+    /// Whether another login of this session has not finished yet
+    pub fn login_in_progress(&self) -> bool {
+        self.session
+            .get::<bool>(SESSION_KEY_LOGIN_IN_PROGRESS)
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    pub fn start_login(&self) -> Result<(), SessionInsertError> {
+        self.session.insert(SESSION_KEY_LOGIN_IN_PROGRESS, true)
+    }
+
+    pub fn login_finished(&self) {
+        self.session.remove(SESSION_KEY_LOGIN_IN_PROGRESS);
+    }
+
+    /// The id of the token of the "remember me" cookie, see [RememberMeConfig]
+    pub fn remembered(&self, token_id: &str) -> Result<(), SessionInsertError> {
+        self.session.insert(SESSION_KEY_REMEMBER_ME, token_id)
+    }
+
+    pub fn remember_me_token(&self) -> Option<String> {
+        self.session
+            .get::<String>(SESSION_KEY_REMEMBER_ME)
+            .ok()
+            .flatten()
+    }
+
+    pub fn registered(&self, user_id: &str, session_id: &str) -> Result<(), SessionInsertError> {
+        self.session
+            .insert(SESSION_KEY_REGISTERED_SESSION, (user_id, session_id))
+    }
+
+    pub fn registered_session(&self) -> Option<(String, String)> {
+        registered_session(&self.session)
+    }
+
+    pub fn authenticated_at(&self, authenticated_at: SystemTime) -> Result<(), SessionInsertError> {
+        self.session
+            .insert(SESSION_KEY_AUTHENTICATED_AT, authenticated_at)
+    }
+
+    pub fn store_context(&self, context: &StoredContext) -> Result<(), SessionInsertError> {
+        self.session.insert(SESSION_KEY_MFA_CONTEXT, context)
+    }
+
+    pub fn stored_context(&self) -> Option<StoredContext> {
+        self.session
+            .get::<StoredContext>(SESSION_KEY_MFA_CONTEXT)
+            .ok()
+            .flatten()
     }
 
     pub fn valid_until(&self, valid_until: SystemTime) -> Result<(), SessionInsertError> {
@@ -147,3 +621,47 @@ pub fn session_login_factory<U: Serialize + DeserializeOwned + Clone + 'static>(
         .wrap(auth_middleware)
         .wrap(SessionMiddleware::new(session_store, key))
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_session::SessionExt;
+    use actix_web::test::TestRequest;
+
+    use super::{is_bound_to_client_ip, load_user, store_user, SESSION_KEY_USER};
+    use crate::session::user_serializer::JsonUserSerializer;
+
+    #[test]
+    fn session_should_be_bound_to_first_client_ip() {
+        let req = TestRequest::default().to_http_request();
+        let session = req.get_session();
+
+        assert!(is_bound_to_client_ip(&session, "10.0.0.1".parse().ok()));
+        assert!(is_bound_to_client_ip(&session, "10.0.0.1".parse().ok()));
+        assert!(!is_bound_to_client_ip(&session, "10.0.0.2".parse().ok()));
+        assert!(!is_bound_to_client_ip(&session, None));
+    }
+
+    #[test]
+    fn json_user_should_be_stored_as_plain_json() {
+        let req = TestRequest::default().to_http_request();
+        let session = req.get_session();
+
+        store_user(&session, &vec!["anna".to_owned()], &JsonUserSerializer).unwrap();
+
+        assert_eq!(
+            session.get::<Vec<String>>(SESSION_KEY_USER).unwrap(),
+            Some(vec!["anna".to_owned()])
+        );
+    }
+
+    #[test]
+    fn user_of_a_session_without_serializer_should_be_loaded() {
+        let req = TestRequest::default().to_http_request();
+        let session = req.get_session();
+        session.insert(SESSION_KEY_USER, "anna").unwrap();
+
+        let user: Option<String> = load_user(&session, &JsonUserSerializer);
+
+        assert_eq!(user.as_deref(), Some("anna"));
+    }
+}
@@ -0,0 +1,203 @@
+use actix_web::{HttpResponse, ResponseError};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+/// Converts the user into bytes that can be stored in the session and back
+///
+/// [SessionAuthProvider](struct@crate::session::session_auth::SessionAuthProvider) uses [JsonUserSerializer] by default.
+/// Use [SessionAuthProvider::with_serializer](crate::session::session_auth::SessionAuthProvider::with_serializer) to change it.
+pub trait UserSerializer<U> {
+    fn serialize(&self, user: &U) -> Result<Vec<u8>, UserSerializerError>;
+    fn deserialize(&self, bytes: &[u8]) -> Result<U, UserSerializerError>;
+
+    /// JSON is stored in the session as it is, other bytes are base64 encoded (default: `false`)
+    fn is_json(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UserSerializerError {
+    #[error("Cannot serialize user: {0}")]
+    Serialize(String),
+    #[error("Cannot deserialize user: {0}")]
+    Deserialize(String),
+}
+
+impl ResponseError for UserSerializerError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Serializes the user as JSON (default)
+#[derive(Clone, Default)]
+pub struct JsonUserSerializer;
+
+impl<U> UserSerializer<U> for JsonUserSerializer
+where
+    U: Serialize + DeserializeOwned,
+{
+    fn serialize(&self, user: &U) -> Result<Vec<u8>, UserSerializerError> {
+        serde_json::to_vec(user).map_err(|e| UserSerializerError::Serialize(e.to_string()))
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<U, UserSerializerError> {
+        serde_json::from_slice(bytes).map_err(|e| UserSerializerError::Deserialize(e.to_string()))
+    }
+
+    fn is_json(&self) -> bool {
+        true
+    }
+}
+
+/// Serializes the user with [bincode](https://docs.rs/bincode/latest/bincode/)
+///
+/// The result is smaller than JSON, but the user type must not rely on self-describing formats
+/// (e.g. `#[serde(untagged)]` or `serde_json::Value` fields).
+#[cfg(feature = "bincode")]
+#[derive(Clone, Default)]
+pub struct BincodeUserSerializer;
+
+#[cfg(feature = "bincode")]
+impl<U> UserSerializer<U> for BincodeUserSerializer
+where
+    U: Serialize + DeserializeOwned,
+{
+    fn serialize(&self, user: &U) -> Result<Vec<u8>, UserSerializerError> {
+        bincode::serialize(user).map_err(|e| UserSerializerError::Serialize(e.to_string()))
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<U, UserSerializerError> {
+        bincode::deserialize(bytes).map_err(|e| UserSerializerError::Deserialize(e.to_string()))
+    }
+}
+
+/// Compression algorithm for [CompressedUserSerializer]
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy, Debug)]
+pub enum Compression {
+    Gzip,
+}
+
+#[cfg(feature = "compression")]
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+
+/// Compresses the bytes of another [UserSerializer]
+///
+/// Created by [SessionAuthProvider::with_compression](crate::session::session_auth::SessionAuthProvider::with_compression).
+/// Sessions that have been stored without compression can still be read, they are recognized by the missing gzip magic bytes.
+///
+/// Compression costs CPU time on every request and gzip adds a header of about 20 bytes,
+/// so it only pays off for large users (e.g. with many roles or permissions). Small users even get bigger.
+/// For a user with 100 permissions (`cargo bench --bench user_serializer`) the JSON shrinks from 2048 to 318 bytes,
+/// while serializing and deserializing takes about 56µs instead of 8µs.
+#[cfg(feature = "compression")]
+#[derive(Clone)]
+pub struct CompressedUserSerializer<S> {
+    inner: S,
+    compression: Compression,
+}
+
+#[cfg(feature = "compression")]
+impl<S> CompressedUserSerializer<S> {
+    pub fn new(inner: S, compression: Compression) -> Self {
+        Self { inner, compression }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl<U, S> UserSerializer<U> for CompressedUserSerializer<S>
+where
+    S: UserSerializer<U>,
+{
+    fn serialize(&self, user: &U) -> Result<Vec<u8>, UserSerializerError> {
+        use std::io::Write;
+
+        use flate2::write::GzEncoder;
+
+        let bytes = self.inner.serialize(user)?;
+        match self.compression {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(&bytes)
+                    .and_then(|_| encoder.finish())
+                    .map_err(|e| UserSerializerError::Serialize(e.to_string()))
+            }
+        }
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<U, UserSerializerError> {
+        use std::io::Read;
+
+        use flate2::read::GzDecoder;
+
+        if !bytes.starts_with(&GZIP_MAGIC_BYTES) {
+            return self.inner.deserialize(bytes);
+        }
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(bytes)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| UserSerializerError::Deserialize(e.to_string()))?;
+        self.inner.deserialize(&decompressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{JsonUserSerializer, UserSerializer};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct User {
+        email: String,
+        name: String,
+    }
+
+    fn anna() -> User {
+        User {
+            email: "anna@example.org".to_owned(),
+            name: "anna".to_owned(),
+        }
+    }
+
+    #[test]
+    fn json_serializer_should_restore_user() {
+        let serializer = JsonUserSerializer;
+        let bytes = serializer.serialize(&anna()).unwrap();
+
+        let user: User = serializer.deserialize(&bytes).unwrap();
+
+        assert_eq!(user, anna());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_serializer_should_restore_user() {
+        let serializer = super::BincodeUserSerializer;
+        let bytes = serializer.serialize(&anna()).unwrap();
+
+        let user: User = serializer.deserialize(&bytes).unwrap();
+
+        assert_eq!(user, anna());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_serializer_should_restore_compressed_and_uncompressed_users() {
+        use super::{CompressedUserSerializer, Compression};
+
+        let serializer = CompressedUserSerializer::new(JsonUserSerializer, Compression::Gzip);
+        let compressed = serializer.serialize(&anna()).unwrap();
+        let uncompressed = JsonUserSerializer.serialize(&anna()).unwrap();
+
+        assert_ne!(compressed, uncompressed);
+        let user: User = serializer.deserialize(&compressed).unwrap();
+        assert_eq!(user, anna());
+        let user: User = serializer.deserialize(&uncompressed).unwrap();
+        assert_eq!(user, anna());
+    }
+}
@@ -0,0 +1,141 @@
+//! Verification of signed requests (e.g. for internal APIs)
+//!
+//! The client signs `METHOD\npath\ntimestamp\nbody` and sends the signature in the `X-Signature` header
+//! and the unix timestamp (seconds) in the `X-Timestamp` header.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{
+    dev::{Payload, ServiceRequest},
+    error::PayloadError,
+    web::Bytes,
+};
+use futures::stream;
+use log::debug;
+
+use crate::errors::UnauthorizedError;
+
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Timestamp";
+
+/// Checks the signature of a request
+///
+/// Is registered with [AuthMiddleware::with_signature_verifier](crate::middleware::AuthMiddleware::with_signature_verifier).
+pub trait RequestSignatureVerifier {
+    /// Returns `true` if `signature` (the value of `X-Signature`) is valid for the message built by [signature_message]
+    fn verify(&self, message: &[u8], signature: &str) -> bool;
+
+    /// Requests whose timestamp differs more than `max_age` from now are rejected to prevent replay attacks (default: 5 minutes)
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(60 * 5)
+    }
+}
+
+/// The message that is signed: `METHOD\npath\ntimestamp\nbody`
+pub fn signature_message(method: &str, path: &str, timestamp: u64, body: &[u8]) -> Vec<u8> {
+    let mut message = format!("{method}\n{path}\n{timestamp}\n").into_bytes();
+    message.extend_from_slice(body);
+    message
+}
+
+/// Verifies base64 encoded HMAC-SHA256 signatures
+#[cfg(feature = "request_signature")]
+pub struct HmacSignatureVerifier {
+    secret: Vec<u8>,
+    max_age: Duration,
+}
+
+#[cfg(feature = "request_signature")]
+impl HmacSignatureVerifier {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.to_vec(),
+            max_age: Duration::from_secs(60 * 5),
+        }
+    }
+
+    /// See [RequestSignatureVerifier::max_age]
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+}
+
+#[cfg(feature = "request_signature")]
+impl RequestSignatureVerifier for HmacSignatureVerifier {
+    fn verify(&self, message: &[u8], signature: &str) -> bool {
+        use base64::{prelude::BASE64_STANDARD, Engine};
+        use hmac::{Hmac, Mac};
+        use sha2::Sha256;
+
+        let Ok(signature) = BASE64_STANDARD.decode(signature) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(&self.secret) else {
+            return false;
+        };
+        mac.update(message);
+        // constant time comparison
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn max_age(&self) -> Duration {
+        self.max_age
+    }
+}
+
+/// Reads the body to verify the signature and puts it back into the request afterwards
+pub(crate) async fn verify_request(
+    req: &mut ServiceRequest,
+    verifier: &dyn RequestSignatureVerifier,
+) -> Result<(), UnauthorizedError> {
+    let signature = header_value(req, SIGNATURE_HEADER)
+        .ok_or_else(|| UnauthorizedError::new("Missing request signature"))?;
+    let timestamp = header_value(req, TIMESTAMP_HEADER)
+        .and_then(|timestamp| timestamp.parse::<u64>().ok())
+        .ok_or_else(|| UnauthorizedError::new("Missing request timestamp"))?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if now.abs_diff(timestamp) > verifier.max_age().as_secs() {
+        debug!("Request timestamp {timestamp} is outside of the allowed window");
+        return Err(UnauthorizedError::new("Request signature expired"));
+    }
+
+    let body = req.extract::<Bytes>().await.map_err(|e| {
+        debug!("Cannot read body to verify the signature: {e}");
+        UnauthorizedError::default()
+    })?;
+    let message = signature_message(req.method().as_str(), req.path(), timestamp, &body);
+    let is_valid = verifier.verify(&message, &signature);
+
+    req.set_payload(Payload::Stream {
+        payload: Box::pin(stream::once(async move { Ok::<_, PayloadError>(body) })),
+    });
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(UnauthorizedError::new("Invalid request signature"))
+    }
+}
+
+fn header_value(req: &ServiceRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::signature_message;
+
+    #[test]
+    fn signature_message_should_contain_all_parts() {
+        let message = signature_message("POST", "/api/orders", 1700000000, b"{}");
+
+        assert_eq!(message, b"POST\n/api/orders\n1700000000\n{}");
+    }
+}
@@ -0,0 +1,108 @@
+//! Companion middleware for session stores that are not shared between instances
+//!
+//! [CookieSessionStore](https://docs.rs/actix-session/latest/actix_session/storage/struct.CookieSessionStore.html) keeps the whole
+//! session in the cookie, and stores like Redis are shared by all instances. But if a process-local store (e.g. an in-memory store) is used
+//! and the app runs as multiple instances, every request of a session has to reach the instance that created it.
+//!
+//! This crate does not route requests. [StickySessionMiddleware] only marks the responses with a `X-Worker-Id` cookie,
+//! the routing has to be done by the load balancer in front of the instances (e.g. by hashing the cookie value).
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use actix_web::{
+    cookie::Cookie,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error,
+};
+use futures::future::LocalBoxFuture;
+use log::warn;
+
+/// Name of the cookie that holds the id of the worker
+pub const WORKER_ID_COOKIE: &str = "X-Worker-Id";
+
+/// Sets the `X-Worker-Id` cookie to the id of this instance if the client does not send it already
+///
+/// # Examples
+/// ```ignore
+/// App::new()
+///     .wrap(AuthMiddleware::<_, User>::new(SessionAuthProvider::default(), PathMatcher::default()))
+///     .wrap(session_middleware)
+///     .wrap(StickySessionMiddleware::new("worker-1"))
+/// ```
+#[derive(Clone)]
+pub struct StickySessionMiddleware {
+    worker_id: Rc<String>,
+}
+
+impl StickySessionMiddleware {
+    pub fn new(worker_id: &str) -> Self {
+        Self {
+            worker_id: Rc::new(worker_id.to_owned()),
+        }
+    }
+}
+
+pub struct StickySessionMiddlewareInner<S> {
+    service: Rc<S>,
+    worker_id: Rc<String>,
+}
+
+impl<S, B> Service<ServiceRequest> for StickySessionMiddlewareInner<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let worker_id = Rc::clone(&self.worker_id);
+
+        Box::pin(async move {
+            let has_worker_id = req
+                .cookie(WORKER_ID_COOKIE)
+                .is_some_and(|c| c.value() == worker_id.as_str());
+
+            let mut res = service.call(req).await?;
+
+            if !has_worker_id {
+                let cookie = Cookie::build(WORKER_ID_COOKIE, worker_id.as_str())
+                    .path("/")
+                    .http_only(true)
+                    .finish();
+                if let Err(e) = res.response_mut().add_cookie(&cookie) {
+                    warn!("Could not set {WORKER_ID_COOKIE} cookie: {e}");
+                }
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for StickySessionMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = StickySessionMiddlewareInner<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(StickySessionMiddlewareInner {
+            service: Rc::new(service),
+            worker_id: Rc::clone(&self.worker_id),
+        }))
+    }
+}
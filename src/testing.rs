@@ -0,0 +1,71 @@
+//! Helpers for end-to-end tests of applications using this crate
+//!
+//! Only available with the `testing` feature, which cannot be compiled in release builds.
+use std::{future::Future, pin::Pin, time::SystemTime};
+
+use actix_web::HttpRequest;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    middleware::{AuthMiddleware, PathMatcher},
+    AuthState, AuthToken, AuthenticationProvider, UnauthorizedError,
+};
+
+/// [AuthenticationProvider] that authenticates every request as the given user
+#[derive(Clone)]
+pub struct TestAuthProvider<U> {
+    user: U,
+}
+
+impl<U> TestAuthProvider<U> {
+    pub fn new(user: U) -> Self {
+        Self { user }
+    }
+}
+
+impl<U> AuthenticationProvider<U> for TestAuthProvider<U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    fn get_auth_token(
+        &self,
+        _req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<AuthToken<U>, UnauthorizedError>>>> {
+        let token = AuthToken::new(
+            self.user.clone(),
+            AuthState::Authenticated,
+            SystemTime::now(),
+        );
+        Box::pin(async move { Ok(token) })
+    }
+
+    fn invalidate(&self, _req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async {})
+    }
+
+    fn name(&self) -> &'static str {
+        "testing"
+    }
+}
+
+impl<U> AuthMiddleware<TestAuthProvider<U>, U>
+where
+    U: DeserializeOwned + Clone + 'static,
+{
+    /// Skips authentication: every route is secured and every request gets an [AuthToken] of `user`
+    ///
+    /// No session backend is needed.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// App::new()
+    ///     .wrap(AuthMiddleware::test_mode(User { name: "anna".to_owned() }))
+    ///     .service(secured_route)
+    /// ```
+    pub fn test_mode(user: U) -> Self {
+        Self::new(
+            TestAuthProvider::new(user),
+            PathMatcher::new(Vec::<&str>::new(), true),
+        )
+    }
+}
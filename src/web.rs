@@ -1,4 +1,71 @@
-// hardcoded routes
+// default routes, see LoginRouteConfig
 pub const LOGIN_ROUTE: &str = "/login";
 pub const LOGOUT_ROUTE: &str = "/logout";
 pub const MFA_ROUTE: &str = "/login/mfa";
+pub const CONFIRM_LINK_ROUTE: &str = "/login/confirm";
+
+/// Paths of the routes of the [SessionLoginHandler](crate::session::handlers::SessionLoginHandler)
+///
+/// Pass it to [login_config_with_routes](crate::session::handlers::login_config_with_routes) and to
+/// [AuthMiddleware::with_login_routes](crate::middleware::AuthMiddleware::with_login_routes).
+/// The confirm route of signed links is `{login_path}/confirm/{token}`.
+///
+/// # Examples
+/// ```ignore
+/// let routes = LoginRouteConfig::default()
+///     .login_path("/auth/signin")
+///     .mfa_path("/auth/signin/mfa")
+///     .logout_path("/auth/signout");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoginRouteConfig {
+    /// Default: `/login`
+    pub login_path: String,
+    /// Default: `/login/mfa`
+    pub mfa_path: String,
+    /// Default: `/logout`
+    pub logout_path: String,
+}
+
+impl Default for LoginRouteConfig {
+    fn default() -> Self {
+        Self {
+            login_path: LOGIN_ROUTE.to_owned(),
+            mfa_path: MFA_ROUTE.to_owned(),
+            logout_path: LOGOUT_ROUTE.to_owned(),
+        }
+    }
+}
+
+impl LoginRouteConfig {
+    pub fn login_path(mut self, login_path: &str) -> Self {
+        self.login_path = login_path.to_owned();
+        self
+    }
+
+    pub fn mfa_path(mut self, mfa_path: &str) -> Self {
+        self.mfa_path = mfa_path.to_owned();
+        self
+    }
+
+    pub fn logout_path(mut self, logout_path: &str) -> Self {
+        self.logout_path = logout_path.to_owned();
+        self
+    }
+
+    /// Prefixes all paths with `prefix` (e.g. `/auth` for `/auth/login`)
+    pub fn with_prefix(self, prefix: &str) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        Self {
+            login_path: format!("{prefix}{}", self.login_path),
+            mfa_path: format!("{prefix}{}", self.mfa_path),
+            logout_path: format!("{prefix}{}", self.logout_path),
+        }
+    }
+
+    #[cfg(feature = "signed_link")]
+    pub(crate) fn confirm_link_path(&self) -> String {
+        let confirm = CONFIRM_LINK_ROUTE.trim_start_matches(LOGIN_ROUTE);
+        format!("{}{confirm}", self.login_path)
+    }
+}
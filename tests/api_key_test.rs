@@ -0,0 +1,168 @@
+use std::{
+    collections::HashSet,
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use actix_web::{get, post, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    api_key::{ApiKeyAuthProvider, ApiKeyLookup},
+    errors::UnauthorizedError,
+    middleware::{AuthMiddleware, PathMatcher},
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+const VALID_KEY: &str = "key-123";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ServiceClient {
+    name: String,
+}
+
+#[derive(Clone, Default)]
+struct InMemoryKeyLookup {
+    revoked: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ApiKeyLookup<ServiceClient> for InMemoryKeyLookup {
+    fn lookup(
+        &self,
+        key: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<ServiceClient, UnauthorizedError>>>> {
+        let result = if key == VALID_KEY && !self.revoked.lock().unwrap().contains(key) {
+            Ok(ServiceClient {
+                name: "billing-service".to_owned(),
+            })
+        } else {
+            Err(UnauthorizedError::new("Invalid API key"))
+        };
+        Box::pin(ready(result))
+    }
+
+    fn revoke(&self, key: &str) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.revoked.lock().unwrap().insert(key.to_owned());
+        Box::pin(ready(()))
+    }
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<ServiceClient>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[post("/revoke")]
+async fn revoke(token: AuthToken<ServiceClient>) -> impl Responder {
+    token.invalidate();
+    HttpResponse::Ok()
+}
+
+#[actix_rt::test]
+async fn key_in_header_should_authenticate() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryKeyLookup::default());
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .header("X-API-Key", VALID_KEY)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "billing-service");
+}
+
+#[actix_rt::test]
+async fn key_in_query_param_should_authenticate() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryKeyLookup::default());
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route?api_key={VALID_KEY}"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn missing_key_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryKeyLookup::default());
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn invalid_key_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryKeyLookup::default());
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .header("X-API-Key", "unknown")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn invalidate_should_revoke_key() {
+    let addr = actix_test::unused_addr();
+    let lookup = InMemoryKeyLookup::default();
+    start_test_server(addr, lookup.clone());
+
+    let client = Client::new();
+    let res = client
+        .post(format!("http://{addr}/revoke"))
+        .header("X-API-Key", VALID_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(lookup.revoked.lock().unwrap().contains(VALID_KEY));
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .header("X-API-Key", VALID_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn start_test_server(addr: SocketAddr, lookup: InMemoryKeyLookup) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .service(revoke)
+                        .wrap(AuthMiddleware::<_, ServiceClient>::new(
+                            ApiKeyAuthProvider::new(lookup.clone()),
+                            PathMatcher::default(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,181 @@
+use std::{
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, App, HttpRequest, HttpServer};
+use authfix::{
+    audit::AuditLogger,
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{CheckCodeError, Factor, FactorContext, GenerateCodeError},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[derive(Default)]
+struct RecordingAuditLogger {
+    events: Mutex<Vec<String>>,
+}
+
+impl RecordingAuditLogger {
+    fn record(&self, event: String) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.events.lock().unwrap().push(event);
+        Box::pin(ready(()))
+    }
+}
+
+impl AuditLogger for RecordingAuditLogger {
+    fn on_login_success(
+        &self,
+        username: &str,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.record(format!("login_success:{username}"))
+    }
+
+    fn on_login_failure(
+        &self,
+        username: &str,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.record(format!("login_failure:{username}"))
+    }
+
+    fn on_logout(&self, _: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.record("logout".to_owned())
+    }
+
+    fn on_mfa_success(&self, _: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.record("mfa_success".to_owned())
+    }
+
+    fn on_mfa_failure(&self, _: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.record("mfa_failure".to_owned())
+    }
+}
+
+#[actix_rt::test]
+async fn every_authentication_event_should_be_audited() {
+    let addr = actix_test::unused_addr();
+    let audit_logger = Arc::new(RecordingAuditLogger::default());
+    start_test_server(addr, Arc::clone(&audit_logger));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = login(&client, addr, "wrong").await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = login(&client, addr, "test123").await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = send_code(&client, addr, "wrong").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = send_code(&client, addr, "123456").await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{addr}/logout"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    assert_eq!(
+        *audit_logger.events.lock().unwrap(),
+        vec![
+            "login_failure:anna",
+            "login_success:anna",
+            "mfa_failure",
+            "mfa_success",
+            "logout"
+        ]
+    );
+}
+
+async fn login(client: &Client, addr: SocketAddr, password: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login"))
+        .body(format!(
+            "{{ \"username\": \"anna\", \"password\": \"{password}\" }}"
+        ))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn send_code(client: &Client, addr: SocketAddr, code: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{code}\" }}"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Accepts only `123456`
+struct FixedCodeFactor;
+
+impl Factor for FixedCodeFactor {
+    fn generate_code(&self, _: &FactorContext) -> Result<(), GenerateCodeError> {
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        "FIXED".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        if code == "123456" {
+            Box::pin(ready(Ok(())))
+        } else {
+            Box::pin(ready(Err(CheckCodeError::InvalidCode)))
+        }
+    }
+}
+
+fn start_test_server(addr: SocketAddr, audit_logger: Arc<RecordingAuditLogger>) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .configure(login_config(
+                            SessionLoginHandler::with_mfa(HardCodedLoadUserService {})
+                                .with_audit_logger(Arc::clone(&audit_logger)),
+                        ))
+                        .wrap(
+                            AuthMiddleware::<_, User>::new_with_factor(
+                                SessionAuthProvider::default(),
+                                PathMatcher::new(vec!["/login"], true),
+                                Box::new(FixedCodeFactor),
+                            )
+                            .with_audit_logger(Arc::clone(&audit_logger)),
+                        )
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
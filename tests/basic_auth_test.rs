@@ -0,0 +1,136 @@
+use std::{
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    thread,
+};
+
+use actix_web::{get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    basic_auth::{BasicAuthProvider, CredentialVerifier},
+    errors::UnauthorizedError,
+    middleware::{AuthMiddleware, PathMatcher},
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    name: String,
+}
+
+struct HardCodedVerifier;
+
+impl CredentialVerifier<User> for HardCodedVerifier {
+    fn verify(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<User, UnauthorizedError>>>> {
+        let result = if username == "anna" && password == "test123" {
+            Ok(User {
+                name: username.to_owned(),
+            })
+        } else {
+            Err(UnauthorizedError::default())
+        };
+        Box::pin(ready(result))
+    }
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[actix_rt::test]
+async fn correct_credentials_should_authenticate() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .basic_auth("anna", Some("test123"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anna");
+}
+
+#[actix_rt::test]
+async fn wrong_password_should_be_rejected_with_challenge() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .basic_auth("anna", Some("wrong"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_www_authenticate(&res);
+}
+
+#[actix_rt::test]
+async fn missing_header_should_be_rejected_with_challenge() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_www_authenticate(&res);
+}
+
+#[actix_rt::test]
+async fn malformed_base64_should_be_rejected_with_challenge() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .header("Authorization", "Basic not-base64!")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_www_authenticate(&res);
+}
+
+fn assert_www_authenticate(res: &reqwest::Response) {
+    assert_eq!(
+        res.headers().get("WWW-Authenticate").unwrap(),
+        "Basic realm=\"admin\", charset=\"UTF-8\""
+    );
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            BasicAuthProvider::new(HardCodedVerifier).realm("admin"),
+                            PathMatcher::default(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,137 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_session::storage::CookieSessionStore;
+use actix_web::{cookie::Key, get, HttpResponse, HttpServer, Responder};
+use authfix::{
+    composite::CompositeAuthProvider,
+    jwt::JwtAuthProvider,
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::SessionLoginHandler,
+        session_auth::{session_login_factory, SessionAuthProvider},
+    },
+    AuthToken,
+};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+const SECRET: &[u8] = b"composite-test-secret";
+
+#[actix_rt::test]
+async fn jwt_should_authenticate_without_session() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .bearer_auth(create_token("bob"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "bob via jwt");
+}
+
+#[actix_rt::test]
+async fn session_should_authenticate_without_jwt() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anna via session");
+}
+
+#[actix_rt::test]
+async fn neither_jwt_nor_session_should_be_unauthorized() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .bearer_auth("not-a-token")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn create_token(name: &str) -> String {
+    let exp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 60;
+    let claims = json!({ "email": format!("{name}@example.org"), "name": name, "exp": exp });
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(SECRET),
+    )
+    .unwrap()
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!(
+        "{} via {}",
+        token.get_authenticated_user().name,
+        token.provider()
+    ))
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    session_login_factory(
+                        SessionLoginHandler::new(HardCodedLoadUserService {}),
+                        AuthMiddleware::<_, User>::new(
+                            CompositeAuthProvider::first_successful(vec![
+                                Box::new(JwtAuthProvider::with_secret(SECRET)),
+                                Box::new(SessionAuthProvider::default()),
+                            ]),
+                            PathMatcher::default(),
+                        ),
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    )
+                    .service(secured_route)
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,116 @@
+use std::{net::SocketAddr, thread};
+
+use actix_session::storage::CookieSessionStore;
+use actix_web::{cookie::Key, get, HttpRequest, HttpResponse, HttpServer, Responder};
+use authfix::{
+    login::{HandlerError, LoadUserError, LoadUserService, LoginToken},
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::SessionLoginHandler,
+        session_auth::{session_login_factory, SessionAuthProvider},
+    },
+    AuthToken,
+};
+use futures::future::LocalBoxFuture;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+/// Returns the user as raw JSON, e.g. the claims of an upstream identity provider
+struct JsonLoadUserService;
+
+impl LoadUserService for JsonLoadUserService {
+    type User = Value;
+
+    fn load_user(
+        &self,
+        login_token: &LoginToken,
+    ) -> LocalBoxFuture<'_, Result<Value, LoadUserError>> {
+        let username = login_token.username.clone();
+        Box::pin(async move { Ok(json!({ "sub": username, "groups": ["admin", "dev"] })) })
+    }
+
+    fn on_success_handler(
+        &self,
+        _: &HttpRequest,
+        _: &Value,
+    ) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn on_error_handler(&self, _: &HttpRequest) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[actix_rt::test]
+async fn session_should_store_and_load_a_json_user() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/forward"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("X-User-Sub").unwrap(), "anna");
+    assert_eq!(res.headers().get("X-User-Groups").unwrap(), "admin,dev");
+}
+
+/// Forwards the claims as headers without knowing the user type
+#[get("/forward")]
+async fn forward(token: AuthToken<Value>) -> impl Responder {
+    let user = token.get_authenticated_user();
+    let groups = user["groups"]
+        .as_array()
+        .map(|groups| {
+            groups
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .unwrap_or_default();
+
+    HttpResponse::Ok()
+        .insert_header(("X-User-Sub", user["sub"].as_str().unwrap_or_default()))
+        .insert_header(("X-User-Groups", groups))
+        .finish()
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    session_login_factory(
+                        SessionLoginHandler::new(JsonLoadUserService),
+                        AuthMiddleware::<_, Value>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::default(),
+                        ),
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    )
+                    .service(forward)
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,122 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    thread,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    geo::GeoBlocker,
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+/// Stands in for a country lookup: the tests run on localhost, so blocking loopback blocks the client
+struct LoopbackBlocker {
+    block_loopback: bool,
+}
+
+impl GeoBlocker for LoopbackBlocker {
+    fn is_blocked(&self, ip: IpAddr) -> bool {
+        self.block_loopback && ip.is_loopback()
+    }
+}
+
+#[actix_rt::test]
+async fn blocked_client_should_get_forbidden_before_the_login() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, true);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = login(&client, addr).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    for path in ["/public", "/secured-route"] {
+        let res = client
+            .get(format!("http://{addr}{path}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[actix_rt::test]
+async fn client_that_is_not_blocked_should_be_able_to_log_in() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, false);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = login(&client, addr).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+async fn login(client: &Client, addr: SocketAddr) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("Hello {}", token.get_authenticated_user().name))
+}
+
+#[get("/public")]
+async fn public_route() -> impl Responder {
+    HttpResponse::Ok().body("Hello")
+}
+
+fn start_test_server(addr: SocketAddr, block_loopback: bool) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                let key = Key::generate();
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .service(public_route)
+                        .configure(login_config(SessionLoginHandler::new(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(
+                            AuthMiddleware::<_, User>::new(
+                                SessionAuthProvider::default(),
+                                PathMatcher::new(vec!["/login", "/public"], true),
+                            )
+                            .with_geo_blocker(LoopbackBlocker { block_loopback }),
+                        )
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            key.clone(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,113 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{App, HttpRequest, HttpServer};
+use authfix::jwt::{token_introspection_config, IntrospectionResponse};
+use jsonwebtoken::{encode, DecodingKey, EncodingKey, Header, Validation};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+
+const SECRET: &[u8] = b"introspection-test-secret";
+
+#[derive(Serialize)]
+struct Claims {
+    sub: String,
+    exp: u64,
+    scope: String,
+}
+
+fn create_token(secret: &[u8]) -> String {
+    let exp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 300;
+    let claims = Claims {
+        sub: "user-1".to_owned(),
+        exp,
+        scope: "read write".to_owned(),
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret),
+    )
+    .unwrap()
+}
+
+#[actix_rt::test]
+async fn valid_token_should_be_active() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = introspect(addr, &create_token(SECRET), "trusted").await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: IntrospectionResponse = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert!(body.active);
+    assert_eq!(body.sub.as_deref(), Some("user-1"));
+    assert_eq!(body.scope.as_deref(), Some("read write"));
+    assert!(body.exp.is_some());
+}
+
+#[actix_rt::test]
+async fn token_with_wrong_signature_should_be_inactive() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = introspect(addr, &create_token(b"another-secret"), "trusted").await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.text().await.unwrap();
+    assert_eq!(body, "{\"active\":false}");
+}
+
+#[actix_rt::test]
+async fn untrusted_clients_should_not_introspect() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = introspect(addr, &create_token(SECRET), "unknown").await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+async fn introspect(addr: SocketAddr, token: &str, client_secret: &str) -> reqwest::Response {
+    Client::new()
+        .post(format!("http://{addr}/auth/introspect"))
+        .body(format!("{{ \"token\": \"{token}\" }}"))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {client_secret}"))
+        .send()
+        .await
+        .unwrap()
+}
+
+fn is_trusted_client(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("Authorization")
+        .is_some_and(|value| value == "Bearer trusted")
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new().configure(token_introspection_config(
+                        DecodingKey::from_secret(SECRET),
+                        Validation::default(),
+                        is_trusted_client,
+                    ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
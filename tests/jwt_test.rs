@@ -0,0 +1,168 @@
+use std::{
+    collections::HashSet,
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{get, post, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    jwt::{JwtAuthProvider, TokenBlocklist},
+    middleware::{AuthMiddleware, PathMatcher},
+    AuthToken,
+};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+const SECRET: &[u8] = b"jwt-provider-test-secret";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    sub: String,
+    name: String,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Clone, Default)]
+struct InMemoryBlocklist {
+    revoked: Arc<Mutex<HashSet<String>>>,
+}
+
+impl TokenBlocklist for InMemoryBlocklist {
+    fn is_revoked(&self, token: &str) -> Pin<Box<dyn Future<Output = bool>>> {
+        Box::pin(ready(self.revoked.lock().unwrap().contains(token)))
+    }
+
+    fn revoke(&self, token: &str) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.revoked.lock().unwrap().insert(token.to_owned());
+        Box::pin(ready(()))
+    }
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[post("/logout")]
+async fn logout(token: AuthToken<User>) -> impl Responder {
+    token.invalidate();
+    HttpResponse::Ok()
+}
+
+fn create_token(algorithm: Algorithm, expires_in: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let user = User {
+        sub: "user-1".to_owned(),
+        name: "anna".to_owned(),
+        iat: now,
+        exp: now.checked_add_signed(expires_in).unwrap(),
+    };
+
+    encode(
+        &Header::new(algorithm),
+        &user,
+        &EncodingKey::from_secret(SECRET),
+    )
+    .unwrap()
+}
+
+#[actix_rt::test]
+async fn valid_token_should_authenticate_user() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryBlocklist::default());
+
+    let res = get_secured(addr, Some(&create_token(Algorithm::HS256, 300))).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anna");
+}
+
+#[actix_rt::test]
+async fn expired_token_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryBlocklist::default());
+
+    // expired beyond the default leeway of 60 seconds
+    let res = get_secured(addr, Some(&create_token(Algorithm::HS256, -300))).await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn token_with_wrong_algorithm_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryBlocklist::default());
+
+    let res = get_secured(addr, Some(&create_token(Algorithm::HS384, 300))).await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn missing_header_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, InMemoryBlocklist::default());
+
+    let res = get_secured(addr, None).await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn token_should_be_revoked_after_logout() {
+    let addr = actix_test::unused_addr();
+    let blocklist = InMemoryBlocklist::default();
+    start_test_server(addr, blocklist.clone());
+
+    let token = create_token(Algorithm::HS256, 300);
+
+    let res = Client::new()
+        .post(format!("http://{addr}/logout"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(blocklist.revoked.lock().unwrap().contains(&token));
+
+    let res = get_secured(addr, Some(&token)).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+async fn get_secured(addr: SocketAddr, token: Option<&str>) -> reqwest::Response {
+    let mut req = Client::new().get(format!("http://{addr}/secured-route"));
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("Bearer {token}"));
+    }
+    req.send().await.unwrap()
+}
+
+fn start_test_server(addr: SocketAddr, blocklist: InMemoryBlocklist) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new().service(secured_route).service(logout).wrap(
+                        AuthMiddleware::<_, User>::new(
+                            JwtAuthProvider::with_secret(SECRET).with_blocklist(blocklist.clone()),
+                            PathMatcher::default(),
+                        ),
+                    )
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,143 @@
+use std::{
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use actix_session::storage::CookieSessionStore;
+use actix_web::{cookie::Key, get, post, HttpRequest, HttpResponse, HttpServer, Responder};
+use authfix::{
+    errors::UnauthorizedError,
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::SessionLoginHandler,
+        session_auth::{session_login_factory, SessionAuthProvider},
+    },
+    AuthToken, AuthenticationProvider,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+static LOADED_TOKENS: AtomicUsize = AtomicUsize::new(0);
+
+/// [SessionAuthProvider] that counts how often the user is loaded
+#[derive(Clone)]
+struct CountingProvider(SessionAuthProvider);
+
+impl AuthenticationProvider<User> for CountingProvider {
+    fn get_auth_token(
+        &self,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<AuthToken<User>, UnauthorizedError>>>> {
+        LOADED_TOKENS.fetch_add(1, Ordering::SeqCst);
+        self.0.get_auth_token(req)
+    }
+
+    fn invalidate(&self, req: HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        AuthenticationProvider::<User>::invalidate(&self.0, req)
+    }
+}
+
+#[actix_rt::test]
+async fn lazy_middleware_should_authenticate_only_when_the_token_is_extracted() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/without-token"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(LOADED_TOKENS.load(Ordering::SeqCst), 0);
+
+    let res = client
+        .get(format!("http://{addr}/with-token"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(LOADED_TOKENS.load(Ordering::SeqCst), 1);
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/with-token"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anna");
+    assert_eq!(LOADED_TOKENS.load(Ordering::SeqCst), 2);
+
+    let res = client
+        .post(format!("http://{addr}/logout"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/with-token"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[get("/without-token")]
+async fn without_token() -> impl Responder {
+    HttpResponse::Ok()
+}
+
+#[get("/with-token")]
+async fn with_token(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[post("/logout")]
+async fn logout(token: AuthToken<User>) -> impl Responder {
+    token.invalidate();
+    HttpResponse::Ok()
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    session_login_factory(
+                        SessionLoginHandler::new(HardCodedLoadUserService {}),
+                        AuthMiddleware::<_, User>::new(
+                            CountingProvider(SessionAuthProvider::default()),
+                            PathMatcher::default(),
+                        )
+                        .lazy(),
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    )
+                    .service(without_token)
+                    .service(with_token)
+                    .service(logout)
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
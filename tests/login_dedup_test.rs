@@ -0,0 +1,112 @@
+use std::{net::SocketAddr, thread, time::Duration};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, App, HttpRequest, HttpServer};
+use authfix::{
+    login::{HandlerError, LoadUserError, LoadUserService, LoginToken},
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+};
+use futures::future::LocalBoxFuture;
+use reqwest::{Client, StatusCode};
+use test_utils::User;
+
+mod test_utils;
+
+struct SlowLoginService;
+
+impl LoadUserService for SlowLoginService {
+    type User = User;
+
+    fn load_user(
+        &self,
+        login_token: &LoginToken,
+    ) -> LocalBoxFuture<'_, Result<User, LoadUserError>> {
+        let name = login_token.username.clone();
+        Box::pin(async move {
+            actix_rt::time::sleep(Duration::from_millis(500)).await;
+            Ok(User {
+                email: format!("{name}@example.org"),
+                name,
+            })
+        })
+    }
+
+    fn on_success_handler(
+        &self,
+        _: &HttpRequest,
+        _: &Self::User,
+    ) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn on_error_handler(&self, _: &HttpRequest) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[actix_rt::test]
+async fn finished_login_should_not_block_the_next_login_of_the_session() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    assert_eq!(login(&client, addr, "anna").await, StatusCode::OK);
+    assert_eq!(login(&client, addr, "anna").await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn concurrent_logins_of_different_users_should_not_be_deduplicated() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+    let (first, second) = futures::join!(login(&client, addr, "anna"), login(&client, addr, "bob"));
+
+    assert_eq!(first, StatusCode::OK);
+    assert_eq!(second, StatusCode::OK);
+}
+
+async fn login(client: &Client, addr: SocketAddr, username: &str) -> StatusCode {
+    client
+        .post(format!("http://{addr}/login"))
+        .body(format!(
+            "{{ \"username\": \"{username}\", \"password\": \"test123\" }}"
+        ))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .configure(login_config(
+                            SessionLoginHandler::new(SlowLoginService).deduplicate_logins(true),
+                        ))
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,216 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, post, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::random_code_auth::{CodeSender, MfaRandomCode, RandomCode},
+    session::{
+        handlers::{login_config_with_routes, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    web::LoginRouteConfig,
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{CustomError, HardCodedLoadUserService, User};
+
+mod test_utils;
+
+struct DummySender;
+
+impl CodeSender for DummySender {
+    type Error = CustomError;
+
+    fn send_code(&self, _: RandomCode) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+fn fixed_code() -> RandomCode {
+    RandomCode::new("123abc", SystemTime::now() + Duration::from_secs(60 * 5))
+}
+
+fn routes() -> LoginRouteConfig {
+    LoginRouteConfig::default()
+        .login_path("/auth/signin")
+        .mfa_path("/auth/signin/mfa")
+        .logout_path("/auth/signout")
+}
+
+#[actix_rt::test]
+async fn login_and_mfa_should_work_at_custom_paths() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/auth/signin"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client
+        .post(format!("http://{addr}/auth/signin/mfa"))
+        .body("{ \"code\": \"123abc\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .post(format!("http://{addr}/auth/signout"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn default_paths_should_not_be_mounted() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    // nothing is mounted at `/login`, which the default PathMatcher does not secure
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn mfa_path_should_not_be_reachable_without_login() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/auth/signin/mfa"))
+        .body("{ \"code\": \"123abc\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn login_path_should_stay_secured_without_login_routes() {
+    let addr = actix_test::unused_addr();
+    start_test_server_without_login_routes(addr);
+
+    let res = Client::new()
+        .post(format!("http://{addr}/login"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("Hello {}", token.get_authenticated_user().name))
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                let key = Key::generate();
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config_with_routes(
+                            routes(),
+                            SessionLoginHandler::with_mfa(HardCodedLoadUserService {}),
+                        ))
+                        // secures everything, the login path is excluded by the middleware
+                        .wrap(
+                            AuthMiddleware::<_, User>::new_with_factor(
+                                SessionAuthProvider::default(),
+                                PathMatcher::default(),
+                                Box::new(MfaRandomCode::new(fixed_code, DummySender)),
+                            )
+                            .with_login_routes(&routes()),
+                        )
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            key.clone(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
+
+/// E.g. an app with its own login route, which is not the one of the SessionLoginHandler
+#[post("/login")]
+async fn custom_login_route() -> impl Responder {
+    HttpResponse::Ok()
+}
+
+fn start_test_server_without_login_routes(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(custom_login_route)
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/public"], true),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,134 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{
+        async_code_auth::{AsyncCodeSender, MfaRandomCodeAsync},
+        random_code_auth::{Charset, RandomCode},
+    },
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+/// Simulates a slow gateway, e.g. for SMS
+#[derive(Clone, Default)]
+struct SlowGatewaySender {
+    sent: Arc<Mutex<Vec<String>>>,
+}
+
+impl AsyncCodeSender for SlowGatewaySender {
+    type Error = Infallible;
+
+    fn send_code(
+        &self,
+        random_code: RandomCode,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Self::Error>>>> {
+        let sent = Arc::clone(&self.sent);
+        Box::pin(async move {
+            actix_rt::time::sleep(Duration::from_millis(50)).await;
+            sent.lock().unwrap().push(random_code.value().to_owned());
+            Ok(())
+        })
+    }
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[actix_rt::test]
+async fn code_should_be_sent_by_async_sender() {
+    let addr = actix_test::unused_addr();
+    let sender = SlowGatewaySender::default();
+    start_test_server(addr, sender.clone());
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let code = wait_for_code(&sender).await;
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{code}\" }}"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+// the code is sent in the background
+async fn wait_for_code(sender: &SlowGatewaySender) -> String {
+    for _ in 0..50 {
+        if let Some(code) = sender.sent.lock().unwrap().first() {
+            return code.clone();
+        }
+        actix_rt::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Sender has not sent a code");
+}
+
+fn start_test_server(addr: SocketAddr, sender: SlowGatewaySender) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::default(),
+                            Box::new(MfaRandomCodeAsync::new(
+                                || RandomCode::generate_secure(6, Charset::Numeric),
+                                sender.clone(),
+                            )),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::from(&[0; 64]),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,143 @@
+use std::{
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    thread,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{CheckCodeError, Factor, FactorContext, GenerateCodeError, StoredContext},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[actix_rt::test]
+async fn code_should_be_accepted_from_the_same_user_agent() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    login(&client, addr).await;
+
+    let res = send_code(&client, addr, "device-a").await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn code_should_be_rejected_from_another_user_agent() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    login(&client, addr).await;
+
+    let res = send_code(&client, addr, "device-b").await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // the login has been rejected finally
+    let res = send_code(&client, addr, "device-a").await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+async fn login(client: &Client, addr: SocketAddr) {
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "device-a")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+async fn send_code(client: &Client, addr: SocketAddr, user_agent: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("{ \"code\": \"123\" }")
+        .header("Content-Type", "application/json")
+        .header("User-Agent", user_agent)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Accepts the code "123", but only from the user agent that has started the login
+struct SameUserAgentFactor;
+
+impl Factor for SameUserAgentFactor {
+    fn generate_code(&self, _: &FactorContext) -> Result<(), GenerateCodeError> {
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        "SAME_AGENT".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        if code == "123" {
+            Box::pin(ready(Ok(())))
+        } else {
+            Box::pin(ready(Err(CheckCodeError::InvalidCode)))
+        }
+    }
+
+    fn validate_context(&self, original_req: &StoredContext, current_req: &HttpRequest) -> bool {
+        original_req == &StoredContext::from_request(current_req)
+    }
+}
+
+#[get("/secured-route")]
+pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("User: {}", token.get_authenticated_user().email))
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                            Box::new(SameUserAgentFactor),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
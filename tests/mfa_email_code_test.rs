@@ -0,0 +1,182 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{
+        email_code::{EmailMessage, EmailSender, MfaEmailCode},
+        ChallengeResponse,
+    },
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{CustomError, HardCodedLoadUserService, User};
+
+mod test_utils;
+
+/// Keeps the last code or link instead of sending a mail
+#[derive(Clone, Default)]
+struct Mailbox {
+    last: Arc<Mutex<Option<String>>>,
+}
+
+impl Mailbox {
+    fn last(&self) -> String {
+        self.last.lock().unwrap().clone().unwrap()
+    }
+}
+
+impl EmailSender for Mailbox {
+    type Error = CustomError;
+
+    fn send(&self, _: &str, message: EmailMessage) -> Result<(), Self::Error> {
+        let content = match message {
+            EmailMessage::Code(random_code) => random_code.value().to_owned(),
+            EmailMessage::MagicLink { url, .. } => url,
+        };
+        *self.last.lock().unwrap() = Some(content);
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn code_from_the_mail_should_complete_the_login_once() {
+    let addr = actix_test::unused_addr();
+    let mailbox = Mailbox::default();
+    start_test_server(addr, mailbox.clone(), None);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = login(&client, addr).await;
+    let challenge: ChallengeResponse = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(challenge.factor, "EMAIL_CODE");
+
+    let code = mailbox.last();
+    assert_eq!(code.len(), 8);
+
+    let res = send_code(&client, addr, &code).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn wrong_code_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Mailbox::default(), None);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    login(&client, addr).await;
+    let res = send_code(&client, addr, "wrong123").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn token_of_the_magic_link_should_complete_the_login() {
+    let addr = actix_test::unused_addr();
+    let mailbox = Mailbox::default();
+    start_test_server(addr, mailbox.clone(), Some("https://example.org/confirm"));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = login(&client, addr).await;
+    let challenge: ChallengeResponse = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(challenge.factor, "MAGIC_LINK");
+
+    let url = mailbox.last();
+    let token = url
+        .strip_prefix("https://example.org/confirm?token=")
+        .unwrap();
+
+    let res = send_code(&client, addr, token).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+async fn login(client: &Client, addr: SocketAddr) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn send_code(client: &Client, addr: SocketAddr, code: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{code}\" }}"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("Hello {}", token.get_authenticated_user().name))
+}
+
+fn start_test_server(addr: SocketAddr, mailbox: Mailbox, magic_link: Option<&'static str>) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                let key = Key::generate();
+                HttpServer::new(move || {
+                    let mut factor = MfaEmailCode::new(mailbox.clone());
+                    if let Some(base_url) = magic_link {
+                        factor = factor.magic_link(base_url);
+                    }
+
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                            Box::new(factor),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            key.clone(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,130 @@
+use std::{
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, App, HttpRequest, HttpServer};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{CheckCodeError, Factor, FactorContext, GenerateCodeError},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+static SUCCESSES: AtomicUsize = AtomicUsize::new(0);
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+#[actix_rt::test]
+async fn hooks_should_be_called_after_the_code_was_checked() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = send_code(&client, addr, "wrong").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(FAILURES.load(Ordering::SeqCst), 1);
+    assert_eq!(SUCCESSES.load(Ordering::SeqCst), 0);
+
+    let res = send_code(&client, addr, "123456").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(FAILURES.load(Ordering::SeqCst), 1);
+    assert_eq!(SUCCESSES.load(Ordering::SeqCst), 1);
+}
+
+async fn send_code(client: &Client, addr: SocketAddr, code: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{code}\" }}"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Accepts `123456` and counts the calls of the hooks
+struct CountingFactor;
+
+impl Factor for CountingFactor {
+    fn generate_code(&self, _: &FactorContext) -> Result<(), GenerateCodeError> {
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        "COUNTING".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        if code == "123456" {
+            Box::pin(ready(Ok(())))
+        } else {
+            Box::pin(ready(Err(CheckCodeError::InvalidCode)))
+        }
+    }
+
+    fn on_success(&self, _: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        SUCCESSES.fetch_add(1, Ordering::SeqCst);
+        Box::pin(ready(()))
+    }
+
+    fn on_failure(
+        &self,
+        error: &CheckCodeError,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = ()>>> {
+        assert!(matches!(error, CheckCodeError::InvalidCode));
+        FAILURES.fetch_add(1, Ordering::SeqCst);
+        Box::pin(ready(()))
+    }
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                            Box::new(CountingFactor),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,131 @@
+use std::{net::SocketAddr, thread};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{
+        push_code_auth::{DeviceTokenResolver, MfaPush, PushSender},
+        random_code_auth::{CodeSender, RandomCode},
+        FactorContext,
+    },
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use chrono::{Local, TimeDelta};
+use reqwest::{Client, StatusCode};
+use test_utils::{CustomError, HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[actix_rt::test]
+async fn should_be_logged_in_after_entering_pushed_code() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("{ \"code\": \"654321\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+struct DummyPushSender;
+
+impl CodeSender for DummyPushSender {
+    type Error = CustomError;
+
+    fn send_code(&self, _: RandomCode) -> Result<(), Self::Error> {
+        Err(CustomError::Error)
+    }
+}
+
+impl PushSender for DummyPushSender {
+    fn send_push(&self, device_token: &str, code: &RandomCode) -> Result<(), Self::Error> {
+        println!("Push code {} to device {device_token}", code.value());
+        Ok(())
+    }
+}
+
+struct DummyDeviceTokenResolver;
+
+impl DeviceTokenResolver for DummyDeviceTokenResolver {
+    type Error = CustomError;
+
+    fn resolve_device_token(&self, ctx: &FactorContext) -> Result<String, Self::Error> {
+        match ctx.user_id {
+            "anna" => Ok("device-of-anna".to_owned()),
+            _ => Err(CustomError::Error),
+        }
+    }
+}
+
+#[get("/secured-route")]
+pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("User: {}", token.get_authenticated_user().email))
+}
+
+fn code_generator() -> RandomCode {
+    let valid_until = Local::now()
+        .checked_add_signed(TimeDelta::minutes(5))
+        .unwrap();
+    RandomCode::new("654321", valid_until.into())
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                            Box::new(MfaPush::new(
+                                code_generator,
+                                DummyPushSender,
+                                DummyDeviceTokenResolver,
+                            )),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -1,12 +1,24 @@
-use std::{net::SocketAddr, thread};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
 
 use actix_session::{storage::CookieSessionStore, SessionExt, SessionMiddleware};
 use actix_web::{cookie::Key, get, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use authfix::{
     middleware::{AuthMiddleware, PathMatcher},
-    multifactor::random_code_auth::{CodeSender, MfaRandomCode, RandomCode},
+    multifactor::{
+        random_code_auth::{
+            CodeSender, InMemoryMfaLockStore, MfaRandomCode, RandomCode, RandomCodeConfig,
+        },
+        ChallengeResponse, ChannelInfo,
+    },
     session::{
-        handlers::{login_config, SessionLoginHandler},
+        handlers::{login_config, login_config_with_prefix, SessionLoginHandler},
         session_auth::SessionAuthProvider,
     },
     AuthToken,
@@ -55,6 +67,92 @@ async fn should_be_able_to_logout() {
     assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[actix_rt::test]
+async fn login_should_respond_with_challenge() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, single_code_generator);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let challenge: ChallengeResponse = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(challenge.factor, "RNDCODE");
+    assert_eq!(challenge.estimated_delivery_seconds, Some(30));
+    let channel = challenge.channel.unwrap();
+    assert_eq!(channel.channel, "sms");
+    assert_eq!(channel.masked_destination.as_deref(), Some("***-1234"));
+}
+
+#[actix_rt::test]
+async fn secured_routes_should_not_resend_code_while_mfa_is_pending() {
+    let addr = actix_test::unused_addr();
+    let sent_codes = Arc::new(AtomicUsize::new(0));
+    start_test_server_with_counting_sender(addr, Arc::clone(&sent_codes));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        let res = client
+            .get(format!("http://{addr}/secured-route"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    assert_eq!(sent_codes.load(Ordering::SeqCst), 1);
+}
+
+#[actix_rt::test]
+async fn should_accept_code_as_plain_text() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, single_code_generator);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("123abc\n")
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
 #[actix_rt::test]
 async fn should_be_possible_to_try_mfa_again() {
     let addr = actix_test::unused_addr();
@@ -95,6 +193,104 @@ async fn should_be_possible_to_try_mfa_again() {
     assert_eq!(res.status(), StatusCode::OK);
 }
 
+#[actix_rt::test]
+async fn should_be_locked_after_too_many_wrong_codes() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, single_code_generator);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let mut statuses = Vec::new();
+    for _ in 0..3 {
+        let res = client
+            .post(format!("http://{addr}/login/mfa"))
+            .body(format!("{{ \"code\": \"{}\" }}", "oops wrong code"))
+            .header("Content-Type", "application/json")
+            .send()
+            .await
+            .unwrap();
+        statuses.push(res.status());
+    }
+
+    assert_eq!(
+        statuses,
+        vec![
+            StatusCode::BAD_REQUEST,
+            StatusCode::BAD_REQUEST,
+            StatusCode::TOO_MANY_REQUESTS
+        ]
+    );
+
+    // a new login does not lift the lock
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{}\" }}", "123abc"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_rt::test]
+async fn new_session_of_a_locked_user_should_stay_locked() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_lock_store(addr, Arc::new(InMemoryMfaLockStore::default()));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    login_anna(&client, addr).await;
+    for _ in 0..3 {
+        send_code(&client, addr, "oops wrong code").await;
+    }
+
+    // another client does not share the session with the first one
+    let new_client = Client::builder().cookie_store(true).build().unwrap();
+    login_anna(&new_client, addr).await;
+
+    assert_eq!(
+        send_code(&new_client, addr, "123abc").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+async fn login_anna(client: &Client, addr: SocketAddr) {
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+}
+
+async fn send_code(client: &Client, addr: SocketAddr, code: &str) -> StatusCode {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{code}\" }}"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
 #[actix_rt::test]
 async fn should_be_possible_to_login_again_before_mfa_has_been_passed() {
     let addr = actix_test::unused_addr();
@@ -236,6 +432,40 @@ async fn should_not_be_logged_in_after_time_is_up() {
     assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[actix_rt::test]
+async fn should_be_logged_in_with_prefixed_routes() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_prefix(addr, "/auth");
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/auth/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/auth/login/mfa"))
+        .body(format!("{{ \"code\": \"{}\" }}", "123abc"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
 struct DummySender {}
 impl CodeSender for DummySender {
     type Error = CustomError;
@@ -252,6 +482,27 @@ impl CodeSender for DummySender {
         );
         Ok(())
     }
+
+    fn estimated_delivery_time(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(30))
+    }
+
+    fn channel_info(&self) -> ChannelInfo {
+        ChannelInfo::new("sms").with_destination("+49 170 1231234")
+    }
+}
+
+struct CountingSender {
+    sent_codes: Arc<AtomicUsize>,
+}
+
+impl CodeSender for CountingSender {
+    type Error = CustomError;
+
+    fn send_code(&self, _: RandomCode) -> Result<(), Self::Error> {
+        self.sent_codes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
 }
 
 #[get("/secured-route")]
@@ -319,3 +570,93 @@ fn start_test_server(addr: SocketAddr, generator: fn() -> RandomCode) {
             .unwrap();
     });
 }
+
+fn start_test_server_with_lock_store(addr: SocketAddr, lock_store: Arc<InMemoryMfaLockStore>) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    let config = RandomCodeConfig::default().lock_store(Arc::clone(&lock_store));
+                    App::new()
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider,
+                            PathMatcher::new(vec!["/login"], true),
+                            Box::new(
+                                MfaRandomCode::new(single_code_generator, DummySender {})
+                                    .with_config(config),
+                            ),
+                        ))
+                        .wrap(create_actix_session_middleware())
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
+
+fn start_test_server_with_prefix(addr: SocketAddr, prefix: &'static str) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config_with_prefix(
+                            prefix,
+                            SessionLoginHandler::with_mfa(HardCodedLoadUserService {}),
+                        ))
+                        .wrap(
+                            AuthMiddleware::<_, User>::new_with_factor(
+                                SessionAuthProvider,
+                                PathMatcher::new(vec!["/auth/login", "/unsecure/*"], true),
+                                Box::new(MfaRandomCode::new(single_code_generator, DummySender {})),
+                            )
+                            .with_route_prefix(prefix),
+                        )
+                        .wrap(create_actix_session_middleware())
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
+
+fn start_test_server_with_counting_sender(addr: SocketAddr, sent_codes: Arc<AtomicUsize>) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider,
+                            PathMatcher::new(vec!["/login", "/unsecure/*"], true),
+                            Box::new(MfaRandomCode::new(
+                                single_code_generator,
+                                CountingSender {
+                                    sent_codes: Arc::clone(&sent_codes),
+                                },
+                            )),
+                        ))
+                        .wrap(create_actix_session_middleware())
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,147 @@
+use std::{
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    thread,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{ChallengeResponse, CheckCodeError, Factor, FactorContext, GenerateCodeError},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[actix_rt::test]
+async fn all_factors_should_be_required_one_after_another() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    let challenge: ChallengeResponse = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(challenge.factor, "MAIL");
+
+    let res = send_code(&client, addr, "mail-code").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let challenge: ChallengeResponse = serde_json::from_str(&res.text().await.unwrap()).unwrap();
+    assert_eq!(challenge.factor, "TOTP");
+
+    // the first factor is not enough
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = send_code(&client, addr, "mail-code").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = send_code(&client, addr, "totp-code").await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+async fn send_code(client: &Client, addr: SocketAddr, code: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{code}\" }}"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Accepts only its fixed code
+struct FixedCodeFactor {
+    id: &'static str,
+    code: &'static str,
+}
+
+impl Factor for FixedCodeFactor {
+    fn generate_code(&self, _: &FactorContext) -> Result<(), GenerateCodeError> {
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        self.id.to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        if code == self.code {
+            Box::pin(ready(Ok(())))
+        } else {
+            Box::pin(ready(Err(CheckCodeError::InvalidCode)))
+        }
+    }
+}
+
+#[get("/secured-route")]
+pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("User: {}", token.get_authenticated_user().email))
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa_factors(
+                            HardCodedLoadUserService {},
+                            vec![
+                                Box::new(FixedCodeFactor {
+                                    id: "MAIL",
+                                    code: "mail-code",
+                                }),
+                                Box::new(FixedCodeFactor {
+                                    id: "TOTP",
+                                    code: "totp-code",
+                                }),
+                            ],
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,138 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{
+        random_code_auth::{Charset, MfaRandomCode, RandomCode},
+        signed_link::{LinkSender, MfaSignedLink},
+    },
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+const SECRET: [u8; 32] = [42; 32];
+
+#[derive(Clone, Default)]
+struct Inbox(Arc<Mutex<Vec<String>>>);
+
+impl LinkSender for Inbox {
+    type Error = Infallible;
+
+    fn send_link(&self, link: &str) -> Result<(), Self::Error> {
+        self.0.lock().unwrap().push(link.to_owned());
+        Ok(())
+    }
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[actix_rt::test]
+async fn login_should_be_confirmed_by_opening_the_link() {
+    let addr = actix_test::unused_addr();
+    let inbox = Inbox::default();
+    start_test_server(addr, inbox.clone());
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    login(&client, addr).await;
+
+    let link = inbox.0.lock().unwrap().first().unwrap().clone();
+    assert!(link.starts_with(&format!("http://{addr}/login/confirm/")));
+
+    let res = client.get(link).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn tampered_link_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    let inbox = Inbox::default();
+    start_test_server(addr, inbox.clone());
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    login(&client, addr).await;
+
+    let link = inbox.0.lock().unwrap().first().unwrap().clone();
+    let res = client.get(format!("{link}x")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+async fn login(client: &Client, addr: SocketAddr) {
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+fn start_test_server(addr: SocketAddr, inbox: Inbox) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(
+                            SessionLoginHandler::with_mfa(HardCodedLoadUserService {})
+                                .with_link_confirmation(SECRET),
+                        ))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login", "/login/confirm/*"], true),
+                            Box::new(MfaRandomCode::new(
+                                || RandomCode::generate_secure(32, Charset::Alphanumeric),
+                                MfaSignedLink::new(
+                                    &format!("http://{addr}/login/confirm"),
+                                    SECRET,
+                                    Duration::from_secs(300),
+                                    inbox.clone(),
+                                ),
+                            )),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
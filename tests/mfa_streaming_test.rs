@@ -0,0 +1,320 @@
+use std::{
+    future::{pending, ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, LazyLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{
+        random_code_auth::{
+            CodeSender, InMemoryMfaLockStore, MfaRandomCode, RandomCode, RandomCodeConfig,
+        },
+        CheckCodeError, Factor, FactorContext, GenerateCodeError,
+    },
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        registry::InMemorySessionRegistry,
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{CustomError, HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[actix_rt::test]
+async fn streaming_factor_should_send_result_as_event() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, || Box::new(ApprovalFactor));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("{ \"code\": \"approved\" }")
+        .header("Content-Type", "application/json")
+        .header("Accept", "text/event-stream")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers().get("Content-Type").unwrap(),
+        "text/event-stream"
+    );
+    assert!(res.text().await.unwrap().starts_with("event: approved"));
+    assert_eq!(APPROVALS.load(Ordering::SeqCst), 1);
+
+    // the approved login is completed without sending the code again
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn stream_should_start_before_the_check_is_done() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, || Box::new(ApprovalFactor));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let started = Instant::now();
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("{ \"code\": \"pending\" }")
+        .header("Content-Type", "application/json")
+        .header("Accept", "text/event-stream")
+        .send()
+        .await
+        .unwrap();
+    assert!(started.elapsed() < STREAM_TIMEOUT);
+
+    let events = res.text().await.unwrap();
+    assert!(events.starts_with("event: rejected"));
+    assert!(events.contains("time_is_up"));
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn streaming_factor_should_send_rejection_as_event() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, || Box::new(ApprovalFactor));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("{ \"code\": \"denied\" }")
+        .header("Content-Type", "application/json")
+        .header("Accept", "text/event-stream")
+        .send()
+        .await
+        .unwrap();
+
+    assert!(res.text().await.unwrap().starts_with("event: rejected"));
+}
+
+#[actix_rt::test]
+async fn wrong_codes_in_a_stream_should_be_counted() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, || Box::new(StreamingRandomCode::new()));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        let res = client
+            .post(format!("http://{addr}/login/mfa"))
+            .body("{ \"code\": \"wrong\" }")
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/event-stream")
+            .send()
+            .await
+            .unwrap();
+        assert!(res.text().await.unwrap().starts_with("event: rejected"));
+    }
+    assert_eq!(FAILURES.load(Ordering::SeqCst), 3);
+
+    // locked after 3 wrong codes, a new login does not lift the lock
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("{ \"code\": \"123abc\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+const STREAM_TIMEOUT: Duration = Duration::from_secs(2);
+
+static APPROVALS: AtomicUsize = AtomicUsize::new(0);
+
+/// Approves if the code is "approved" and waits forever for "pending", a real implementation would wait for the user
+struct ApprovalFactor;
+
+impl Factor for ApprovalFactor {
+    fn generate_code(&self, _: &FactorContext) -> Result<(), GenerateCodeError> {
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> String {
+        "APPROVAL".to_owned()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        _: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        match code {
+            "approved" => Box::pin(ready(Ok(()))),
+            "pending" => Box::pin(pending()),
+            _ => Box::pin(ready(Err(CheckCodeError::InvalidCode))),
+        }
+    }
+
+    fn on_success(&self, _: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        APPROVALS.fetch_add(1, Ordering::SeqCst);
+        Box::pin(ready(()))
+    }
+
+    fn is_streaming(&self) -> bool {
+        true
+    }
+}
+
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+struct DummySender;
+
+impl CodeSender for DummySender {
+    type Error = CustomError;
+
+    fn send_code(&self, _: RandomCode) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+// shared by the workers of the server
+static LOCK_STORE: LazyLock<Arc<InMemoryMfaLockStore>> = LazyLock::new(Arc::default);
+
+/// A random code that is checked in a stream, e.g. while the app also waits for a push approval
+struct StreamingRandomCode(MfaRandomCode<DummySender>);
+
+impl StreamingRandomCode {
+    fn new() -> Self {
+        Self(
+            MfaRandomCode::new(
+                || RandomCode::new("123abc", SystemTime::now() + Duration::from_secs(60)),
+                DummySender,
+            )
+            .with_config(RandomCodeConfig::default().lock_store(Arc::clone(&LOCK_STORE))),
+        )
+    }
+}
+
+impl Factor for StreamingRandomCode {
+    fn generate_code(&self, ctx: &FactorContext) -> Result<(), GenerateCodeError> {
+        self.0.generate_code(ctx)
+    }
+
+    fn get_unique_id(&self) -> String {
+        self.0.get_unique_id()
+    }
+
+    fn check_code(
+        &self,
+        code: &str,
+        req: &HttpRequest,
+    ) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        self.0.check_code(code, req)
+    }
+
+    fn on_failure(&self, _: &CheckCodeError, _: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        FAILURES.fetch_add(1, Ordering::SeqCst);
+        Box::pin(ready(()))
+    }
+
+    fn is_streaming(&self) -> bool {
+        true
+    }
+}
+
+#[get("/secured-route")]
+pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("User: {}", token.get_authenticated_user().email))
+}
+
+fn start_test_server(addr: SocketAddr, factor: fn() -> Box<dyn Factor>) {
+    // streamed logins are completed through the registry
+    let registry = Arc::new(InMemorySessionRegistry::default());
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(
+                            SessionLoginHandler::with_mfa(HardCodedLoadUserService {})
+                                .with_session_registry(Arc::clone(&registry), 5)
+                                .with_stream_timeout(STREAM_TIMEOUT),
+                        ))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default()
+                                .with_session_registry(Arc::clone(&registry), 5),
+                            PathMatcher::new(vec!["/login"], true),
+                            factor(),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,156 @@
+use std::{convert::Infallible, net::SocketAddr, thread};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{
+        webauthn::{
+            AuthenticatorAssertionResponse, MfaWebAuthn, WebAuthnAssertion, WebAuthnVerifier,
+        },
+        ChallengeResponse, WebAuthnChallenge,
+    },
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+/// Stands in for a real verifier (e.g. with `webauthn-rs`), accepts the signature `valid`
+struct FakeVerifier;
+
+impl WebAuthnVerifier for FakeVerifier {
+    type Error = Infallible;
+
+    fn verify(
+        &self,
+        _: &str,
+        _: &WebAuthnChallenge,
+        assertion: &WebAuthnAssertion,
+    ) -> Result<bool, Self::Error> {
+        Ok(assertion.response.signature == "valid")
+    }
+}
+
+/// What the browser would send after `navigator.credentials.get()`
+fn assertion(challenge: &WebAuthnChallenge, signature: &str) -> String {
+    let client_data = format!(
+        "{{\"type\":\"webauthn.get\",\"challenge\":\"{}\",\"origin\":\"https://{}\"}}",
+        challenge.challenge, challenge.rp_id
+    );
+    serde_json::to_string(&WebAuthnAssertion {
+        id: "credential-of-anna".to_owned(),
+        response: AuthenticatorAssertionResponse {
+            client_data_json: BASE64_URL_SAFE_NO_PAD.encode(client_data),
+            authenticator_data: BASE64_URL_SAFE_NO_PAD.encode("authenticator data"),
+            signature: signature.to_owned(),
+            user_handle: None,
+        },
+    })
+    .unwrap()
+}
+
+#[actix_rt::test]
+async fn valid_assertion_should_complete_the_login() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let challenge = login(&client, addr).await;
+    assert_eq!(challenge.factor, "WEBAUTHN");
+    let webauthn = challenge.webauthn.unwrap();
+    assert_eq!(webauthn.rp_id, "localhost");
+
+    let res = send_assertion(&client, addr, &assertion(&webauthn, "valid")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn invalid_assertion_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let webauthn = login(&client, addr).await.webauthn.unwrap();
+
+    let res = send_assertion(&client, addr, &assertion(&webauthn, "forged")).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+async fn login(client: &Client, addr: SocketAddr) -> ChallengeResponse {
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    serde_json::from_str(&res.text().await.unwrap()).unwrap()
+}
+
+async fn send_assertion(client: &Client, addr: SocketAddr, assertion: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(serde_json::json!({ "code": assertion }).to_string())
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!("Hello {}", token.get_authenticated_user().name))
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                let key = Key::generate();
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                            Box::new(MfaWebAuthn::new(FakeVerifier, "localhost")),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            key.clone(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
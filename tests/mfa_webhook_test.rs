@@ -0,0 +1,147 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::Key, get, post, web::Bytes, web::Data, App, HttpRequest, HttpResponse, HttpServer,
+    Responder,
+};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::{
+        random_code_auth::{Charset, MfaRandomCode, RandomCode},
+        webhook::WebhookCodeSender,
+    },
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode};
+use sha2::Sha256;
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+const SECRET: &str = "webhook-secret";
+
+/// Codes received by the webhook, only if the signature is valid
+#[derive(Clone, Default)]
+struct ReceivedCodes(Arc<Mutex<Vec<String>>>);
+
+#[post("/webhook")]
+async fn webhook(req: HttpRequest, body: Bytes, received: Data<ReceivedCodes>) -> impl Responder {
+    let signature = req
+        .headers()
+        .get("X-Signature")
+        .and_then(|signature| BASE64_STANDARD.decode(signature.as_bytes()).ok())
+        .unwrap_or_default();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(&body);
+    if mac.verify_slice(&signature).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(payload["expires_at"].as_str().unwrap().ends_with('Z'));
+    received
+        .0
+        .lock()
+        .unwrap()
+        .push(payload["code"].as_str().unwrap().to_owned());
+    HttpResponse::Ok().finish()
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[actix_rt::test]
+async fn code_should_be_sent_to_webhook() {
+    let addr = actix_test::unused_addr();
+    let received = ReceivedCodes::default();
+    start_test_server(addr, received.clone());
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let code = wait_for_code(&received).await;
+
+    let res = client
+        .post(format!("http://{addr}/login/mfa"))
+        .body(format!("{{ \"code\": \"{code}\" }}"))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+// the webhook is called in the background
+async fn wait_for_code(received: &ReceivedCodes) -> String {
+    for _ in 0..50 {
+        if let Some(code) = received.0.lock().unwrap().first() {
+            return code.clone();
+        }
+        actix_rt::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Webhook has not received a code");
+}
+
+fn start_test_server(addr: SocketAddr, received: ReceivedCodes) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .app_data(Data::new(received.clone()))
+                        .service(webhook)
+                        .service(secured_route)
+                        .configure(login_config(SessionLoginHandler::with_mfa(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login", "/webhook"], true),
+                            Box::new(MfaRandomCode::new(
+                                || RandomCode::generate_secure(6, Charset::Numeric),
+                                WebhookCodeSender::new(&format!("http://{addr}/webhook"), SECRET),
+                            )),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::from(&[0; 64]),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,99 @@
+use std::{net::SocketAddr, thread};
+
+use actix_web::{get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    testing::TestAuthProvider,
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct User {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Admin {
+    pub name: String,
+}
+
+#[get("/api/profile")]
+pub async fn user_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[get("/admin/dashboard")]
+pub async fn admin_route(token: AuthToken<Admin>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[get("/admin/profile")]
+pub async fn admin_route_with_user(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[actix_rt::test]
+async fn each_user_type_should_be_checked_for_its_own_paths() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/api/profile"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anna");
+
+    let res = client
+        .get(format!("http://{addr}/admin/dashboard"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "root");
+
+    let res = client
+        .get(format!("http://{addr}/admin/profile"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(user_route)
+                        .service(admin_route)
+                        .service(admin_route_with_user)
+                        .wrap(
+                            AuthMiddleware::<_, User>::new(
+                                TestAuthProvider::new(User {
+                                    name: "anna".to_owned(),
+                                }),
+                                PathMatcher::new(vec!["/api/*"], false),
+                            )
+                            .for_user_type::<Admin, _>(
+                                TestAuthProvider::new(Admin {
+                                    name: "root".to_owned(),
+                                }),
+                                PathMatcher::new(vec!["/admin/*"], false),
+                            ),
+                        )
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,71 @@
+use std::{env, net::SocketAddr, thread};
+
+use actix_session::storage::CookieSessionStore;
+use actix_web::{cookie::Key, get, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher, PASSTHROUGH_ENV},
+    session::{
+        handlers::SessionLoginHandler,
+        session_auth::{session_login_factory, SessionAuthProvider},
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!(
+        "{} via {}",
+        token.get_authenticated_user().name,
+        token.provider()
+    ))
+}
+
+#[actix_rt::test]
+async fn passthrough_should_authenticate_every_request_with_the_mock_user() {
+    // the only test of this binary, so no other test sees the variable
+    env::set_var(PASSTHROUGH_ENV, "1");
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "dev via passthrough");
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    session_login_factory(
+                        SessionLoginHandler::new(HardCodedLoadUserService {}),
+                        AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::default(),
+                        )
+                        .passthrough_user(User {
+                            email: "dev@example.org".to_owned(),
+                            name: "dev".to_owned(),
+                        }),
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    )
+                    .service(secured_route)
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,100 @@
+use std::{net::SocketAddr, thread};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, App, HttpServer};
+use authfix::{
+    login::LoadUserError,
+    middleware::{AuthMiddleware, PathMatcher},
+    password::{PasswordHashStore, PlainTextVerifier, UserWithHash},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+};
+use futures::future::LocalBoxFuture;
+use reqwest::{Client, StatusCode};
+use test_utils::User;
+
+mod test_utils;
+
+struct UserRepository;
+
+impl PasswordHashStore for UserRepository {
+    type User = User;
+
+    fn load_user_with_hash(
+        &self,
+        username: &str,
+    ) -> LocalBoxFuture<'_, Result<Option<UserWithHash<User>>, LoadUserError>> {
+        let user = (username == "anna").then(|| {
+            let user = User {
+                email: "anna@example.org".to_owned(),
+                name: "anna".to_owned(),
+            };
+            (user, "test123".to_owned())
+        });
+        Box::pin(async move { Ok(user) })
+    }
+}
+
+#[actix_rt::test]
+async fn login_should_check_the_password_with_the_verifier() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+
+    assert_eq!(
+        login(&client, addr, "anna", "wrong").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        login(&client, addr, "bob", "test123").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        login(&client, addr, "anna", "test123").await,
+        StatusCode::OK
+    );
+}
+
+async fn login(client: &Client, addr: SocketAddr, username: &str, password: &str) -> StatusCode {
+    client
+        .post(format!("http://{addr}/login"))
+        .body(format!(
+            "{{ \"username\": \"{username}\", \"password\": \"{password}\" }}"
+        ))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .configure(login_config(SessionLoginHandler::with_password_verifier(
+                            UserRepository,
+                            PlainTextVerifier,
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,130 @@
+use std::{net::SocketAddr, thread};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, post, App, HttpResponse, HttpServer, Responder};
+use authfix::pkce::{code_challenge, PkceProtection};
+use reqwest::{Client, StatusCode};
+
+const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+#[actix_rt::test]
+async fn code_should_be_exchanged_with_the_matching_verifier_once() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = authorize(&client, addr, &code_challenge(VERIFIER)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = exchange(&client, addr, VERIFIER).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = exchange(&client, addr, VERIFIER).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn code_should_not_be_exchanged_with_another_verifier() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    authorize(&client, addr, &code_challenge(VERIFIER)).await;
+    let res = exchange(&client, addr, &"a".repeat(43)).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // the challenge is gone after a failed attempt
+    let res = exchange(&client, addr, VERIFIER).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn authorization_request_without_challenge_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/authorize"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = exchange(&client, addr, VERIFIER).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn other_routes_should_not_be_affected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = Client::new()
+        .get(format!("http://{addr}/public"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+async fn authorize(client: &Client, addr: SocketAddr, challenge: &str) -> reqwest::Response {
+    client
+        .get(format!(
+            "http://{addr}/authorize?code_challenge={challenge}&code_challenge_method=S256"
+        ))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn exchange(client: &Client, addr: SocketAddr, verifier: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/token?code_verifier={verifier}"))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[get("/authorize")]
+async fn authorize_route() -> impl Responder {
+    HttpResponse::Ok().body("code")
+}
+
+#[post("/token")]
+async fn token_route() -> impl Responder {
+    HttpResponse::Ok().body("token")
+}
+
+#[get("/public")]
+async fn public_route() -> impl Responder {
+    HttpResponse::Ok().body("Hello")
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                let key = Key::generate();
+                HttpServer::new(move || {
+                    App::new()
+                        .service(authorize_route)
+                        .service(token_route)
+                        .service(public_route)
+                        .wrap(PkceProtection::new("/authorize", "/token"))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            key.clone(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,190 @@
+use std::{net::SocketAddr, sync::Arc, thread, time::Duration};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, App, HttpServer};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    ratelimit::{InMemoryRateLimiter, RateLimitPolicy},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[actix_rt::test]
+async fn client_should_be_locked_after_too_many_failed_logins() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(60));
+
+    let client = Client::new();
+
+    assert_eq!(
+        login(&client, addr, "wrong").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        login(&client, addr, "wrong").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    // the correct password is not checked while locked
+    let res = send_login(&client, addr, "test123").await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers().get("Retry-After").unwrap(), "60");
+}
+
+#[actix_rt::test]
+async fn client_should_be_unlocked_after_the_lockout() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_millis(500));
+
+    let client = Client::new();
+
+    login(&client, addr, "wrong").await;
+    login(&client, addr, "wrong").await;
+    assert_eq!(
+        login(&client, addr, "test123").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    actix_rt::time::sleep(Duration::from_millis(600)).await;
+
+    assert_eq!(login(&client, addr, "test123").await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn successful_login_should_not_reset_the_failures() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(60));
+
+    let client = Client::new();
+
+    login(&client, addr, "wrong").await;
+    // e.g. the attacker logs into its own account between the guesses
+    assert_eq!(login(&client, addr, "test123").await, StatusCode::OK);
+
+    assert_eq!(
+        login(&client, addr, "wrong").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        login(&client, addr, "test123").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[actix_rt::test]
+async fn forwarded_header_should_be_ignored_by_default() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(60));
+
+    let client = Client::new();
+
+    for forwarded_for in ["10.0.0.1", "10.0.0.2"] {
+        let res = send_login_from(&client, addr, "wrong", forwarded_for).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = send_login_from(&client, addr, "test123", "10.0.0.3").await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_rt::test]
+async fn forwarded_header_should_identify_the_client_if_trusted() {
+    let addr = actix_test::unused_addr();
+    start_test_server_behind_proxy(addr);
+
+    let client = Client::new();
+
+    for _ in 0..2 {
+        let res = send_login_from(&client, addr, "wrong", "10.0.0.1").await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = send_login_from(&client, addr, "test123", "10.0.0.1").await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let res = send_login_from(&client, addr, "test123", "10.0.0.2").await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+async fn login(client: &Client, addr: SocketAddr, password: &str) -> StatusCode {
+    send_login(client, addr, password).await.status()
+}
+
+async fn send_login(client: &Client, addr: SocketAddr, password: &str) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login"))
+        .body(format!(
+            "{{ \"username\": \"anna\", \"password\": \"{password}\" }}"
+        ))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn send_login_from(
+    client: &Client,
+    addr: SocketAddr,
+    password: &str,
+    forwarded_for: &str,
+) -> reqwest::Response {
+    client
+        .post(format!("http://{addr}/login"))
+        .body(format!(
+            "{{ \"username\": \"anna\", \"password\": \"{password}\" }}"
+        ))
+        .header("Content-Type", "application/json")
+        .header("X-Forwarded-For", forwarded_for)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn start_test_server(addr: SocketAddr, lockout_duration: Duration) {
+    start_server(addr, lockout_duration, false);
+}
+
+fn start_test_server_behind_proxy(addr: SocketAddr) {
+    start_server(addr, Duration::from_secs(60), true);
+}
+
+fn start_server(addr: SocketAddr, lockout_duration: Duration, trust_proxy_headers: bool) {
+    // shared by all workers
+    let rate_limiter = Arc::new(InMemoryRateLimiter::new(
+        RateLimitPolicy::default()
+            .max_attempts(2)
+            .lockout_duration(lockout_duration),
+    ));
+
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .configure(login_config(
+                            SessionLoginHandler::new(HardCodedLoadUserService {})
+                                .with_rate_limiter(Arc::clone(&rate_limiter))
+                                .trust_proxy_headers(trust_proxy_headers),
+                        ))
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::default(),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
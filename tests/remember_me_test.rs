@@ -0,0 +1,179 @@
+use std::{future::ready, net::SocketAddr, thread, time::Duration};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        remember_me::{RememberMeConfig, RememberedUserLoader},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use futures::future::LocalBoxFuture;
+use reqwest::{header::SET_COOKIE, Client, Response, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+/// Loads the users again, bob has been banned after the login
+struct UserRepository;
+
+impl RememberedUserLoader<User> for UserRepository {
+    fn load_user(&self, user_id: &str) -> LocalBoxFuture<'_, Option<User>> {
+        let user = (user_id == "anna").then(|| User {
+            name: user_id.to_owned(),
+            email: format!("{user_id}@example.org"),
+        });
+        Box::pin(ready(user))
+    }
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    token.get_authenticated_user().name.clone()
+}
+
+#[actix_rt::test]
+async fn remember_me_cookie_should_restore_the_session() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(60 * 60));
+
+    let res = login(&Client::new(), addr, "anna", true).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let remember_me = remember_me_cookie(&res).expect("remember me cookie is set");
+    assert!(remember_me.contains("HttpOnly"));
+
+    // a new client without the session cookie, e.g. after the browser was closed
+    let res = get_secured_route(addr, &remember_me).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anna");
+}
+
+#[actix_rt::test]
+async fn login_without_remember_me_should_not_set_the_cookie() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(60 * 60));
+
+    let res = login(&Client::new(), addr, "anna", false).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(remember_me_cookie(&res).is_none());
+}
+
+#[actix_rt::test]
+async fn expired_remember_me_cookie_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(1));
+
+    let res = login(&Client::new(), addr, "anna", true).await;
+    let remember_me = remember_me_cookie(&res).unwrap();
+
+    actix_rt::time::sleep(Duration::from_secs(2)).await;
+
+    let res = get_secured_route(addr, &remember_me).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn logout_should_remove_the_remember_me_cookie() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(60 * 60));
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    let res = login(&client, addr, "anna", true).await;
+    let remember_me = remember_me_cookie(&res).unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/logout"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let removal = remember_me_cookie(&res).expect("removal cookie is set");
+    assert!(removal.starts_with("remember_me=;"));
+    assert!(removal.contains("Max-Age=0"));
+
+    // the token is revoked, so a copy of the cookie is useless
+    let res = get_secured_route(addr, &remember_me).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn user_that_cannot_be_loaded_should_not_be_restored() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, Duration::from_secs(60 * 60));
+
+    let res = login(&Client::new(), addr, "bob", true).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let remember_me = remember_me_cookie(&res).unwrap();
+
+    let res = get_secured_route(addr, &remember_me).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+// The Set-Cookie header of the remember me cookie (with attributes)
+fn remember_me_cookie(res: &Response) -> Option<String> {
+    res.headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.starts_with("remember_me="))
+        .map(str::to_owned)
+}
+
+async fn get_secured_route(addr: SocketAddr, set_cookie: &str) -> Response {
+    let cookie = set_cookie.split(';').next().unwrap();
+    Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .header("Cookie", cookie)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn login(client: &Client, addr: SocketAddr, username: &str, remember_me: bool) -> Response {
+    client
+        .post(format!("http://{addr}/login"))
+        .body(format!(
+            "{{ \"username\": \"{username}\", \"password\": \"test123\", \"remember_me\": {remember_me} }}"
+        ))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+}
+
+fn start_test_server(addr: SocketAddr, ttl: Duration) {
+    let key = Key::generate();
+    let remember_me = RememberMeConfig::new(key.clone()).ttl(ttl);
+
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(
+                            SessionLoginHandler::new(HardCodedLoadUserService {})
+                                .with_remember_me(remember_me.clone()),
+                        ))
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default()
+                                .with_remember_me(remember_me.clone(), UserRepository),
+                            PathMatcher::new(vec!["/login"], true),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            key.clone(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
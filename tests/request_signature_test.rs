@@ -0,0 +1,118 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{post, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::AuthMiddleware,
+    signature::{signature_message, HmacSignatureVerifier},
+    AuthToken,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+const SECRET: &[u8] = b"signature-test-secret";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct User {
+    pub name: String,
+}
+
+#[post("/orders")]
+pub async fn create_order(_token: AuthToken<User>, body: String) -> impl Responder {
+    HttpResponse::Ok().body(body)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn sign(secret: &[u8], timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(&signature_message(
+        "POST",
+        "/orders",
+        timestamp,
+        body.as_bytes(),
+    ));
+    BASE64_STANDARD.encode(mac.finalize().into_bytes())
+}
+
+async fn send(addr: SocketAddr, timestamp: u64, signature: &str) -> reqwest::Response {
+    Client::new()
+        .post(format!("http://{addr}/orders"))
+        .body("{ \"item\": 1 }")
+        .header("X-Timestamp", timestamp.to_string())
+        .header("X-Signature", signature)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[actix_rt::test]
+async fn signed_request_should_be_accepted_and_keep_its_body() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let timestamp = now();
+    let res = send(addr, timestamp, &sign(SECRET, timestamp, "{ \"item\": 1 }")).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "{ \"item\": 1 }");
+}
+
+#[actix_rt::test]
+async fn request_with_wrong_signature_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let timestamp = now();
+    let res = send(
+        addr,
+        timestamp,
+        &sign(b"another-secret", timestamp, "{ \"item\": 1 }"),
+    )
+    .await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn replayed_request_should_be_rejected() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let timestamp = now() - 60 * 10;
+    let res = send(addr, timestamp, &sign(SECRET, timestamp, "{ \"item\": 1 }")).await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new().service(create_order).wrap(
+                        AuthMiddleware::test_mode(User {
+                            name: "anna".to_owned(),
+                        })
+                        .with_signature_verifier(HmacSignatureVerifier::new(SECRET)),
+                    )
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,140 @@
+use std::{
+    future::{ready, Future},
+    net::SocketAddr,
+    pin::Pin,
+    thread,
+};
+
+use actix_web::{get, http::Method, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    api_key::{ApiKeyAuthProvider, ApiKeyLookup},
+    errors::UnauthorizedError,
+    middleware::{AuthMiddleware, PathMatcher, PathMethodRule, RolePathRule},
+    AuthToken, Authorizable,
+};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+struct User {
+    name: String,
+    roles: Vec<String>,
+}
+
+impl Authorizable for User {
+    fn roles(&self) -> Vec<String> {
+        self.roles.clone()
+    }
+}
+
+struct KeyPerRole;
+
+impl ApiKeyLookup<User> for KeyPerRole {
+    fn lookup(&self, key: &str) -> Pin<Box<dyn Future<Output = Result<User, UnauthorizedError>>>> {
+        let user = match key {
+            "admin-key" => Ok(User {
+                name: "anna".to_owned(),
+                roles: vec!["user".to_owned(), "admin".to_owned()],
+            }),
+            "user-key" => Ok(User {
+                name: "bob".to_owned(),
+                roles: vec!["user".to_owned()],
+            }),
+            _ => Err(UnauthorizedError::default()),
+        };
+        Box::pin(ready(user))
+    }
+}
+
+#[get("/admin/users")]
+async fn admin_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[get("/profile")]
+async fn profile_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+async fn get(addr: SocketAddr, path: &str, key: Option<&str>) -> reqwest::Response {
+    let mut req = Client::new().get(format!("http://{addr}{path}"));
+    if let Some(key) = key {
+        req = req.header("X-API-Key", key);
+    }
+    req.send().await.unwrap()
+}
+
+#[actix_rt::test]
+async fn user_with_role_should_access_the_path() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = get(addr, "/admin/users", Some("admin-key")).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "anna");
+}
+
+#[actix_rt::test]
+async fn user_without_role_should_be_forbidden() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = get(addr, "/admin/users", Some("user-key")).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = get(addr, "/profile", Some("user-key")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn unauthenticated_user_should_be_unauthorized() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let res = get(addr, "/admin/users", None).await;
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn method_exclusion_should_not_skip_the_role_rule() {
+    let addr = actix_test::unused_addr();
+    start_server(addr, || {
+        PathMatcher::with_method_rules(
+            vec![PathMethodRule::new("/admin/*", vec![Method::GET])],
+            true,
+        )
+    });
+
+    let res = get(addr, "/admin/users", Some("user-key")).await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+fn start_test_server(addr: SocketAddr) {
+    start_server(addr, PathMatcher::default);
+}
+
+fn start_server(addr: SocketAddr, path_matcher: fn() -> PathMatcher) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(admin_route)
+                        .service(profile_route)
+                        .wrap(AuthMiddleware::<_, User>::new_with_roles(
+                            ApiKeyAuthProvider::new(KeyPerRole),
+                            path_matcher(),
+                            vec![RolePathRule::new("/admin/*", &["admin"])],
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
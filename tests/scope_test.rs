@@ -0,0 +1,112 @@
+use std::{net::SocketAddr, thread};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    session::session_auth::SessionAuthProvider,
+    testing::TestAuthProvider,
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct User {
+    pub name: String,
+}
+
+#[get("/secured-route")]
+pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().name.clone())
+}
+
+#[get("/public-route")]
+pub async fn public_route() -> impl Responder {
+    HttpResponse::Ok()
+}
+
+#[actix_rt::test]
+async fn wrapped_scope_should_be_secured_and_other_routes_not() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/api/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = client
+        .get(format!("http://{addr}/public-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn scopes_should_use_their_own_provider_and_patterns() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+
+    // the pattern is relative to the scope
+    let res = client
+        .get(format!("http://{addr}/api/public-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/dev/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "dev");
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(
+                            AuthMiddleware::<_, User>::new(
+                                SessionAuthProvider::default(),
+                                PathMatcher::new(vec!["/public-route"], true),
+                            )
+                            .wrap_scope("/api", |scope| {
+                                scope.service(secured_route).service(public_route)
+                            }),
+                        )
+                        .service(
+                            AuthMiddleware::<_, User>::new(
+                                TestAuthProvider::new(User {
+                                    name: "dev".to_owned(),
+                                }),
+                                PathMatcher::new(Vec::<&str>::new(), true),
+                            )
+                            .wrap_scope("/dev", |scope| scope.service(secured_route)),
+                        )
+                        .service(public_route)
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
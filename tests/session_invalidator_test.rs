@@ -0,0 +1,143 @@
+use std::{
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use actix_session::storage::CookieSessionStore;
+use actix_web::{cookie::Key, get, HttpRequest, HttpResponse, HttpServer, Responder};
+use authfix::{
+    login::{HandlerError, LoadUserError, LoadUserService, LoginToken},
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::SessionLoginHandler,
+        session_auth::{session_login_factory, SessionAuthProvider},
+    },
+    AuthToken, SessionInvalidator,
+};
+use futures::future::LocalBoxFuture;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+static LOGOUTS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct User {
+    pub email: String,
+}
+
+struct AcceptEveryoneLoginService;
+
+impl LoadUserService for AcceptEveryoneLoginService {
+    type User = User;
+
+    fn load_user(&self, _: &LoginToken) -> LocalBoxFuture<'_, Result<Self::User, LoadUserError>> {
+        Box::pin(async {
+            Ok(User {
+                email: "test@example.org".to_owned(),
+            })
+        })
+    }
+
+    fn on_success_handler(
+        &self,
+        _: &HttpRequest,
+        _: &Self::User,
+    ) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn on_error_handler(&self, _: &HttpRequest) -> LocalBoxFuture<'_, Result<(), HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+struct CountingInvalidator;
+
+impl SessionInvalidator for CountingInvalidator {
+    fn invalidate(&self, _req: &HttpRequest) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async {
+            LOGOUTS.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+}
+
+#[get("/secured-route")]
+pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.get_authenticated_user().email.clone())
+}
+
+#[actix_rt::test]
+async fn logout_should_call_the_session_invalidator() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(LOGOUTS.load(Ordering::SeqCst), 0);
+
+    client
+        .post(format!("http://{addr}/logout"))
+        .send()
+        .await
+        .unwrap();
+
+    // the invalidator is spawned, so it may complete after the response
+    for _ in 0..20 {
+        if LOGOUTS.load(Ordering::SeqCst) > 0 {
+            break;
+        }
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(LOGOUTS.load(Ordering::SeqCst), 1);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    session_login_factory(
+                        SessionLoginHandler::new(AcceptEveryoneLoginService),
+                        AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::default(),
+                        )
+                        .with_session_invalidator(CountingInvalidator),
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    )
+                    .service(secured_route)
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,321 @@
+use std::{
+    future::ready,
+    net::SocketAddr,
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, App, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    multifactor::random_code_auth::{CodeSender, MfaRandomCode, RandomCode},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        registry::{InMemorySessionRegistry, SessionEvictionPolicy},
+        remember_me::{RememberMeConfig, RememberedUserLoader},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use futures::future::LocalBoxFuture;
+use reqwest::{header::SET_COOKIE, Client, StatusCode};
+use test_utils::{CustomError, HardCodedLoadUserService, User};
+
+mod test_utils;
+
+struct UserRepository;
+
+impl RememberedUserLoader<User> for UserRepository {
+    fn load_user(&self, user_id: &str) -> LocalBoxFuture<'_, Option<User>> {
+        Box::pin(ready(Some(User {
+            name: user_id.to_owned(),
+            email: format!("{user_id}@example.org"),
+        })))
+    }
+}
+
+struct SilentSender;
+
+impl CodeSender for SilentSender {
+    type Error = CustomError;
+
+    fn send_code(&self, _code: RandomCode) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[get("/secured-route")]
+async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    token.get_authenticated_user().name.clone()
+}
+
+#[actix_rt::test]
+async fn login_should_be_rejected_if_the_limit_is_reached() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, SessionEvictionPolicy::Reject);
+
+    let first_browser = new_client();
+    assert_eq!(login(&first_browser, addr, "anna").await, StatusCode::OK);
+    assert_eq!(login(&new_client(), addr, "bob").await, StatusCode::OK);
+
+    assert_eq!(
+        login(&new_client(), addr, "anna").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        get_secured_route(&first_browser, addr).await,
+        StatusCode::OK
+    );
+}
+
+#[actix_rt::test]
+async fn oldest_session_should_be_evicted() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, SessionEvictionPolicy::EvictOldest);
+
+    let first_browser = new_client();
+    let second_browser = new_client();
+    assert_eq!(login(&first_browser, addr, "anna").await, StatusCode::OK);
+    assert_eq!(login(&second_browser, addr, "anna").await, StatusCode::OK);
+
+    assert_eq!(
+        get_secured_route(&first_browser, addr).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        get_secured_route(&second_browser, addr).await,
+        StatusCode::OK
+    );
+}
+
+#[actix_rt::test]
+async fn logout_should_free_the_session() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, SessionEvictionPolicy::Reject);
+
+    let first_browser = new_client();
+    assert_eq!(login(&first_browser, addr, "anna").await, StatusCode::OK);
+    let res = first_browser
+        .post(format!("http://{addr}/logout"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    assert_eq!(login(&new_client(), addr, "anna").await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn login_again_in_the_same_browser_should_not_count_twice() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, SessionEvictionPolicy::Reject);
+
+    let client = new_client();
+    assert_eq!(login(&client, addr, "anna").await, StatusCode::OK);
+    assert_eq!(login(&client, addr, "anna").await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn expired_session_should_free_the_session() {
+    let addr = actix_test::unused_addr();
+    start_server(addr, SessionEvictionPolicy::Reject, Duration::from_secs(1));
+
+    let first_browser = new_client();
+    assert_eq!(login(&first_browser, addr, "anna").await, StatusCode::OK);
+
+    actix_rt::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        get_secured_route(&first_browser, addr).await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    assert_eq!(login(&new_client(), addr, "anna").await, StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn session_restored_by_remember_me_should_be_registered() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr, SessionEvictionPolicy::EvictOldest);
+
+    let first_browser = new_client();
+    let res = first_browser
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\", \"remember_me\": true }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    let remember_me = res
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.starts_with("remember_me="))
+        .and_then(|value| value.split(';').next())
+        .unwrap()
+        .to_owned();
+
+    // a second browser with only the remember me cookie evicts the first session
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route"))
+        .header("Cookie", remember_me)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    assert_eq!(
+        get_secured_route(&first_browser, addr).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_rt::test]
+async fn login_without_mfa_should_not_evict_sessions() {
+    let addr = actix_test::unused_addr();
+    start_mfa_server(addr);
+
+    let first_browser = new_client();
+    assert_eq!(login(&first_browser, addr, "anna").await, StatusCode::OK);
+    assert_eq!(send_code(&first_browser, addr).await, StatusCode::OK);
+
+    // knows the password, but not the code
+    let attacker = new_client();
+    assert_eq!(login(&attacker, addr, "anna").await, StatusCode::OK);
+    assert_eq!(
+        get_secured_route(&first_browser, addr).await,
+        StatusCode::OK
+    );
+
+    // the session is only evicted by a complete login
+    let second_browser = new_client();
+    assert_eq!(login(&second_browser, addr, "anna").await, StatusCode::OK);
+    assert_eq!(send_code(&second_browser, addr).await, StatusCode::OK);
+    assert_eq!(
+        get_secured_route(&first_browser, addr).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+fn new_client() -> Client {
+    Client::builder().cookie_store(true).build().unwrap()
+}
+
+async fn get_secured_route(client: &Client, addr: SocketAddr) -> StatusCode {
+    client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn login(client: &Client, addr: SocketAddr, username: &str) -> StatusCode {
+    client
+        .post(format!("http://{addr}/login"))
+        .body(format!(
+            "{{ \"username\": \"{username}\", \"password\": \"test123\" }}"
+        ))
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn send_code(client: &Client, addr: SocketAddr) -> StatusCode {
+    client
+        .post(format!("http://{addr}/login/mfa"))
+        .body("{ \"code\": \"123abc\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+fn start_test_server(addr: SocketAddr, policy: SessionEvictionPolicy) {
+    start_server(addr, policy, Duration::from_secs(60 * 60));
+}
+
+fn start_server(addr: SocketAddr, policy: SessionEvictionPolicy, session_timeout: Duration) {
+    let registry = Arc::new(InMemorySessionRegistry::default());
+    let key = Key::generate();
+    let remember_me = RememberMeConfig::new(key.clone());
+
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(
+                            SessionLoginHandler::new(HardCodedLoadUserService {})
+                                .with_session_registry(Arc::clone(&registry), 1)
+                                .session_eviction_policy(policy)
+                                .with_remember_me(remember_me.clone()),
+                        ))
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default()
+                                .with_session_registry(Arc::clone(&registry), 1)
+                                .session_eviction_policy(policy)
+                                .with_session_timeout(session_timeout)
+                                .with_remember_me(remember_me.clone(), UserRepository),
+                            PathMatcher::new(vec!["/login"], true),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            key.clone(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
+
+fn start_mfa_server(addr: SocketAddr) {
+    let registry = Arc::new(InMemorySessionRegistry::default());
+
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .configure(login_config(
+                            SessionLoginHandler::with_mfa(HardCodedLoadUserService {})
+                                .with_session_registry(Arc::clone(&registry), 1),
+                        ))
+                        .wrap(AuthMiddleware::<_, User>::new_with_factor(
+                            SessionAuthProvider::default()
+                                .with_session_registry(Arc::clone(&registry), 1),
+                            PathMatcher::new(vec!["/login"], true),
+                            Box::new(MfaRandomCode::new(
+                                || {
+                                    RandomCode::new(
+                                        "123abc",
+                                        SystemTime::now() + Duration::from_secs(300),
+                                    )
+                                },
+                                SilentSender,
+                            )),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
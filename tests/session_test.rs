@@ -1,15 +1,17 @@
-use std::{net::SocketAddr, thread};
+use std::{net::SocketAddr, thread, time::Duration};
 
 use actix_session::storage::CookieSessionStore;
-use actix_web::{cookie::Key, get, HttpResponse, HttpServer, Responder};
+use actix_web::{cookie::Key, get, post, Error, HttpResponse, HttpServer, Responder};
 use authfix::{
-    login::LoadUserService,
+    errors::UnauthorizedResponseBuilder,
+    login::{DefaultLoginOutcomeMapper, LoadUserService, LoginOutcome, LoginOutcomeMapper},
     middleware::{AuthMiddleware, PathMatcher},
     session::{
         handlers::SessionLoginHandler,
         session_auth::{session_login_factory, SessionAuthProvider},
+        user_serializer::{BincodeUserSerializer, UserSerializer},
     },
-    AuthToken,
+    AuthToken, OptionalAuthToken, UserExtractor,
 };
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
@@ -54,6 +56,46 @@ impl LoadUserService for AcceptEveryoneLoginService {
     }
 }
 
+struct SlowLoginService {}
+
+impl LoadUserService for SlowLoginService {
+    type User = User;
+
+    fn load_user(
+        &self,
+        _: &authfix::login::LoginToken,
+    ) -> futures::future::LocalBoxFuture<'_, Result<Self::User, authfix::login::LoadUserError>>
+    {
+        Box::pin(async {
+            actix_rt::time::sleep(Duration::from_secs(2)).await;
+            Ok(User {
+                email: "test@example.org".to_owned(),
+                name: "Test User".to_owned(),
+            })
+        })
+    }
+
+    fn on_success_handler(
+        &self,
+        _: &actix_web::HttpRequest,
+        _: &Self::User,
+    ) -> futures::future::LocalBoxFuture<'_, Result<(), authfix::login::HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn on_error_handler(
+        &self,
+        _: &actix_web::HttpRequest,
+    ) -> futures::future::LocalBoxFuture<'_, Result<(), authfix::login::HandlerError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[get("/secured-route/user")]
+pub async fn user_route(user: UserExtractor<User>) -> impl Responder {
+    HttpResponse::Ok().body(user.name.clone())
+}
+
 #[get("/public-route")]
 pub async fn public_route(token: AuthToken<User>) -> impl Responder {
     HttpResponse::Ok().body(format!(
@@ -62,6 +104,19 @@ pub async fn public_route(token: AuthToken<User>) -> impl Responder {
     ))
 }
 
+#[get("/optional-route")]
+pub async fn optional_route(token: OptionalAuthToken<User>) -> impl Responder {
+    match token.user() {
+        Some(user) => HttpResponse::Ok().body(format!("Hello {}", user.name)),
+        None => HttpResponse::Ok().body("Hello anonymous"),
+    }
+}
+
+#[get("/secured-route/provider")]
+pub async fn provider_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.provider())
+}
+
 #[get("/secured-route")]
 pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
     HttpResponse::Ok().body(format!(
@@ -70,6 +125,58 @@ pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
     ))
 }
 
+#[get("/secured-route/request-path")]
+pub async fn request_path(token: AuthToken<User>) -> impl Responder {
+    let path = token
+        .request()
+        .map(|req| req.path().to_owned())
+        .unwrap_or_default();
+    HttpResponse::Ok().body(path)
+}
+
+#[get("/secured-route/recent-login")]
+pub async fn recent_login_route(token: AuthToken<User>) -> Result<impl Responder, Error> {
+    token.assert_not_expired(Duration::from_secs(60))?;
+    Ok(HttpResponse::Ok())
+}
+
+#[get("/secured-route/immediate-expiry")]
+pub async fn immediate_expiry_route(token: AuthToken<User>) -> Result<impl Responder, Error> {
+    token.assert_not_expired(Duration::ZERO)?;
+    Ok(HttpResponse::Ok())
+}
+
+#[post("/secured-route/touch")]
+pub async fn touch_route(token: AuthToken<User>) -> impl Responder {
+    token.touch();
+    HttpResponse::Ok()
+}
+
+#[get("/secured-route/last-active")]
+pub async fn last_active_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(token.last_active_at().is_some().to_string())
+}
+
+#[actix_rt::test]
+async fn unauthorized_response_should_redirect_to_the_login_page() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_redirect(addr);
+
+    let client = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(res.headers().get("Location").unwrap(), "/login.html");
+}
+
 #[actix_rt::test]
 async fn should_can_login() {
     let addr = actix_test::unused_addr();
@@ -94,6 +201,175 @@ async fn should_can_login() {
     assert_eq!(res.status(), StatusCode::OK);
 }
 
+#[actix_rt::test]
+async fn user_extractor_should_provide_the_user() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route/user"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "Test User");
+}
+
+#[actix_rt::test]
+async fn auth_token_should_name_the_session_provider() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route/provider"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "session");
+}
+
+#[actix_rt::test]
+async fn optional_auth_token_should_provide_the_user_only_if_logged_in() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/optional-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "Hello anonymous");
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/optional-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "Hello Test User");
+}
+
+#[actix_rt::test]
+async fn should_can_login_with_bincode_serializer() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_provider(
+        addr,
+        SessionAuthProvider::with_serializer(BincodeUserSerializer),
+    );
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.text().await.unwrap(),
+        "Request from user: test@example.org"
+    );
+}
+
+#[actix_rt::test]
+async fn auth_token_should_provide_the_request() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route/request-path"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.text().await.unwrap(), "/secured-route/request-path");
+}
+
+#[actix_rt::test]
+async fn should_reject_authentication_older_than_max_age() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route/recent-login"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route/immediate-expiry"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
 #[actix_rt::test]
 async fn should_return_401_when_auth_token_is_used_in_a_non_secured_route() {
     let addr = actix_test::unused_addr();
@@ -128,6 +404,61 @@ async fn should_return_401_when_not_authenticated() {
     assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[actix_rt::test]
+async fn unauthorized_response_should_contain_the_requested_path() {
+    let addr = actix_test::unused_addr();
+
+    start_test_server(addr);
+
+    let res = Client::new()
+        .get(format!("http://{addr}/secured-route?tab=2"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        res.headers().get("X-Requested-Path").unwrap(),
+        "/secured-route?tab=2"
+    );
+}
+
+#[actix_rt::test]
+async fn touch_should_store_last_activity_in_session() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route/last-active"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.text().await.unwrap(), "false");
+
+    client
+        .post(format!("http://{addr}/secured-route/touch"))
+        .send()
+        .await
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route/last-active"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.text().await.unwrap(), "true");
+}
+
 #[actix_rt::test]
 async fn logout_should_invalidate_session() {
     let addr = actix_test::unused_addr();
@@ -158,7 +489,160 @@ async fn logout_should_invalidate_session() {
     assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[actix_rt::test]
+async fn should_return_503_when_loading_the_user_times_out() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_login_handler(addr, || {
+        SessionLoginHandler::new(SlowLoginService {})
+            .with_load_user_timeout(Duration::from_millis(100))
+    });
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers().get("Retry-After").unwrap(), "1");
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_rt::test]
+async fn should_can_login_with_form() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_login_handler(addr, || {
+        SessionLoginHandler::new(AcceptEveryoneLoginService {}).form_encoded()
+    });
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("username=any&password=none")
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn should_can_login_with_pin() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_login_handler(addr, || {
+        SessionLoginHandler::new(AcceptEveryoneLoginService {}).pin_login()
+    });
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"device_id\": \"kiosk-1\", \"pin\": \"0815\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_rt::test]
+async fn should_respond_with_no_content_on_success() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_login_handler(addr, || {
+        SessionLoginHandler::new(AcceptEveryoneLoginService {}).no_content_on_success(true)
+    });
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+struct NoContentOutcomeMapper;
+
+impl LoginOutcomeMapper for NoContentOutcomeMapper {
+    fn map(&self, outcome: LoginOutcome) -> HttpResponse {
+        match outcome {
+            LoginOutcome::Success => HttpResponse::NoContent().finish(),
+            outcome => DefaultLoginOutcomeMapper.map(outcome),
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn outcome_mapper_should_create_the_login_response() {
+    let addr = actix_test::unused_addr();
+    start_test_server_with_login_handler(addr, || {
+        SessionLoginHandler::new(AcceptEveryoneLoginService {})
+            .with_outcome_mapper(NoContentOutcomeMapper)
+    });
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"any\", \"password\": \"none\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+}
+
 fn start_test_server(addr: SocketAddr) {
+    start_test_server_with_provider(addr, SessionAuthProvider::default());
+}
+
+fn start_test_server_with_provider<S>(addr: SocketAddr, provider: SessionAuthProvider<S>)
+where
+    S: UserSerializer<User> + Clone + Send + Sync + 'static,
+{
     thread::spawn(move || {
         actix_rt::System::new()
             .block_on(async {
@@ -166,13 +650,24 @@ fn start_test_server(addr: SocketAddr) {
                     session_login_factory(
                         SessionLoginHandler::new(AcceptEveryoneLoginService {}),
                         AuthMiddleware::<_, User>::new(
-                            SessionAuthProvider,
-                            PathMatcher::new(vec!["/login", "/public-route"], true),
+                            provider.clone(),
+                            PathMatcher::new(
+                                vec!["/login", "/public-route", "/optional-route"],
+                                true,
+                            ),
                         ),
                         CookieSessionStore::default(),
                         Key::generate(),
                     )
+                    .service(optional_route)
+                    .service(provider_route)
                     .service(secured_route)
+                    .service(user_route)
+                    .service(request_path)
+                    .service(recent_login_route)
+                    .service(immediate_expiry_route)
+                    .service(touch_route)
+                    .service(last_active_route)
                     .service(public_route)
                 })
                 .bind(format!("{addr}"))
@@ -183,3 +678,61 @@ fn start_test_server(addr: SocketAddr) {
             .unwrap();
     });
 }
+
+fn start_test_server_with_login_handler<L>(
+    addr: SocketAddr,
+    create_login_handler: fn() -> SessionLoginHandler<L, User>,
+) where
+    L: LoadUserService<User = User> + 'static,
+{
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    session_login_factory(
+                        create_login_handler(),
+                        AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::new(vec!["/login"], true),
+                        ),
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    )
+                    .service(secured_route)
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
+
+fn start_test_server_with_redirect(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    session_login_factory(
+                        SessionLoginHandler::new(AcceptEveryoneLoginService {}),
+                        AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default(),
+                            PathMatcher::default(),
+                        )
+                        .with_unauthorized_response(
+                            UnauthorizedResponseBuilder::default().redirect_to("/login.html"),
+                        ),
+                        CookieSessionStore::default(),
+                        Key::generate(),
+                    )
+                    .service(secured_route)
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,113 @@
+use std::{net::SocketAddr, thread, time::Duration};
+
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, get, post, App, HttpResponse, HttpServer, Responder};
+use authfix::{
+    middleware::{AuthMiddleware, PathMatcher},
+    session::{
+        handlers::{login_config, SessionLoginHandler},
+        session_auth::SessionAuthProvider,
+    },
+    AuthToken,
+};
+use reqwest::{Client, StatusCode};
+use test_utils::{HardCodedLoadUserService, User};
+
+mod test_utils;
+
+#[get("/secured-route")]
+async fn secured_route(_token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok()
+}
+
+#[post("/secured-route/long-operation")]
+async fn long_operation_route(token: AuthToken<User>) -> impl Responder {
+    token.extend_session(Duration::from_secs(60));
+    HttpResponse::Ok()
+}
+
+#[actix_rt::test]
+async fn session_should_expire_after_the_timeout() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    login(&client, addr).await;
+    assert_eq!(get_secured_route(&client, addr).await, StatusCode::OK);
+
+    actix_rt::time::sleep(Duration::from_millis(1200)).await;
+
+    assert_eq!(
+        get_secured_route(&client, addr).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_rt::test]
+async fn extended_session_should_outlive_the_timeout() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+    login(&client, addr).await;
+    let res = client
+        .post(format!("http://{addr}/secured-route/long-operation"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    actix_rt::time::sleep(Duration::from_millis(1200)).await;
+
+    assert_eq!(get_secured_route(&client, addr).await, StatusCode::OK);
+}
+
+async fn get_secured_route(client: &Client, addr: SocketAddr) -> StatusCode {
+    client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+async fn login(client: &Client, addr: SocketAddr) {
+    let res = client
+        .post(format!("http://{addr}/login"))
+        .body("{ \"username\": \"anna\", \"password\": \"test123\" }")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .service(long_operation_route)
+                        .configure(login_config(SessionLoginHandler::new(
+                            HardCodedLoadUserService {},
+                        )))
+                        .wrap(AuthMiddleware::<_, User>::new(
+                            SessionAuthProvider::default()
+                                .with_session_timeout(Duration::from_secs(1)),
+                            PathMatcher::new(vec!["/login"], true),
+                        ))
+                        .wrap(SessionMiddleware::new(
+                            CookieSessionStore::default(),
+                            Key::generate(),
+                        ))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
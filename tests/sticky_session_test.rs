@@ -0,0 +1,57 @@
+use std::{net::SocketAddr, thread};
+
+use actix_web::{get, App, HttpResponse, HttpServer, Responder};
+use authfix::sticky_session::{StickySessionMiddleware, WORKER_ID_COOKIE};
+use reqwest::{Client, StatusCode};
+
+#[get("/public-route")]
+pub async fn public_route() -> impl Responder {
+    HttpResponse::Ok()
+}
+
+#[actix_rt::test]
+async fn should_set_worker_id_cookie_only_once() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::builder().cookie_store(true).build().unwrap();
+
+    let res = client
+        .get(format!("http://{addr}/public-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let worker_cookie = res
+        .cookies()
+        .find(|c| c.name() == WORKER_ID_COOKIE)
+        .unwrap();
+    assert_eq!(worker_cookie.value(), "worker-1");
+
+    let res = client
+        .get(format!("http://{addr}/public-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(!res.cookies().any(|c| c.name() == WORKER_ID_COOKIE));
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(public_route)
+                        .wrap(StickySessionMiddleware::new("worker-1"))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
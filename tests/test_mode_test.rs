@@ -0,0 +1,56 @@
+use std::{net::SocketAddr, thread};
+
+use actix_web::{get, App, HttpResponse, HttpServer, Responder};
+use authfix::{middleware::AuthMiddleware, AuthToken};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct User {
+    pub name: String,
+}
+
+#[get("/secured-route")]
+pub async fn secured_route(token: AuthToken<User>) -> impl Responder {
+    HttpResponse::Ok().body(format!(
+        "Request from user: {}",
+        token.get_authenticated_user().name
+    ))
+}
+
+#[actix_rt::test]
+async fn test_mode_should_authenticate_every_request_with_mock_user() {
+    let addr = actix_test::unused_addr();
+    start_test_server(addr);
+
+    let client = Client::new();
+
+    let res = client
+        .get(format!("http://{addr}/secured-route"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await.unwrap(), "Request from user: anna");
+}
+
+fn start_test_server(addr: SocketAddr) {
+    thread::spawn(move || {
+        actix_rt::System::new()
+            .block_on(async {
+                HttpServer::new(move || {
+                    App::new()
+                        .service(secured_route)
+                        .wrap(AuthMiddleware::test_mode(User {
+                            name: "anna".to_owned(),
+                        }))
+                })
+                .bind(format!("{addr}"))
+                .unwrap()
+                .run()
+                .await
+            })
+            .unwrap();
+    });
+}
@@ -1,3 +1,6 @@
+// Not every test crate uses every helper of this module
+#![allow(dead_code)]
+
 use std::future::ready;
 
 use actix_web::HttpRequest;